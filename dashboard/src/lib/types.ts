@@ -69,6 +69,16 @@ export interface IndexSettings {
   removeStopWords?: boolean | string[];
   ignorePlurals?: boolean | string[];
   queryLanguages?: string[];
+  indexLanguages?: string[];
+  decompoundedAttributes?: Record<string, string[]>;
+  decompoundQuery?: boolean;
+  disableTypoToleranceOnAttributes?: string[];
+  disableTypoToleranceOnWords?: string[];
+  disablePrefixOnAttributes?: string[];
+  allowTyposOnNumericTokens?: boolean;
+  minProximity?: number;
+  separatorsToIndex?: string;
+  camelCaseAttributes?: string[];
   queryType?: "prefixLast" | "prefixAll" | "prefixNone";
   minWordSizefor1Typo?: number;
   minWordSizefor2Typos?: number;
@@ -8,11 +8,13 @@ use serde::{Deserialize, Serialize};
 
 use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use chrono::Utc;
+use flapjack::index::aliases::AliasStore;
+use flapjack::index::encryption;
 use hmac::{Hmac, Mac};
 use rand::Rng;
 use sha2::Sha256;
 use std::path::{Path, PathBuf};
-use std::sync::RwLock;
+use std::sync::{Arc, RwLock};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiKey {
@@ -50,17 +52,26 @@ pub struct KeyStore {
 }
 
 impl KeyStore {
-    pub fn load_or_create(data_dir: &Path, admin_key: &str) -> Self {
+    /// Load `keys.json` from `data_dir`, creating the default keys when it is
+    /// missing or unparsable. A file that is encrypted but cannot be
+    /// decrypted is an error: recreating the keys would lock out every client.
+    pub fn load_or_create(data_dir: &Path, admin_key: &str) -> flapjack::error::Result<Self> {
         let file_path = data_dir.join("keys.json");
         let data = if file_path.exists() {
-            match std::fs::read_to_string(&file_path) {
-                Ok(contents) => match serde_json::from_str::<KeyStoreData>(&contents) {
+            match encryption::read_file(&file_path) {
+                Ok(contents) => match serde_json::from_slice::<KeyStoreData>(&contents) {
                     Ok(d) => d,
                     Err(e) => {
                         tracing::warn!("Failed to parse keys.json, recreating: {}", e);
                         Self::create_default_keys(admin_key)
                     }
                 },
+                Err(e) if encryption::is_encrypted_file(&file_path).unwrap_or(false) => {
+                    return Err(flapjack::error::FlapjackError::Config(format!(
+                        "Failed to decrypt keys.json, check the master key: {}",
+                        e
+                    )));
+                }
                 Err(e) => {
                     tracing::warn!("Failed to read keys.json, recreating: {}", e);
                     Self::create_default_keys(admin_key)
@@ -87,7 +98,7 @@ impl KeyStore {
             admin_key_value: admin_key.to_string(),
         };
         store.save();
-        store
+        Ok(store)
     }
 
     fn create_default_keys(admin_key: &str) -> KeyStoreData {
@@ -148,7 +159,7 @@ impl KeyStore {
             if let Some(parent) = self.file_path.parent() {
                 let _ = std::fs::create_dir_all(parent);
             }
-            if let Err(e) = std::fs::write(&self.file_path, json) {
+            if let Err(e) = encryption::write_file(&self.file_path, json.as_bytes()) {
                 tracing::warn!("Failed to save keys.json: {}", e);
             }
         }
@@ -338,6 +349,16 @@ pub fn required_acl_for_route(method: &Method, path: &str) -> Option<&'static st
         return Some("admin");
     }
 
+    // A/B tests: reading results is analytics, creating/stopping/deleting changes search behavior
+    if path.starts_with("/2/abtests") && *method != Method::GET {
+        return Some("editSettings");
+    }
+
+    // Arbitrary SQL over raw analytics data is admin-only
+    if path == "/2/analytics/sql" {
+        return Some("admin");
+    }
+
     // Analytics API endpoints (/2/*) require "analytics" ACL
     if path.starts_with("/2/") {
         return Some("analytics");
@@ -348,6 +369,36 @@ pub fn required_acl_for_route(method: &Method, path: &str) -> Option<&'static st
         return Some("search");
     }
 
+    // Personalization profiles (/1/profiles/*) hold per-user data
+    if path.starts_with("/1/profiles/") {
+        return Some("personalization");
+    }
+
+    // Query Suggestions configs (/1/configs/*) are index configuration
+    if path == "/1/configs" || path.starts_with("/1/configs/") {
+        return match *method {
+            Method::GET => Some("settings"),
+            _ => Some("editSettings"),
+        };
+    }
+
+    // Repointing an alias changes what keys restricted to it can read
+    if path == "/1/aliases" || path.starts_with("/1/aliases/") {
+        return match *method {
+            Method::GET => Some("listIndexes"),
+            _ => Some("admin"),
+        };
+    }
+
+    // Custom dictionaries apply to every index; searching them only reads
+    if path.starts_with("/1/dictionaries/") {
+        return match *method {
+            Method::GET => Some("settings"),
+            _ if path.ends_with("/search") => Some("settings"),
+            _ => Some("editSettings"),
+        };
+    }
+
     let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
 
     if parts.len() >= 3 && parts[0] == "1" && parts[1] == "indexes" {
@@ -365,9 +416,13 @@ pub fn required_acl_for_route(method: &Method, path: &str) -> Option<&'static st
                 "queries" => Some("search"),
                 "browse" => Some("browse"),
                 "batch" => Some("addObject"),
+                "bulk" => Some("addObject"),
                 "clear" => Some("deleteObject"),
                 "deleteByQuery" => Some("deleteObject"),
                 "operation" => Some("addObject"),
+                "reencrypt" => Some("admin"),
+                "migrate" => Some("admin"),
+                "dump" => Some("admin"),
                 "objects" => Some("search"),
                 "settings" => match *method {
                     Method::GET => Some("settings"),
@@ -428,6 +483,56 @@ pub fn index_pattern_matches(patterns: &[String], index_name: &str) -> bool {
     })
 }
 
+/// Whether `patterns` allow `index_name`, or the index it points to when it
+/// is an alias: keys restricted to either name can use the alias.
+pub fn index_allowed(patterns: &[String], index_name: &str, aliases: Option<&AliasStore>) -> bool {
+    if index_pattern_matches(patterns, index_name) {
+        return true;
+    }
+    aliases.is_some_and(|aliases| {
+        aliases.is_alias(index_name)
+            && index_pattern_matches(patterns, &aliases.resolve(index_name))
+    })
+}
+
+/// The indexes the calling key may use, for handlers that take index names
+/// from the request body rather than the path.
+#[derive(Clone)]
+pub struct KeyIndexScope {
+    indexes: Vec<String>,
+    restrict_indices: Option<Vec<String>>,
+    aliases: Option<Arc<AliasStore>>,
+}
+
+impl KeyIndexScope {
+    /// Whether both the key's `indexes` and any secured-key `restrictIndices`
+    /// allow `index_name`.
+    pub fn allows(&self, index_name: &str) -> bool {
+        let aliases = self.aliases.as_deref();
+        if !index_allowed(&self.indexes, index_name, aliases) {
+            return false;
+        }
+        match &self.restrict_indices {
+            Some(restrict_indices) => index_allowed(restrict_indices, index_name, aliases),
+            None => true,
+        }
+    }
+}
+
+/// The 403 for a request naming an index outside the caller's scope, the
+/// same one the middleware returns for index paths. Requests without a scope
+/// (auth disabled) are allowed.
+pub fn reject_out_of_scope<'a>(
+    scope: Option<&KeyIndexScope>,
+    index_names: impl IntoIterator<Item = &'a str>,
+) -> Option<Response> {
+    let scope = scope?;
+    index_names
+        .into_iter()
+        .any(|name| !scope.allows(name))
+        .then(|| error_json("Invalid Application-ID or API key", 403))
+}
+
 fn extract_index_name(path: &str) -> Option<String> {
     let parts: Vec<&str> = path.trim_start_matches('/').split('/').collect();
     if parts.len() >= 3 && parts[0] == "1" && parts[1] == "indexes" {
@@ -539,24 +644,48 @@ pub async fn authenticate_and_authorize(
         }
     }
 
+    // Custom dictionaries apply to every index, so a key limited to some
+    // cannot change them
+    if path.starts_with("/1/dictionaries/") && required == Some("editSettings") {
+        let unrestricted = api_key.indexes.is_empty()
+            && secured_restrictions
+                .as_ref()
+                .and_then(|r| r.restrict_indices.as_ref())
+                .is_none();
+        if !unrestricted && !key_store.is_admin(&api_key_value) {
+            return Err(error_json("Method not allowed with this API key", 403));
+        }
+    }
+
+    let alias_store = request.extensions().get::<Arc<AliasStore>>().cloned();
+    let aliases = alias_store.as_deref();
     if let Some(ref restrictions) = secured_restrictions {
         if let Some(ref index_name) = extract_index_name(&path) {
-            if !api_key.indexes.is_empty() && !index_pattern_matches(&api_key.indexes, index_name) {
+            if !api_key.indexes.is_empty() && !index_allowed(&api_key.indexes, index_name, aliases)
+            {
                 return Err(error_json("Invalid Application-ID or API key", 403));
             }
             if let Some(ref restrict_indices) = restrictions.restrict_indices {
-                if !index_pattern_matches(restrict_indices, index_name) {
+                if !index_allowed(restrict_indices, index_name, aliases) {
                     return Err(error_json("Invalid Application-ID or API key", 403));
                 }
             }
         }
     } else if let Some(index_name) = extract_index_name(&path) {
-        if !index_pattern_matches(&api_key.indexes, &index_name) {
+        if !index_allowed(&api_key.indexes, &index_name, aliases) {
             return Err(error_json("Invalid Application-ID or API key", 403));
         }
     }
 
+    let scope = KeyIndexScope {
+        indexes: api_key.indexes.clone(),
+        restrict_indices: secured_restrictions
+            .as_ref()
+            .and_then(|r| r.restrict_indices.clone()),
+        aliases: alias_store,
+    };
     let mut request = request;
+    request.extensions_mut().insert(scope);
     if let Some(restrictions) = secured_restrictions {
         request.extensions_mut().insert(restrictions);
     }
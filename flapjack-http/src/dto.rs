@@ -190,6 +190,13 @@ pub struct SearchRequest {
     pub click_analytics: Option<bool>,
     #[serde(default, rename = "analyticsTags")]
     pub analytics_tags: Option<Vec<String>>,
+    #[serde(default, rename = "enableABTest")]
+    pub enable_ab_test: Option<bool>,
+    #[serde(default, rename = "enablePersonalization")]
+    pub enable_personalization: Option<bool>,
+    /// 0-100: how strongly the user's profile boosts results (default 100).
+    #[serde(default, rename = "personalizationImpact")]
+    pub personalization_impact: Option<u32>,
     /// URL-encoded params string (used by multi-query). Merged during deserialization.
     #[serde(default)]
     pub params: Option<String>,
@@ -233,6 +240,9 @@ pub struct SearchRequest {
     pub ignore_plurals: Option<flapjack::query::plurals::IgnorePluralsValue>,
     #[serde(default, rename = "queryLanguages")]
     pub query_languages: Option<Vec<String>>,
+    /// Query embedding, matched against the records' `_vectors`
+    #[serde(default)]
+    pub vector: Option<Vec<f32>>,
 }
 
 impl SearchRequest {
@@ -403,6 +413,15 @@ impl SearchRequest {
                 "clickAnalytics" => {
                     self.click_analytics = value.parse().ok();
                 }
+                "enableABTest" => {
+                    self.enable_ab_test = value.parse().ok();
+                }
+                "enablePersonalization" => {
+                    self.enable_personalization = value.parse().ok();
+                }
+                "personalizationImpact" => {
+                    self.personalization_impact = value.parse().ok();
+                }
                 "facetQuery" => {
                     if self.facet_query.is_none() {
                         self.facet_query = Some(value.into_owned());
@@ -516,6 +535,13 @@ impl SearchRequest {
                         }
                     }
                 }
+                "vector" => {
+                    if self.vector.is_none() {
+                        if let Ok(v) = serde_json::from_str::<Vec<f32>>(&value) {
+                            self.vector = Some(v);
+                        }
+                    }
+                }
                 _ => {}
             }
         }
@@ -0,0 +1,176 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+use flapjack::analytics::ab_testing::{AbTest, AbTestRequest, AbTestStore, AbTestVariant};
+use flapjack::analytics::AnalyticsQueryEngine;
+use flapjack::error::FlapjackError;
+use flapjack::IndexManager;
+
+use crate::auth::{reject_out_of_scope, KeyIndexScope};
+
+pub struct AbTestingState {
+    pub manager: Arc<IndexManager>,
+    pub engine: Arc<AnalyticsQueryEngine>,
+    pub store: Arc<AbTestStore>,
+}
+
+fn ab_test_not_found(id: i64) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "message": format!("A/B test {} not found", id),
+            "status": 404
+        })),
+    )
+        .into_response()
+}
+
+/// Serialize a test with its current status and per-variant metrics merged in.
+async fn ab_test_with_metrics(
+    engine: &AnalyticsQueryEngine,
+    test: &AbTest,
+) -> Result<serde_json::Value, FlapjackError> {
+    let metrics = engine
+        .ab_test_metrics(
+            test.index(),
+            test.ab_test_id,
+            test.variants.len(),
+            test.created_at.timestamp_millis(),
+        )
+        .await
+        .map_err(|e| FlapjackError::InvalidQuery(format!("Analytics error: {}", e)))?;
+
+    let mut out = serde_json::to_value(test)?;
+    out["status"] = serde_json::to_value(test.status())?;
+    out["clickSignificance"] = metrics["clickSignificance"].clone();
+    out["conversionSignificance"] = metrics["conversionSignificance"].clone();
+    if let (Some(variants), Some(stats)) = (
+        out["variants"].as_array_mut(),
+        metrics["variants"].as_array(),
+    ) {
+        for (variant, stat) in variants.iter_mut().zip(stats) {
+            if let (Some(v), Some(s)) = (variant.as_object_mut(), stat.as_object()) {
+                for (k, val) in s {
+                    v.insert(k.clone(), val.clone());
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// The 403 for a test with a variant index outside the caller key's scope.
+fn reject_variants(
+    scope: Option<Extension<KeyIndexScope>>,
+    variants: &[AbTestVariant],
+) -> Option<axum::response::Response> {
+    reject_out_of_scope(scope.as_deref(), variants.iter().map(|v| v.index.as_str()))
+}
+
+/// POST /2/abtests - Create an A/B test
+///
+/// Every variant index must exist and be allowed by the caller's key, since
+/// searches on the first one are served from the others.
+pub async fn create_ab_test(
+    State(state): State<Arc<AbTestingState>>,
+    scope: Option<Extension<KeyIndexScope>>,
+    Json(body): Json<AbTestRequest>,
+) -> Result<axum::response::Response, FlapjackError> {
+    if let Some(forbidden) = reject_variants(scope, &body.variants) {
+        return Ok(forbidden);
+    }
+    for variant in &body.variants {
+        state.manager.get_or_load(&variant.index)?;
+    }
+    let test = state
+        .store
+        .create(body)
+        .map_err(FlapjackError::InvalidQuery)?;
+    let task = state.manager.make_noop_task(test.index())?;
+    Ok(Json(serde_json::json!({
+        "abTestID": test.ab_test_id,
+        "index": test.index(),
+        "taskID": task.numeric_id
+    }))
+    .into_response())
+}
+
+/// GET /2/abtests - List A/B tests with their results
+pub async fn list_ab_tests(
+    State(state): State<Arc<AbTestingState>>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let tests = state.store.list();
+    let mut abtests = Vec::with_capacity(tests.len());
+    for test in &tests {
+        abtests.push(ab_test_with_metrics(&state.engine, test).await?);
+    }
+    Ok(Json(serde_json::json!({
+        "abtests": abtests,
+        "count": abtests.len(),
+        "total": tests.len()
+    })))
+}
+
+/// GET /2/abtests/:id - Get an A/B test with per-variant CTR, conversion rate and significance
+pub async fn get_ab_test(
+    State(state): State<Arc<AbTestingState>>,
+    Path(id): Path<i64>,
+) -> Result<axum::response::Response, FlapjackError> {
+    match state.store.get(id) {
+        Some(test) => Ok(Json(ab_test_with_metrics(&state.engine, &test).await?).into_response()),
+        None => Ok(ab_test_not_found(id)),
+    }
+}
+
+/// POST /2/abtests/:id/stop - Stop splitting traffic; results are kept
+pub async fn stop_ab_test(
+    State(state): State<Arc<AbTestingState>>,
+    Path(id): Path<i64>,
+    scope: Option<Extension<KeyIndexScope>>,
+) -> Result<axum::response::Response, FlapjackError> {
+    let Some(test) = state.store.get(id) else {
+        return Ok(ab_test_not_found(id));
+    };
+    if let Some(forbidden) = reject_variants(scope, &test.variants) {
+        return Ok(forbidden);
+    }
+    match state.store.stop(id) {
+        Some(test) => {
+            let task = state.manager.make_noop_task(test.index())?;
+            Ok(Json(serde_json::json!({
+                "abTestID": id,
+                "index": test.index(),
+                "taskID": task.numeric_id
+            }))
+            .into_response())
+        }
+        None => Ok(ab_test_not_found(id)),
+    }
+}
+
+/// DELETE /2/abtests/:id - Delete an A/B test
+pub async fn delete_ab_test(
+    State(state): State<Arc<AbTestingState>>,
+    Path(id): Path<i64>,
+    scope: Option<Extension<KeyIndexScope>>,
+) -> Result<axum::response::Response, FlapjackError> {
+    let Some(test) = state.store.get(id) else {
+        return Ok(ab_test_not_found(id));
+    };
+    if let Some(forbidden) = reject_variants(scope, &test.variants) {
+        return Ok(forbidden);
+    }
+    state.store.delete(id);
+    let task = state.manager.make_noop_task(test.index())?;
+    Ok(Json(serde_json::json!({
+        "abTestID": id,
+        "index": test.index(),
+        "taskID": task.numeric_id
+    }))
+    .into_response())
+}
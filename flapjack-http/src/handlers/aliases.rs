@@ -0,0 +1,146 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::AppState;
+use flapjack::error::FlapjackError;
+use flapjack::index::aliases::{Alias, ALIASES_DIR};
+
+#[derive(Debug, Deserialize)]
+pub struct SetAliasBody {
+    pub index: String,
+}
+
+/// List the index aliases
+#[utoipa::path(
+    get,
+    path = "/1/aliases",
+    tag = "aliases",
+    responses(
+        (status = 200, description = "Aliases and the index each points to", body = serde_json::Value)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn list_aliases(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(serde_json::json!({ "items": state.manager.aliases().list() }))
+}
+
+/// Get the index an alias points to
+#[utoipa::path(
+    get,
+    path = "/1/aliases/{alias}",
+    tag = "aliases",
+    params(
+        ("alias" = String, Path, description = "Alias name")
+    ),
+    responses(
+        (status = 200, description = "The alias", body = serde_json::Value),
+        (status = 404, description = "Alias not found")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn get_alias(
+    State(state): State<Arc<AppState>>,
+    Path(alias): Path<String>,
+) -> Result<Json<Alias>, FlapjackError> {
+    state
+        .manager
+        .aliases()
+        .get(&alias)
+        .map(Json)
+        .ok_or(FlapjackError::TenantNotFound(alias))
+}
+
+/// Point an alias at an index, replacing its previous target atomically
+#[utoipa::path(
+    put,
+    path = "/1/aliases/{alias}",
+    tag = "aliases",
+    params(
+        ("alias" = String, Path, description = "Alias name")
+    ),
+    request_body(content = serde_json::Value, description = "index: the index the alias points to"),
+    responses(
+        (status = 200, description = "Alias saved", body = serde_json::Value),
+        (status = 400, description = "Invalid alias or target"),
+        (status = 404, description = "Target index not found")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn set_alias(
+    State(state): State<Arc<AppState>>,
+    Path(alias): Path<String>,
+    Json(body): Json<SetAliasBody>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let aliases = state.manager.aliases();
+    // Pointing to another alias is rejected by the store
+    if !aliases.is_alias(&body.index) && !state.manager.base_path.join(&body.index).exists() {
+        return Err(FlapjackError::TenantNotFound(body.index));
+    }
+    let previous = aliases.set(&alias, &body.index)?;
+
+    state.manager.append_oplog(
+        ALIASES_DIR,
+        "alias_set",
+        serde_json::json!({ "alias": alias, "index": body.index }),
+    );
+    tracing::info!(
+        "[ALIAS {}] {} -> {}",
+        alias,
+        previous.as_deref().unwrap_or("(new)"),
+        body.index
+    );
+
+    let task = state.manager.make_noop_task(ALIASES_DIR)?;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "previousIndex": previous,
+        "updatedAt": chrono::Utc::now().to_rfc3339()
+    })))
+}
+
+/// Delete an alias; the index it points to is kept
+#[utoipa::path(
+    delete,
+    path = "/1/aliases/{alias}",
+    tag = "aliases",
+    params(
+        ("alias" = String, Path, description = "Alias name")
+    ),
+    responses(
+        (status = 200, description = "Alias deleted", body = serde_json::Value),
+        (status = 404, description = "Alias not found")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn delete_alias(
+    State(state): State<Arc<AppState>>,
+    Path(alias): Path<String>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    if state.manager.aliases().remove(&alias)?.is_none() {
+        return Err(FlapjackError::TenantNotFound(alias));
+    }
+
+    state.manager.append_oplog(
+        ALIASES_DIR,
+        "alias_delete",
+        serde_json::json!({ "alias": alias }),
+    );
+
+    let task = state.manager.make_noop_task(ALIASES_DIR)?;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "deletedAt": chrono::Utc::now().to_rfc3339()
+    })))
+}
@@ -1,13 +1,26 @@
 use axum::{
+    body::Body,
     extract::{Query, State},
+    http::header,
+    response::{IntoResponse, Response},
     Json,
 };
+use futures::StreamExt;
 use serde::Deserialize;
 use std::sync::Arc;
 
+use flapjack::analytics::buckets::TimeBuckets;
+use flapjack::analytics::export::{EventTable, ExportFormat, RowEncoder};
 use flapjack::analytics::AnalyticsQueryEngine;
 use flapjack::error::FlapjackError;
 
+/// Row cap for `/2/analytics/sql` when the request does not set `limit`.
+const SQL_DEFAULT_ROWS: usize = 1000;
+const SQL_MAX_ROWS: usize = 100_000;
+/// Time limit for `/2/analytics/sql` when the request does not set `timeoutMs`.
+const SQL_DEFAULT_TIMEOUT_MS: u64 = 10_000;
+const SQL_MAX_TIMEOUT_MS: u64 = 60_000;
+
 /// Shared query parameters for all analytics endpoints.
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -29,6 +42,15 @@ pub struct AnalyticsParams {
     pub country: Option<String>,
     #[serde(default)]
     pub order_by: Option<String>,
+    /// Series bucket size: `minute`, `hour`, `day` (default) or `week`.
+    #[serde(default)]
+    pub granularity: Option<String>,
+    /// Number of minutes or hours per bucket (e.g. 15 with `granularity=minute`).
+    #[serde(default)]
+    pub interval: Option<u32>,
+    /// IANA timezone for day boundaries and bucket labels (default UTC).
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 /// Query parameters for the overview endpoint (no index required).
@@ -39,6 +61,36 @@ pub struct OverviewParams {
     pub start_date: String,
     #[serde(default = "default_end_date")]
     pub end_date: String,
+    /// Series bucket size: `minute`, `hour`, `day` (default) or `week`.
+    #[serde(default)]
+    pub granularity: Option<String>,
+    /// Number of minutes or hours per bucket (e.g. 15 with `granularity=minute`).
+    #[serde(default)]
+    pub interval: Option<u32>,
+    /// IANA timezone for day boundaries and bucket labels (default UTC).
+    #[serde(default)]
+    pub timezone: Option<String>,
+}
+
+impl AnalyticsParams {
+    fn time_buckets(&self) -> Result<TimeBuckets, FlapjackError> {
+        parse_time_buckets(&self.granularity, self.interval, &self.timezone)
+    }
+}
+
+impl OverviewParams {
+    fn time_buckets(&self) -> Result<TimeBuckets, FlapjackError> {
+        parse_time_buckets(&self.granularity, self.interval, &self.timezone)
+    }
+}
+
+fn parse_time_buckets(
+    granularity: &Option<String>,
+    interval: Option<u32>,
+    timezone: &Option<String>,
+) -> Result<TimeBuckets, FlapjackError> {
+    TimeBuckets::parse(granularity.as_deref(), interval, timezone.as_deref())
+        .map_err(|e| FlapjackError::InvalidQuery(format!("Analytics error: {}", e)))
 }
 
 fn default_start_date() -> String {
@@ -56,6 +108,7 @@ pub async fn get_top_searches(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(10);
     let click_analytics = params.click_analytics.unwrap_or(false);
     let result = engine
@@ -78,6 +131,7 @@ pub async fn get_search_count(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .search_count(&params.index, &params.start_date, &params.end_date)
         .await
@@ -90,6 +144,7 @@ pub async fn get_no_results(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(1000);
     let result = engine
         .no_results_searches(&params.index, &params.start_date, &params.end_date, limit)
@@ -103,6 +158,7 @@ pub async fn get_no_result_rate(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .no_results_rate(&params.index, &params.start_date, &params.end_date)
         .await
@@ -115,6 +171,7 @@ pub async fn get_no_clicks(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(1000);
     let result = engine
         .no_click_searches(&params.index, &params.start_date, &params.end_date, limit)
@@ -128,6 +185,7 @@ pub async fn get_no_click_rate(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .no_click_rate(&params.index, &params.start_date, &params.end_date)
         .await
@@ -140,6 +198,7 @@ pub async fn get_click_through_rate(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .click_through_rate(&params.index, &params.start_date, &params.end_date)
         .await
@@ -152,6 +211,7 @@ pub async fn get_average_click_position(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .average_click_position(&params.index, &params.start_date, &params.end_date)
         .await
@@ -164,6 +224,7 @@ pub async fn get_click_positions(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .click_positions(&params.index, &params.start_date, &params.end_date)
         .await
@@ -176,6 +237,7 @@ pub async fn get_conversion_rate(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .conversion_rate(&params.index, &params.start_date, &params.end_date)
         .await
@@ -188,6 +250,7 @@ pub async fn get_top_hits(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(1000);
     let result = engine
         .top_hits(&params.index, &params.start_date, &params.end_date, limit)
@@ -201,6 +264,7 @@ pub async fn get_top_filters(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(1000);
     let result = engine
         .top_filters(&params.index, &params.start_date, &params.end_date, limit)
@@ -215,6 +279,7 @@ pub async fn get_filter_values(
     axum::extract::Path(attribute): axum::extract::Path<String>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(1000);
     let result = engine
         .filter_values(
@@ -234,6 +299,7 @@ pub async fn get_filters_no_results(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(1000);
     let result = engine
         .filters_no_results(&params.index, &params.start_date, &params.end_date, limit)
@@ -247,6 +313,7 @@ pub async fn get_users_count(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .users_count(&params.index, &params.start_date, &params.end_date)
         .await
@@ -259,6 +326,7 @@ pub async fn get_overview(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<OverviewParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .overview(&params.start_date, &params.end_date)
         .await
@@ -346,11 +414,117 @@ pub async fn clear_analytics(
     })))
 }
 
+/// Query parameters for raw event export.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportParams {
+    pub index: String,
+    #[serde(default = "default_start_date")]
+    pub start_date: String,
+    #[serde(default = "default_end_date")]
+    pub end_date: String,
+    /// `searches` or `events`
+    #[serde(default = "default_export_type", rename = "type")]
+    pub event_type: String,
+    /// `ndjson`, `csv` or `parquet`
+    #[serde(default = "default_export_format")]
+    pub format: String,
+}
+
+fn default_export_type() -> String {
+    "searches".to_string()
+}
+
+fn default_export_format() -> String {
+    "ndjson".to_string()
+}
+
+/// GET /2/analytics/export - Raw search or insight events as NDJSON, CSV or Parquet
+///
+/// NDJSON and CSV are streamed batch by batch. Parquet is returned as one file: the
+/// rows in the date range are filtered from the stored files and re-encoded, so the
+/// download is a new file rather than a copy of the files on disk.
+pub async fn export_events(
+    State(engine): State<Arc<AnalyticsQueryEngine>>,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, FlapjackError> {
+    let table = EventTable::parse(&params.event_type).map_err(FlapjackError::InvalidQuery)?;
+    let format = ExportFormat::parse(&params.format).map_err(FlapjackError::InvalidQuery)?;
+    let filename = format!(
+        "{}_{}_{}_{}.{}",
+        params.index,
+        table.table_name(),
+        params.start_date,
+        params.end_date,
+        format.extension()
+    );
+    let headers = [
+        (header::CONTENT_TYPE, format.content_type().to_string()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        ),
+    ];
+
+    if format == ExportFormat::Parquet {
+        let bytes = engine
+            .export_parquet(&params.index, table, &params.start_date, &params.end_date)
+            .await
+            .map_err(|e| FlapjackError::InvalidQuery(format!("Analytics error: {}", e)))?;
+        return Ok((headers, bytes).into_response());
+    }
+
+    let mut encoder = RowEncoder::new(format).map_err(FlapjackError::InvalidQuery)?;
+    let stream = engine
+        .export_stream(&params.index, table, &params.start_date, &params.end_date)
+        .await
+        .map_err(|e| FlapjackError::InvalidQuery(format!("Analytics error: {}", e)))?
+        .map(move |batch| batch.map(|b| encoder.encode(&b)).map_err(|e| e.to_string()));
+    Ok((headers, Body::from_stream(stream)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SqlRequest {
+    pub index: String,
+    pub query: String,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// POST /2/analytics/sql - Read-only SQL over an index's `searches` and `events` tables
+pub async fn run_sql(
+    State(engine): State<Arc<AnalyticsQueryEngine>>,
+    Json(body): Json<SqlRequest>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let limit = body
+        .limit
+        .unwrap_or(SQL_DEFAULT_ROWS)
+        .clamp(1, SQL_MAX_ROWS);
+    let timeout_ms = body
+        .timeout_ms
+        .unwrap_or(SQL_DEFAULT_TIMEOUT_MS)
+        .clamp(1, SQL_MAX_TIMEOUT_MS);
+    let result = engine
+        .run_sql(
+            &body.index,
+            &body.query,
+            limit,
+            std::time::Duration::from_millis(timeout_ms),
+        )
+        .await
+        .map_err(|e| FlapjackError::InvalidQuery(format!("Analytics error: {}", e)))?;
+    Ok(Json(result))
+}
+
 /// GET /2/devices - Device/platform breakdown from analytics_tags
 pub async fn get_device_breakdown(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let result = engine
         .device_breakdown(&params.index, &params.start_date, &params.end_date)
         .await
@@ -363,6 +537,7 @@ pub async fn get_geo_breakdown(
     State(engine): State<Arc<AnalyticsQueryEngine>>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(50);
     let result = engine
         .geo_breakdown(&params.index, &params.start_date, &params.end_date, limit)
@@ -377,6 +552,7 @@ pub async fn get_geo_top_searches(
     axum::extract::Path(country): axum::extract::Path<String>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(10);
     let result = engine
         .geo_top_searches(
@@ -397,6 +573,7 @@ pub async fn get_geo_regions(
     axum::extract::Path(country): axum::extract::Path<String>,
     Query(params): Query<AnalyticsParams>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let engine = engine.with_time_buckets(params.time_buckets()?);
     let limit = params.limit.unwrap_or(50);
     let result = engine
         .geo_region_breakdown(
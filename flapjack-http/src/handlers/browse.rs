@@ -10,7 +10,7 @@ use super::AppState;
 use crate::filter_parser::parse_filter;
 use flapjack::error::FlapjackError;
 
-use super::field_value_to_json;
+use super::{field_value_to_json, is_retrieved};
 
 #[derive(Deserialize)]
 pub struct BrowseRequest {
@@ -23,6 +23,10 @@ pub struct BrowseRequest {
     #[serde(default = "default_browse_hits_per_page")]
     #[serde(rename = "hitsPerPage")]
     pub hits_per_page: usize,
+
+    #[serde(default)]
+    #[serde(rename = "attributesToRetrieve")]
+    pub attributes_to_retrieve: Option<Vec<String>>,
 }
 
 fn default_browse_hits_per_page() -> usize {
@@ -128,7 +132,9 @@ pub async fn browse_index(
             );
 
             for (key, value) in &scored_doc.document.fields {
-                doc_map.insert(key.clone(), field_value_to_json(value));
+                if is_retrieved(key, req.attributes_to_retrieve.as_deref()) {
+                    doc_map.insert(key.clone(), field_value_to_json(value));
+                }
             }
 
             serde_json::Value::Object(doc_map)
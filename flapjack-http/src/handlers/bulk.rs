@@ -0,0 +1,166 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::{header, HeaderMap},
+    Json,
+};
+use flate2::read::MultiGzDecoder;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::snapshot::ChannelReader;
+use super::AppState;
+use flapjack::error::FlapjackError;
+use flapjack::index::bulk::{BulkFormat, CsvOptions, CsvType};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct BulkParams {
+    /// `ndjson` or `csv`; defaults to the Content-Type, then NDJSON.
+    pub format: Option<String>,
+    /// CSV delimiter, a single character or `tab`.
+    pub delimiter: Option<String>,
+    /// CSV header to attribute pairs, e.g. `sku:objectID,name:title`.
+    pub mapping: Option<String>,
+    /// CSV attribute to type pairs, e.g. `price:float,inStock:boolean`.
+    pub types: Option<String>,
+}
+
+/// Stream NDJSON or CSV records into an index
+#[utoipa::path(
+    post,
+    path = "/1/indexes/{indexName}/bulk",
+    tag = "documents",
+    params(
+        ("indexName" = String, Path, description = "Index name"),
+        ("format" = Option<String>, Query, description = "ndjson or csv, defaults to the Content-Type"),
+        ("delimiter" = Option<String>, Query, description = "CSV delimiter, a single character or tab"),
+        ("mapping" = Option<String>, Query, description = "CSV header to attribute pairs, e.g. sku:objectID,name:title"),
+        ("types" = Option<String>, Query, description = "CSV attribute types, e.g. price:float,stock:integer,tags:json")
+    ),
+    request_body(content = Vec<u8>, description = "NDJSON or CSV, optionally gzipped"),
+    responses(
+        (status = 200, description = "Records queued as one task", body = serde_json::Value),
+        (status = 400, description = "Invalid format parameters or CSV header"),
+        (status = 429, description = "Write queue closed")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn bulk_import(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Query(params): Query<BulkParams>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let format = bulk_format(&params, &headers)?;
+    state.manager.create_tenant(&index_name)?;
+
+    // Parsed on a blocking thread as the body arrives; the write queue's
+    // backpressure holds the body back when indexing falls behind
+    let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+    let manager = Arc::clone(&state.manager);
+    let import = tokio::task::spawn_blocking(move || {
+        let reader = decompressed(ChannelReader::new(rx))?;
+        manager.import_records(&index_name, reader, &format)
+    });
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other);
+        // A closed channel means the import already failed
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    let task = import
+        .await
+        .map_err(|e| FlapjackError::Io(format!("Bulk import failed: {}", e)))??;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "receivedDocuments": task.received_documents,
+        "rejectedCount": task.rejected_count,
+        "rejectedDocuments": task.rejected_documents.iter().map(|f| serde_json::json!({
+            "objectID": f.doc_id,
+            "error": f.error,
+            "message": f.message,
+        })).collect::<Vec<_>>(),
+    })))
+}
+
+/// Gzip is detected from the content, whatever the headers say.
+pub(crate) fn decompressed(
+    reader: impl Read + Send + 'static,
+) -> std::io::Result<Box<dyn Read + Send>> {
+    let mut reader = BufReader::new(reader);
+    if reader.fill_buf()?.starts_with(&[0x1f, 0x8b]) {
+        Ok(Box::new(MultiGzDecoder::new(reader)))
+    } else {
+        Ok(Box::new(reader))
+    }
+}
+
+fn bulk_format(params: &BulkParams, headers: &HeaderMap) -> Result<BulkFormat, FlapjackError> {
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let format = match params.format.as_deref() {
+        Some(format) => format,
+        None if content_type.contains("csv") => "csv",
+        None => "ndjson",
+    };
+    match format {
+        "ndjson" | "jsonl" => Ok(BulkFormat::Ndjson),
+        "csv" => {
+            let mut options = CsvOptions::default();
+            if let Some(delimiter) = params.delimiter.as_deref() {
+                options.delimiter = match delimiter {
+                    "tab" | "\t" => b'\t',
+                    d if d.len() == 1 => d.as_bytes()[0],
+                    _ => {
+                        return Err(FlapjackError::InvalidQuery(format!(
+                            "Invalid CSV delimiter '{}'",
+                            delimiter
+                        )))
+                    }
+                };
+            }
+            if let Some(mapping) = params.mapping.as_deref() {
+                options.mapping = pairs("mapping", mapping)?;
+            }
+            if let Some(types) = params.types.as_deref() {
+                options.types = pairs("types", types)?
+                    .into_iter()
+                    .map(|(attribute, kind)| Ok((attribute, kind.parse::<CsvType>()?)))
+                    .collect::<Result<_, FlapjackError>>()?;
+            }
+            Ok(BulkFormat::Csv(options))
+        }
+        _ => Err(FlapjackError::InvalidQuery(format!(
+            "Unknown bulk format '{}', expected ndjson or csv",
+            format
+        ))),
+    }
+}
+
+/// Parse `a:b,c:d` into a map.
+fn pairs(param: &str, value: &str) -> Result<HashMap<String, String>, FlapjackError> {
+    value
+        .split(',')
+        .filter(|pair| !pair.trim().is_empty())
+        .map(|pair| {
+            pair.split_once(':')
+                .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+                .ok_or_else(|| {
+                    FlapjackError::InvalidQuery(format!("Invalid {} entry '{}'", param, pair))
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,188 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use super::AppState;
+use flapjack::error::FlapjackError;
+use flapjack::index::dictionaries::{
+    DictionaryBatchRequest, DictionaryName, DictionarySettings, DICTIONARIES_DIR,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionaryBatchBody {
+    #[serde(default)]
+    pub clear_existing_dictionary_entries: bool,
+    pub requests: Vec<DictionaryBatchRequest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionarySearchBody {
+    #[serde(default)]
+    pub query: String,
+    #[serde(default)]
+    pub page: usize,
+    #[serde(default = "default_hits_per_page")]
+    pub hits_per_page: usize,
+    pub language: Option<String>,
+}
+
+fn default_hits_per_page() -> usize {
+    20
+}
+
+fn parse_dictionary(name: &str) -> Result<DictionaryName, FlapjackError> {
+    DictionaryName::parse(name).ok_or_else(|| {
+        FlapjackError::InvalidQuery(format!(
+            "Unknown dictionary '{}': expected stopwords, plurals or compounds",
+            name
+        ))
+    })
+}
+
+/// Add or delete entries of a custom dictionary
+#[utoipa::path(
+    post,
+    path = "/1/dictionaries/{dictionaryName}/batch",
+    tag = "dictionaries",
+    params(
+        ("dictionaryName" = String, Path, description = "stopwords, plurals or compounds")
+    ),
+    request_body(content = serde_json::Value, description = "clearExistingDictionaryEntries and addEntry/deleteEntry requests"),
+    responses(
+        (status = 200, description = "Entries saved", body = serde_json::Value),
+        (status = 400, description = "Unknown dictionary or invalid entry")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn batch_dictionary_entries(
+    State(state): State<Arc<AppState>>,
+    Path(dictionary_name): Path<String>,
+    Json(body): Json<DictionaryBatchBody>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let dictionary = parse_dictionary(&dictionary_name)?;
+    state.manager.dictionaries().batch(
+        dictionary,
+        body.clear_existing_dictionary_entries,
+        &body.requests,
+    )?;
+
+    state.manager.append_oplog(
+        DICTIONARIES_DIR,
+        "dictionary_batch",
+        serde_json::json!({
+            "dictionary": dictionary.as_str(),
+            "clearExistingDictionaryEntries": body.clear_existing_dictionary_entries,
+            "requests": body.requests,
+        }),
+    );
+
+    let task = state.manager.make_noop_task(DICTIONARIES_DIR)?;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "updatedAt": chrono::Utc::now().to_rfc3339()
+    })))
+}
+
+/// Search the entries of a custom dictionary
+#[utoipa::path(
+    post,
+    path = "/1/dictionaries/{dictionaryName}/search",
+    tag = "dictionaries",
+    params(
+        ("dictionaryName" = String, Path, description = "stopwords, plurals or compounds")
+    ),
+    request_body(content = serde_json::Value, description = "query, language, page and hitsPerPage"),
+    responses(
+        (status = 200, description = "Matching entries", body = serde_json::Value)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn search_dictionary_entries(
+    State(state): State<Arc<AppState>>,
+    Path(dictionary_name): Path<String>,
+    Json(body): Json<DictionarySearchBody>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let dictionary = parse_dictionary(&dictionary_name)?;
+    let entries =
+        state
+            .manager
+            .dictionaries()
+            .search(dictionary, &body.query, body.language.as_deref());
+
+    let hits_per_page = body.hits_per_page.max(1);
+    let nb_hits = entries.len();
+    let hits: Vec<_> = entries
+        .into_iter()
+        .skip(body.page * hits_per_page)
+        .take(hits_per_page)
+        .collect();
+
+    Ok(Json(serde_json::json!({
+        "hits": hits,
+        "nbHits": nb_hits,
+        "page": body.page,
+        "nbPages": nb_hits.div_ceil(hits_per_page)
+    })))
+}
+
+/// Get the dictionaries settings
+#[utoipa::path(
+    get,
+    path = "/1/dictionaries/*/settings",
+    tag = "dictionaries",
+    responses(
+        (status = 200, description = "Dictionaries settings", body = serde_json::Value)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn get_dictionary_settings(
+    State(state): State<Arc<AppState>>,
+) -> Json<DictionarySettings> {
+    Json(state.manager.dictionaries().settings())
+}
+
+/// Update the dictionaries settings (`disableStandardEntries`)
+#[utoipa::path(
+    put,
+    path = "/1/dictionaries/*/settings",
+    tag = "dictionaries",
+    request_body(content = serde_json::Value, description = "disableStandardEntries per dictionary and language"),
+    responses(
+        (status = 200, description = "Settings saved", body = serde_json::Value)
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn set_dictionary_settings(
+    State(state): State<Arc<AppState>>,
+    Json(settings): Json<DictionarySettings>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    state
+        .manager
+        .dictionaries()
+        .set_settings(settings.clone())?;
+
+    state.manager.append_oplog(
+        DICTIONARIES_DIR,
+        "dictionary_settings",
+        serde_json::to_value(&settings).unwrap_or_default(),
+    );
+
+    let task = state.manager.make_noop_task(DICTIONARIES_DIR)?;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "updatedAt": chrono::Utc::now().to_rfc3339()
+    })))
+}
@@ -0,0 +1,200 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::StreamExt;
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+
+use super::bulk::decompressed;
+use super::has_records;
+use super::snapshot::{ChannelReader, ChannelWriter};
+use super::AppState;
+use crate::auth::{index_pattern_matches, ApiKey};
+use flapjack::error::FlapjackError;
+
+/// Size of the body chunks of a streamed dump.
+const DUMP_CHUNK_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportDumpParams {
+    /// Recreate the dumped API keys, with new values.
+    #[serde(default)]
+    pub restore_keys: bool,
+    /// Import into an index that already has records, keeping those the
+    /// dump does not replace.
+    #[serde(default)]
+    pub overwrite: bool,
+}
+
+/// Export an index as a portable NDJSON dump
+#[utoipa::path(
+    get,
+    path = "/1/indexes/{indexName}/dump",
+    tag = "snapshots",
+    params(
+        ("indexName" = String, Path, description = "Index name")
+    ),
+    responses(
+        (status = 200, description = "NDJSON dump of the settings, synonyms, rules, key metadata and documents", body = Vec<u8>),
+        (status = 404, description = "Index not found")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn export_dump(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Response {
+    let tenant = state.manager.resolve_alias(&index_name);
+    if !state.manager.base_path.join(&tenant).exists() {
+        return (StatusCode::NOT_FOUND, "Index not found").into_response();
+    }
+    let keys = state
+        .key_store
+        .as_ref()
+        .map(|store| index_keys(&store.list_all(), store.admin_key_value(), &index_name))
+        .unwrap_or_default();
+
+    // Written on a blocking thread and sent chunk by chunk, like a snapshot
+    // export
+    let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+    let manager = Arc::clone(&state.manager);
+    tokio::task::spawn_blocking(move || {
+        let writer = std::io::BufWriter::with_capacity(DUMP_CHUNK_BYTES, ChannelWriter(tx.clone()));
+        if let Err(e) = manager.export_dump(&tenant, keys, writer) {
+            tracing::error!("Dump of {} failed: {:?}", tenant, e);
+            // Aborts the response so that the client sees a failed download
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
+        }
+    });
+    let body = Body::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+
+    let headers = [
+        ("Content-Type", "application/x-ndjson"),
+        (
+            "Content-Disposition",
+            &format!("attachment; filename=\"{}.ndjson\"", index_name),
+        ),
+    ];
+    (headers, body).into_response()
+}
+
+/// Import a dump into an index through the write queue
+#[utoipa::path(
+    post,
+    path = "/1/indexes/{indexName}/dump",
+    tag = "snapshots",
+    params(
+        ("indexName" = String, Path, description = "Index name"),
+        ("restoreKeys" = Option<bool>, Query, description = "Recreate the dumped API keys with new values"),
+        ("overwrite" = Option<bool>, Query, description = "Import into an index that already has records")
+    ),
+    request_body(content = Vec<u8>, description = "NDJSON dump, optionally gzipped"),
+    responses(
+        (status = 200, description = "Dump imported, documents queued as one task", body = serde_json::Value),
+        (status = 400, description = "Not a dump, or of a newer version"),
+        (status = 409, description = "The index has records and overwrite is not set")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn import_dump(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+    Query(params): Query<ImportDumpParams>,
+    body: Body,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    if !params.overwrite && has_records(&state.manager, &index_name)? {
+        return Err(FlapjackError::IndexAlreadyExists(index_name));
+    }
+    let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+    let manager = Arc::clone(&state.manager);
+    let import = tokio::task::spawn_blocking(move || {
+        let reader = decompressed(ChannelReader::new(rx))?;
+        manager.import_dump(&index_name, reader)
+    });
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other);
+        // A closed channel means the import already failed
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    drop(tx);
+
+    let imported = import
+        .await
+        .map_err(|e| FlapjackError::Io(format!("Dump import failed: {}", e)))??;
+
+    let keys: Vec<serde_json::Value> = match (&state.key_store, params.restore_keys) {
+        (Some(store), true) => imported
+            .keys
+            .into_iter()
+            .filter_map(restorable_key)
+            .map(|key| {
+                let created = store.create_key(key);
+                serde_json::json!({
+                    "key": created.value,
+                    "description": created.description,
+                    "acl": created.acl,
+                    "indexes": created.indexes,
+                })
+            })
+            .collect(),
+        _ => imported.keys,
+    };
+
+    let task = imported.task;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "receivedDocuments": task.received_documents,
+        "rejectedCount": task.rejected_count,
+        "rejectedDocuments": task.rejected_documents.iter().map(|f| serde_json::json!({
+            "objectID": f.doc_id,
+            "error": f.error,
+            "message": f.message,
+        })).collect::<Vec<_>>(),
+        "settings": imported.settings,
+        "synonyms": imported.synonyms,
+        "rules": imported.rules,
+        "keys": keys,
+    })))
+}
+
+/// Metadata of the keys restricted to `index_name`, without their values.
+/// Unrestricted keys are not tied to one index and are left out.
+fn index_keys(keys: &[ApiKey], admin_key: &str, index_name: &str) -> Vec<serde_json::Value> {
+    keys.iter()
+        .filter(|key| key.value != admin_key && !key.indexes.is_empty())
+        .filter(|key| index_pattern_matches(&key.indexes, index_name))
+        .filter_map(|key| {
+            let mut value = serde_json::to_value(key).ok()?;
+            value.as_object_mut()?.remove("value");
+            Some(value)
+        })
+        .collect()
+}
+
+fn restorable_key(mut metadata: serde_json::Value) -> Option<ApiKey> {
+    let object = metadata.as_object_mut()?;
+    object.insert("value".to_string(), String::new().into());
+    object.entry("createdAt").or_insert(0.into());
+    match serde_json::from_value(metadata) {
+        Ok(key) => Some(key),
+        Err(e) => {
+            tracing::warn!("Skipping dumped key: {}", e);
+            None
+        }
+    }
+}
@@ -64,7 +64,7 @@ pub async fn search_facet_values_inline(
     let settings_path = state
         .manager
         .base_path
-        .join(index_name)
+        .join(state.manager.resolve_alias(index_name))
         .join("settings.json");
     let settings = if settings_path.exists() {
         IndexSettings::load(&settings_path)?
@@ -225,7 +225,7 @@ pub async fn search_facet_values(
     let settings_path = state
         .manager
         .base_path
-        .join(&index_name)
+        .join(state.manager.resolve_alias(&index_name))
         .join("settings.json");
     let settings = if settings_path.exists() {
         IndexSettings::load(&settings_path)?
@@ -23,6 +23,7 @@ pub async fn health(State(state): State<Arc<AppState>>) -> Json<serde_json::Valu
         "max_concurrent_writers": budget.max_concurrent_writers(),
         "facet_cache_entries": state.manager.facet_cache.len(),
         "facet_cache_cap": state.manager.facet_cache_cap.load(std::sync::atomic::Ordering::Relaxed),
+        "tenant_eviction": state.manager.eviction_stats(),
         "heap_allocated_mb": mem_stats.heap_allocated_bytes / (1024 * 1024),
         "system_limit_mb": mem_stats.system_limit_bytes / (1024 * 1024),
         "pressure_level": mem_stats.pressure_level.to_string(),
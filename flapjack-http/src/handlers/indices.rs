@@ -112,6 +112,10 @@ pub async fn list_indices(
         }
 
         let name = entry.file_name().to_string_lossy().to_string();
+        // Dot-dirs hold app-wide data such as the dictionaries, not indexes
+        if name.starts_with('.') {
+            continue;
+        }
         let index_path = entry.path();
         let size = dir_size(&index_path);
         tracing::debug!(index = %name, path = ?index_path, bytes = size, "Index directory size");
@@ -168,6 +172,7 @@ pub async fn clear_index(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let index_path = state.manager.base_path.join(&index_name);
     let settings_path = index_path.join("settings.json");
     let relevance_path = index_path.join("relevance.json");
@@ -231,6 +236,64 @@ pub async fn compact_index(
     })))
 }
 
+/// Re-encrypt an index with the current master key
+#[utoipa::path(
+    post,
+    path = "/1/indexes/{indexName}/reencrypt",
+    tag = "indices",
+    params(
+        ("indexName" = String, Path, description = "Index name to re-encrypt")
+    ),
+    responses(
+        (status = 200, description = "Index re-encrypted", body = serde_json::Value),
+        (status = 404, description = "Index not found"),
+        (status = 500, description = "No master key configured")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn reencrypt_index(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let rewritten = state.manager.reencrypt_tenant(&index_name).await?;
+    let key_id = flapjack::index::encryption::keyring()?.map(|k| k.current_key_id());
+    Ok(Json(serde_json::json!({
+        "rewrittenFiles": rewritten,
+        "keyId": key_id,
+        "updatedAt": chrono::Utc::now().to_rfc3339()
+    })))
+}
+
+/// Rebuild an index with the current internal schema
+#[utoipa::path(
+    post,
+    path = "/1/indexes/{indexName}/migrate",
+    tag = "indices",
+    params(
+        ("indexName" = String, Path, description = "Index name to migrate")
+    ),
+    responses(
+        (status = 200, description = "Migration started, or the running one", body = serde_json::Value),
+        (status = 404, description = "Index not found")
+    ),
+    security(
+        ("api_key" = [])
+    )
+)]
+pub async fn migrate_index(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> Result<Json<serde_json::Value>, FlapjackError> {
+    let task = state.manager.migrate_schema(&index_name)?;
+    Ok(Json(serde_json::json!({
+        "taskID": task.numeric_id,
+        "schemaVersion": flapjack::index::migration::SCHEMA_VERSION,
+        "updatedAt": chrono::Utc::now().to_rfc3339()
+    })))
+}
+
 #[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct OperationIndexRequest {
     pub operation: String,
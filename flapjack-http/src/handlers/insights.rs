@@ -1,13 +1,21 @@
 use axum::{extract::State, Json};
 use std::sync::Arc;
 
+use flapjack::analytics::personalization::{self, PersonalizationStore, ProfileUpdate};
 use flapjack::analytics::schema::InsightEvent;
 use flapjack::analytics::AnalyticsCollector;
 use flapjack::error::FlapjackError;
+use flapjack::IndexManager;
+
+pub struct InsightsState {
+    pub collector: Arc<AnalyticsCollector>,
+    pub manager: Arc<IndexManager>,
+    pub personalization: Arc<PersonalizationStore>,
+}
 
 /// POST /1/events - Algolia Insights API compatible event ingestion
 pub async fn post_events(
-    State(collector): State<Arc<AnalyticsCollector>>,
+    State(state): State<Arc<InsightsState>>,
     Json(body): Json<InsightsRequest>,
 ) -> Result<Json<serde_json::Value>, FlapjackError> {
     if body.events.len() > 1000 {
@@ -18,11 +26,15 @@ pub async fn post_events(
 
     let mut accepted = 0;
     let mut errors: Vec<String> = Vec::new();
+    let mut personalized: Vec<InsightEvent> = Vec::new();
 
     for event in body.events {
         match event.validate() {
             Ok(()) => {
-                collector.record_insight(event);
+                if personalization::event_weight(&event.event_type).is_some() {
+                    personalized.push(event.clone());
+                }
+                state.collector.record_insight(event);
                 accepted += 1;
             }
             Err(e) => {
@@ -38,12 +50,50 @@ pub async fn post_events(
         )));
     }
 
+    // Profiles are updated in the background; the objects behind the events
+    // are looked up off the request path
+    if !personalized.is_empty() {
+        let manager = Arc::clone(&state.manager);
+        let store = Arc::clone(&state.personalization);
+        tokio::task::spawn_blocking(move || {
+            store.apply(profile_updates(&manager, &personalized));
+        });
+    }
+
     Ok(Json(serde_json::json!({
         "status": 200,
         "message": "OK"
     })))
 }
 
+/// Resolve the facet values of the objects behind each event.
+fn profile_updates(manager: &IndexManager, events: &[InsightEvent]) -> Vec<ProfileUpdate> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut updates = Vec::with_capacity(events.len());
+    for event in events {
+        let Some(weight) = personalization::event_weight(&event.event_type) else {
+            continue;
+        };
+        let Some(settings) = manager.get_settings(&event.index) else {
+            continue;
+        };
+        let mut facet_values = Vec::new();
+        for object_id in event.effective_object_ids() {
+            if let Ok(Some(doc)) = manager.get_document(&event.index, object_id) {
+                facet_values.extend(personalization::facet_values(&doc, &settings));
+            }
+        }
+        updates.push(ProfileUpdate {
+            // The token searches send as `userToken`
+            user_token: event.user_token.clone(),
+            facet_values,
+            weight,
+            timestamp_ms: event.timestamp.unwrap_or(now).min(now),
+        });
+    }
+    updates
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct InsightsRequest {
     pub events: Vec<InsightEvent>,
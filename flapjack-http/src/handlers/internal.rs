@@ -5,6 +5,8 @@ use axum::{
     response::IntoResponse,
     Json,
 };
+use flapjack::index::aliases::ALIASES_DIR;
+use flapjack::index::dictionaries::DICTIONARIES_DIR;
 use flapjack::types::Document;
 use flapjack_replication::types::{
     GetOpsQuery, GetOpsResponse, ReplicateOpsRequest, ReplicateOpsResponse,
@@ -20,6 +22,40 @@ pub async fn replicate_ops(
 ) -> impl IntoResponse {
     let tenant_id = req.tenant_id.clone();
 
+    // Dictionaries and aliases are shared by all indexes and have no tenant
+    // of their own
+    if tenant_id == DICTIONARIES_DIR || tenant_id == ALIASES_DIR {
+        let mut max_seq = 0u64;
+        for op_entry in &req.ops {
+            max_seq = max_seq.max(op_entry.seq);
+            let applied = if tenant_id == DICTIONARIES_DIR {
+                state
+                    .manager
+                    .dictionaries()
+                    .apply_op(&op_entry.op_type, &op_entry.payload)
+            } else {
+                state
+                    .manager
+                    .aliases()
+                    .apply_op(&op_entry.op_type, &op_entry.payload)
+            };
+            if let Err(e) = applied {
+                tracing::warn!(
+                    "[REPL {}] failed to apply {} at seq {}: {}",
+                    tenant_id,
+                    op_entry.op_type,
+                    op_entry.seq,
+                    e
+                );
+            }
+        }
+        let response = ReplicateOpsResponse {
+            tenant_id,
+            acked_seq: max_seq,
+        };
+        return (StatusCode::OK, Json(response)).into_response();
+    }
+
     // Create tenant if it doesn't exist
     if let Err(e) = state.manager.create_tenant(&tenant_id) {
         tracing::warn!("[REPL {}] failed to create tenant: {}", tenant_id, e);
@@ -1,11 +1,17 @@
 use crate::auth::KeyStore;
+use flapjack::analytics::{AbTestStore, PersonalizationStore};
 use flapjack::IndexManager;
 use flapjack::SslManager;
 use flapjack_replication::manager::ReplicationManager;
 use std::sync::Arc;
 
+pub mod ab_testing;
+pub mod aliases;
 pub mod analytics;
 pub mod browse;
+pub mod bulk;
+pub mod dictionaries;
+pub mod dump;
 pub mod facets;
 pub mod health;
 pub mod indices;
@@ -14,6 +20,8 @@ pub mod internal;
 pub mod keys;
 pub mod migration;
 pub mod objects;
+pub mod personalization;
+pub mod query_suggestions;
 pub mod quickstart;
 pub mod rules;
 pub mod search;
@@ -27,6 +35,34 @@ pub struct AppState {
     pub key_store: Option<Arc<KeyStore>>,
     pub replication_manager: Option<Arc<ReplicationManager>>,
     pub ssl_manager: Option<Arc<SslManager>>,
+    /// A/B tests consulted by the search handler to route traffic between variants.
+    pub ab_tests: Option<Arc<AbTestStore>>,
+    /// User affinity profiles applied as boosts when `enablePersonalization` is set.
+    pub personalization: Option<Arc<PersonalizationStore>>,
+}
+
+/// Whether `attribute` is returned for the `attributesToRetrieve` of a
+/// request: all attributes without a list, those listed or all with `*`.
+/// `_vectors` is only returned when listed by name.
+pub(crate) fn is_retrieved(attribute: &str, attributes_to_retrieve: Option<&[String]>) -> bool {
+    match attributes_to_retrieve {
+        Some(attrs) if attrs.iter().any(|a| a == attribute) => true,
+        _ if attribute == flapjack::index::vectors::VECTORS_ATTRIBUTE => false,
+        Some(attrs) => attrs.iter().any(|a| a == "*"),
+        None => true,
+    }
+}
+
+/// Whether the index `index_name` exists and holds records.
+pub(crate) fn has_records(
+    manager: &IndexManager,
+    index_name: &str,
+) -> Result<bool, flapjack::error::FlapjackError> {
+    match manager.get_or_load(index_name) {
+        Ok(index) => Ok(index.reader().searcher().num_docs() > 0),
+        Err(flapjack::error::FlapjackError::TenantNotFound(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
 }
 
 /// Convert a FieldValue to serde_json::Value. Shared across handlers.
@@ -54,7 +90,8 @@ pub use browse::browse_index;
 pub use facets::{parse_facet_params, search_facet_values};
 pub use health::health;
 pub use indices::{
-    clear_index, compact_index, create_index, delete_index, list_indices, operation_index,
+    clear_index, compact_index, create_index, delete_index, list_indices, migrate_index,
+    operation_index, reencrypt_index,
 };
 pub use keys::{
     create_key, delete_key, generate_secured_key, get_key, list_keys, restore_key, update_key,
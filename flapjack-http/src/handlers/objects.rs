@@ -188,7 +188,7 @@ fn merge_partial_update(
     }
 }
 
-use super::field_value_to_json;
+use super::{field_value_to_json, is_retrieved};
 
 pub async fn add_documents_batch_impl(
     State(state): State<Arc<AppState>>,
@@ -438,7 +438,8 @@ pub async fn add_documents(
     tag = "documents",
     params(
         ("indexName" = String, Path, description = "Index name"),
-        ("objectID" = String, Path, description = "Object ID to retrieve")
+        ("objectID" = String, Path, description = "Object ID to retrieve"),
+        ("attributesToRetrieve" = Option<String>, Query, description = "Comma-separated attributes to return; `_vectors` only when listed")
     ),
     responses(
         (status = 200, description = "Object retrieved successfully", body = serde_json::Value),
@@ -451,7 +452,11 @@ pub async fn add_documents(
 pub async fn get_object(
     State(state): State<Arc<AppState>>,
     Path((index_name, object_id)): Path<(String, String)>,
+    Query(params): Query<GetObjectParams>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let attributes_to_retrieve: Option<Vec<String>> = params
+        .attributes_to_retrieve
+        .map(|attrs| attrs.split(',').map(|a| a.trim().to_string()).collect());
     let doc = state
         .manager
         .get_document(&index_name, &object_id)
@@ -470,7 +475,9 @@ pub async fn get_object(
             );
 
             for (key, value) in document.fields {
-                obj.insert(key, field_value_to_json(&value));
+                if is_retrieved(&key, attributes_to_retrieve.as_deref()) {
+                    obj.insert(key, field_value_to_json(&value));
+                }
             }
 
             Ok(Json(serde_json::Value::Object(obj)))
@@ -602,12 +609,9 @@ pub async fn get_objects(
                 );
 
                 for (key, value) in document.fields {
-                    if let Some(attrs) = &request.attributes_to_retrieve {
-                        if !attrs.contains(&key) {
-                            continue;
-                        }
+                    if is_retrieved(&key, request.attributes_to_retrieve.as_deref()) {
+                        obj.insert(key, field_value_to_json(&value));
                     }
-                    obj.insert(key, field_value_to_json(&value));
                 }
 
                 results.push(serde_json::Value::Object(obj));
@@ -814,3 +818,10 @@ pub async fn partial_update_object(
 pub struct PartialUpdateParams {
     pub create_if_not_exists: Option<bool>,
 }
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GetObjectParams {
+    /// Comma-separated attributes to return.
+    pub attributes_to_retrieve: Option<String>,
+}
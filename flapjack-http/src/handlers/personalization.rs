@@ -0,0 +1,56 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+use flapjack::analytics::PersonalizationStore;
+
+fn profile_not_found(user_token: &str) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "message": format!("No profile found for userToken '{}'", user_token),
+            "status": 404
+        })),
+    )
+        .into_response()
+}
+
+fn millis_to_rfc3339(ms: i64) -> Option<String> {
+    chrono::DateTime::from_timestamp_millis(ms).map(|t| t.to_rfc3339())
+}
+
+/// GET /1/profiles/personalization/:userToken - Affinity scores of a user, decayed to now
+pub async fn get_user_profile(
+    State(store): State<Arc<PersonalizationStore>>,
+    Path(user_token): Path<String>,
+) -> axum::response::Response {
+    let now = chrono::Utc::now().timestamp_millis();
+    match store.get(&user_token, now) {
+        Some(profile) => Json(serde_json::json!({
+            "userToken": profile.user_token,
+            "lastEventAt": millis_to_rfc3339(profile.last_event_at),
+            "scores": profile.scores
+        }))
+        .into_response(),
+        None => profile_not_found(&user_token),
+    }
+}
+
+/// DELETE /1/profiles/:userToken - Delete everything stored about a user (GDPR)
+pub async fn delete_user_profile(
+    State(store): State<Arc<PersonalizationStore>>,
+    Path(user_token): Path<String>,
+) -> axum::response::Response {
+    if !store.delete(&user_token) {
+        return profile_not_found(&user_token);
+    }
+    Json(serde_json::json!({
+        "userToken": user_token,
+        "deletedUntil": chrono::Utc::now().to_rfc3339()
+    }))
+    .into_response()
+}
@@ -0,0 +1,153 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Extension, Json,
+};
+use std::sync::Arc;
+
+use flapjack::analytics::suggestions::{self, QuerySuggestionsConfig, QuerySuggestionsStore};
+use flapjack::analytics::AnalyticsQueryEngine;
+use flapjack::error::FlapjackError;
+use flapjack::IndexManager;
+
+use super::has_records;
+use crate::auth::{reject_out_of_scope, KeyIndexScope};
+
+pub struct QuerySuggestionsState {
+    pub manager: Arc<IndexManager>,
+    pub engine: Arc<AnalyticsQueryEngine>,
+    pub store: Arc<QuerySuggestionsStore>,
+}
+
+fn config_not_found(index_name: &str) -> axum::response::Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(serde_json::json!({
+            "message": format!("Query suggestions config '{}' not found", index_name),
+            "status": 404
+        })),
+    )
+        .into_response()
+}
+
+/// GET /1/configs - List all query suggestions configs
+pub async fn list_configs(
+    State(state): State<Arc<QuerySuggestionsState>>,
+) -> Json<Vec<QuerySuggestionsConfig>> {
+    Json(state.store.list())
+}
+
+/// POST /1/configs - Create a query suggestions config
+///
+/// The builder removes every record of `indexName` it did not produce, so the
+/// target must not be an alias or an index that already holds records.
+pub async fn create_config(
+    State(state): State<Arc<QuerySuggestionsState>>,
+    scope: Option<Extension<KeyIndexScope>>,
+    Json(config): Json<QuerySuggestionsConfig>,
+) -> Result<axum::response::Response, FlapjackError> {
+    config.validate().map_err(FlapjackError::InvalidQuery)?;
+    let names = [config.index_name.as_str(), config.source_index.as_str()];
+    if let Some(forbidden) = reject_out_of_scope(scope.as_deref(), names) {
+        return Ok(forbidden);
+    }
+    if state.store.get(&config.index_name).is_some()
+        || state.manager.resolve_alias(&config.index_name) != config.index_name
+        || has_records(&state.manager, &config.index_name)?
+    {
+        return Err(FlapjackError::IndexAlreadyExists(config.index_name));
+    }
+    let index_name = config.index_name.clone();
+    state.store.upsert(config);
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({
+            "status": 201,
+            "message": "Configuration was created, and a new indexing job has been scheduled.",
+            "indexName": index_name
+        })),
+    )
+        .into_response())
+}
+
+/// GET /1/configs/:indexName - Get a query suggestions config
+pub async fn get_config(
+    State(state): State<Arc<QuerySuggestionsState>>,
+    Path(index_name): Path<String>,
+) -> impl IntoResponse {
+    match state.store.get(&index_name) {
+        Some(config) => Json(config).into_response(),
+        None => config_not_found(&index_name),
+    }
+}
+
+/// PUT /1/configs/:indexName - Replace a query suggestions config
+pub async fn update_config(
+    State(state): State<Arc<QuerySuggestionsState>>,
+    Path(index_name): Path<String>,
+    scope: Option<Extension<KeyIndexScope>>,
+    Json(mut config): Json<QuerySuggestionsConfig>,
+) -> Result<axum::response::Response, FlapjackError> {
+    if state.store.get(&index_name).is_none() {
+        return Ok(config_not_found(&index_name));
+    }
+    config.index_name = index_name;
+    config.validate().map_err(FlapjackError::InvalidQuery)?;
+    let names = [config.index_name.as_str(), config.source_index.as_str()];
+    if let Some(forbidden) = reject_out_of_scope(scope.as_deref(), names) {
+        return Ok(forbidden);
+    }
+    state.store.upsert(config);
+    Ok(Json(serde_json::json!({
+        "status": 200,
+        "message": "Configuration was updated, and a new indexing job has been scheduled."
+    }))
+    .into_response())
+}
+
+/// DELETE /1/configs/:indexName - Delete a config (the suggestions index is kept)
+pub async fn delete_config(
+    State(state): State<Arc<QuerySuggestionsState>>,
+    Path(index_name): Path<String>,
+) -> impl IntoResponse {
+    if state.store.delete(&index_name) {
+        Json(serde_json::json!({
+            "status": 200,
+            "message": "Configuration was deleted with success."
+        }))
+        .into_response()
+    } else {
+        config_not_found(&index_name)
+    }
+}
+
+/// GET /1/configs/:indexName/status - Build status of a query suggestions index
+pub async fn get_config_status(
+    State(state): State<Arc<QuerySuggestionsState>>,
+    Path(index_name): Path<String>,
+) -> impl IntoResponse {
+    match state.store.status(&index_name) {
+        Some(status) => Json(status).into_response(),
+        None => config_not_found(&index_name),
+    }
+}
+
+/// POST /1/configs/:indexName/build - Rebuild a query suggestions index now
+pub async fn build_config(
+    State(state): State<Arc<QuerySuggestionsState>>,
+    Path(index_name): Path<String>,
+    scope: Option<Extension<KeyIndexScope>>,
+) -> Result<axum::response::Response, FlapjackError> {
+    let Some(config) = state.store.get(&index_name) else {
+        return Ok(config_not_found(&index_name));
+    };
+    let names = [config.index_name.as_str(), config.source_index.as_str()];
+    if let Some(forbidden) = reject_out_of_scope(scope.as_deref(), names) {
+        return Ok(forbidden);
+    }
+    let result = suggestions::rebuild(&state.engine, &state.manager, &state.store, &config)
+        .await
+        .map_err(|e| FlapjackError::InvalidQuery(format!("Query suggestions error: {}", e)))?;
+    Ok(Json(result).into_response())
+}
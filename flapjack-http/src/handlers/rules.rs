@@ -31,6 +31,7 @@ pub async fn get_rule(
     State(state): State<Arc<AppState>>,
     Path((index_name, object_id)): Path<(String, String)>,
 ) -> Result<Json<Rule>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let rules_path = state.manager.base_path.join(&index_name).join("rules.json");
 
     if !rules_path.exists() {
@@ -77,6 +78,7 @@ pub async fn save_rule(
     Path((index_name, _object_id)): Path<(String, String)>,
     Json(rule): Json<Rule>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     state
         .manager
         .create_tenant(&index_name)
@@ -137,6 +139,7 @@ pub async fn delete_rule(
     State(state): State<Arc<AppState>>,
     Path((index_name, object_id)): Path<(String, String)>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let rules_path = state.manager.base_path.join(&index_name).join("rules.json");
 
     if !rules_path.exists() {
@@ -200,6 +203,7 @@ pub async fn save_rules(
     Query(params): Query<HashMap<String, String>>,
     Json(rules): Json<Vec<Rule>>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     state
         .manager
         .create_tenant(&index_name)
@@ -269,6 +273,7 @@ pub async fn clear_rules(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let rules_path = state.manager.base_path.join(&index_name).join("rules.json");
 
     if rules_path.exists() {
@@ -328,6 +333,7 @@ pub async fn search_rules(
     Path(index_name): Path<String>,
     Json(req): Json<SearchRulesRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let rules_path = state.manager.base_path.join(&index_name).join("rules.json");
 
     let store = if rules_path.exists() {
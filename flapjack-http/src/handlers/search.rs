@@ -13,9 +13,10 @@ use crate::dto::SearchRequest;
 use flapjack::query::highlighter::{
     extract_query_words, parse_snippet_spec, HighlightValue, Highlighter, SnippetValue,
 };
+use flapjack::tokenizer::{CjkSegmenter, KeepDiacritics};
 use flapjack::types::{FacetRequest, FieldValue, Sort, SortOrder};
 
-use super::field_value_to_json;
+use super::{field_value_to_json, is_retrieved};
 
 /// Extract userToken and client IP from request headers for analytics.
 fn extract_analytics_headers(headers: &axum::http::HeaderMap) -> (Option<String>, Option<String>) {
@@ -289,6 +290,22 @@ fn search_single_sync(
         None
     };
 
+    // A/B test: users are pinned to a variant of the test running on this index
+    let ab_assignment = if req.enable_ab_test != Some(false) {
+        let user_key = req.user_token.as_deref().or(req.user_ip.as_deref());
+        state
+            .ab_tests
+            .as_ref()
+            .zip(user_key)
+            .and_then(|(store, key)| store.assign(&index_name, key))
+    } else {
+        None
+    };
+    let search_index = ab_assignment
+        .as_ref()
+        .map(|a| a.index.clone())
+        .unwrap_or_else(|| index_name.clone());
+
     let filter = req.build_combined_filter();
 
     let sort = if let Some(sort_specs) = &req.sort {
@@ -315,7 +332,23 @@ fn search_single_sync(
         None
     };
 
-    let loaded_settings = state.manager.get_settings(&index_name);
+    let settings_override = ab_assignment
+        .as_ref()
+        .and_then(|a| a.settings_overlay.as_ref())
+        .and_then(|overlay| {
+            let base = state.manager.get_settings(&search_index);
+            match flapjack::analytics::ab_testing::apply_settings_overlay(base.as_deref(), overlay)
+            {
+                Ok(settings) => Some(Arc::new(settings)),
+                Err(e) => {
+                    tracing::warn!("[ab-test] ignoring invalid settings overlay: {}", e);
+                    None
+                }
+            }
+        });
+    let loaded_settings = settings_override
+        .clone()
+        .or_else(|| state.manager.get_settings(&search_index));
 
     let facet_requests = req.facets.as_ref().and_then(|facets| {
         let allowed_facets = loaded_settings.as_ref().map(|s| s.facet_set());
@@ -383,14 +416,29 @@ fn search_single_sync(
         Some(serde_json::Value::String(s)) if s == "false" => Some(false),
         _ => None,
     };
-    let optional_filter_specs = req
+    let mut optional_filter_specs = req
         .optional_filters
         .as_ref()
         .map(crate::dto::parse_optional_filters)
-        .filter(|v| !v.is_empty());
+        .unwrap_or_default();
+    // Personalization: the user's affinities become extra optional boosts
+    if req.enable_personalization == Some(true) {
+        if let Some((store, user_token)) = state
+            .personalization
+            .as_ref()
+            .zip(req.user_token.as_deref())
+        {
+            optional_filter_specs.extend(store.boosts(
+                user_token,
+                chrono::Utc::now().timestamp_millis(),
+                req.personalization_impact.unwrap_or(100),
+            ));
+        }
+    }
+    let optional_filter_specs = Some(optional_filter_specs).filter(|v| !v.is_empty());
 
-    let result = state.manager.search_full_with_stop_words(
-        &index_name,
+    let result = state.manager.search_full_with_settings(
+        &search_index,
         &req.query,
         filter.as_ref(),
         sort.as_ref(),
@@ -411,6 +459,8 @@ fn search_single_sync(
         req.enable_rules,
         req.rule_contexts.as_deref(),
         req.restrict_searchable_attributes.as_deref(),
+        settings_override,
+        req.vector.as_deref(),
     )?;
 
     let search_elapsed = start.elapsed();
@@ -419,7 +469,30 @@ fn search_single_sync(
     let highlighter = match (&req.highlight_pre_tag, &req.highlight_post_tag) {
         (Some(pre), Some(post)) => Highlighter::new(pre.clone(), post.clone()),
         _ => Highlighter::default(),
-    };
+    }
+    .with_keep_diacritics(
+        loaded_settings
+            .as_ref()
+            .map(|s| KeepDiacritics::parse(&s.keep_diacritics_on_characters))
+            .unwrap_or_default(),
+    )
+    .with_cjk_segmenter(
+        loaded_settings
+            .as_ref()
+            .and_then(|s| CjkSegmenter::for_languages(&s.index_languages)),
+    )
+    .with_decompound_languages(match &loaded_settings {
+        Some(s) if s.decompound_query => req.query_languages.clone().unwrap_or_else(|| {
+            if s.query_languages.is_empty() {
+                s.index_languages.clone()
+            } else {
+                s.query_languages.clone()
+            }
+        }),
+        Some(_) => Vec::new(),
+        None => req.query_languages.clone().unwrap_or_default(),
+    })
+    .with_custom_compounds(state.manager.dictionaries().custom().compounds.clone());
 
     let searchable_paths = loaded_settings
         .as_ref()
@@ -523,14 +596,13 @@ fn search_single_sync(
             );
 
             for (key, value) in &scored_doc.document.fields {
-                if let Some(ref attrs) = req.attributes_to_retrieve {
-                    if !attrs.contains(key) && !attrs.iter().any(|a| a == "*") {
-                        continue;
-                    }
-                } else if let Some(ref settings) = loaded_settings {
-                    if !settings.should_retrieve(key) {
-                        continue;
-                    }
+                let retrieved = match (&req.attributes_to_retrieve, &loaded_settings) {
+                    (Some(attrs), _) => is_retrieved(key, Some(attrs)),
+                    (None, Some(settings)) => settings.should_retrieve(key),
+                    (None, None) => is_retrieved(key, None),
+                };
+                if !retrieved {
+                    continue;
                 }
                 doc_map.insert(key.clone(), field_value_to_json(value));
             }
@@ -568,7 +640,7 @@ fn search_single_sync(
                 let mut ranking_info = serde_json::json!({
                     "nbTypos": 0,
                     "firstMatchedWord": 0,
-                    "proximityDistance": 0,
+                    "proximityDistance": scored_doc.proximity_distance,
                     "userScore": 0,
                     "geoDistance": 0,
                     "geoPrecision": 1,
@@ -598,6 +670,9 @@ fn search_single_sync(
                         "distance": dist as u64
                     });
                 }
+                if let Some(similarity) = scored_doc.semantic_score {
+                    ranking_info["semanticScore"] = serde_json::json!(similarity);
+                }
                 doc_map.insert("_rankingInfo".to_string(), ranking_info);
             }
 
@@ -730,6 +805,12 @@ fn search_single_sync(
         );
     }
 
+    if let Some(ref ab) = ab_assignment {
+        response["abTestID"] = serde_json::json!(ab.ab_test_id);
+        response["abTestVariantID"] = serde_json::json!(ab.variant_id);
+        response["indexUsed"] = serde_json::json!(search_index);
+    }
+
     // Add queryID to response when clickAnalytics is enabled
     if let Some(ref qid) = query_id {
         response["queryID"] = serde_json::json!(qid);
@@ -772,6 +853,8 @@ fn search_single_sync(
                 has_results: result.total > 0,
                 country: None,
                 region: None,
+                ab_test_id: ab_assignment.as_ref().map(|a| a.ab_test_id),
+                ab_test_variant_id: ab_assignment.as_ref().map(|a| a.variant_id),
             });
         }
     }
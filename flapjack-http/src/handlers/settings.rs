@@ -4,11 +4,13 @@ use axum::{
     response::IntoResponse,
     Json,
 };
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use super::AppState;
-use flapjack::index::settings::{DistinctValue, IndexSettings};
+use flapjack::index::settings::{DistinctValue, IndexSettings, SemanticSearch};
+use flapjack::tokenizer::{CjkSegmenter, IndexedSeparators};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SetSettingsRequest {
@@ -44,10 +46,61 @@ pub struct SetSettingsRequest {
     #[serde(rename = "queryLanguages")]
     pub query_languages: Option<Vec<String>>,
 
+    #[serde(rename = "indexLanguages")]
+    pub index_languages: Option<Vec<String>>,
+
+    #[serde(rename = "keepDiacriticsOnCharacters")]
+    pub keep_diacritics_on_characters: Option<String>,
+
+    #[serde(rename = "decompoundedAttributes")]
+    pub decompounded_attributes: Option<BTreeMap<String, Vec<String>>>,
+
+    #[serde(rename = "decompoundQuery")]
+    pub decompound_query: Option<bool>,
+
+    #[serde(rename = "disableTypoToleranceOnAttributes")]
+    pub disable_typo_tolerance_on_attributes: Option<Vec<String>>,
+
+    #[serde(rename = "disableTypoToleranceOnWords")]
+    pub disable_typo_tolerance_on_words: Option<Vec<String>>,
+
+    #[serde(rename = "disablePrefixOnAttributes")]
+    pub disable_prefix_on_attributes: Option<Vec<String>>,
+
+    #[serde(rename = "allowTyposOnNumericTokens")]
+    pub allow_typos_on_numeric_tokens: Option<bool>,
+
+    #[serde(rename = "minProximity")]
+    pub min_proximity: Option<u32>,
+
+    #[serde(rename = "separatorsToIndex")]
+    pub separators_to_index: Option<String>,
+
+    #[serde(rename = "camelCaseAttributes")]
+    pub camel_case_attributes: Option<Vec<String>>,
+
+    /// `Some(Null)` when sent as `null`, which clears the setting.
+    #[serde(rename = "semanticSearch", default, deserialize_with = "present")]
+    pub semantic_search: Option<serde_json::Value>,
+
+    #[serde(rename = "expiresAtAttribute")]
+    pub expires_at_attribute: Option<String>,
+
+    #[serde(rename = "timeToLive")]
+    pub time_to_live: Option<u64>,
+
     #[serde(flatten)]
     pub other: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
+/// A field that is present, `null` included.
+fn present<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
 #[derive(Debug, Serialize)]
 pub struct SetSettingsResponse {
     #[serde(rename = "updatedAt")]
@@ -82,6 +135,7 @@ pub async fn set_settings(
     Path(index_name): Path<String>,
     Json(payload): Json<SetSettingsRequest>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     state
         .manager
         .create_tenant(&index_name)
@@ -148,6 +202,90 @@ pub async fn set_settings(
     if let Some(ql) = payload.query_languages {
         settings.query_languages = ql;
     }
+    if let Some(dq) = payload.decompound_query {
+        settings.decompound_query = dq;
+    }
+    if let Some(attrs) = payload.disable_typo_tolerance_on_attributes {
+        settings.disable_typo_tolerance_on_attributes = attrs;
+    }
+    if let Some(words) = payload.disable_typo_tolerance_on_words {
+        settings.disable_typo_tolerance_on_words = words;
+    }
+    if let Some(attrs) = payload.disable_prefix_on_attributes {
+        settings.disable_prefix_on_attributes = attrs;
+    }
+    if let Some(allow) = payload.allow_typos_on_numeric_tokens {
+        settings.allow_typos_on_numeric_tokens = allow;
+    }
+    if let Some(min_proximity) = payload.min_proximity {
+        settings.min_proximity = min_proximity.clamp(1, 7);
+    }
+    // `null`, `false` or `{}` turn the semantic fallback off
+    if let Some(semantic_search) = payload.semantic_search {
+        settings.semantic_search = match semantic_search {
+            serde_json::Value::Null | serde_json::Value::Bool(false) => None,
+            serde_json::Value::Object(map) if map.is_empty() => None,
+            value => Some(
+                serde_json::from_value::<SemanticSearch>(value).map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid semanticSearch: {}", e),
+                    )
+                })?,
+            ),
+        };
+    }
+    // Indexed terms are segmented, folded and split, so changing the CJK
+    // segmenter, the folding exemptions, the indexed separators or the
+    // decompounded and camelCase attributes requires a reindex
+    let mut reindex = false;
+    if let Some(il) = payload.index_languages {
+        reindex |= CjkSegmenter::for_languages(&il)
+            != CjkSegmenter::for_languages(&settings.index_languages);
+        settings.index_languages = il;
+    }
+    if let Some(keep) = payload.keep_diacritics_on_characters {
+        if keep != settings.keep_diacritics_on_characters {
+            settings.keep_diacritics_on_characters = keep;
+            reindex = true;
+        }
+    }
+    if let Some(attrs) = payload.decompounded_attributes {
+        if attrs != settings.decompounded_attributes {
+            settings.decompounded_attributes = attrs;
+            reindex = true;
+        }
+    }
+    if let Some(separators) = payload.separators_to_index {
+        if IndexedSeparators::parse(&separators)
+            != IndexedSeparators::parse(&settings.separators_to_index)
+        {
+            reindex = true;
+        }
+        settings.separators_to_index = separators;
+    }
+    if let Some(attrs) = payload.camel_case_attributes {
+        if attrs != settings.camel_case_attributes {
+            settings.camel_case_attributes = attrs;
+            reindex = true;
+        }
+    }
+    // Expiry times are indexed with the documents as well; an empty
+    // attribute or a zero TTL turns them off
+    if let Some(attr) = payload.expires_at_attribute {
+        let attr = Some(attr).filter(|a| !a.is_empty());
+        if attr != settings.expires_at_attribute {
+            settings.expires_at_attribute = attr;
+            reindex = true;
+        }
+    }
+    if let Some(ttl) = payload.time_to_live {
+        let ttl = Some(ttl).filter(|t| *t > 0);
+        if ttl != settings.time_to_live {
+            settings.time_to_live = ttl;
+            reindex = true;
+        }
+    }
 
     if let Some(parent) = settings_path.parent() {
         std::fs::create_dir_all(parent).map_err(|e| {
@@ -171,13 +309,15 @@ pub async fn set_settings(
         serde_json::to_value(&settings).unwrap_or_default(),
     );
 
-    let noop_task = state
-        .manager
-        .make_noop_task(&index_name)
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let task = if reindex {
+        state.manager.reindex(&index_name)
+    } else {
+        state.manager.make_noop_task(&index_name)
+    }
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
     let response = SetSettingsResponse {
         updated_at: chrono::Utc::now().to_rfc3339(),
-        task_id: noop_task.numeric_id,
+        task_id: task.numeric_id,
         unsupported_params: if unsupported.is_empty() {
             None
         } else {
@@ -214,6 +354,7 @@ pub async fn get_settings(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
 ) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let settings_path = state
         .manager
         .base_path
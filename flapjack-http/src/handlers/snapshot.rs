@@ -1,14 +1,21 @@
 use super::AppState;
 use axum::{
-    body::Bytes,
+    body::{Body, Bytes},
     extract::{Path, State},
     http::StatusCode,
     response::IntoResponse,
     Json,
 };
-use flapjack::index::s3::S3Config;
-use flapjack::index::snapshot::{export_to_bytes, import_from_bytes};
+use flapjack::error::FlapjackError;
+use flapjack::index::snapshot::{export_to_writer, import_from_reader};
+use flapjack::index::snapshot_store::{self, SnapshotStore};
+use futures::StreamExt;
+use std::io::{Read, Write};
 use std::sync::Arc;
+use tokio::sync::mpsc;
+
+/// Size of the body chunks of a streamed export.
+const EXPORT_CHUNK_BYTES: usize = 256 * 1024;
 
 /// Export index as downloadable snapshot
 #[utoipa::path(
@@ -30,31 +37,38 @@ pub async fn export_snapshot(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
 ) -> impl IntoResponse {
+    let index_name = state.manager.resolve_alias(&index_name);
     let index_path = state.manager.base_path.join(&index_name);
     if !index_path.exists() {
         return (StatusCode::NOT_FOUND, "Index not found").into_response();
     }
 
-    match export_to_bytes(&index_path) {
-        Ok(bytes) => {
-            let headers = [
-                ("Content-Type", "application/gzip"),
-                (
-                    "Content-Disposition",
-                    &format!("attachment; filename=\"{}.tar.gz\"", index_name),
-                ),
-            ];
-            (headers, bytes).into_response()
-        }
-        Err(e) => {
+    // Archived on a blocking thread and sent chunk by chunk, so that the
+    // archive is never held in memory
+    let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+    tokio::task::spawn_blocking(move || {
+        let writer =
+            std::io::BufWriter::with_capacity(EXPORT_CHUNK_BYTES, ChannelWriter(tx.clone()));
+        let exported =
+            export_to_writer(&index_path, writer).and_then(|mut writer| Ok(writer.flush()?));
+        if let Err(e) = exported {
             tracing::error!("Export failed: {:?}", e);
-            (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Export failed: {}", e),
-            )
-                .into_response()
+            // Aborts the response so that the client sees a failed download
+            let _ = tx.blocking_send(Err(std::io::Error::other(e.to_string())));
         }
-    }
+    });
+    let body = Body::from_stream(futures::stream::unfold(rx, |mut rx| async move {
+        rx.recv().await.map(|chunk| (chunk, rx))
+    }));
+
+    let headers = [
+        ("Content-Type", "application/gzip"),
+        (
+            "Content-Disposition",
+            &format!("attachment; filename=\"{}.tar.gz\"", index_name),
+        ),
+    ];
+    (headers, body).into_response()
 }
 
 /// Import index from uploaded snapshot
@@ -77,26 +91,33 @@ pub async fn export_snapshot(
 pub async fn import_snapshot(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
-    body: Bytes,
+    body: Body,
 ) -> impl IntoResponse {
+    let index_name = state.manager.resolve_alias(&index_name);
     let index_path = state.manager.base_path.join(&index_name);
 
-    if index_path.exists() {
-        if let Err(e) = std::fs::remove_dir_all(&index_path) {
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to clear existing index: {}", e),
-            )
-                .into_response();
+    // Unpacked on a blocking thread as the body arrives; the existing index
+    // is only replaced once the archive's checksums match
+    let (tx, rx) = mpsc::channel::<std::io::Result<Bytes>>(4);
+    let import = tokio::task::spawn_blocking(move || {
+        import_from_reader(ChannelReader::new(rx), &index_path)
+    });
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(std::io::Error::other);
+        // A closed channel means the import already failed
+        if tx.send(chunk).await.is_err() {
+            break;
         }
     }
+    drop(tx);
 
-    match import_from_bytes(&body, &index_path) {
-        Ok(()) => {
+    match import.await {
+        Ok(Ok(())) => {
             state.manager.unload_tenant(&index_name);
             (StatusCode::OK, r#"{"status":"imported"}"#).into_response()
         }
-        Err(e) => {
+        Ok(Err(e)) => {
             tracing::error!("Import failed: {:?}", e);
             (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -104,10 +125,61 @@ pub async fn import_snapshot(
             )
                 .into_response()
         }
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Import failed: {}", e),
+        )
+            .into_response(),
+    }
+}
+
+/// Sends what is written as the chunks of a streamed response body.
+pub(crate) struct ChannelWriter(pub(crate) mpsc::Sender<std::io::Result<Bytes>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::BrokenPipe, "client went away"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the chunks of a streamed request body.
+pub(crate) struct ChannelReader {
+    rx: mpsc::Receiver<std::io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl ChannelReader {
+    pub(crate) fn new(rx: mpsc::Receiver<std::io::Result<Bytes>>) -> Self {
+        ChannelReader {
+            rx,
+            chunk: Bytes::new(),
+        }
     }
 }
 
-/// Upload index snapshot to S3
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk = self.chunk.slice(n..);
+        Ok(n)
+    }
+}
+
+/// Upload index snapshot to the snapshot store (S3 or a directory)
 #[utoipa::path(
     post,
     path = "/1/indexes/{indexName}/snapshot",
@@ -115,9 +187,10 @@ pub async fn import_snapshot(
     params(
         ("indexName" = String, Path, description = "Index name")
     ),
+    request_body(content = serde_json::Value, description = "Snapshot options: `incremental` uploads only the files not stored by the previous incremental snapshot"),
     responses(
-        (status = 200, description = "Snapshot uploaded to S3", body = serde_json::Value),
-        (status = 400, description = "S3 not configured"),
+        (status = 200, description = "Snapshot uploaded", body = serde_json::Value),
+        (status = 503, description = "Snapshot store not configured"),
         (status = 404, description = "Index not found")
     ),
     security(
@@ -127,58 +200,87 @@ pub async fn import_snapshot(
 pub async fn snapshot_to_s3(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
+    body: Option<Json<serde_json::Value>>,
 ) -> impl IntoResponse {
-    let s3_config =
-        match S3Config::from_env() {
-            Some(c) => c,
-            None => return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                r#"{"error":"S3 not configured. Set FLAPJACK_S3_BUCKET and FLAPJACK_S3_REGION."}"#,
-            )
-                .into_response(),
-        };
+    let index_name = state.manager.resolve_alias(&index_name);
+    let Some(store) = snapshot_store::from_env() else {
+        return not_configured();
+    };
 
     let index_path = state.manager.base_path.join(&index_name);
     if !index_path.exists() {
         return (StatusCode::NOT_FOUND, r#"{"error":"Index not found"}"#).into_response();
     }
 
-    let bytes = match export_to_bytes(&index_path) {
-        Ok(b) => b,
-        Err(e) => {
-            return (
+    let incremental = body
+        .and_then(|b| b.get("incremental").and_then(|v| v.as_bool()))
+        .unwrap_or(false);
+    if incremental {
+        let commit = match state.manager.pin_commit(&index_name) {
+            Ok(commit) => commit,
+            Err(e) => {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!(r#"{{"error":"Snapshot upload failed: {}"}}"#, e),
+                )
+                    .into_response()
+            }
+        };
+        return match snapshot_store::upload_incremental_snapshot(
+            store.as_ref(),
+            &index_name,
+            &index_path,
+            &commit,
+        )
+        .await
+        {
+            Ok(upload) => {
+                let _ = snapshot_store::enforce_incremental_retention(
+                    store.as_ref(),
+                    &index_name,
+                    snapshot_retention(),
+                )
+                .await;
+                Json(serde_json::json!({
+                    "status": "uploaded",
+                    "key": upload.manifest_key,
+                    "incremental": true,
+                    "files": upload.files,
+                    "uploaded_files": upload.uploaded_files,
+                    "size_bytes": upload.uploaded_bytes,
+                }))
+                .into_response()
+            }
+            Err(e) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
-                format!(r#"{{"error":"Export failed: {}"}}"#, e),
+                format!(r#"{{"error":"Snapshot upload failed: {}"}}"#, e),
             )
-                .into_response()
-        }
-    };
+                .into_response(),
+        };
+    }
 
-    match flapjack::index::s3::upload_snapshot(&s3_config, &index_name, &bytes).await {
-        Ok(key) => {
-            let retention = std::env::var("FLAPJACK_SNAPSHOT_RETENTION")
-                .ok()
-                .and_then(|v| v.parse::<usize>().ok())
-                .unwrap_or(24);
-            let _ =
-                flapjack::index::s3::enforce_retention(&s3_config, &index_name, retention).await;
+    match snapshot_store::upload_snapshot(store.as_ref(), &index_name, &index_path).await {
+        Ok((key, size)) => {
+            let _ = store
+                .enforce_retention(&index_name, snapshot_retention())
+                .await;
 
             Json(serde_json::json!({
                 "status": "uploaded",
                 "key": key,
-                "size_bytes": bytes.len(),
+                "size_bytes": size,
             }))
             .into_response()
         }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
-            format!(r#"{{"error":"S3 upload failed: {}"}}"#, e),
+            format!(r#"{{"error":"Snapshot upload failed: {}"}}"#, e),
         )
             .into_response(),
     }
 }
 
-/// Restore index from S3 snapshot
+/// Restore index from a stored snapshot
 #[utoipa::path(
     post,
     path = "/1/indexes/{indexName}/restore",
@@ -186,10 +288,10 @@ pub async fn snapshot_to_s3(
     params(
         ("indexName" = String, Path, description = "Index name")
     ),
-    request_body(content = serde_json::Value, description = "Restore options with snapshot ID"),
+    request_body(content = serde_json::Value, description = "Restore options: `key` of a snapshot, or `timestamp` (epoch milliseconds or RFC 3339) for a point-in-time restore from incremental snapshots, or `incremental` for the latest one"),
     responses(
         (status = 200, description = "Restore successful", body = serde_json::Value),
-        (status = 400, description = "S3 not configured"),
+        (status = 503, description = "Snapshot store not configured"),
         (status = 404, description = "Snapshot not found")
     ),
     security(
@@ -201,52 +303,45 @@ pub async fn restore_from_s3(
     Path(index_name): Path<String>,
     body: Option<Json<serde_json::Value>>,
 ) -> impl IntoResponse {
-    let s3_config = match S3Config::from_env() {
-        Some(c) => c,
-        None => {
+    let index_name = state.manager.resolve_alias(&index_name);
+    let Some(store) = snapshot_store::from_env() else {
+        return not_configured();
+    };
+
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+    if body.get("timestamp").is_some() || body["incremental"].as_bool() == Some(true) {
+        let target_ms = body.get("timestamp").map(parse_timestamp);
+        if target_ms == Some(None) {
             return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                r#"{"error":"S3 not configured"}"#,
+                StatusCode::BAD_REQUEST,
+                r#"{"error":"timestamp must be epoch milliseconds or an RFC 3339 date"}"#,
             )
-                .into_response()
+                .into_response();
         }
-    };
+        return restore_point_in_time(&state, store.as_ref(), &index_name, target_ms.flatten())
+            .await;
+    }
 
-    let key_override = body.and_then(|b| b.get("key").and_then(|v| v.as_str()).map(String::from));
+    let key_override = body.get("key").and_then(|v| v.as_str()).map(String::from);
 
-    let (key, data) = if let Some(key) = key_override {
-        let data = match flapjack::index::s3::download_snapshot(&s3_config, &key).await {
-            Ok(d) => d,
-            Err(e) => {
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!(r#"{{"error":"S3 download failed: {}"}}"#, e),
-                )
-                    .into_response()
-            }
-        };
-        (key, data)
-    } else {
-        match flapjack::index::s3::download_latest_snapshot(&s3_config, &index_name).await {
-            Ok(r) => r,
+    let key = match key_override {
+        Some(key) => key,
+        None => match snapshot_store::latest_snapshot(store.as_ref(), &index_name).await {
+            Ok(key) => key,
             Err(e) => {
                 return (StatusCode::NOT_FOUND, format!(r#"{{"error":"{}"}}"#, e)).into_response()
             }
-        }
+        },
     };
 
     let index_path = state.manager.base_path.join(&index_name);
-    if index_path.exists() {
-        let _ = std::fs::remove_dir_all(&index_path);
-    }
-
-    match import_from_bytes(&data, &index_path) {
-        Ok(()) => {
+    match snapshot_store::restore_snapshot(store.as_ref(), &key, &index_path).await {
+        Ok(size) => {
             state.manager.unload_tenant(&index_name);
             Json(serde_json::json!({
                 "status": "restored",
                 "key": key,
-                "size_bytes": data.len(),
+                "size_bytes": size,
             }))
             .into_response()
         }
@@ -258,7 +353,7 @@ pub async fn restore_from_s3(
     }
 }
 
-/// List available S3 snapshots for an index
+/// List the stored snapshots of an index
 #[utoipa::path(
     get,
     path = "/1/indexes/{indexName}/snapshots",
@@ -268,26 +363,38 @@ pub async fn restore_from_s3(
     ),
     responses(
         (status = 200, description = "List of snapshots", body = serde_json::Value),
-        (status = 400, description = "S3 not configured")
+        (status = 503, description = "Snapshot store not configured")
     ),
     security(
         ("api_key" = [])
     )
 )]
-pub async fn list_s3_snapshots(Path(index_name): Path<String>) -> impl IntoResponse {
-    let s3_config = match S3Config::from_env() {
-        Some(c) => c,
-        None => {
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                r#"{"error":"S3 not configured"}"#,
-            )
-                .into_response()
-        }
+pub async fn list_s3_snapshots(
+    State(state): State<Arc<AppState>>,
+    Path(index_name): Path<String>,
+) -> impl IntoResponse {
+    let index_name = state.manager.resolve_alias(&index_name);
+    let Some(store) = snapshot_store::from_env() else {
+        return not_configured();
     };
 
-    match flapjack::index::s3::list_snapshots(&s3_config, &index_name).await {
-        Ok(keys) => Json(serde_json::json!({ "snapshots": keys })).into_response(),
+    let listed = match snapshot_store::list_snapshots(store.as_ref(), &index_name).await {
+        Ok(keys) => snapshot_store::list_manifests(store.as_ref(), &index_name)
+            .await
+            .map(|manifests| (keys, manifests)),
+        Err(e) => Err(e),
+    };
+    match listed {
+        Ok((keys, manifests)) => {
+            let incremental: Vec<serde_json::Value> = manifests
+                .into_iter()
+                .map(|(created_at_ms, key)| {
+                    serde_json::json!({"key": key, "createdAt": created_at_ms})
+                })
+                .collect();
+            Json(serde_json::json!({ "snapshots": keys, "incremental": incremental }))
+                .into_response()
+        }
         Err(e) => (
             StatusCode::INTERNAL_SERVER_ERROR,
             format!(r#"{{"error":"{}"}}"#, e),
@@ -295,3 +402,79 @@ pub async fn list_s3_snapshots(Path(index_name): Path<String>) -> impl IntoRespo
             .into_response(),
     }
 }
+
+/// Restore from incremental snapshots, replaying the oplog up to `target_ms`.
+///
+/// The tenant is evicted first, so that pending writes reach the live oplog,
+/// and stays unloaded until its directory is replaced.
+async fn restore_point_in_time(
+    state: &AppState,
+    store: &dyn SnapshotStore,
+    index_name: &str,
+    target_ms: Option<u64>,
+) -> axum::response::Response {
+    let index_name = state.manager.resolve_alias(index_name);
+    let index_path = state.manager.base_path.join(&index_name);
+    let restored = state
+        .manager
+        .rewrite_tenant(
+            &index_name,
+            snapshot_store::restore_point_in_time(store, &index_name, &index_path, target_ms),
+        )
+        .await;
+    match restored {
+        Ok(restore) => {
+            // Load now so that the oplog replay happens before the response
+            if let Err(e) = state.manager.get_or_load(&index_name) {
+                return (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    format!(r#"{{"error":"Oplog replay failed: {}"}}"#, e),
+                )
+                    .into_response();
+            }
+            Json(serde_json::json!({
+                "status": "restored",
+                "key": restore.manifest_key,
+                "snapshotAt": restore.snapshot_at_ms,
+                "replayedOps": restore.replayed_ops,
+            }))
+            .into_response()
+        }
+        Err(FlapjackError::TenantBusy(_)) => (
+            StatusCode::CONFLICT,
+            r#"{"error":"Tenant busy, retry once its pending tasks are done"}"#,
+        )
+            .into_response(),
+        Err(e) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!(r#"{{"error":"Restore failed: {}"}}"#, e),
+        )
+            .into_response(),
+    }
+}
+
+/// Epoch milliseconds, or an RFC 3339 date.
+fn parse_timestamp(value: &serde_json::Value) -> Option<u64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_u64(),
+        serde_json::Value::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+            .ok()
+            .and_then(|t| u64::try_from(t.timestamp_millis()).ok()),
+        _ => None,
+    }
+}
+
+fn not_configured() -> axum::response::Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        r#"{"error":"Snapshot store not configured. Set FLAPJACK_SNAPSHOT_DIR or FLAPJACK_S3_BUCKET."}"#,
+    )
+        .into_response()
+}
+
+fn snapshot_retention() -> usize {
+    std::env::var("FLAPJACK_SNAPSHOT_RETENTION")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(24)
+}
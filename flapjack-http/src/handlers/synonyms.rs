@@ -31,6 +31,7 @@ pub async fn get_synonym(
     State(state): State<Arc<AppState>>,
     Path((index_name, object_id)): Path<(String, String)>,
 ) -> Result<Json<Synonym>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let synonyms_path = state
         .manager
         .base_path
@@ -81,6 +82,7 @@ pub async fn save_synonym(
     Path((index_name, _object_id)): Path<(String, String)>,
     Json(synonym): Json<Synonym>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     state
         .manager
         .create_tenant(&index_name)
@@ -145,6 +147,7 @@ pub async fn delete_synonym(
     State(state): State<Arc<AppState>>,
     Path((index_name, object_id)): Path<(String, String)>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let synonyms_path = state
         .manager
         .base_path
@@ -212,6 +215,7 @@ pub async fn save_synonyms(
     Query(params): Query<HashMap<String, String>>,
     Json(synonyms): Json<Vec<Synonym>>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     state
         .manager
         .create_tenant(&index_name)
@@ -285,6 +289,7 @@ pub async fn clear_synonyms(
     State(state): State<Arc<AppState>>,
     Path(index_name): Path<String>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let synonyms_path = state
         .manager
         .base_path
@@ -351,6 +356,7 @@ pub async fn search_synonyms(
     Path(index_name): Path<String>,
     Json(req): Json<SearchSynonymsRequest>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, String)> {
+    let index_name = state.manager.resolve_alias(&index_name);
     let synonyms_path = state
         .manager
         .base_path
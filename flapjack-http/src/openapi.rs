@@ -23,9 +23,11 @@ use utoipa::OpenApi;
         crate::handlers::indices::list_indices,
         crate::handlers::indices::clear_index,
         crate::handlers::indices::operation_index,
+        crate::handlers::indices::migrate_index,
         crate::handlers::search::search,
         crate::handlers::search::batch_search,
         crate::handlers::objects::add_documents,
+        crate::handlers::bulk::bulk_import,
         crate::handlers::objects::get_object,
         crate::handlers::objects::delete_object,
         crate::handlers::objects::put_object,
@@ -43,6 +45,14 @@ use utoipa::OpenApi;
         crate::handlers::synonyms::save_synonyms,
         crate::handlers::synonyms::clear_synonyms,
         crate::handlers::synonyms::search_synonyms,
+        crate::handlers::dictionaries::batch_dictionary_entries,
+        crate::handlers::dictionaries::search_dictionary_entries,
+        crate::handlers::dictionaries::get_dictionary_settings,
+        crate::handlers::dictionaries::set_dictionary_settings,
+        crate::handlers::aliases::list_aliases,
+        crate::handlers::aliases::get_alias,
+        crate::handlers::aliases::set_alias,
+        crate::handlers::aliases::delete_alias,
         crate::handlers::rules::get_rule,
         crate::handlers::rules::save_rule,
         crate::handlers::rules::delete_rule,
@@ -58,6 +68,8 @@ use utoipa::OpenApi;
         crate::handlers::keys::generate_secured_key,
         crate::handlers::snapshot::export_snapshot,
         crate::handlers::snapshot::import_snapshot,
+        crate::handlers::dump::export_dump,
+        crate::handlers::dump::import_dump,
         crate::handlers::snapshot::snapshot_to_s3,
         crate::handlers::snapshot::restore_from_s3,
         crate::handlers::snapshot::list_s3_snapshots,
@@ -91,6 +103,8 @@ use utoipa::OpenApi;
         (name = "settings", description = "Index settings"),
         (name = "synonyms", description = "Synonym management"),
         (name = "rules", description = "Query rules"),
+        (name = "dictionaries", description = "Custom stop words, plurals and compounds"),
+        (name = "aliases", description = "Index aliases for zero-downtime reindexing"),
         (name = "keys", description = "API key management"),
         (name = "snapshots", description = "Backup and restore operations"),
         (name = "tasks", description = "Task status endpoints"),
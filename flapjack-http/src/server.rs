@@ -18,9 +18,9 @@ use crate::handlers::{
     add_documents, add_record_auto_id, batch_search, browse_index, clear_index, clear_rules,
     clear_synonyms, compact_index, create_index, delete_by_query, delete_index, delete_object,
     delete_rule, delete_synonym, get_object, get_objects, get_rule, get_synonym, get_task,
-    get_task_for_index, health, list_indices, migrate_from_algolia, operation_index,
-    partial_update_object, put_object, save_rule, save_rules, save_synonym, save_synonyms, search,
-    search_facet_values, search_rules, search_synonyms, AppState,
+    get_task_for_index, health, list_indices, migrate_from_algolia, migrate_index, operation_index,
+    partial_update_object, put_object, reencrypt_index, save_rule, save_rules, save_synonym,
+    save_synonyms, search, search_facet_values, search_rules, search_synonyms, AppState,
 };
 use crate::middleware::{allow_private_network, normalize_content_type};
 use crate::openapi::ApiDoc;
@@ -71,12 +71,21 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
 
     let data_dir = std::env::var("FLAPJACK_DATA_DIR").unwrap_or_else(|_| "./data".to_string());
 
+    // An invalid master key configuration stops the server here
+    if let Some(keyring) = flapjack::index::encryption::keyring()? {
+        tracing::info!(
+            "Encryption at rest enabled (key {})",
+            keyring.current_key_id()
+        );
+    }
+
     let key_store = match std::env::var("FLAPJACK_ADMIN_KEY") {
         Ok(admin_key) if !admin_key.is_empty() => {
+            // keys.json encrypted with another master key stops the server here
             let ks = Arc::new(KeyStore::load_or_create(
                 std::path::Path::new(&data_dir),
                 &admin_key,
-            ));
+            )?);
             tracing::info!("API key authentication enabled");
             Some(ks)
         }
@@ -90,6 +99,17 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
     };
 
     let manager = IndexManager::new(&data_dir);
+    if manager.eviction_config().is_enabled() {
+        let config = manager.eviction_config();
+        tracing::info!(
+            "Tenant eviction enabled (max loaded: {}, idle timeout: {:?})",
+            config.max_loaded_tenants,
+            config.idle_timeout
+        );
+    }
+    tokio::spawn(Arc::clone(&manager).run_eviction_loop());
+    tokio::spawn(Arc::clone(&manager).run_expiry_loop());
+    tokio::spawn(Arc::clone(&manager).run_schema_migrations());
 
     // Load replication config and initialize ReplicationManager
     let node_config =
@@ -138,20 +158,21 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
         }
     };
 
-    if let Some(s3_config) = flapjack::index::s3::S3Config::from_env() {
-        auto_restore_from_s3(&data_dir, &s3_config, &manager).await;
+    if let Some(store) = flapjack::index::snapshot_store::from_env() {
+        tracing::info!("Snapshot store: {}", store.location());
+        auto_restore_snapshots(&data_dir, store.as_ref(), &manager).await;
         let interval_secs: u64 = std::env::var("FLAPJACK_SNAPSHOT_INTERVAL")
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(0);
         if interval_secs > 0 {
             let mgr = Arc::clone(&manager);
-            let s3 = s3_config.clone();
+            let store = Arc::clone(&store);
             let dd = data_dir.clone();
             tokio::spawn(async move {
-                scheduled_s3_backups(dd, s3, mgr, interval_secs).await;
+                scheduled_backups(dd, store, mgr, interval_secs).await;
             });
-            tracing::info!("Scheduled S3 backups every {}s", interval_secs);
+            tracing::info!("Scheduled snapshot backups every {}s", interval_secs);
         }
     }
 
@@ -188,11 +209,55 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
         tracing::info!("[analytics] Analytics disabled");
     }
 
+    // Query suggestions: configs live in the data dir, indices are rebuilt on schedule
+    let suggestions_store = Arc::new(flapjack::analytics::QuerySuggestionsStore::load(Path::new(
+        &data_dir,
+    )));
+    if analytics_config.enabled {
+        let engine = Arc::clone(&analytics_engine);
+        let mgr = Arc::clone(&manager);
+        let store = Arc::clone(&suggestions_store);
+        tokio::spawn(async move {
+            flapjack::analytics::suggestions::run_suggestions_loop(engine, mgr, store).await;
+        });
+    }
+    let suggestions_state = Arc::new(crate::handlers::query_suggestions::QuerySuggestionsState {
+        manager: Arc::clone(&manager),
+        engine: Arc::clone(&analytics_engine),
+        store: suggestions_store,
+    });
+
+    let ab_tests = Arc::new(flapjack::analytics::AbTestStore::load(Path::new(&data_dir)));
+    let ab_testing_state = Arc::new(crate::handlers::ab_testing::AbTestingState {
+        manager: Arc::clone(&manager),
+        engine: Arc::clone(&analytics_engine),
+        store: Arc::clone(&ab_tests),
+    });
+
+    // Personalization profiles are fed by Insights events and read by search
+    let personalization_half_life_days: f64 =
+        std::env::var("FLAPJACK_PERSONALIZATION_HALF_LIFE_DAYS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .unwrap_or(flapjack::analytics::personalization::DEFAULT_HALF_LIFE_DAYS);
+    let personalization = Arc::new(
+        flapjack::analytics::PersonalizationStore::load(Path::new(&data_dir))
+            .with_half_life_days(personalization_half_life_days),
+    );
+    tokio::spawn(Arc::clone(&personalization).run_flush_loop());
+    let insights_state = Arc::new(crate::handlers::insights::InsightsState {
+        collector: Arc::clone(&analytics_collector),
+        manager: Arc::clone(&manager),
+        personalization: Arc::clone(&personalization),
+    });
+
     let state = Arc::new(AppState {
         manager,
         key_store: key_store.clone(),
         replication_manager,
         ssl_manager,
+        ab_tests: Some(ab_tests),
+        personalization: Some(Arc::clone(&personalization)),
     });
 
     let key_routes = if let Some(ref ks) = key_store {
@@ -223,7 +288,13 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
         .route("/1/indexes/:indexName/browse", post(browse_index))
         .route("/1/indexes/:indexName/clear", post(clear_index))
         .route("/1/indexes/:indexName/compact", post(compact_index))
+        .route("/1/indexes/:indexName/reencrypt", post(reencrypt_index))
+        .route("/1/indexes/:indexName/migrate", post(migrate_index))
         .route("/1/indexes/:indexName/batch", post(add_documents))
+        .route(
+            "/1/indexes/:indexName/bulk",
+            post(crate::handlers::bulk::bulk_import),
+        )
         .route("/1/indexes/:indexName/query", post(search))
         .route("/1/indexes/:indexName/deleteByQuery", post(delete_by_query))
         .route(
@@ -267,6 +338,10 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
             "/1/indexes/:indexName/import",
             post(snapshot::import_snapshot),
         )
+        .route(
+            "/1/indexes/:indexName/dump",
+            get(crate::handlers::dump::export_dump).post(crate::handlers::dump::import_dump),
+        )
         .route(
             "/1/indexes/:indexName/snapshot",
             post(snapshot::snapshot_to_s3),
@@ -301,6 +376,26 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
             "/1/indexes/:indexName",
             post(add_record_auto_id).delete(delete_index),
         )
+        .route(
+            "/1/dictionaries/:dictionaryName/batch",
+            post(crate::handlers::dictionaries::batch_dictionary_entries),
+        )
+        .route(
+            "/1/dictionaries/:dictionaryName/search",
+            post(crate::handlers::dictionaries::search_dictionary_entries),
+        )
+        .route(
+            "/1/dictionaries/:dictionaryName/settings",
+            get(crate::handlers::dictionaries::get_dictionary_settings)
+                .put(crate::handlers::dictionaries::set_dictionary_settings),
+        )
+        .route("/1/aliases", get(crate::handlers::aliases::list_aliases))
+        .route(
+            "/1/aliases/:alias",
+            get(crate::handlers::aliases::get_alias)
+                .put(crate::handlers::aliases::set_alias)
+                .delete(crate::handlers::aliases::delete_alias),
+        )
         .route("/1/migrate-from-algolia", post(migrate_from_algolia))
         .route("/1/tasks/:task_id", get(get_task))
         .route(
@@ -310,13 +405,16 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
         .with_state(state.clone());
 
     let ks_for_middleware = key_store.clone();
+    let aliases_for_middleware = Arc::clone(state.manager.aliases());
     let auth_middleware = middleware::from_fn(
         move |mut request: axum::extract::Request, next: middleware::Next| {
             let ks = ks_for_middleware.clone();
+            let aliases = aliases_for_middleware.clone();
             async move {
                 if let Some(ref store) = ks {
                     request.extensions_mut().insert(store.clone());
                 }
+                request.extensions_mut().insert(aliases);
                 authenticate_and_authorize(request, next).await
             }
         },
@@ -430,12 +528,73 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
             "/2/analytics/flush",
             post(crate::handlers::analytics::flush_analytics),
         )
+        .route(
+            "/2/analytics/export",
+            get(crate::handlers::analytics::export_events),
+        )
+        .route(
+            "/2/analytics/sql",
+            post(crate::handlers::analytics::run_sql),
+        )
         .with_state(analytics_engine);
 
     // Insights API (event ingestion - Algolia compatible)
     let insights_routes = Router::new()
         .route("/1/events", post(crate::handlers::insights::post_events))
-        .with_state(analytics_collector);
+        .with_state(insights_state);
+
+    // Personalization profiles (read + GDPR deletion)
+    let personalization_routes = Router::new()
+        .route(
+            "/1/profiles/personalization/:userToken",
+            get(crate::handlers::personalization::get_user_profile),
+        )
+        .route(
+            "/1/profiles/:userToken",
+            delete(crate::handlers::personalization::delete_user_profile),
+        )
+        .with_state(personalization);
+
+    // A/B testing API (Algolia A/B testing API v2 compatible)
+    let ab_testing_routes = Router::new()
+        .route(
+            "/2/abtests",
+            get(crate::handlers::ab_testing::list_ab_tests)
+                .post(crate::handlers::ab_testing::create_ab_test),
+        )
+        .route(
+            "/2/abtests/:id",
+            get(crate::handlers::ab_testing::get_ab_test)
+                .delete(crate::handlers::ab_testing::delete_ab_test),
+        )
+        .route(
+            "/2/abtests/:id/stop",
+            post(crate::handlers::ab_testing::stop_ab_test),
+        )
+        .with_state(ab_testing_state);
+
+    // Query Suggestions API (Algolia compatible configs + on-demand build)
+    let query_suggestions_routes = Router::new()
+        .route(
+            "/1/configs",
+            get(crate::handlers::query_suggestions::list_configs)
+                .post(crate::handlers::query_suggestions::create_config),
+        )
+        .route(
+            "/1/configs/:indexName",
+            get(crate::handlers::query_suggestions::get_config)
+                .put(crate::handlers::query_suggestions::update_config)
+                .delete(crate::handlers::query_suggestions::delete_config),
+        )
+        .route(
+            "/1/configs/:indexName/status",
+            get(crate::handlers::query_suggestions::get_config_status),
+        )
+        .route(
+            "/1/configs/:indexName/build",
+            post(crate::handlers::query_suggestions::build_config),
+        )
+        .with_state(suggestions_state);
 
     // Dashboard static files
     let dashboard_path = Path::new("dashboard/dist");
@@ -463,7 +622,10 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
         .merge(key_routes)
         .merge(protected)
         .merge(analytics_routes)
+        .merge(ab_testing_routes)
         .merge(insights_routes)
+        .merge(personalization_routes)
+        .merge(query_suggestions_routes)
         .merge(internal); // Add internal routes before auth middleware
 
     // Add dashboard route if available (before auth middleware so static files don't require API key)
@@ -553,95 +715,88 @@ pub async fn serve() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
-async fn auto_restore_from_s3(
+async fn auto_restore_snapshots(
     data_dir: &str,
-    s3_config: &flapjack::index::s3::S3Config,
+    store: &dyn flapjack::index::snapshot_store::SnapshotStore,
     _manager: &std::sync::Arc<flapjack::IndexManager>,
 ) {
+    use flapjack::index::snapshot_store;
+
     let data_path = std::path::Path::new(data_dir);
     let has_tenants = data_path
         .read_dir()
         .map(|mut rd| rd.any(|e| e.ok().map(|e| e.path().is_dir()).unwrap_or(false)))
         .unwrap_or(false);
     if has_tenants {
-        tracing::info!("Data dir has existing tenants, skipping S3 auto-restore");
+        tracing::info!("Data dir has existing tenants, skipping snapshot auto-restore");
         return;
     }
 
-    tracing::info!("Empty data dir detected, attempting S3 auto-restore...");
-    let bucket = match s3_config.clone().bucket_internal() {
-        Ok(b) => b,
+    tracing::info!("Empty data dir detected, attempting snapshot auto-restore...");
+    let tenant_ids = match snapshot_store::list_indexes(store).await {
+        Ok(ids) => ids,
         Err(e) => {
-            tracing::warn!("S3 auto-restore: couldn't create bucket client: {}", e);
+            tracing::warn!("Snapshot auto-restore: list failed: {}", e);
             return;
         }
     };
-    let results = match bucket
-        .list("snapshots/".to_string(), Some("/".to_string()))
-        .await
-    {
-        Ok(r) => r,
-        Err(e) => {
-            tracing::warn!("S3 auto-restore: list failed: {}", e);
-            return;
-        }
-    };
-    let mut tenant_ids: Vec<String> = results
-        .iter()
-        .flat_map(|r| r.common_prefixes.iter().flatten())
-        .filter_map(|p| {
-            p.prefix
-                .strip_prefix("snapshots/")
-                .and_then(|s| s.strip_suffix("/"))
-                .map(|s| s.to_string())
-        })
-        .collect();
-    tenant_ids.sort();
-    tenant_ids.dedup();
 
     if tenant_ids.is_empty() {
-        tracing::info!("S3 auto-restore: no snapshots found");
+        tracing::info!("Snapshot auto-restore: no snapshots found");
         return;
     }
 
     tracing::info!(
-        "S3 auto-restore: found {} tenants: {:?}",
+        "Snapshot auto-restore: found {} tenants: {:?}",
         tenant_ids.len(),
         tenant_ids
     );
     for tid in &tenant_ids {
-        match flapjack::index::s3::download_latest_snapshot(s3_config, tid).await {
-            Ok((key, data)) => {
-                let index_path = data_path.join(tid);
-                if let Err(e) = flapjack::index::snapshot::import_from_bytes(&data, &index_path) {
-                    tracing::error!("S3 auto-restore: failed to import {}: {}", tid, e);
-                    continue;
-                }
+        let index_path = data_path.join(tid);
+        // Prefer incremental snapshots, which the scheduled backups take
+        match snapshot_store::restore_point_in_time(store, tid, &index_path, None).await {
+            Ok(restore) => {
                 tracing::info!(
-                    "S3 auto-restore: restored {} from {} ({} bytes)",
+                    "Snapshot auto-restore: restored {} from {}",
                     tid,
-                    key,
-                    data.len()
+                    restore.manifest_key
                 );
+                continue;
             }
+            Err(e) => tracing::debug!(
+                "Snapshot auto-restore: no incremental snapshot for {}: {}",
+                tid,
+                e
+            ),
+        }
+        match snapshot_store::latest_snapshot(store, tid).await {
+            Ok(key) => match snapshot_store::restore_snapshot(store, &key, &index_path).await {
+                Ok(size) => tracing::info!(
+                    "Snapshot auto-restore: restored {} from {} ({} bytes)",
+                    tid,
+                    key,
+                    size
+                ),
+                Err(e) => tracing::error!("Snapshot auto-restore: failed to import {}: {}", tid, e),
+            },
             Err(e) => {
-                tracing::warn!("S3 auto-restore: no snapshot for {}: {}", tid, e);
+                tracing::warn!("Snapshot auto-restore: no snapshot for {}: {}", tid, e);
             }
         }
     }
 }
 
-async fn scheduled_s3_backups(
+async fn scheduled_backups(
     data_dir: String,
-    s3_config: flapjack::index::s3::S3Config,
-    _manager: std::sync::Arc<flapjack::IndexManager>,
+    store: std::sync::Arc<dyn flapjack::index::snapshot_store::SnapshotStore>,
+    manager: std::sync::Arc<flapjack::IndexManager>,
     interval_secs: u64,
 ) {
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(interval_secs));
     interval.tick().await;
     loop {
         interval.tick().await;
-        tracing::info!("[BACKUP] Starting scheduled S3 snapshot...");
+        tracing::info!("[BACKUP] Starting scheduled snapshot...");
         let data_path = std::path::Path::new(&data_dir);
         let tenant_dirs: Vec<String> = match data_path.read_dir() {
             Ok(rd) => rd
@@ -658,25 +813,44 @@ async fn scheduled_s3_backups(
                 continue;
             }
         };
+        let retention = std::env::var("FLAPJACK_SNAPSHOT_RETENTION")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(24);
         for tid in &tenant_dirs {
             let index_path = data_path.join(tid);
-            match flapjack::index::snapshot::export_to_bytes(&index_path) {
-                Ok(bytes) => {
-                    match flapjack::index::s3::upload_snapshot(&s3_config, tid, &bytes).await {
-                        Ok(key) => {
-                            let retention = std::env::var("FLAPJACK_SNAPSHOT_RETENTION")
-                                .ok()
-                                .and_then(|v| v.parse::<usize>().ok())
-                                .unwrap_or(24);
-                            let _ =
-                                flapjack::index::s3::enforce_retention(&s3_config, tid, retention)
-                                    .await;
-                            tracing::info!("[BACKUP] {} -> {} ({} bytes)", tid, key, bytes.len());
-                        }
-                        Err(e) => tracing::error!("[BACKUP] upload {} failed: {}", tid, e),
-                    }
+            let commit = match manager.pin_commit(tid) {
+                Ok(commit) => commit,
+                Err(e) => {
+                    tracing::error!("[BACKUP] upload {} failed: {}", tid, e);
+                    continue;
+                }
+            };
+            match flapjack::index::snapshot_store::upload_incremental_snapshot(
+                store.as_ref(),
+                tid,
+                &index_path,
+                &commit,
+            )
+            .await
+            {
+                Ok(upload) => {
+                    let _ = flapjack::index::snapshot_store::enforce_incremental_retention(
+                        store.as_ref(),
+                        tid,
+                        retention,
+                    )
+                    .await;
+                    tracing::info!(
+                        "[BACKUP] {} -> {} ({} of {} files, {} bytes)",
+                        tid,
+                        upload.manifest_key,
+                        upload.uploaded_files,
+                        upload.files,
+                        upload.uploaded_bytes
+                    );
                 }
-                Err(e) => tracing::error!("[BACKUP] export {} failed: {}", tid, e),
+                Err(e) => tracing::error!("[BACKUP] upload {} failed: {}", tid, e),
             }
         }
         tracing::info!(
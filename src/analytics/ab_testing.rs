@@ -0,0 +1,427 @@
+//! A/B testing of index configurations.
+//!
+//! An A/B test splits the traffic of an index between two variants. A variant
+//! either targets another index (e.g. a replica with a different `customRanking`)
+//! or overlays settings on top of the variant index's own settings. Users are
+//! assigned to a variant by hashing their `userToken`, so the same user always
+//! sees the same variant. Searches are tagged with `ab_test_id`/`ab_test_variant_id`
+//! and clicks/conversions are attributed through the search `queryID`.
+//!
+//! Tests are persisted in `ab_tests.json` in the data dir.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use crate::index::settings::IndexSettings;
+
+/// One side of an A/B test.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AbTestVariant {
+    /// Index searched for users assigned to this variant.
+    pub index: String,
+    /// Share of the traffic (1-99) routed to this variant.
+    pub traffic_percentage: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Index settings applied on top of `index`'s settings (e.g. `customRanking`).
+    #[serde(
+        default,
+        alias = "customSearchParameters",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub settings_overlay: Option<serde_json::Value>,
+}
+
+/// Body of `POST /2/abtests`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AbTestRequest {
+    pub name: String,
+    pub variants: Vec<AbTestVariant>,
+    pub end_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AbTestStatus {
+    Active,
+    Stopped,
+    Expired,
+}
+
+/// A persisted A/B test.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AbTest {
+    #[serde(rename = "abTestID")]
+    pub ab_test_id: i64,
+    pub name: String,
+    pub variants: Vec<AbTestVariant>,
+    pub created_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    #[serde(default)]
+    pub stopped_at: Option<DateTime<Utc>>,
+}
+
+impl AbTest {
+    /// The index whose traffic is split (the first variant's index).
+    pub fn index(&self) -> &str {
+        &self.variants[0].index
+    }
+
+    pub fn status_at(&self, now: DateTime<Utc>) -> AbTestStatus {
+        if self.stopped_at.is_some() {
+            AbTestStatus::Stopped
+        } else if now >= self.end_at {
+            AbTestStatus::Expired
+        } else {
+            AbTestStatus::Active
+        }
+    }
+
+    pub fn status(&self) -> AbTestStatus {
+        self.status_at(Utc::now())
+    }
+
+    /// Pick a variant for `user_key`. Returns the 0-based variant index.
+    pub fn variant_for(&self, user_key: &str) -> usize {
+        let bucket = traffic_bucket(self.ab_test_id, user_key);
+        if bucket < self.variants[0].traffic_percentage {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+/// Variant chosen for a search.
+#[derive(Debug, Clone)]
+pub struct AbTestAssignment {
+    pub ab_test_id: i64,
+    /// 1-based variant ID, as exposed in `abTestVariantID`.
+    pub variant_id: u32,
+    /// Index to search.
+    pub index: String,
+    pub settings_overlay: Option<serde_json::Value>,
+}
+
+/// Stable bucket in `0..100` for a user within a test.
+///
+/// Uses SHA-256 rather than `DefaultHasher` so assignments survive restarts and
+/// toolchain upgrades.
+pub fn traffic_bucket(ab_test_id: i64, user_key: &str) -> u32 {
+    let mut hasher = Sha256::new();
+    hasher.update(ab_test_id.to_be_bytes());
+    hasher.update(b":");
+    hasher.update(user_key.as_bytes());
+    let digest = hasher.finalize();
+    let mut prefix = [0u8; 8];
+    prefix.copy_from_slice(&digest[..8]);
+    (u64::from_be_bytes(prefix) % 100) as u32
+}
+
+/// Merge a settings overlay onto base settings.
+///
+/// Top-level keys of `overlay` replace the corresponding keys of `base`.
+pub fn apply_settings_overlay(
+    base: Option<&IndexSettings>,
+    overlay: &serde_json::Value,
+) -> Result<IndexSettings, String> {
+    let mut merged = serde_json::to_value(base.cloned().unwrap_or_default())
+        .map_err(|e| format!("failed to serialize settings: {}", e))?;
+    let overlay = overlay
+        .as_object()
+        .ok_or_else(|| "settings overlay must be a JSON object".to_string())?;
+    if let Some(obj) = merged.as_object_mut() {
+        for (k, v) in overlay {
+            obj.insert(k.clone(), v.clone());
+        }
+    }
+    serde_json::from_value(merged).map_err(|e| format!("invalid settings overlay: {}", e))
+}
+
+/// Confidence (0.0-1.0) that two rates differ, using a two-sided two-proportion z-test.
+///
+/// Returns `None` when either sample is empty or the pooled rate is degenerate.
+pub fn two_proportion_significance(
+    successes_a: i64,
+    trials_a: i64,
+    successes_b: i64,
+    trials_b: i64,
+) -> Option<f64> {
+    if trials_a <= 0 || trials_b <= 0 {
+        return None;
+    }
+    let (n1, n2) = (trials_a as f64, trials_b as f64);
+    let p1 = successes_a as f64 / n1;
+    let p2 = successes_b as f64 / n2;
+    let pooled = (successes_a + successes_b) as f64 / (n1 + n2);
+    let se = (pooled * (1.0 - pooled) * (1.0 / n1 + 1.0 / n2)).sqrt();
+    if se <= 0.0 || !se.is_finite() {
+        return None;
+    }
+    let z = ((p1 - p2) / se).abs();
+    let p_value = 2.0 * (1.0 - normal_cdf(z));
+    Some((1.0 - p_value).clamp(0.0, 1.0))
+}
+
+/// Standard normal CDF (Abramowitz & Stegun 7.1.26 erf approximation).
+fn normal_cdf(z: f64) -> f64 {
+    let x = z / std::f64::consts::SQRT_2;
+    let t = 1.0 / (1.0 + 0.327_591_1 * x.abs());
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    let erf = 1.0 - poly * (-x * x).exp();
+    let erf = if x >= 0.0 { erf } else { -erf };
+    0.5 * (1.0 + erf)
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AbTestData {
+    #[serde(default)]
+    next_id: i64,
+    #[serde(default)]
+    tests: Vec<AbTest>,
+}
+
+/// File-backed store of A/B tests.
+pub struct AbTestStore {
+    data: RwLock<AbTestData>,
+    file_path: PathBuf,
+}
+
+impl AbTestStore {
+    pub fn load(data_dir: &Path) -> Self {
+        let file_path = data_dir.join("ab_tests.json");
+        let data = match std::fs::read_to_string(&file_path) {
+            Ok(contents) => match serde_json::from_str::<AbTestData>(&contents) {
+                Ok(d) => d,
+                Err(e) => {
+                    tracing::warn!("Failed to parse ab_tests.json: {}", e);
+                    AbTestData::default()
+                }
+            },
+            Err(_) => AbTestData::default(),
+        };
+        Self {
+            data: RwLock::new(data),
+            file_path,
+        }
+    }
+
+    fn save(&self) {
+        let data = self.data.read().unwrap();
+        if let Ok(json) = serde_json::to_string_pretty(&*data) {
+            if let Some(parent) = self.file_path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            if let Err(e) = std::fs::write(&self.file_path, json) {
+                tracing::warn!("Failed to save ab_tests.json: {}", e);
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<AbTest> {
+        self.data.read().unwrap().tests.clone()
+    }
+
+    pub fn get(&self, ab_test_id: i64) -> Option<AbTest> {
+        let data = self.data.read().unwrap();
+        data.tests
+            .iter()
+            .find(|t| t.ab_test_id == ab_test_id)
+            .cloned()
+    }
+
+    /// Validate and persist a new test.
+    pub fn create(&self, req: AbTestRequest) -> Result<AbTest, String> {
+        validate_request(&req)?;
+        let test = {
+            let mut data = self.data.write().unwrap();
+            let now = Utc::now();
+            let index = &req.variants[0].index;
+            if data
+                .tests
+                .iter()
+                .any(|t| t.index() == index && t.status_at(now) == AbTestStatus::Active)
+            {
+                return Err(format!(
+                    "index '{}' is already part of an active A/B test",
+                    index
+                ));
+            }
+            data.next_id += 1;
+            let test = AbTest {
+                ab_test_id: data.next_id,
+                name: req.name,
+                variants: req.variants,
+                created_at: now,
+                end_at: req.end_at,
+                stopped_at: None,
+            };
+            data.tests.push(test.clone());
+            test
+        };
+        self.save();
+        Ok(test)
+    }
+
+    /// Stop a test. Stopped tests keep their results but no longer split traffic.
+    pub fn stop(&self, ab_test_id: i64) -> Option<AbTest> {
+        let stopped = {
+            let mut data = self.data.write().unwrap();
+            let test = data.tests.iter_mut().find(|t| t.ab_test_id == ab_test_id)?;
+            if test.stopped_at.is_none() {
+                test.stopped_at = Some(Utc::now());
+            }
+            test.clone()
+        };
+        self.save();
+        Some(stopped)
+    }
+
+    pub fn delete(&self, ab_test_id: i64) -> bool {
+        let removed = {
+            let mut data = self.data.write().unwrap();
+            let before = data.tests.len();
+            data.tests.retain(|t| t.ab_test_id != ab_test_id);
+            data.tests.len() != before
+        };
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    /// Assign a search on `index_name` by `user_key` to a variant of the active test, if any.
+    pub fn assign(&self, index_name: &str, user_key: &str) -> Option<AbTestAssignment> {
+        let data = self.data.read().unwrap();
+        let now = Utc::now();
+        let test = data
+            .tests
+            .iter()
+            .find(|t| t.index() == index_name && t.status_at(now) == AbTestStatus::Active)?;
+        let idx = test.variant_for(user_key);
+        let variant = &test.variants[idx];
+        Some(AbTestAssignment {
+            ab_test_id: test.ab_test_id,
+            variant_id: idx as u32 + 1,
+            index: variant.index.clone(),
+            settings_overlay: variant.settings_overlay.clone(),
+        })
+    }
+}
+
+fn validate_request(req: &AbTestRequest) -> Result<(), String> {
+    if req.name.trim().is_empty() {
+        return Err("name must not be empty".to_string());
+    }
+    if req.variants.len() != 2 {
+        return Err("an A/B test must have exactly 2 variants".to_string());
+    }
+    for v in &req.variants {
+        if v.index.trim().is_empty() {
+            return Err("variant index must not be empty".to_string());
+        }
+        if v.traffic_percentage == 0 || v.traffic_percentage >= 100 {
+            return Err("trafficPercentage must be between 1 and 99".to_string());
+        }
+        if let Some(ref overlay) = v.settings_overlay {
+            apply_settings_overlay(None, overlay)?;
+        }
+    }
+    let total: u32 = req.variants.iter().map(|v| v.traffic_percentage).sum();
+    if total != 100 {
+        return Err(format!("trafficPercentage must sum to 100, got {}", total));
+    }
+    let (a, b) = (&req.variants[0], &req.variants[1]);
+    if a.index == b.index && a.settings_overlay == b.settings_overlay {
+        return Err("variants must differ by index or settings overlay".to_string());
+    }
+    if req.end_at <= Utc::now() {
+        return Err("endAt must be in the future".to_string());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_bucket_is_stable_and_in_range() {
+        for user in ["alice", "bob", "carol", ""] {
+            let b = traffic_bucket(7, user);
+            assert!(b < 100);
+            assert_eq!(b, traffic_bucket(7, user));
+        }
+    }
+
+    #[test]
+    fn test_traffic_split_roughly_matches_percentage() {
+        let test = AbTest {
+            ab_test_id: 1,
+            name: "t".to_string(),
+            variants: vec![
+                AbTestVariant {
+                    index: "a".to_string(),
+                    traffic_percentage: 70,
+                    description: None,
+                    settings_overlay: None,
+                },
+                AbTestVariant {
+                    index: "b".to_string(),
+                    traffic_percentage: 30,
+                    description: None,
+                    settings_overlay: None,
+                },
+            ],
+            created_at: Utc::now(),
+            end_at: Utc::now() + chrono::Duration::days(1),
+            stopped_at: None,
+        };
+        let in_a = (0..10_000)
+            .filter(|i| test.variant_for(&format!("user-{}", i)) == 0)
+            .count();
+        assert!((6_500..7_500).contains(&in_a), "got {}", in_a);
+    }
+
+    #[test]
+    fn test_settings_overlay_replaces_top_level_keys() {
+        let base = IndexSettings {
+            custom_ranking: Some(vec!["desc(price)".to_string()]),
+            hits_per_page: 5,
+            ..Default::default()
+        };
+        let merged = apply_settings_overlay(
+            Some(&base),
+            &serde_json::json!({"customRanking": ["desc(popularity)"]}),
+        )
+        .unwrap();
+        assert_eq!(
+            merged.custom_ranking,
+            Some(vec!["desc(popularity)".to_string()])
+        );
+        assert_eq!(merged.hits_per_page, 5);
+        assert!(apply_settings_overlay(None, &serde_json::json!([1])).is_err());
+    }
+
+    #[test]
+    fn test_significance() {
+        // Identical rates are not significant
+        let same = two_proportion_significance(50, 1000, 50, 1000).unwrap();
+        assert!(same < 0.01);
+        // 5% vs 10% over 2000 trials each is highly significant
+        let diff = two_proportion_significance(100, 2000, 200, 2000).unwrap();
+        assert!(diff > 0.99);
+        assert!(two_proportion_significance(0, 0, 1, 10).is_none());
+        assert!(two_proportion_significance(0, 10, 0, 10).is_none());
+    }
+}
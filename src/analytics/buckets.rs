@@ -0,0 +1,343 @@
+//! Time buckets for analytics series.
+//!
+//! Series endpoints group events into buckets of a [`Granularity`] (optionally
+//! several units wide, e.g. 15 minutes) in a reporting timezone. Bucket keys
+//! are *local* wall-clock milliseconds: every event of a local day shares the
+//! same key even when a DST transition happens inside that day. Keys are turned
+//! back into labels with [`TimeBuckets::label`].
+//!
+//! The bucket key is computed in SQL so distinct counts (users) stay exact. The
+//! timezone offset is piecewise-constant over a range, so the SQL expression
+//! carries one `CASE` branch per DST transition inside the queried range.
+
+use chrono::{NaiveDate, NaiveDateTime, Offset, TimeZone};
+use chrono_tz::Tz;
+
+const MINUTE_MS: i64 = 60_000;
+const HOUR_MS: i64 = 3_600_000;
+const DAY_MS: i64 = 86_400_000;
+const WEEK_MS: i64 = 7 * DAY_MS;
+/// 1970-01-01 was a Thursday; weeks start on Monday 1970-01-05.
+const WEEK_SHIFT_MS: i64 = 4 * DAY_MS;
+
+/// Upper bound on the number of buckets a single series may have.
+const MAX_BUCKETS: i64 = 50_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Granularity {
+    Minute,
+    Hour,
+    Day,
+    Week,
+}
+
+impl Granularity {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "minute" => Ok(Self::Minute),
+            "hour" => Ok(Self::Hour),
+            "day" | "daily" => Ok(Self::Day),
+            "week" | "weekly" => Ok(Self::Week),
+            other => Err(format!(
+                "Unknown granularity '{}' (expected minute, hour, day or week)",
+                other
+            )),
+        }
+    }
+}
+
+/// How a date range is split into series buckets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeBuckets {
+    granularity: Granularity,
+    /// Number of granularity units per bucket (minute and hour only).
+    interval: u32,
+    tz: Tz,
+}
+
+impl Default for TimeBuckets {
+    /// Daily buckets in UTC.
+    fn default() -> Self {
+        Self {
+            granularity: Granularity::Day,
+            interval: 1,
+            tz: Tz::UTC,
+        }
+    }
+}
+
+impl TimeBuckets {
+    /// Build from request parameters. Everything defaults to daily buckets in UTC.
+    ///
+    /// `interval` groups several minutes or hours per bucket (e.g. `minute` x 15);
+    /// buckets restart at local midnight. `timezone` is an IANA name such as
+    /// `Europe/Paris`.
+    pub fn parse(
+        granularity: Option<&str>,
+        interval: Option<u32>,
+        timezone: Option<&str>,
+    ) -> Result<Self, String> {
+        let granularity = granularity
+            .map(Granularity::parse)
+            .transpose()?
+            .unwrap_or(Granularity::Day);
+        let interval = interval.unwrap_or(1);
+        let max_interval = match granularity {
+            Granularity::Minute => 720,
+            Granularity::Hour => 24,
+            Granularity::Day | Granularity::Week => 1,
+        };
+        if interval == 0 || interval > max_interval {
+            return Err(format!(
+                "interval must be between 1 and {} for this granularity",
+                max_interval
+            ));
+        }
+        let tz = match timezone {
+            Some(name) => name
+                .parse::<Tz>()
+                .map_err(|_| format!("Unknown timezone '{}'", name))?,
+            None => Tz::UTC,
+        };
+        Ok(Self {
+            granularity,
+            interval,
+            tz,
+        })
+    }
+
+    pub fn granularity(&self) -> Granularity {
+        self.granularity
+    }
+
+    /// UTC millis of local midnight at the start of `date` (YYYY-MM-DD).
+    pub fn range_start_ms(&self, date: &str) -> Result<i64, String> {
+        self.local_to_utc_ms(parse_date(date)?.and_hms_opt(0, 0, 0).unwrap())
+    }
+
+    /// UTC millis of local 23:59:59 at the end of `date` (YYYY-MM-DD).
+    pub fn range_end_ms(&self, date: &str) -> Result<i64, String> {
+        self.local_to_utc_ms(parse_date(date)?.and_hms_opt(23, 59, 59).unwrap())
+    }
+
+    fn local_to_utc_ms(&self, local: NaiveDateTime) -> Result<i64, String> {
+        // Midnight may fall in a DST gap in a few zones; the next hour always exists.
+        self.tz
+            .from_local_datetime(&local)
+            .earliest()
+            .or_else(|| {
+                self.tz
+                    .from_local_datetime(&(local + chrono::Duration::hours(1)))
+                    .earliest()
+            })
+            .map(|dt| dt.timestamp_millis())
+            .ok_or_else(|| format!("Invalid local time {} in {}", local, self.tz))
+    }
+
+    fn offset_ms(&self, utc_ms: i64) -> i64 {
+        let utc = chrono::DateTime::from_timestamp_millis(utc_ms)
+            .unwrap_or_default()
+            .naive_utc();
+        self.tz
+            .offset_from_utc_datetime(&utc)
+            .fix()
+            .local_minus_utc() as i64
+            * 1000
+    }
+
+    /// `(period, shift, step)`: buckets of `step` ms restart every `period` ms,
+    /// with periods aligned to `shift` ms after the epoch.
+    fn layout(&self) -> (i64, i64, i64) {
+        let n = self.interval as i64;
+        match self.granularity {
+            Granularity::Minute => (DAY_MS, 0, n * MINUTE_MS),
+            Granularity::Hour => (DAY_MS, 0, n * HOUR_MS),
+            Granularity::Day => (DAY_MS, 0, DAY_MS),
+            Granularity::Week => (WEEK_MS, WEEK_SHIFT_MS, WEEK_MS),
+        }
+    }
+
+    /// Bucket key (local wall-clock millis) of a UTC timestamp.
+    pub fn bucket_key(&self, utc_ms: i64) -> i64 {
+        let (period, shift, step) = self.layout();
+        let local = utc_ms + self.offset_ms(utc_ms);
+        let anchor = (local - shift).div_euclid(period) * period + shift;
+        anchor + (local - anchor).div_euclid(step) * step
+    }
+
+    /// SQL expression computing [`Self::bucket_key`] of `column` for rows in `[start_ms, end_ms]`.
+    pub fn sql_key(&self, column: &str, start_ms: i64, end_ms: i64) -> Result<String, String> {
+        let (period, shift, step) = self.layout();
+        if (end_ms - start_ms) / step >= MAX_BUCKETS {
+            return Err(format!(
+                "Too many buckets (max {}): use a coarser granularity or a shorter range",
+                MAX_BUCKETS
+            ));
+        }
+
+        let segments = self.offset_segments(start_ms, end_ms);
+        let offset = if segments.len() == 1 {
+            segments[0].1.to_string()
+        } else {
+            let mut case = String::from("CASE");
+            for window in segments.windows(2) {
+                case.push_str(&format!(
+                    " WHEN {} < {} THEN {}",
+                    column, window[1].0, window[0].1
+                ));
+            }
+            case.push_str(&format!(" ELSE {} END", segments[segments.len() - 1].1));
+            case
+        };
+        let local = format!("({} + {})", column, offset);
+        let anchor = format!(
+            "(({} - {}) / {} * {} + {})",
+            local, shift, period, period, shift
+        );
+        if step == period {
+            return Ok(format!("CAST({} AS BIGINT)", anchor));
+        }
+        Ok(format!(
+            "CAST({} + ({} - {}) / {} * {} AS BIGINT)",
+            anchor, local, anchor, step, step
+        ))
+    }
+
+    /// `(from_utc_ms, offset_ms)` pairs: the offset in force from each instant on.
+    fn offset_segments(&self, start_ms: i64, end_ms: i64) -> Vec<(i64, i64)> {
+        let mut segments = vec![(start_ms, self.offset_ms(start_ms))];
+        if self.tz == Tz::UTC {
+            return segments;
+        }
+        // Offsets change at most a few times a year; probe hourly, then bisect.
+        let mut prev = start_ms;
+        let mut t = start_ms;
+        while t < end_ms {
+            t = (t + HOUR_MS).min(end_ms);
+            let current = segments[segments.len() - 1].1;
+            if self.offset_ms(t) != current {
+                let (mut lo, mut hi) = (prev, t);
+                while hi - lo > 1 {
+                    let mid = lo + (hi - lo) / 2;
+                    if self.offset_ms(mid) == current {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                segments.push((hi, self.offset_ms(hi)));
+            }
+            prev = t;
+        }
+        segments
+    }
+
+    /// Label of a bucket key: `YYYY-MM-DD` for days and weeks, local RFC 3339
+    /// (with the zone offset) for minute and hour buckets.
+    pub fn label(&self, key: i64) -> String {
+        let local = chrono::DateTime::from_timestamp_millis(key)
+            .unwrap_or_default()
+            .naive_utc();
+        match self.granularity {
+            Granularity::Day | Granularity::Week => local.format("%Y-%m-%d").to_string(),
+            Granularity::Minute | Granularity::Hour => {
+                match self.tz.from_local_datetime(&local).earliest() {
+                    Some(dt) => dt.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+                    None => local.format("%Y-%m-%dT%H:%M:%S").to_string(),
+                }
+            }
+        }
+    }
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid date '{}': {}", date, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc_ms(s: &str) -> i64 {
+        chrono::DateTime::parse_from_rfc3339(s)
+            .unwrap()
+            .timestamp_millis()
+    }
+
+    #[test]
+    fn default_is_daily_utc() {
+        let b = TimeBuckets::default();
+        assert_eq!(
+            b.range_start_ms("2026-03-01").unwrap(),
+            utc_ms("2026-03-01T00:00:00Z")
+        );
+        assert_eq!(
+            b.label(b.bucket_key(utc_ms("2026-03-01T23:10:00Z"))),
+            "2026-03-01"
+        );
+        assert_eq!(
+            b.sql_key("ts", 0, DAY_MS).unwrap(),
+            "CAST((((ts + 0) - 0) / 86400000 * 86400000 + 0) AS BIGINT)"
+        );
+    }
+
+    #[test]
+    fn fifteen_minute_buckets_in_timezone() {
+        let b = TimeBuckets::parse(Some("minute"), Some(15), Some("Asia/Kolkata")).unwrap();
+        let key = b.bucket_key(utc_ms("2026-03-01T10:20:00Z")); // 15:50 IST
+        assert_eq!(b.label(key), "2026-03-01T15:45:00+05:30");
+        assert_eq!(
+            b.range_start_ms("2026-03-01").unwrap(),
+            utc_ms("2026-02-28T18:30:00Z")
+        );
+    }
+
+    #[test]
+    fn local_days_span_dst_transitions() {
+        // Europe/Paris switches to CEST at 2026-03-29 01:00 UTC.
+        let b = TimeBuckets::parse(Some("day"), None, Some("Europe/Paris")).unwrap();
+        let before = b.bucket_key(utc_ms("2026-03-29T00:30:00Z")); // 01:30 CET
+        let after = b.bucket_key(utc_ms("2026-03-29T21:30:00Z")); // 23:30 CEST
+        assert_eq!(before, after);
+        assert_eq!(b.label(before), "2026-03-29");
+        assert_eq!(
+            b.label(b.bucket_key(utc_ms("2026-03-29T22:30:00Z"))),
+            "2026-03-30"
+        );
+
+        let start = b.range_start_ms("2026-03-28").unwrap();
+        let end = b.range_end_ms("2026-03-30").unwrap();
+        let segments = b.offset_segments(start, end);
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[1], (utc_ms("2026-03-29T01:00:00Z"), 2 * HOUR_MS));
+        assert!(b
+            .sql_key("ts", start, end)
+            .unwrap()
+            .contains("CASE WHEN ts < "));
+    }
+
+    #[test]
+    fn weeks_start_on_monday() {
+        let b = TimeBuckets::parse(Some("week"), None, None).unwrap();
+        // Sunday 2026-10-18 belongs to the week of Monday 2026-10-12
+        assert_eq!(
+            b.label(b.bucket_key(utc_ms("2026-10-18T12:00:00Z"))),
+            "2026-10-12"
+        );
+        assert_eq!(
+            b.label(b.bucket_key(utc_ms("2026-10-19T00:00:00Z"))),
+            "2026-10-19"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_parameters() {
+        assert!(TimeBuckets::parse(Some("month"), None, None).is_err());
+        assert!(TimeBuckets::parse(Some("day"), Some(2), None).is_err());
+        assert!(TimeBuckets::parse(Some("hour"), Some(0), None).is_err());
+        assert!(TimeBuckets::parse(None, None, Some("Mars/Olympus")).is_err());
+        let minutes = TimeBuckets::parse(Some("minute"), None, None).unwrap();
+        assert!(minutes.sql_key("ts", 0, 365 * DAY_MS).is_err());
+    }
+}
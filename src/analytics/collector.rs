@@ -27,6 +27,8 @@ pub struct QueryIdEntry {
     pub query: String,
     pub index_name: String,
     pub timestamp_ms: i64,
+    pub ab_test_id: Option<i64>,
+    pub ab_test_variant_id: Option<u32>,
 }
 
 impl AnalyticsCollector {
@@ -59,6 +61,8 @@ impl AnalyticsCollector {
                     query: event.query.clone(),
                     index_name: event.index_name.clone(),
                     timestamp_ms: event.timestamp_ms,
+                    ab_test_id: event.ab_test_id,
+                    ab_test_variant_id: event.ab_test_variant_id,
                 },
             );
         }
@@ -87,11 +91,23 @@ impl AnalyticsCollector {
     }
 
     /// Record an insight event (click, conversion, view).
-    pub fn record_insight(&self, event: InsightEvent) {
+    pub fn record_insight(&self, mut event: InsightEvent) {
         if !self.config.enabled {
             return;
         }
 
+        // Events for A/B-tested searches are stored with the test's index, even when
+        // the client reports the variant index it actually queried (`indexUsed`).
+        if let Some(entry) = event
+            .query_id
+            .as_deref()
+            .and_then(|q| self.lookup_query_id(q))
+        {
+            if entry.ab_test_id.is_some() && entry.index_name != event.index {
+                event.index = entry.index_name;
+            }
+        }
+
         let should_flush = {
             let mut buf = self.insight_buffer.lock().unwrap();
             buf.push(event);
@@ -0,0 +1,165 @@
+//! Raw analytics export.
+//!
+//! Search and insight events are exported as they are stored: one row per
+//! event, columns named as in the Parquet schema. NDJSON and CSV are encoded
+//! batch by batch so large ranges can be streamed; Parquet is written as a
+//! single file covering the requested range.
+
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::file::properties::WriterProperties;
+
+use super::query::arrow_value_at;
+
+/// Which event table to export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventTable {
+    Searches,
+    Events,
+}
+
+impl EventTable {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "searches" => Ok(Self::Searches),
+            "events" => Ok(Self::Events),
+            other => Err(format!(
+                "Unknown type '{}' (expected searches or events)",
+                other
+            )),
+        }
+    }
+
+    pub fn table_name(&self) -> &'static str {
+        match self {
+            Self::Searches => "searches",
+            Self::Events => "events",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "ndjson" | "jsonl" => Ok(Self::Ndjson),
+            "csv" => Ok(Self::Csv),
+            "parquet" => Ok(Self::Parquet),
+            other => Err(format!(
+                "Unknown format '{}' (expected ndjson, csv or parquet)",
+                other
+            )),
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Parquet => "application/vnd.apache.parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "ndjson",
+            Self::Csv => "csv",
+            Self::Parquet => "parquet",
+        }
+    }
+}
+
+/// Encodes record batches as NDJSON or CSV. The CSV header is written once,
+/// before the first batch.
+pub struct RowEncoder {
+    format: ExportFormat,
+    wrote_header: bool,
+}
+
+impl RowEncoder {
+    pub fn new(format: ExportFormat) -> Result<Self, String> {
+        if format == ExportFormat::Parquet {
+            return Err("Parquet is not a row format; use write_parquet".to_string());
+        }
+        Ok(Self {
+            format,
+            wrote_header: false,
+        })
+    }
+
+    pub fn encode(&mut self, batch: &RecordBatch) -> Vec<u8> {
+        let schema = batch.schema();
+        let mut out = String::new();
+        if self.format == ExportFormat::Csv && !self.wrote_header {
+            let header: Vec<String> = schema
+                .fields()
+                .iter()
+                .map(|f| csv_field(f.name()))
+                .collect();
+            out.push_str(&header.join(","));
+            out.push('\n');
+            self.wrote_header = true;
+        }
+        for row in 0..batch.num_rows() {
+            match self.format {
+                ExportFormat::Csv => {
+                    let cells: Vec<String> = (0..batch.num_columns())
+                        .map(|col| match arrow_value_at(batch.column(col), row) {
+                            serde_json::Value::Null => String::new(),
+                            serde_json::Value::String(s) => csv_field(&s),
+                            other => other.to_string(),
+                        })
+                        .collect();
+                    out.push_str(&cells.join(","));
+                }
+                _ => {
+                    let mut obj = serde_json::Map::new();
+                    for (col, field) in schema.fields().iter().enumerate() {
+                        obj.insert(field.name().clone(), arrow_value_at(batch.column(col), row));
+                    }
+                    out.push_str(&serde_json::Value::Object(obj).to_string());
+                }
+            }
+            out.push('\n');
+        }
+        out.into_bytes()
+    }
+}
+
+/// Quote a CSV cell when it contains a separator, quote or line break (RFC 4180).
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Write batches into a single ZSTD-compressed Parquet file.
+pub fn write_parquet(
+    schema: arrow::datatypes::SchemaRef,
+    batches: &[RecordBatch],
+) -> Result<Vec<u8>, String> {
+    let props = WriterProperties::builder()
+        .set_compression(Compression::ZSTD(Default::default()))
+        .build();
+    let mut buf = Vec::new();
+    let mut writer = ArrowWriter::try_new(&mut buf, schema, Some(props))
+        .map_err(|e| format!("Parquet writer error: {}", e))?;
+    for batch in batches {
+        writer
+            .write(batch)
+            .map_err(|e| format!("Parquet write error: {}", e))?;
+    }
+    writer
+        .close()
+        .map_err(|e| format!("Parquet close error: {}", e))?;
+    Ok(buf)
+}
@@ -4,18 +4,26 @@
 //! Data is stored in Parquet files with Hive-style date partitioning and queried
 //! using DataFusion SQL for efficient analytics aggregation.
 
+pub mod ab_testing;
 pub mod aggregation;
+pub mod buckets;
 pub mod collector;
 pub mod config;
+pub mod export;
+pub mod personalization;
 pub mod query;
 pub mod retention;
 pub mod schema;
 pub mod seed;
+pub mod suggestions;
 pub mod writer;
 
+pub use ab_testing::AbTestStore;
 pub use collector::AnalyticsCollector;
 pub use config::AnalyticsConfig;
+pub use personalization::PersonalizationStore;
 pub use query::AnalyticsQueryEngine;
+pub use suggestions::{QuerySuggestionsConfig, QuerySuggestionsStore};
 
 use once_cell::sync::OnceCell;
 use std::sync::Arc;
@@ -0,0 +1,438 @@
+//! Per-user personalization profiles built from Insights events.
+//!
+//! Every view/click/conversion event adds weight to the `attributesForFaceting`
+//! values of the objects involved (e.g. `brand:Apple`), keyed by the event's
+//! `userToken`, the token searches send with `enablePersonalization`; the
+//! `authenticatedUserToken` of an event does not select the profile. Scores decay exponentially with a configurable half-life, so old
+//! interests fade out. At query time a profile is turned into weighted optional
+//! filters that boost, but never exclude, matching records.
+//!
+//! Profiles are kept in memory and persisted in `personalization_profiles.json`
+//! in the data dir by [`PersonalizationStore::run_flush_loop`] and
+//! [`PersonalizationStore::flush`], off the request path.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use crate::index::settings::IndexSettings;
+use crate::types::Document;
+
+/// Default half-life of an affinity score.
+pub const DEFAULT_HALF_LIFE_DAYS: f64 = 30.0;
+
+/// Scores that decay below this are dropped from the profile.
+const MIN_SCORE: f64 = 0.01;
+
+/// Only the strongest values of each attribute are kept.
+const MAX_VALUES_PER_ATTRIBUTE: usize = 20;
+
+/// Boost given to the user's strongest affinity at `personalizationImpact=100`.
+const MAX_BOOST: f32 = 2.0;
+
+/// Boosts weaker than this are not worth a query clause.
+const MIN_BOOST: f32 = 0.05;
+
+const MS_PER_DAY: f64 = 86_400_000.0;
+
+/// How often changed profiles are written to disk.
+const FLUSH_INTERVAL_SECS: u64 = 5;
+
+/// Weight an Insights event type contributes to a profile, if it counts at all.
+pub fn event_weight(event_type: &str) -> Option<f64> {
+    match event_type {
+        "view" => Some(1.0),
+        "click" => Some(2.0),
+        "conversion" => Some(5.0),
+        _ => None,
+    }
+}
+
+/// Affinity profile of one user token.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct UserProfile {
+    pub user_token: String,
+    /// Unix millis of the most recent event. `scores` are decayed up to this instant.
+    pub last_event_at: i64,
+    /// attribute -> facet value -> score
+    pub scores: BTreeMap<String, BTreeMap<String, f64>>,
+}
+
+impl UserProfile {
+    fn new(user_token: &str) -> Self {
+        Self {
+            user_token: user_token.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Decay all scores to `at_ms`. Never moves `last_event_at` backwards.
+    fn decay_to(&mut self, at_ms: i64, half_life_ms: f64) {
+        let factor = decay_factor(at_ms - self.last_event_at, half_life_ms);
+        if factor < 1.0 {
+            for values in self.scores.values_mut() {
+                for score in values.values_mut() {
+                    *score *= factor;
+                }
+            }
+        }
+        self.last_event_at = self.last_event_at.max(at_ms);
+    }
+
+    fn add(&mut self, attribute: &str, value: &str, weight: f64) {
+        *self
+            .scores
+            .entry(attribute.to_string())
+            .or_default()
+            .entry(value.to_string())
+            .or_insert(0.0) += weight;
+    }
+
+    fn prune(&mut self) {
+        for values in self.scores.values_mut() {
+            values.retain(|_, score| *score >= MIN_SCORE);
+            if values.len() > MAX_VALUES_PER_ATTRIBUTE {
+                let mut ranked: Vec<(String, f64)> =
+                    values.iter().map(|(v, s)| (v.clone(), *s)).collect();
+                ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                ranked.truncate(MAX_VALUES_PER_ATTRIBUTE);
+                *values = ranked.into_iter().collect();
+            }
+        }
+        self.scores.retain(|_, values| !values.is_empty());
+    }
+}
+
+fn decay_factor(elapsed_ms: i64, half_life_ms: f64) -> f64 {
+    if elapsed_ms <= 0 {
+        1.0
+    } else {
+        0.5f64.powf(elapsed_ms as f64 / half_life_ms)
+    }
+}
+
+/// One event's contribution to a profile.
+#[derive(Debug, Clone)]
+pub struct ProfileUpdate {
+    pub user_token: String,
+    /// `(attribute, value)` pairs of the objects the event refers to.
+    pub facet_values: Vec<(String, String)>,
+    pub weight: f64,
+    pub timestamp_ms: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersonalizationData {
+    #[serde(default)]
+    profiles: HashMap<String, UserProfile>,
+}
+
+/// File-backed store of user affinity profiles.
+pub struct PersonalizationStore {
+    data: RwLock<PersonalizationData>,
+    file_path: PathBuf,
+    half_life_ms: f64,
+    /// Profiles changed since the last flush.
+    dirty: AtomicBool,
+}
+
+impl PersonalizationStore {
+    pub fn load(data_dir: &Path) -> Self {
+        let file_path = data_dir.join("personalization_profiles.json");
+        let data = match std::fs::read_to_string(&file_path) {
+            Ok(contents) => match serde_json::from_str::<PersonalizationData>(&contents) {
+                Ok(d) => d,
+                Err(e) => {
+                    tracing::warn!("Failed to parse personalization_profiles.json: {}", e);
+                    PersonalizationData::default()
+                }
+            },
+            Err(_) => PersonalizationData::default(),
+        };
+        Self {
+            data: RwLock::new(data),
+            file_path,
+            half_life_ms: DEFAULT_HALF_LIFE_DAYS * MS_PER_DAY,
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Override how fast affinities fade out.
+    pub fn with_half_life_days(mut self, days: f64) -> Self {
+        if days > 0.0 {
+            self.half_life_ms = days * MS_PER_DAY;
+        }
+        self
+    }
+
+    /// Write the profiles to disk if they changed since the last flush.
+    pub fn flush(&self) {
+        if !self.dirty.swap(false, Ordering::AcqRel) {
+            return;
+        }
+        let json = match serde_json::to_string(&*self.data.read().unwrap()) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!("Failed to serialize personalization profiles: {}", e);
+                return;
+            }
+        };
+        if let Some(parent) = self.file_path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        // Written aside and renamed, so a crash never leaves half a file
+        let tmp_path = self.file_path.with_extension("json.tmp");
+        if let Err(e) = std::fs::write(&tmp_path, json)
+            .and_then(|_| std::fs::rename(&tmp_path, &self.file_path))
+        {
+            tracing::warn!("Failed to save personalization_profiles.json: {}", e);
+            self.dirty.store(true, Ordering::Release);
+        }
+    }
+
+    /// Flush changed profiles every few seconds.
+    pub async fn run_flush_loop(self: Arc<Self>) {
+        let mut ticker =
+            tokio::time::interval(tokio::time::Duration::from_secs(FLUSH_INTERVAL_SECS));
+        ticker.tick().await; // skip the first immediate tick
+
+        loop {
+            ticker.tick().await;
+            let store = Arc::clone(&self);
+            let _ = tokio::task::spawn_blocking(move || store.flush()).await;
+        }
+    }
+
+    /// Fold a batch of events into the profiles. They are persisted by the
+    /// next flush.
+    pub fn apply(&self, updates: Vec<ProfileUpdate>) {
+        let updates: Vec<ProfileUpdate> = updates
+            .into_iter()
+            .filter(|u| !u.facet_values.is_empty() && !u.user_token.is_empty())
+            .collect();
+        if updates.is_empty() {
+            return;
+        }
+        {
+            let mut data = self.data.write().unwrap();
+            for update in updates {
+                let profile = data
+                    .profiles
+                    .entry(update.user_token.clone())
+                    .or_insert_with(|| UserProfile::new(&update.user_token));
+                // Late events are added already decayed relative to the newest one.
+                let weight = update.weight
+                    * decay_factor(
+                        profile.last_event_at - update.timestamp_ms,
+                        self.half_life_ms,
+                    );
+                profile.decay_to(update.timestamp_ms, self.half_life_ms);
+                for (attribute, value) in &update.facet_values {
+                    profile.add(attribute, value, weight);
+                }
+                profile.prune();
+            }
+        }
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    /// The profile of `user_token` with scores decayed to `now_ms`.
+    pub fn get(&self, user_token: &str, now_ms: i64) -> Option<UserProfile> {
+        let mut profile = self.data.read().unwrap().profiles.get(user_token)?.clone();
+        let last_event_at = profile.last_event_at;
+        profile.decay_to(now_ms, self.half_life_ms);
+        profile.last_event_at = last_event_at;
+        profile.prune();
+        Some(profile)
+    }
+
+    /// Forget everything about `user_token`.
+    pub fn delete(&self, user_token: &str) -> bool {
+        let removed = self
+            .data
+            .write()
+            .unwrap()
+            .profiles
+            .remove(user_token)
+            .is_some();
+        if removed {
+            self.dirty.store(true, Ordering::Release);
+        }
+        removed
+    }
+
+    /// Optional filter specs `(attribute, value, boost)` for a user's profile.
+    ///
+    /// Boosts are relative to the user's strongest affinity and scaled by
+    /// `impact` (0-100), Algolia's `personalizationImpact`.
+    pub fn boosts(&self, user_token: &str, now_ms: i64, impact: u32) -> Vec<(String, String, f32)> {
+        let Some(profile) = self.get(user_token, now_ms) else {
+            return Vec::new();
+        };
+        let max = profile
+            .scores
+            .values()
+            .flat_map(|values| values.values())
+            .fold(0.0f64, |acc, s| acc.max(*s));
+        if max <= 0.0 || impact == 0 {
+            return Vec::new();
+        }
+        let scale = MAX_BOOST * impact.min(100) as f32 / 100.0;
+        let mut specs: Vec<(String, String, f32)> = profile
+            .scores
+            .iter()
+            .flat_map(|(attribute, values)| {
+                values.iter().map(move |(value, score)| {
+                    (
+                        attribute.clone(),
+                        value.clone(),
+                        (score / max) as f32 * scale,
+                    )
+                })
+            })
+            .filter(|(_, _, boost)| *boost >= MIN_BOOST)
+            .collect();
+        specs.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+        specs
+    }
+}
+
+/// `(attribute, value)` pairs of `doc` for the index's `attributesForFaceting`.
+///
+/// Nested attributes use dot notation; arrays contribute each scalar element.
+pub fn facet_values(doc: &Document, settings: &IndexSettings) -> Vec<(String, String)> {
+    let json = doc.to_json();
+    let mut out = Vec::new();
+    for attribute in settings.facet_set() {
+        let mut current = Some(&json);
+        for part in attribute.split('.') {
+            current = current.and_then(|v| v.get(part));
+        }
+        let Some(value) = current else {
+            continue;
+        };
+        let scalars: Vec<&serde_json::Value> = match value {
+            serde_json::Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for scalar in scalars {
+            let text = match scalar {
+                serde_json::Value::String(s) if !s.is_empty() => s.clone(),
+                serde_json::Value::Number(n) => n.to_string(),
+                serde_json::Value::Bool(b) => b.to_string(),
+                _ => continue,
+            };
+            out.push((attribute.clone(), text));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn update(user: &str, value: &str, weight: f64, timestamp_ms: i64) -> ProfileUpdate {
+        ProfileUpdate {
+            user_token: user.to_string(),
+            facet_values: vec![("brand".to_string(), value.to_string())],
+            weight,
+            timestamp_ms,
+        }
+    }
+
+    #[test]
+    fn scores_halve_after_one_half_life() {
+        let tmp = TempDir::new().unwrap();
+        let store = PersonalizationStore::load(tmp.path()).with_half_life_days(1.0);
+        let day = MS_PER_DAY as i64;
+        store.apply(vec![update("u1", "Apple", 4.0, 0)]);
+
+        let profile = store.get("u1", day).unwrap();
+        assert!((profile.scores["brand"]["Apple"] - 2.0).abs() < 1e-9);
+        assert_eq!(profile.last_event_at, 0);
+
+        // A late event counts less than a fresh one
+        store.apply(vec![update("u1", "Asus", 4.0, day)]);
+        store.apply(vec![update("u1", "Dell", 4.0, 0)]);
+        let profile = store.get("u1", day).unwrap();
+        assert!((profile.scores["brand"]["Asus"] - 4.0).abs() < 1e-9);
+        assert!((profile.scores["brand"]["Dell"] - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn profiles_are_persisted_on_flush() {
+        let tmp = TempDir::new().unwrap();
+        let store = PersonalizationStore::load(tmp.path());
+        store.apply(vec![update("u1", "Apple", 1.0, 1000)]);
+        assert!(PersonalizationStore::load(tmp.path())
+            .get("u1", 1000)
+            .is_none());
+
+        store.flush();
+        let reloaded = PersonalizationStore::load(tmp.path());
+        assert_eq!(
+            reloaded.get("u1", 1000).unwrap().scores["brand"]["Apple"],
+            1.0
+        );
+
+        store.delete("u1");
+        store.flush();
+        assert!(PersonalizationStore::load(tmp.path())
+            .get("u1", 1000)
+            .is_none());
+    }
+
+    #[test]
+    fn boosts_are_relative_to_strongest_affinity() {
+        let tmp = TempDir::new().unwrap();
+        let store = PersonalizationStore::load(tmp.path());
+        store.apply(vec![
+            update("u1", "Apple", 5.0, 1000),
+            update("u1", "Asus", 1.0, 1000),
+        ]);
+
+        let boosts = store.boosts("u1", 1000, 100);
+        assert_eq!(boosts[0].1, "Apple");
+        assert!((boosts[0].2 - MAX_BOOST).abs() < 1e-6);
+        assert!((boosts[1].2 - MAX_BOOST / 5.0).abs() < 1e-6);
+
+        let half = store.boosts("u1", 1000, 50);
+        assert!((half[0].2 - MAX_BOOST / 2.0).abs() < 1e-6);
+        assert!(store.boosts("u1", 1000, 0).is_empty());
+        assert!(store.boosts("nobody", 1000, 100).is_empty());
+    }
+
+    #[test]
+    fn facet_values_reads_nested_and_array_attributes() {
+        let doc = Document::from_json(&serde_json::json!({
+            "objectID": "1",
+            "brand": "Apple",
+            "tags": ["pro", "laptop"],
+            "specs": {"ram": 16},
+            "name": "MacBook"
+        }))
+        .unwrap();
+        let settings = IndexSettings::default_with_facets(vec![
+            "brand".to_string(),
+            "searchable(tags)".to_string(),
+            "specs.ram".to_string(),
+            "missing".to_string(),
+        ]);
+        let mut values = facet_values(&doc, &settings);
+        values.sort();
+        assert_eq!(
+            values,
+            vec![
+                ("brand".to_string(), "Apple".to_string()),
+                ("specs.ram".to_string(), "16".to_string()),
+                ("tags".to_string(), "laptop".to_string()),
+                ("tags".to_string(), "pro".to_string()),
+            ]
+        );
+    }
+}
@@ -1,9 +1,13 @@
 use datafusion::datasource::listing::ListingOptions;
+use datafusion::execution::context::SQLOptions;
+use datafusion::physical_plan::SendableRecordBatchStream;
 use datafusion::prelude::*;
 use std::path::Path;
 use std::sync::Arc;
 
+use super::buckets::TimeBuckets;
 use super::config::AnalyticsConfig;
+use super::export::EventTable;
 
 /// DataFusion-based analytics query engine.
 ///
@@ -11,11 +15,25 @@ use super::config::AnalyticsConfig;
 /// Supports Hive-style date partitioning for efficient range queries.
 pub struct AnalyticsQueryEngine {
     config: AnalyticsConfig,
+    /// How date ranges are resolved and series are bucketed (daily UTC by default).
+    buckets: TimeBuckets,
 }
 
 impl AnalyticsQueryEngine {
     pub fn new(config: AnalyticsConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            buckets: TimeBuckets::default(),
+        }
+    }
+
+    /// An engine over the same data that buckets series (and resolves dates)
+    /// with the given granularity and timezone.
+    pub fn with_time_buckets(&self, buckets: TimeBuckets) -> Self {
+        Self {
+            config: self.config.clone(),
+            buckets,
+        }
     }
 
     pub fn config(&self) -> &AnalyticsConfig {
@@ -104,8 +122,8 @@ impl AnalyticsQueryEngine {
         }
         let ctx = self.create_session_with_searches(index_name).await?;
 
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let mut where_clause = format!(
             "timestamp_ms >= {} AND timestamp_ms <= {}",
@@ -160,8 +178,9 @@ impl AnalyticsQueryEngine {
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         // Total count
         let total_sql = format!(
@@ -186,7 +205,7 @@ impl AnalyticsQueryEngine {
 
         // Daily breakdown
         let daily_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(*) as count \
              FROM searches \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
@@ -209,7 +228,7 @@ impl AnalyticsQueryEngine {
             .filter_map(|row| {
                 let ms = row.get("day_ms")?.as_i64()?;
                 let count = row.get("count")?.as_i64()?;
-                let date = ms_to_date_string(ms);
+                let date = self.buckets.label(ms);
                 Some(serde_json::json!({"date": date, "count": count}))
             })
             .collect();
@@ -229,8 +248,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let sql = format!(
             "SELECT query as search, COUNT(*) as count, 0 as \"nbHits\" \
@@ -263,8 +282,9 @@ impl AnalyticsQueryEngine {
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         let sql = format!(
             "SELECT \
@@ -299,7 +319,7 @@ impl AnalyticsQueryEngine {
 
         // Daily breakdown
         let daily_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
                COUNT(*) as total, \
                SUM(CASE WHEN has_results = false THEN 1 ELSE 0 END) as no_results \
              FROM searches \
@@ -323,7 +343,7 @@ impl AnalyticsQueryEngine {
                 let n = row.get("no_results")?.as_i64()?;
                 let r = if t > 0 { n as f64 / t as f64 } else { 0.0 };
                 Some(serde_json::json!({
-                    "date": ms_to_date_string(ms),
+                    "date": self.buckets.label(ms),
                     "rate": (r * 1000.0).round() / 1000.0,
                     "count": t,
                     "noResults": n
@@ -346,8 +366,9 @@ impl AnalyticsQueryEngine {
         start_date: &str,
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         // Get tracked search count (searches with queryID)
         let search_ctx = self.create_session_with_searches(index_name).await?;
@@ -372,7 +393,7 @@ impl AnalyticsQueryEngine {
 
         // Daily tracked searches
         let daily_search_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(*) as count \
              FROM searches \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} AND query_id IS NOT NULL \
@@ -412,7 +433,7 @@ impl AnalyticsQueryEngine {
         };
 
         let daily_click_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(*) as count \
              FROM events \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} AND event_type = 'click' \
@@ -456,7 +477,7 @@ impl AnalyticsQueryEngine {
                     0.0
                 };
                 Some(serde_json::json!({
-                    "date": ms_to_date_string(ms),
+                    "date": self.buckets.label(ms),
                     "rate": (day_rate * 1000.0).round() / 1000.0,
                     "clickCount": clicks,
                     "trackedSearchCount": tracked
@@ -480,8 +501,8 @@ impl AnalyticsQueryEngine {
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
         let events_ctx = self.create_session_with_events(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         // Read raw position data and compute in Rust (positions is JSON array)
         let sql = format!(
@@ -514,7 +535,7 @@ impl AnalyticsQueryEngine {
                         .get("timestamp_ms")
                         .and_then(|v| v.as_i64())
                         .unwrap_or(0);
-                    let day_ms = ts / 86400000 * 86400000;
+                    let day_ms = self.buckets.bucket_key(ts);
                     let positions: Vec<f64> = serde_json::from_str(pos_str).unwrap_or_default();
                     for &p in &positions {
                         total_sum += p;
@@ -536,7 +557,7 @@ impl AnalyticsQueryEngine {
                     .map(|(&ms, &(sum, count))| {
                         let day_avg = if count > 0 { sum / count as f64 } else { 0.0 };
                         serde_json::json!({
-                            "date": ms_to_date_string(ms),
+                            "date": self.buckets.label(ms),
                             "average": (day_avg * 10.0).round() / 10.0,
                             "clickCount": count
                         })
@@ -565,8 +586,8 @@ impl AnalyticsQueryEngine {
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
         let events_ctx = self.create_session_with_events(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let sql = format!(
             "SELECT positions FROM events \
@@ -634,8 +655,9 @@ impl AnalyticsQueryEngine {
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         let sql = format!(
             "SELECT COUNT(DISTINCT COALESCE(user_token, user_ip, 'anonymous')) as count \
@@ -660,7 +682,7 @@ impl AnalyticsQueryEngine {
 
         // Daily breakdown
         let daily_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(DISTINCT COALESCE(user_token, user_ip, 'anonymous')) as count \
              FROM searches \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
@@ -680,7 +702,7 @@ impl AnalyticsQueryEngine {
             .filter_map(|row| {
                 let ms = row.get("day_ms")?.as_i64()?;
                 let c = row.get("count")?.as_i64()?;
-                Some(serde_json::json!({"date": ms_to_date_string(ms), "count": c}))
+                Some(serde_json::json!({"date": self.buckets.label(ms), "count": c}))
             })
             .collect();
 
@@ -696,8 +718,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let sql = format!(
             "SELECT filters as attribute, COUNT(*) as count \
@@ -733,8 +755,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         // Filter strings may contain the attribute as "attr:value" or "(attr:value AND ...)"
         // We search for rows containing the attribute name, then parse out values in Rust.
@@ -798,8 +820,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let sql = format!(
             "SELECT filters as attribute, COUNT(*) as count \
@@ -834,8 +856,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let events_ctx = self.create_session_with_events(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let sql = format!(
             "SELECT object_ids as hit, COUNT(*) as count \
@@ -879,8 +901,9 @@ impl AnalyticsQueryEngine {
         start_date: &str,
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         // Get tracked search count + daily
         let search_ctx = self.create_session_with_searches(index_name).await?;
@@ -904,7 +927,7 @@ impl AnalyticsQueryEngine {
             .unwrap_or(0);
 
         let daily_search_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(*) as count \
              FROM searches \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} AND query_id IS NOT NULL \
@@ -944,7 +967,7 @@ impl AnalyticsQueryEngine {
         };
 
         let daily_conv_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(*) as count \
              FROM events \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} AND event_type = 'conversion' \
@@ -988,7 +1011,7 @@ impl AnalyticsQueryEngine {
                     0.0
                 };
                 Some(serde_json::json!({
-                    "date": ms_to_date_string(ms),
+                    "date": self.buckets.label(ms),
                     "rate": (day_rate * 1000.0).round() / 1000.0,
                     "conversionCount": convs,
                     "trackedSearchCount": tracked
@@ -1012,8 +1035,8 @@ impl AnalyticsQueryEngine {
         end_date: &str,
         limit: usize,
     ) -> Result<serde_json::Value, String> {
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         // Get all tracked searches grouped by query
         let search_ctx = self.create_session_with_searches(index_name).await?;
@@ -1110,8 +1133,9 @@ impl AnalyticsQueryEngine {
         start_date: &str,
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         let search_ctx = self.create_session_with_searches(index_name).await?;
         let sql = format!(
@@ -1135,7 +1159,7 @@ impl AnalyticsQueryEngine {
 
         // Daily tracked searches
         let daily_search_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(*) as count \
              FROM searches \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} AND query_id IS NOT NULL \
@@ -1176,7 +1200,7 @@ impl AnalyticsQueryEngine {
 
         // Daily clicked distinct queryIDs
         let daily_click_sql = format!(
-            "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+            "SELECT {bucket} as day_ms, \
              COUNT(DISTINCT query_id) as count \
              FROM events \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
@@ -1223,7 +1247,7 @@ impl AnalyticsQueryEngine {
                     0.0
                 };
                 Some(serde_json::json!({
-                    "date": ms_to_date_string(ms),
+                    "date": self.buckets.label(ms),
                     "rate": (day_rate * 1000.0).round() / 1000.0,
                     "trackedSearchCount": day_tracked,
                     "noClickCount": day_no_click
@@ -1246,8 +1270,9 @@ impl AnalyticsQueryEngine {
         start_date: &str,
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         // Discover all index directories
         let indices = self.list_analytics_indices()?;
@@ -1305,7 +1330,7 @@ impl AnalyticsQueryEngine {
 
             // Daily searches
             let daily_sql = format!(
-                "SELECT CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+                "SELECT {bucket} as day_ms, \
                  COUNT(*) as count FROM searches \
                  WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
                  GROUP BY day_ms",
@@ -1370,7 +1395,9 @@ impl AnalyticsQueryEngine {
 
         let dates: Vec<serde_json::Value> = daily_searches
             .iter()
-            .map(|(&ms, &count)| serde_json::json!({"date": ms_to_date_string(ms), "count": count}))
+            .map(
+                |(&ms, &count)| serde_json::json!({"date": self.buckets.label(ms), "count": count}),
+            )
             .collect();
 
         // Sort per_index by searches descending
@@ -1421,8 +1448,9 @@ impl AnalyticsQueryEngine {
         end_date: &str,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let bucket = self.buckets.sql_key("timestamp_ms", start_ms, end_ms)?;
 
         // Extract platform tag from analytics_tags (comma-separated).
         // DataFusion doesn't have a regexp_extract that returns just the match,
@@ -1462,7 +1490,7 @@ impl AnalyticsQueryEngine {
                  WHEN analytics_tags LIKE '%platform:tablet%' THEN 'tablet' \
                  ELSE 'unknown' \
                END as platform, \
-               CAST(timestamp_ms / 86400000 * 86400000 AS BIGINT) as day_ms, \
+               {bucket} as day_ms, \
                COUNT(*) as count \
              FROM searches \
              WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
@@ -1487,7 +1515,7 @@ impl AnalyticsQueryEngine {
                 let platform = row.get("platform")?.as_str()?.to_string();
                 let ms = row.get("day_ms")?.as_i64()?;
                 let count = row.get("count")?.as_i64()?;
-                let date = ms_to_date_string(ms);
+                let date = self.buckets.label(ms);
                 Some(serde_json::json!({"date": date, "platform": platform, "count": count}))
             })
             .collect();
@@ -1509,8 +1537,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let sql = format!(
             "SELECT country, COUNT(*) as count \
@@ -1551,8 +1579,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let safe_country = country.replace('\'', "''");
         let sql = format!(
@@ -1593,8 +1621,8 @@ impl AnalyticsQueryEngine {
         limit: usize,
     ) -> Result<serde_json::Value, String> {
         let ctx = self.create_session_with_searches(index_name).await?;
-        let start_ms = date_to_start_ms(start_date)?;
-        let end_ms = date_to_end_ms(end_date)?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
 
         let safe_country = country.replace('\'', "''");
         let sql = format!(
@@ -1624,6 +1652,335 @@ impl AnalyticsQueryEngine {
         }))
     }
 
+    /// Normalized queries (lowercased, trimmed) that were searched at least
+    /// `min_count` times with an average of at least `min_hits` results.
+    /// Used as the candidate set for the query suggestions builder.
+    pub async fn popular_queries(
+        &self,
+        index_name: &str,
+        start_date: &str,
+        end_date: &str,
+        min_hits: u32,
+        min_count: u32,
+        min_letters: usize,
+        limit: usize,
+    ) -> Result<Vec<serde_json::Value>, String> {
+        let ctx = self.create_session_with_searches(index_name).await?;
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+
+        let sql = format!(
+            "SELECT LOWER(TRIM(query)) as query, COUNT(*) as count, \
+             CAST(AVG(nb_hits) AS BIGINT) as nb_hits \
+             FROM searches \
+             WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
+               AND CHARACTER_LENGTH(TRIM(query)) >= {} \
+             GROUP BY LOWER(TRIM(query)) \
+             HAVING COUNT(*) >= {} AND AVG(nb_hits) >= {} \
+             ORDER BY count DESC, query ASC \
+             LIMIT {}",
+            start_ms,
+            end_ms,
+            min_letters.max(1),
+            min_count.max(1),
+            min_hits.max(1),
+            limit
+        );
+
+        let df = ctx
+            .sql(&sql)
+            .await
+            .map_err(|e| format!("SQL error: {}", e))?;
+        let batches = df
+            .collect()
+            .await
+            .map_err(|e| format!("Exec error: {}", e))?;
+        batches_to_json(&batches)
+    }
+
+    /// Per-variant searches, clicks and conversions for an A/B test, with
+    /// significance of the click-through and conversion rate differences.
+    ///
+    /// Searches are matched on `ab_test_id`; clicks and conversions are
+    /// attributed to a variant through the queryID of the search they follow.
+    pub async fn ab_test_metrics(
+        &self,
+        index_name: &str,
+        ab_test_id: i64,
+        variant_count: usize,
+        start_ms: i64,
+    ) -> Result<serde_json::Value, String> {
+        #[derive(Default, Clone)]
+        struct VariantStats {
+            searches: i64,
+            users: i64,
+            tracked: i64,
+            clicks: i64,
+            clicked_searches: i64,
+            conversions: i64,
+            converted_searches: i64,
+        }
+        let mut stats = vec![VariantStats::default(); variant_count];
+        let slot = |variant: Option<u64>| {
+            variant
+                .filter(|v| *v >= 1 && (*v as usize) <= variant_count)
+                .map(|v| v as usize - 1)
+        };
+
+        let search_ctx = self.create_session_with_searches(index_name).await?;
+        let counts_sql = format!(
+            "SELECT ab_test_variant_id as variant, COUNT(*) as count, \
+             COUNT(DISTINCT user_token) as users, COUNT(query_id) as tracked \
+             FROM searches \
+             WHERE ab_test_id = {} AND timestamp_ms >= {} \
+             GROUP BY ab_test_variant_id",
+            ab_test_id, start_ms
+        );
+        // Parquet files written before A/B testing existed have no ab_test columns;
+        // if none of the files have them the query fails and there is simply no data yet.
+        let counts = match search_ctx.sql(&counts_sql).await {
+            Ok(df) => {
+                let batches = df
+                    .collect()
+                    .await
+                    .map_err(|e| format!("Exec error: {}", e))?;
+                batches_to_json(&batches)?
+            }
+            Err(_) => Vec::new(),
+        };
+        for row in &counts {
+            if let Some(i) = slot(row.get("variant").and_then(|v| v.as_u64())) {
+                stats[i].searches = row.get("count").and_then(|v| v.as_i64()).unwrap_or(0);
+                stats[i].users = row.get("users").and_then(|v| v.as_i64()).unwrap_or(0);
+                stats[i].tracked = row.get("tracked").and_then(|v| v.as_i64()).unwrap_or(0);
+            }
+        }
+
+        let mut variant_by_query_id: std::collections::HashMap<String, usize> =
+            std::collections::HashMap::new();
+        if stats.iter().any(|s| s.tracked > 0) {
+            let qid_sql = format!(
+                "SELECT query_id, ab_test_variant_id as variant FROM searches \
+                 WHERE ab_test_id = {} AND timestamp_ms >= {} AND query_id IS NOT NULL",
+                ab_test_id, start_ms
+            );
+            let df = search_ctx
+                .sql(&qid_sql)
+                .await
+                .map_err(|e| format!("SQL error: {}", e))?;
+            let batches = df
+                .collect()
+                .await
+                .map_err(|e| format!("Exec error: {}", e))?;
+            for row in batches_to_json(&batches)? {
+                let qid = row.get("query_id").and_then(|v| v.as_str());
+                let variant = slot(row.get("variant").and_then(|v| v.as_u64()));
+                if let (Some(qid), Some(i)) = (qid, variant) {
+                    variant_by_query_id.insert(qid.to_string(), i);
+                }
+            }
+        }
+
+        if !variant_by_query_id.is_empty() {
+            let events_ctx = self.create_session_with_events(index_name).await?;
+            let events_sql = format!(
+                "SELECT query_id, event_type, COUNT(*) as count FROM events \
+                 WHERE query_id IS NOT NULL AND timestamp_ms >= {} \
+                   AND event_type IN ('click', 'conversion') \
+                 GROUP BY query_id, event_type",
+                start_ms
+            );
+            let df = events_ctx
+                .sql(&events_sql)
+                .await
+                .map_err(|e| format!("SQL error: {}", e))?;
+            let batches = df
+                .collect()
+                .await
+                .map_err(|e| format!("Exec error: {}", e))?;
+            for row in batches_to_json(&batches)? {
+                let Some(&i) = row
+                    .get("query_id")
+                    .and_then(|v| v.as_str())
+                    .and_then(|q| variant_by_query_id.get(q))
+                else {
+                    continue;
+                };
+                let count = row.get("count").and_then(|v| v.as_i64()).unwrap_or(0);
+                match row.get("event_type").and_then(|v| v.as_str()) {
+                    Some("click") => {
+                        stats[i].clicks += count;
+                        stats[i].clicked_searches += 1;
+                    }
+                    Some("conversion") => {
+                        stats[i].conversions += count;
+                        stats[i].converted_searches += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let rate = |n: i64, d: i64| {
+            if d > 0 {
+                ((n as f64 / d as f64) * 1000.0).round() / 1000.0
+            } else {
+                0.0
+            }
+        };
+        let variants: Vec<serde_json::Value> = stats
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                serde_json::json!({
+                    "variantID": i + 1,
+                    "searchCount": s.searches,
+                    "trackedSearchCount": s.tracked,
+                    "userCount": s.users,
+                    "clickCount": s.clicks,
+                    "conversionCount": s.conversions,
+                    "clickThroughRate": rate(s.clicked_searches, s.tracked),
+                    "conversionRate": rate(s.converted_searches, s.tracked),
+                })
+            })
+            .collect();
+
+        let significance = |f: fn(&VariantStats) -> i64| {
+            if stats.len() < 2 {
+                return None;
+            }
+            super::ab_testing::two_proportion_significance(
+                f(&stats[0]),
+                stats[0].tracked,
+                f(&stats[1]),
+                stats[1].tracked,
+            )
+            .map(|s| (s * 1000.0).round() / 1000.0)
+        };
+
+        Ok(serde_json::json!({
+            "variants": variants,
+            "clickSignificance": significance(|s| s.clicked_searches),
+            "conversionSignificance": significance(|s| s.converted_searches),
+        }))
+    }
+
+    // ── Raw access ──
+
+    /// Raw rows of `table` between two dates (inclusive), oldest first, as a stream of batches.
+    pub async fn export_stream(
+        &self,
+        index_name: &str,
+        table: EventTable,
+        start_date: &str,
+        end_date: &str,
+    ) -> Result<SendableRecordBatchStream, String> {
+        let df = self
+            .export_dataframe(index_name, table, start_date, end_date)
+            .await?;
+        df.execute_stream()
+            .await
+            .map_err(|e| format!("Exec error: {}", e))
+    }
+
+    /// Raw rows of `table` between two dates (inclusive), re-encoded as one Parquet file.
+    pub async fn export_parquet(
+        &self,
+        index_name: &str,
+        table: EventTable,
+        start_date: &str,
+        end_date: &str,
+    ) -> Result<Vec<u8>, String> {
+        let df = self
+            .export_dataframe(index_name, table, start_date, end_date)
+            .await?;
+        let schema = df.schema().inner().clone();
+        let batches = df
+            .collect()
+            .await
+            .map_err(|e| format!("Exec error: {}", e))?;
+        super::export::write_parquet(schema, &batches)
+    }
+
+    async fn export_dataframe(
+        &self,
+        index_name: &str,
+        table: EventTable,
+        start_date: &str,
+        end_date: &str,
+    ) -> Result<DataFrame, String> {
+        let start_ms = self.buckets.range_start_ms(start_date)?;
+        let end_ms = self.buckets.range_end_ms(end_date)?;
+        let ctx = match table {
+            EventTable::Searches => self.create_session_with_searches(index_name).await?,
+            EventTable::Events => self.create_session_with_events(index_name).await?,
+        };
+        let sql = format!(
+            "SELECT * FROM {} \
+             WHERE timestamp_ms >= {} AND timestamp_ms <= {} \
+             ORDER BY timestamp_ms",
+            table.table_name(),
+            start_ms,
+            end_ms
+        );
+        ctx.sql(&sql).await.map_err(|e| format!("SQL error: {}", e))
+    }
+
+    /// Run a read-only SQL query over the `searches` and `events` tables of an index.
+    ///
+    /// DDL, DML and statements (`SET`, `COPY`, ...) are rejected. At most `max_rows`
+    /// rows are returned (`truncated` tells whether more were available) and the
+    /// query is aborted once `timeout` has elapsed.
+    pub async fn run_sql(
+        &self,
+        index_name: &str,
+        sql: &str,
+        max_rows: usize,
+        timeout: std::time::Duration,
+    ) -> Result<serde_json::Value, String> {
+        let started = std::time::Instant::now();
+        let ctx = self.create_session_with_searches(index_name).await?;
+        self.register_events_table(&ctx, index_name).await?;
+        let options = SQLOptions::new()
+            .with_allow_ddl(false)
+            .with_allow_dml(false)
+            .with_allow_statements(false);
+
+        let run = async {
+            let df = ctx
+                .sql_with_options(sql, options)
+                .await
+                .map_err(|e| format!("SQL error: {}", e))?;
+            let columns: Vec<String> = df
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().clone())
+                .collect();
+            let batches = df
+                .limit(0, Some(max_rows + 1))
+                .map_err(|e| format!("SQL error: {}", e))?
+                .collect()
+                .await
+                .map_err(|e| format!("Exec error: {}", e))?;
+            Ok::<_, String>((columns, batches))
+        };
+        let (columns, batches) = tokio::time::timeout(timeout, run)
+            .await
+            .map_err(|_| format!("Query exceeded the {}ms time limit", timeout.as_millis()))??;
+
+        let mut rows = batches_to_json(&batches)?;
+        let truncated = rows.len() > max_rows;
+        rows.truncate(max_rows);
+        Ok(serde_json::json!({
+            "columns": columns,
+            "rows": rows,
+            "nbRows": rows.len(),
+            "truncated": truncated,
+            "processingTimeMS": started.elapsed().as_millis() as u64,
+        }))
+    }
+
     // ── Internal helpers ──
 
     async fn create_session_with_searches(
@@ -1658,8 +2015,17 @@ impl AnalyticsQueryEngine {
     }
 
     async fn create_session_with_events(&self, index_name: &str) -> Result<SessionContext, String> {
-        let dir = self.config.events_dir(index_name);
         let ctx = SessionContext::new();
+        self.register_events_table(&ctx, index_name).await?;
+        Ok(ctx)
+    }
+
+    async fn register_events_table(
+        &self,
+        ctx: &SessionContext,
+        index_name: &str,
+    ) -> Result<(), String> {
+        let dir = self.config.events_dir(index_name);
         if !dir.exists() {
             let batch =
                 arrow::record_batch::RecordBatch::new_empty(super::schema::insight_event_schema());
@@ -1670,7 +2036,7 @@ impl AnalyticsQueryEngine {
             .map_err(|e| format!("Failed to create empty events table: {}", e))?;
             ctx.register_table("events", Arc::new(mem_table))
                 .map_err(|e| format!("Failed to register empty events: {}", e))?;
-            return Ok(ctx);
+            return Ok(());
         }
         let opts = ListingOptions::new(Arc::new(
             datafusion::datasource::file_format::parquet::ParquetFormat::default(),
@@ -1681,7 +2047,7 @@ impl AnalyticsQueryEngine {
         ctx.register_listing_table("events", &table_path, opts, None, None)
             .await
             .map_err(|e| format!("Failed to register events: {}", e))?;
-        Ok(ctx)
+        Ok(())
     }
 
     async fn enrich_with_click_data(
@@ -1859,31 +2225,6 @@ fn find_parquet_files(dir: &std::path::Path) -> Result<Vec<std::path::PathBuf>,
     Ok(files)
 }
 
-fn date_to_start_ms(date: &str) -> Result<i64, String> {
-    let dt = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
-        .map_err(|e| format!("Invalid date '{}': {}", date, e))?;
-    Ok(dt
-        .and_hms_opt(0, 0, 0)
-        .unwrap()
-        .and_utc()
-        .timestamp_millis())
-}
-
-fn date_to_end_ms(date: &str) -> Result<i64, String> {
-    let dt = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
-        .map_err(|e| format!("Invalid date '{}': {}", date, e))?;
-    Ok(dt
-        .and_hms_opt(23, 59, 59)
-        .unwrap()
-        .and_utc()
-        .timestamp_millis())
-}
-
-fn ms_to_date_string(ms: i64) -> String {
-    let dt = chrono::DateTime::from_timestamp_millis(ms).unwrap_or_default();
-    dt.format("%Y-%m-%d").to_string()
-}
-
 /// Convert Arrow RecordBatches to JSON rows.
 fn batches_to_json(
     batches: &[arrow::record_batch::RecordBatch],
@@ -1904,7 +2245,7 @@ fn batches_to_json(
     Ok(rows)
 }
 
-fn arrow_value_at(col: &dyn arrow::array::Array, idx: usize) -> serde_json::Value {
+pub(crate) fn arrow_value_at(col: &dyn arrow::array::Array, idx: usize) -> serde_json::Value {
     use arrow::array::*;
     use arrow::datatypes::DataType;
 
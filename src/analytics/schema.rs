@@ -20,6 +20,10 @@ pub struct SearchEvent {
     pub has_results: bool,
     pub country: Option<String>,
     pub region: Option<String>,
+    /// A/B test the search was assigned to, if any.
+    pub ab_test_id: Option<i64>,
+    /// Variant within `ab_test_id` (1-based, as returned in `abTestVariantID`).
+    pub ab_test_variant_id: Option<u32>,
 }
 
 /// Sent by client via Insights API (click, conversion, view events).
@@ -121,6 +125,8 @@ pub fn search_event_schema() -> Arc<Schema> {
         Field::new("has_results", DataType::Boolean, false),
         Field::new("country", DataType::Utf8, true),
         Field::new("region", DataType::Utf8, true),
+        Field::new("ab_test_id", DataType::Int64, true),
+        Field::new("ab_test_variant_id", DataType::UInt32, true),
     ]))
 }
 
@@ -346,6 +346,8 @@ pub fn seed_analytics(
                 has_results,
                 country: Some(country_code.to_string()),
                 region: region.map(|r| r.to_string()),
+                ab_test_id: None,
+                ab_test_variant_id: None,
             });
 
             // Generate click events (~35% CTR for searches with results)
@@ -481,6 +483,8 @@ fn write_search_events_to_partition(
     let mut has_results = BooleanBuilder::with_capacity(len);
     let mut country = StringBuilder::with_capacity(len, len * 2);
     let mut region = StringBuilder::with_capacity(len, len * 10);
+    let mut ab_test_id = Int64Builder::with_capacity(len);
+    let mut ab_test_variant_id = UInt32Builder::with_capacity(len);
 
     for e in events {
         timestamp_ms.append_value(e.timestamp_ms);
@@ -523,6 +527,8 @@ fn write_search_events_to_partition(
             Some(r) => region.append_value(r),
             None => region.append_null(),
         }
+        ab_test_id.append_option(e.ab_test_id);
+        ab_test_variant_id.append_option(e.ab_test_variant_id);
     }
 
     let columns: Vec<ArrayRef> = vec![
@@ -542,6 +548,8 @@ fn write_search_events_to_partition(
         Arc::new(has_results.finish()),
         Arc::new(country.finish()),
         Arc::new(region.finish()),
+        Arc::new(ab_test_id.finish()),
+        Arc::new(ab_test_variant_id.finish()),
     ];
 
     let batch = RecordBatch::try_new(schema.clone(), columns)
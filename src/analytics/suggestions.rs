@@ -0,0 +1,571 @@
+//! Query suggestions built from search analytics.
+//!
+//! A [`QuerySuggestionsConfig`] points at a source index whose recorded searches
+//! are ranked by popularity, and a target index that receives one record per
+//! suggestion (`query`, `popularity`, `nb_hits`, optional top facet values).
+//! The target is a regular Flapjack index, so autocomplete UIs query it like any
+//! other. Configs and build status live in `query_suggestions.json` in the data dir.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::query::AnalyticsQueryEngine;
+use crate::index::aliases::validate_name;
+use crate::index::settings::IndexSettings;
+use crate::types::{Document, FacetRequest};
+use crate::IndexManager;
+
+/// Page size used when listing the existing records of a suggestions index.
+const LIST_PAGE_SIZE: usize = 1000;
+
+/// How often the scheduler checks for configs that are due for a rebuild.
+const SCHEDULER_TICK_SECS: u64 = 60;
+
+/// A facet attribute of the source index whose top values are attached to each suggestion.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestionFacet {
+    pub attribute: String,
+    #[serde(default = "default_facet_amount")]
+    pub amount: usize,
+}
+
+/// Configuration for one query suggestions index.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuerySuggestionsConfig {
+    /// Target index that receives the suggestion records.
+    pub index_name: String,
+    /// Index whose search analytics are used as the source of queries.
+    pub source_index: String,
+    /// Minimum average number of hits a query must return. Zero-hit queries are always dropped.
+    #[serde(default = "default_min_hits")]
+    pub min_hits: u32,
+    /// Minimum number of times a query must have been searched.
+    #[serde(default = "default_min_count")]
+    pub min_count: u32,
+    /// Minimum number of characters in a query.
+    #[serde(default = "default_min_letters")]
+    pub min_letters: usize,
+    /// How many days of analytics to consider.
+    #[serde(default = "default_lookback_days")]
+    pub lookback_days: u32,
+    /// Maximum number of suggestions written to the target index.
+    #[serde(default = "default_max_suggestions")]
+    pub max_suggestions: usize,
+    #[serde(default)]
+    pub facets: Vec<SuggestionFacet>,
+    /// Queries that must never be suggested (case-insensitive exact match).
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Interval between scheduled rebuilds, in seconds. 0 disables scheduled rebuilds.
+    #[serde(default = "default_rebuild_interval_secs")]
+    pub rebuild_interval_secs: u64,
+}
+
+fn default_facet_amount() -> usize {
+    3
+}
+fn default_min_hits() -> u32 {
+    1
+}
+fn default_min_count() -> u32 {
+    5
+}
+fn default_min_letters() -> usize {
+    4
+}
+fn default_lookback_days() -> u32 {
+    30
+}
+fn default_max_suggestions() -> usize {
+    1000
+}
+fn default_rebuild_interval_secs() -> u64 {
+    86400
+}
+
+impl QuerySuggestionsConfig {
+    /// Config with default thresholds for the given target/source pair.
+    pub fn new(index_name: &str, source_index: &str) -> Self {
+        Self {
+            index_name: index_name.to_string(),
+            source_index: source_index.to_string(),
+            min_hits: default_min_hits(),
+            min_count: default_min_count(),
+            min_letters: default_min_letters(),
+            lookback_days: default_lookback_days(),
+            max_suggestions: default_max_suggestions(),
+            facets: Vec::new(),
+            exclude: Vec::new(),
+            rebuild_interval_secs: default_rebuild_interval_secs(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.index_name.trim().is_empty() {
+            return Err("indexName must not be empty".to_string());
+        }
+        if self.source_index.trim().is_empty() {
+            return Err("sourceIndex must not be empty".to_string());
+        }
+        for name in [&self.index_name, &self.source_index] {
+            validate_name(name).map_err(|_| format!("invalid index name '{}'", name))?;
+        }
+        if self.index_name == self.source_index {
+            return Err("indexName must differ from sourceIndex".to_string());
+        }
+        if self.max_suggestions == 0 {
+            return Err("maxSuggestions must be greater than 0".to_string());
+        }
+        if let Some(f) = self.facets.iter().find(|f| f.attribute.is_empty()) {
+            return Err(format!("invalid facet attribute '{}'", f.attribute));
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of the most recent build for a config.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuerySuggestionsStatus {
+    pub index_name: String,
+    pub is_running: bool,
+    /// Unix millis when the last build finished (successfully or not).
+    pub last_built_at: Option<i64>,
+    pub last_build_duration_ms: Option<i64>,
+    pub nb_suggestions: usize,
+    pub error: Option<String>,
+}
+
+/// Result of a single suggestions build.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SuggestionsBuildResult {
+    pub index_name: String,
+    pub nb_suggestions: usize,
+    pub nb_removed: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QuerySuggestionsData {
+    #[serde(default)]
+    configs: Vec<QuerySuggestionsConfig>,
+    #[serde(default)]
+    status: HashMap<String, QuerySuggestionsStatus>,
+}
+
+/// File-backed store of query suggestions configs and their build status.
+pub struct QuerySuggestionsStore {
+    data: RwLock<QuerySuggestionsData>,
+    file_path: PathBuf,
+}
+
+impl QuerySuggestionsStore {
+    pub fn load(data_dir: &Path) -> Self {
+        let file_path = data_dir.join("query_suggestions.json");
+        let mut data = match std::fs::read_to_string(&file_path) {
+            Ok(contents) => match serde_json::from_str::<QuerySuggestionsData>(&contents) {
+                Ok(d) => d,
+                Err(e) => {
+                    tracing::warn!("Failed to parse query_suggestions.json: {}", e);
+                    QuerySuggestionsData::default()
+                }
+            },
+            Err(_) => QuerySuggestionsData::default(),
+        };
+        // A build interrupted by a restart is no longer running.
+        for status in data.status.values_mut() {
+            status.is_running = false;
+        }
+        Self {
+            data: RwLock::new(data),
+            file_path,
+        }
+    }
+
+    fn save(&self) {
+        let data = self.data.read().unwrap();
+        if let Ok(json) = serde_json::to_string_pretty(&*data) {
+            if let Some(parent) = self.file_path.parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            if let Err(e) = std::fs::write(&self.file_path, json) {
+                tracing::warn!("Failed to save query_suggestions.json: {}", e);
+            }
+        }
+    }
+
+    pub fn list(&self) -> Vec<QuerySuggestionsConfig> {
+        self.data.read().unwrap().configs.clone()
+    }
+
+    pub fn get(&self, index_name: &str) -> Option<QuerySuggestionsConfig> {
+        let data = self.data.read().unwrap();
+        data.configs
+            .iter()
+            .find(|c| c.index_name == index_name)
+            .cloned()
+    }
+
+    /// Insert or replace a config. Returns `true` if the config is new.
+    pub fn upsert(&self, config: QuerySuggestionsConfig) -> bool {
+        let created = {
+            let mut data = self.data.write().unwrap();
+            match data
+                .configs
+                .iter_mut()
+                .find(|c| c.index_name == config.index_name)
+            {
+                Some(existing) => {
+                    *existing = config;
+                    false
+                }
+                None => {
+                    data.configs.push(config);
+                    true
+                }
+            }
+        };
+        self.save();
+        created
+    }
+
+    /// Remove a config and its status. The suggestions index itself is left untouched.
+    pub fn delete(&self, index_name: &str) -> bool {
+        let removed = {
+            let mut data = self.data.write().unwrap();
+            let before = data.configs.len();
+            data.configs.retain(|c| c.index_name != index_name);
+            data.status.remove(index_name);
+            data.configs.len() != before
+        };
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    pub fn status(&self, index_name: &str) -> Option<QuerySuggestionsStatus> {
+        let data = self.data.read().unwrap();
+        if !data.configs.iter().any(|c| c.index_name == index_name) {
+            return None;
+        }
+        Some(
+            data.status
+                .get(index_name)
+                .cloned()
+                .unwrap_or_else(|| QuerySuggestionsStatus {
+                    index_name: index_name.to_string(),
+                    ..Default::default()
+                }),
+        )
+    }
+
+    /// Configs whose scheduled rebuild is due at `now_ms`.
+    pub fn due_configs(&self, now_ms: i64) -> Vec<QuerySuggestionsConfig> {
+        let data = self.data.read().unwrap();
+        data.configs
+            .iter()
+            .filter(|c| c.rebuild_interval_secs > 0)
+            .filter(|c| match data.status.get(&c.index_name) {
+                Some(s) if s.is_running => false,
+                Some(QuerySuggestionsStatus {
+                    last_built_at: Some(last),
+                    ..
+                }) => now_ms - last >= (c.rebuild_interval_secs as i64) * 1000,
+                _ => true,
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Mark a config as running. Returns `false` if a build is already in progress.
+    fn try_start(&self, index_name: &str) -> bool {
+        let mut data = self.data.write().unwrap();
+        let status = data
+            .status
+            .entry(index_name.to_string())
+            .or_insert_with(|| QuerySuggestionsStatus {
+                index_name: index_name.to_string(),
+                ..Default::default()
+            });
+        if status.is_running {
+            return false;
+        }
+        status.is_running = true;
+        true
+    }
+
+    fn finish(
+        &self,
+        index_name: &str,
+        started_ms: i64,
+        result: &Result<SuggestionsBuildResult, String>,
+    ) {
+        {
+            let mut data = self.data.write().unwrap();
+            let now = chrono::Utc::now().timestamp_millis();
+            let status = data
+                .status
+                .entry(index_name.to_string())
+                .or_insert_with(|| QuerySuggestionsStatus {
+                    index_name: index_name.to_string(),
+                    ..Default::default()
+                });
+            status.is_running = false;
+            status.last_built_at = Some(now);
+            status.last_build_duration_ms = Some(now - started_ms);
+            match result {
+                Ok(r) => {
+                    status.nb_suggestions = r.nb_suggestions;
+                    status.error = None;
+                }
+                Err(e) => status.error = Some(e.clone()),
+            }
+        }
+        self.save();
+    }
+}
+
+/// Build a suggestions index and record the outcome in the store.
+///
+/// Returns an error without building if a build for the same config is already running.
+pub async fn rebuild(
+    engine: &AnalyticsQueryEngine,
+    manager: &Arc<IndexManager>,
+    store: &QuerySuggestionsStore,
+    config: &QuerySuggestionsConfig,
+) -> Result<SuggestionsBuildResult, String> {
+    if !store.try_start(&config.index_name) {
+        return Err(format!(
+            "a build is already running for '{}'",
+            config.index_name
+        ));
+    }
+    let started_ms = chrono::Utc::now().timestamp_millis();
+    let result = build_suggestions(engine, manager, config).await;
+    store.finish(&config.index_name, started_ms, &result);
+    result
+}
+
+/// Compute suggestions for `config` and write them into the target index.
+///
+/// Records that are no longer suggested are deleted from the target index, so
+/// the index always mirrors the latest build.
+pub async fn build_suggestions(
+    engine: &AnalyticsQueryEngine,
+    manager: &Arc<IndexManager>,
+    config: &QuerySuggestionsConfig,
+) -> Result<SuggestionsBuildResult, String> {
+    config.validate()?;
+
+    let today = chrono::Utc::now().date_naive();
+    let start = today - chrono::Duration::days(config.lookback_days as i64);
+    let exclude: HashSet<String> = config
+        .exclude
+        .iter()
+        .map(|q| q.trim().to_lowercase())
+        .collect();
+
+    let rows = engine
+        .popular_queries(
+            &config.source_index,
+            &start.format("%Y-%m-%d").to_string(),
+            &today.format("%Y-%m-%d").to_string(),
+            config.min_hits,
+            config.min_count,
+            config.min_letters,
+            config.max_suggestions + exclude.len(),
+        )
+        .await?;
+
+    let candidates: Vec<(String, i64, i64)> = rows
+        .iter()
+        .filter_map(|row| {
+            let query = row.get("query")?.as_str()?.to_string();
+            let count = row.get("count")?.as_i64()?;
+            let nb_hits = row.get("nb_hits")?.as_i64()?;
+            Some((query, count, nb_hits))
+        })
+        .filter(|(query, _, _)| !exclude.contains(query))
+        .take(config.max_suggestions)
+        .collect();
+
+    let mgr = Arc::clone(manager);
+    let cfg = config.clone();
+    let records = tokio::task::spawn_blocking(move || build_records(&mgr, &cfg, candidates))
+        .await
+        .map_err(|e| format!("suggestions build task failed: {}", e))??;
+
+    write_suggestions(manager, &config.index_name, records).await
+}
+
+fn build_records(
+    manager: &IndexManager,
+    config: &QuerySuggestionsConfig,
+    candidates: Vec<(String, i64, i64)>,
+) -> Result<Vec<serde_json::Value>, String> {
+    let facet_requests: Vec<FacetRequest> = config
+        .facets
+        .iter()
+        .map(|f| FacetRequest {
+            field: f.attribute.clone(),
+            path: format!("/{}", f.attribute),
+        })
+        .collect();
+
+    let mut records = Vec::with_capacity(candidates.len());
+    for (query, popularity, nb_hits) in candidates {
+        let mut record = serde_json::json!({
+            "objectID": query,
+            "query": query,
+            "nb_words": query.split_whitespace().count(),
+            "popularity": popularity,
+            "nb_hits": nb_hits,
+        });
+
+        if !facet_requests.is_empty() {
+            let result = manager
+                .search_with_facets(
+                    &config.source_index,
+                    &query,
+                    None,
+                    None,
+                    0,
+                    0,
+                    Some(&facet_requests),
+                )
+                .map_err(|e| format!("facet lookup for '{}' failed: {}", query, e))?;
+
+            let mut facets = serde_json::Map::new();
+            for facet in &config.facets {
+                let mut counts = result
+                    .facets
+                    .get(&facet.attribute)
+                    .cloned()
+                    .unwrap_or_default();
+                counts.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.path.cmp(&b.path)));
+                let values: Vec<serde_json::Value> = counts
+                    .into_iter()
+                    .take(facet.amount)
+                    .map(|fc| serde_json::json!({"value": fc.path, "count": fc.count}))
+                    .collect();
+                facets.insert(facet.attribute.clone(), serde_json::Value::Array(values));
+            }
+            record["facets"] = serde_json::Value::Object(facets);
+        }
+
+        records.push(record);
+    }
+    Ok(records)
+}
+
+async fn write_suggestions(
+    manager: &Arc<IndexManager>,
+    index_name: &str,
+    records: Vec<serde_json::Value>,
+) -> Result<SuggestionsBuildResult, String> {
+    manager
+        .create_tenant(index_name)
+        .map_err(|e| format!("failed to create '{}': {}", index_name, e))?;
+
+    // Seed ranking settings on first build; later edits by the user are kept.
+    let settings_path = manager.base_path.join(index_name).join("settings.json");
+    let mut settings = IndexSettings::load(&settings_path).unwrap_or_default();
+    if settings.searchable_attributes.is_none() && settings.custom_ranking.is_none() {
+        settings.searchable_attributes = Some(vec!["query".to_string()]);
+        settings.custom_ranking = Some(vec!["desc(popularity)".to_string()]);
+        settings
+            .save(&settings_path)
+            .map_err(|e| format!("failed to save settings for '{}': {}", index_name, e))?;
+        manager.invalidate_settings_cache(index_name);
+    }
+
+    let mgr = Arc::clone(manager);
+    let target = index_name.to_string();
+    let existing = tokio::task::spawn_blocking(move || list_object_ids(&mgr, &target))
+        .await
+        .map_err(|e| format!("listing task failed: {}", e))??;
+
+    let mut docs = Vec::with_capacity(records.len());
+    let mut keep = HashSet::with_capacity(records.len());
+    for record in &records {
+        let doc = Document::from_json(record).map_err(|e| format!("invalid record: {}", e))?;
+        keep.insert(doc.id.clone());
+        docs.push(doc);
+    }
+    let stale: Vec<String> = existing
+        .into_iter()
+        .filter(|id| !keep.contains(id))
+        .collect();
+
+    let nb_suggestions = docs.len();
+    let nb_removed = stale.len();
+    if !docs.is_empty() {
+        manager
+            .add_documents_sync(index_name, docs)
+            .await
+            .map_err(|e| format!("failed to write suggestions: {}", e))?;
+    }
+    if !stale.is_empty() {
+        manager
+            .delete_documents_sync(index_name, stale)
+            .await
+            .map_err(|e| format!("failed to remove stale suggestions: {}", e))?;
+    }
+
+    Ok(SuggestionsBuildResult {
+        index_name: index_name.to_string(),
+        nb_suggestions,
+        nb_removed,
+    })
+}
+
+fn list_object_ids(manager: &IndexManager, index_name: &str) -> Result<Vec<String>, String> {
+    let mut ids = Vec::new();
+    let mut offset = 0;
+    loop {
+        let result = manager
+            .search_with_facets(index_name, "", None, None, LIST_PAGE_SIZE, offset, None)
+            .map_err(|e| format!("failed to list '{}': {}", index_name, e))?;
+        let page_len = result.documents.len();
+        ids.extend(result.documents.into_iter().map(|d| d.document.id));
+        offset += page_len;
+        if page_len < LIST_PAGE_SIZE || offset >= result.total {
+            break;
+        }
+    }
+    Ok(ids)
+}
+
+/// Rebuild suggestions indices on their configured schedule (background task).
+pub async fn run_suggestions_loop(
+    engine: Arc<AnalyticsQueryEngine>,
+    manager: Arc<IndexManager>,
+    store: Arc<QuerySuggestionsStore>,
+) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(SCHEDULER_TICK_SECS));
+    interval.tick().await; // skip first immediate tick
+    loop {
+        interval.tick().await;
+        let now = chrono::Utc::now().timestamp_millis();
+        for config in store.due_configs(now) {
+            match rebuild(&engine, &manager, &store, &config).await {
+                Ok(r) => tracing::info!(
+                    "[analytics] Rebuilt query suggestions '{}': {} suggestions, {} removed",
+                    r.index_name,
+                    r.nb_suggestions,
+                    r.nb_removed
+                ),
+                Err(e) => tracing::warn!(
+                    "[analytics] Query suggestions build for '{}' failed: {}",
+                    config.index_name,
+                    e
+                ),
+            }
+        }
+    }
+}
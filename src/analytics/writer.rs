@@ -95,6 +95,8 @@ fn search_events_to_batch(
     let mut has_results = BooleanBuilder::with_capacity(len);
     let mut country = StringBuilder::with_capacity(len, len * 2);
     let mut region = StringBuilder::with_capacity(len, len * 10);
+    let mut ab_test_id = Int64Builder::with_capacity(len);
+    let mut ab_test_variant_id = UInt32Builder::with_capacity(len);
 
     for e in events {
         timestamp_ms.append_value(e.timestamp_ms);
@@ -137,6 +139,8 @@ fn search_events_to_batch(
             Some(r) => region.append_value(r),
             None => region.append_null(),
         }
+        ab_test_id.append_option(e.ab_test_id);
+        ab_test_variant_id.append_option(e.ab_test_variant_id);
     }
 
     let columns: Vec<ArrayRef> = vec![
@@ -156,6 +160,8 @@ fn search_events_to_batch(
         Arc::new(has_results.finish()),
         Arc::new(country.finish()),
         Arc::new(region.finish()),
+        Arc::new(ab_test_id.finish()),
+        Arc::new(ab_test_variant_id.finish()),
     ];
 
     RecordBatch::try_new(schema.clone(), columns).map_err(|e| format!("RecordBatch error: {}", e))
@@ -6,6 +6,9 @@ pub enum FlapjackError {
     #[error("Tenant not found: {0}")]
     TenantNotFound(String),
 
+    #[error("Tenant is busy: {0}")]
+    TenantBusy(String),
+
     #[error("Index already exists for tenant: {0}")]
     IndexAlreadyExists(String),
 
@@ -126,6 +129,7 @@ impl FlapjackError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             FlapjackError::TenantNotFound(_) => StatusCode::NOT_FOUND,
+            FlapjackError::TenantBusy(_) => StatusCode::SERVICE_UNAVAILABLE,
             FlapjackError::IndexAlreadyExists(_) => StatusCode::CONFLICT,
             FlapjackError::InvalidQuery(_) => StatusCode::BAD_REQUEST,
             FlapjackError::QueryTooComplex(_) => StatusCode::BAD_REQUEST,
@@ -181,6 +185,12 @@ impl IntoResponse for FlapjackError {
                 format!("Index '{}' does not exist", tenant),
                 Some("Create the index first with POST /indexes".to_string()),
             ),
+            FlapjackError::TenantBusy(tenant) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "index_busy",
+                format!("Index '{}' is being rewritten", tenant),
+                Some("Retry after a short delay".to_string()),
+            ),
             FlapjackError::IndexAlreadyExists(tenant) => (
                 StatusCode::CONFLICT,
                 "index_already_exists",
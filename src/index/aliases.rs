@@ -0,0 +1,228 @@
+//! Index aliases: stable names pointing to a concrete index.
+//!
+//! A full reindex goes into a new index, then the alias is pointed at it in
+//! one step, so requests through the alias never see a missing or partially
+//! built index. Aliases are stored in `.aliases/aliases.json` in the data dir
+//! and resolved by the `IndexManager` before it opens an index. Changes are
+//! recorded in the `.aliases` oplog, which replicates them to peers.
+//!
+//! An alias may shadow an existing index of the same name: this lets an index
+//! that clients already query be replaced by an alias without downtime. The
+//! shadowed directory is left in place until the index is deleted.
+
+use crate::error::{FlapjackError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Directory of the aliases in the data dir. The leading dot keeps it out of
+/// the index listing; it also holds the aliases oplog.
+pub const ALIASES_DIR: &str = ".aliases";
+
+const ALIASES_FILE: &str = "aliases.json";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alias {
+    pub alias: String,
+    pub index: String,
+    /// Milliseconds since the epoch of the last change.
+    pub updated_at: i64,
+}
+
+/// File-backed map of alias names to index names.
+pub struct AliasStore {
+    dir: PathBuf,
+    aliases: RwLock<BTreeMap<String, Alias>>,
+}
+
+impl AliasStore {
+    /// Load the aliases from `dir`; a missing or unreadable file is empty.
+    pub fn load(dir: &Path) -> Self {
+        let path = dir.join(ALIASES_FILE);
+        let list: Vec<Alias> = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                tracing::warn!("Failed to parse {}: {}", path.display(), e);
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        AliasStore {
+            dir: dir.to_path_buf(),
+            aliases: RwLock::new(list.into_iter().map(|a| (a.alias.clone(), a)).collect()),
+        }
+    }
+
+    /// The index `name` points to, or `name` itself when it is not an alias.
+    pub fn resolve(&self, name: &str) -> String {
+        self.aliases
+            .read()
+            .unwrap()
+            .get(name)
+            .map(|a| a.index.clone())
+            .unwrap_or_else(|| name.to_string())
+    }
+
+    pub fn is_alias(&self, name: &str) -> bool {
+        self.aliases.read().unwrap().contains_key(name)
+    }
+
+    pub fn get(&self, alias: &str) -> Option<Alias> {
+        self.aliases.read().unwrap().get(alias).cloned()
+    }
+
+    /// Every alias, ordered by name.
+    pub fn list(&self) -> Vec<Alias> {
+        self.aliases.read().unwrap().values().cloned().collect()
+    }
+
+    /// Point `alias` at `index`, replacing its previous target in one step.
+    /// Returns the previous target. Aliases cannot point to other aliases.
+    pub fn set(&self, alias: &str, index: &str) -> Result<Option<String>> {
+        validate_name(alias)?;
+        validate_name(index)?;
+        if alias == index {
+            return Err(FlapjackError::InvalidQuery(format!(
+                "Alias '{}' cannot point to itself",
+                alias
+            )));
+        }
+        let mut aliases = self.aliases.write().unwrap();
+        if aliases.contains_key(index) {
+            return Err(FlapjackError::InvalidQuery(format!(
+                "'{}' is an alias; aliases must point to an index",
+                index
+            )));
+        }
+        if let Some(other) = aliases.values().find(|a| a.index == alias) {
+            return Err(FlapjackError::InvalidQuery(format!(
+                "'{}' is the target of alias '{}'",
+                alias, other.alias
+            )));
+        }
+        let mut updated = aliases.clone();
+        let previous = updated.insert(
+            alias.to_string(),
+            Alias {
+                alias: alias.to_string(),
+                index: index.to_string(),
+                updated_at: chrono::Utc::now().timestamp_millis(),
+            },
+        );
+        self.save(&updated)?;
+        *aliases = updated;
+        Ok(previous.map(|a| a.index))
+    }
+
+    /// Remove `alias`, returning it if it existed.
+    pub fn remove(&self, alias: &str) -> Result<Option<Alias>> {
+        let mut aliases = self.aliases.write().unwrap();
+        if !aliases.contains_key(alias) {
+            return Ok(None);
+        }
+        let mut updated = aliases.clone();
+        let removed = updated.remove(alias);
+        self.save(&updated)?;
+        *aliases = updated;
+        Ok(removed)
+    }
+
+    /// Replay an aliases oplog entry, as written by the HTTP handlers.
+    pub fn apply_op(&self, op_type: &str, payload: &serde_json::Value) -> Result<()> {
+        let field = |name: &str| {
+            payload
+                .get(name)
+                .and_then(|v| v.as_str())
+                .ok_or_else(|| FlapjackError::MissingField(name.to_string()))
+        };
+        match op_type {
+            "alias_set" => self.set(field("alias")?, field("index")?).map(|_| ()),
+            "alias_delete" => self.remove(field("alias")?).map(|_| ()),
+            _ => Err(FlapjackError::InvalidDocument(format!(
+                "unknown aliases op {}",
+                op_type
+            ))),
+        }
+    }
+
+    /// Write through a temporary file so a crash never leaves a torn file.
+    fn save(&self, aliases: &BTreeMap<String, Alias>) -> Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        let list: Vec<&Alias> = aliases.values().collect();
+        let tmp = self.dir.join(format!("{}.tmp", ALIASES_FILE));
+        std::fs::write(&tmp, serde_json::to_string_pretty(&list)?)?;
+        std::fs::rename(&tmp, self.dir.join(ALIASES_FILE))?;
+        Ok(())
+    }
+}
+
+/// Reject names that cannot be used as an index or alias directory.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(FlapjackError::InvalidQuery(format!(
+            "Invalid index or alias name '{}'",
+            name
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_set_swap_and_reload() {
+        let dir = TempDir::new().unwrap();
+        let store = AliasStore::load(dir.path());
+        assert_eq!(store.resolve("products"), "products");
+
+        assert_eq!(store.set("products", "products_v1").unwrap(), None);
+        assert_eq!(store.resolve("products"), "products_v1");
+        assert_eq!(
+            store.set("products", "products_v2").unwrap().as_deref(),
+            Some("products_v1")
+        );
+
+        let reloaded = AliasStore::load(dir.path());
+        assert_eq!(reloaded.resolve("products"), "products_v2");
+        assert_eq!(reloaded.list().len(), 1);
+
+        assert!(reloaded.remove("products").unwrap().is_some());
+        assert!(reloaded.remove("products").unwrap().is_none());
+        assert_eq!(AliasStore::load(dir.path()).resolve("products"), "products");
+    }
+
+    #[test]
+    fn test_rejects_chains() {
+        let dir = TempDir::new().unwrap();
+        let store = AliasStore::load(dir.path());
+        store.set("products", "products_v1").unwrap();
+        assert!(store.set("catalog", "products").is_err());
+        assert!(store.set("products_v1", "products_v0").is_err());
+        assert!(store.set("loop", "loop").is_err());
+        assert!(store.set(".hidden", "products_v1").is_err());
+    }
+
+    #[test]
+    fn test_apply_op() {
+        let dir = TempDir::new().unwrap();
+        let store = AliasStore::load(dir.path());
+        store
+            .apply_op(
+                "alias_set",
+                &serde_json::json!({"alias": "products", "index": "products_v1"}),
+            )
+            .unwrap();
+        assert_eq!(store.resolve("products"), "products_v1");
+        store
+            .apply_op("alias_delete", &serde_json::json!({"alias": "products"}))
+            .unwrap();
+        assert!(!store.is_alias("products"));
+        assert!(store
+            .apply_op("alias_rename", &serde_json::json!({}))
+            .is_err());
+    }
+}
@@ -0,0 +1,301 @@
+//! Streaming bulk import of NDJSON and CSV.
+//!
+//! Records are parsed one at a time from a reader, so an import of any size
+//! runs in constant memory. A record that cannot be turned into a document is
+//! reported as a [`DocFailure`] whose message starts with its line number,
+//! and the import goes on; only a read error (e.g. a truncated upload) stops
+//! it. The [`IndexManager`](crate::IndexManager) feeds the parsed documents to
+//! the tenant's write queue.
+
+use crate::error::{FlapjackError, Result};
+use crate::index::write_queue::classify_error;
+use crate::types::{DocFailure, Document};
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+/// Documents per write queue op of a bulk import.
+pub const BULK_CHUNK_SIZE: usize = 1000;
+
+/// A parsed record, or why it was rejected.
+pub type Record = std::result::Result<Document, DocFailure>;
+
+#[derive(Debug, Clone)]
+pub enum BulkFormat {
+    /// One JSON object per line; blank lines are skipped.
+    Ndjson,
+    Csv(CsvOptions),
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvOptions {
+    pub delimiter: u8,
+    /// Column header to attribute name; unmapped columns keep their header.
+    pub mapping: HashMap<String, String>,
+    /// Attribute name to cell type; cells are strings by default.
+    pub types: HashMap<String, CsvType>,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            mapping: HashMap::new(),
+            types: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    /// A JSON value, e.g. an array of tags.
+    Json,
+}
+
+impl FromStr for CsvType {
+    type Err = FlapjackError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "string" => Ok(CsvType::String),
+            "integer" | "int" => Ok(CsvType::Integer),
+            "float" | "number" => Ok(CsvType::Float),
+            "boolean" | "bool" => Ok(CsvType::Boolean),
+            "json" => Ok(CsvType::Json),
+            _ => Err(FlapjackError::InvalidQuery(format!(
+                "Unknown CSV type '{}', expected string, integer, float, boolean or json",
+                s
+            ))),
+        }
+    }
+}
+
+/// Parse `reader` as `format`, calling `f` with each record in order.
+/// Returns early with the first read error or error of `f`.
+pub fn for_each_record<R: Read>(
+    reader: R,
+    format: &BulkFormat,
+    f: impl FnMut(Record) -> Result<()>,
+) -> Result<()> {
+    match format {
+        BulkFormat::Ndjson => for_each_ndjson(BufReader::new(reader), f),
+        BulkFormat::Csv(options) => for_each_csv(reader, options, f),
+    }
+}
+
+fn for_each_ndjson<R: BufRead>(
+    mut reader: R,
+    mut f: impl FnMut(Record) -> Result<()>,
+) -> Result<()> {
+    let mut line = String::new();
+    let mut number = 0;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = match serde_json::from_str::<serde_json::Value>(&line) {
+            Ok(value) => Document::from_json(&value),
+            Err(e) => Err(FlapjackError::InvalidDocument(format!(
+                "invalid JSON: {}",
+                e
+            ))),
+        };
+        let record = record.map_err(|e| failure(number, String::new(), &e));
+        f(record)?;
+    }
+}
+
+fn for_each_csv<R: Read>(
+    reader: R,
+    options: &CsvOptions,
+    mut f: impl FnMut(Record) -> Result<()>,
+) -> Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(options.delimiter)
+        .from_reader(reader);
+    let attributes: Vec<String> = reader
+        .headers()
+        .map_err(csv_error)?
+        .iter()
+        .map(|header| {
+            options
+                .mapping
+                .get(header)
+                .cloned()
+                .unwrap_or_else(|| header.to_string())
+        })
+        .collect();
+    if !attributes.iter().any(|a| a == "objectID") {
+        return Err(FlapjackError::InvalidDocument(
+            "CSV header has no objectID column; map one with the mapping parameter".to_string(),
+        ));
+    }
+
+    let mut record = csv::StringRecord::new();
+    loop {
+        let number = reader.position().line();
+        match reader.read_record(&mut record) {
+            Ok(false) => return Ok(()),
+            Ok(true) => f(csv_document(&attributes, &record, options)
+                .map_err(|(id, e)| failure(number, id, &e)))?,
+            Err(e) if e.is_io_error() => return Err(csv_error(e)),
+            Err(e) => f(Err(failure(
+                number,
+                String::new(),
+                &FlapjackError::InvalidDocument(e.to_string()),
+            )))?,
+        }
+    }
+}
+
+fn csv_document(
+    attributes: &[String],
+    record: &csv::StringRecord,
+    options: &CsvOptions,
+) -> std::result::Result<Document, (String, FlapjackError)> {
+    let object_id = attributes
+        .iter()
+        .position(|a| a == "objectID")
+        .and_then(|i| record.get(i))
+        .unwrap_or_default()
+        .to_string();
+    let mut object = serde_json::Map::new();
+    for (attribute, cell) in attributes.iter().zip(record.iter()) {
+        // An empty cell leaves the attribute out
+        if cell.is_empty() {
+            continue;
+        }
+        let kind = match attribute.as_str() {
+            "objectID" => CsvType::String,
+            _ => options
+                .types
+                .get(attribute)
+                .copied()
+                .unwrap_or(CsvType::String),
+        };
+        let value = cell_value(attribute, cell, kind).map_err(|e| (object_id.clone(), e))?;
+        object.insert(attribute.clone(), value);
+    }
+    Document::from_json(&serde_json::Value::Object(object)).map_err(|e| (object_id, e))
+}
+
+fn cell_value(attribute: &str, cell: &str, kind: CsvType) -> Result<serde_json::Value> {
+    let mismatch = |expected: &str| FlapjackError::TypeMismatch {
+        field: attribute.to_string(),
+        expected: expected.to_string(),
+        actual: format!("'{}'", cell),
+    };
+    // Strings are kept as is, other types may be padded
+    let trimmed = cell.trim();
+    match kind {
+        CsvType::String => Ok(serde_json::Value::String(cell.to_string())),
+        CsvType::Integer => trimmed
+            .parse::<i64>()
+            .map(Into::into)
+            .map_err(|_| mismatch("integer")),
+        CsvType::Float => trimmed
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(serde_json::Value::Number)
+            .ok_or_else(|| mismatch("float")),
+        CsvType::Boolean => match trimmed.to_ascii_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(true.into()),
+            "false" | "0" | "no" => Ok(false.into()),
+            _ => Err(mismatch("boolean")),
+        },
+        CsvType::Json => serde_json::from_str(trimmed).map_err(|_| mismatch("json")),
+    }
+}
+
+pub(crate) fn failure(line: u64, doc_id: String, e: &FlapjackError) -> DocFailure {
+    DocFailure {
+        doc_id,
+        error: classify_error(e),
+        message: format!("line {}: {}", line, e),
+    }
+}
+
+fn csv_error(e: csv::Error) -> FlapjackError {
+    let message = e.to_string();
+    match e.into_kind() {
+        csv::ErrorKind::Io(e) => e.into(),
+        _ => FlapjackError::InvalidDocument(format!("invalid CSV: {}", message)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str, format: &BulkFormat) -> Vec<Record> {
+        let mut records = Vec::new();
+        for_each_record(input.as_bytes(), format, |r| {
+            records.push(r);
+            Ok(())
+        })
+        .unwrap();
+        records
+    }
+
+    #[test]
+    fn test_ndjson() {
+        let records = parse(
+            "{\"objectID\":\"1\",\"title\":\"a\"}\n\nnot json\n{\"title\":\"b\"}\n",
+            &BulkFormat::Ndjson,
+        );
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].as_ref().unwrap().id, "1");
+        let rejected = records[1].as_ref().unwrap_err();
+        assert!(rejected.message.starts_with("line 3:"));
+        let rejected = records[2].as_ref().unwrap_err();
+        assert_eq!(rejected.error, "missing_field");
+        assert!(rejected.message.starts_with("line 4:"));
+    }
+
+    #[test]
+    fn test_csv_mapping_and_types() {
+        let options = CsvOptions {
+            mapping: HashMap::from([("sku".to_string(), "objectID".to_string())]),
+            types: HashMap::from([
+                ("price".to_string(), CsvType::Float),
+                ("stock".to_string(), CsvType::Integer),
+                ("tags".to_string(), CsvType::Json),
+            ]),
+            ..Default::default()
+        };
+        let records = parse(
+            "sku,title,price,stock,tags\n\
+             A1,lamp,9.5,3,\"[\"\"home\"\"]\"\n\
+             A2,desk,,x,\n",
+            &BulkFormat::Csv(options),
+        );
+        let lamp = records[0].as_ref().unwrap().to_json();
+        assert_eq!(lamp["price"], 9.5);
+        assert_eq!(lamp["stock"], 3);
+        assert_eq!(lamp["tags"][0], "home");
+        let rejected = records[1].as_ref().unwrap_err();
+        assert_eq!(rejected.doc_id, "A2");
+        assert_eq!(rejected.error, "type_mismatch");
+        assert!(rejected.message.starts_with("line 3:"));
+    }
+
+    #[test]
+    fn test_csv_without_object_id() {
+        let result = for_each_record(
+            "title\nlamp\n".as_bytes(),
+            &BulkFormat::Csv(CsvOptions::default()),
+            |_| Ok(()),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,404 @@
+//! Custom stop words, plurals and compounds (the dictionaries API).
+//!
+//! Entries are shared by every index and stored under `.dictionaries/` in the
+//! data dir, one JSON file per dictionary. They are merged with the bundled
+//! lists at query time: custom stop words are removed along with the standard
+//! ones, and custom plurals and compounds take precedence over the standard
+//! forms. `disableStandardEntries` turns the bundled lists off per language.
+
+use crate::error::{FlapjackError, Result};
+use crate::query::decompound::CustomCompounds;
+use crate::query::plurals::CustomPlurals;
+use crate::query::stopwords::CustomStopWords;
+use crate::tokenizer::diacritics::{fold, KeepDiacritics};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Directory of the dictionaries in the data dir. The leading dot keeps it
+/// out of the index listing; it also holds the dictionaries oplog.
+pub const DICTIONARIES_DIR: &str = ".dictionaries";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DictionaryName {
+    Stopwords,
+    Plurals,
+    Compounds,
+}
+
+impl DictionaryName {
+    pub const ALL: [DictionaryName; 3] = [
+        DictionaryName::Stopwords,
+        DictionaryName::Plurals,
+        DictionaryName::Compounds,
+    ];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "stopwords" => Some(DictionaryName::Stopwords),
+            "plurals" => Some(DictionaryName::Plurals),
+            "compounds" => Some(DictionaryName::Compounds),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DictionaryName::Stopwords => "stopwords",
+            DictionaryName::Plurals => "plurals",
+            DictionaryName::Compounds => "compounds",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryState {
+    #[default]
+    Enabled,
+    Disabled,
+}
+
+/// A custom dictionary entry. Stop words use `word`, plurals use `words`
+/// (all forms of one word) and compounds use `word` and `decomposition`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DictionaryEntry {
+    #[serde(rename = "objectID")]
+    pub object_id: String,
+    pub language: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub word: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub words: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub decomposition: Option<Vec<String>>,
+    #[serde(default)]
+    pub state: EntryState,
+}
+
+impl DictionaryEntry {
+    /// Check that the entry has the fields `dictionary` needs.
+    pub fn validate(&self, dictionary: DictionaryName) -> Result<()> {
+        let invalid = |msg: &str| {
+            Err(FlapjackError::InvalidDocument(format!(
+                "{} entry {}: {}",
+                dictionary.as_str(),
+                self.object_id,
+                msg
+            )))
+        };
+        if self.object_id.is_empty() {
+            return invalid("objectID is required");
+        }
+        if self.language.is_empty() {
+            return invalid("language is required");
+        }
+        let has_word = self.word.as_ref().is_some_and(|w| !w.trim().is_empty());
+        let count = |list: &Option<Vec<String>>| list.as_ref().map_or(0, Vec::len);
+        match dictionary {
+            DictionaryName::Stopwords if !has_word => invalid("word is required"),
+            DictionaryName::Plurals if count(&self.words) < 2 => {
+                invalid("words needs at least two forms")
+            }
+            DictionaryName::Compounds if !has_word => invalid("word is required"),
+            DictionaryName::Compounds if count(&self.decomposition) < 2 => {
+                invalid("decomposition needs at least two parts")
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether any word of the entry contains `query`, both folded.
+    fn matches(&self, query: &str) -> bool {
+        let keep = KeepDiacritics::default();
+        self.word
+            .iter()
+            .chain(self.words.iter().flatten())
+            .chain(self.decomposition.iter().flatten())
+            .any(|w| fold(w, &keep).contains(query))
+    }
+}
+
+/// An operation of a `/1/dictionaries/{dictionaryName}/batch` request.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", content = "body", rename_all = "camelCase")]
+pub enum DictionaryBatchRequest {
+    AddEntry(DictionaryEntry),
+    DeleteEntry {
+        #[serde(rename = "objectID")]
+        object_id: String,
+    },
+}
+
+/// Languages whose bundled entries are ignored, per dictionary.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DisableStandardEntries {
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub stopwords: BTreeMap<String, bool>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub plurals: BTreeMap<String, bool>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub compounds: BTreeMap<String, bool>,
+}
+
+impl DisableStandardEntries {
+    fn languages(&self, dictionary: DictionaryName) -> impl Iterator<Item = &String> {
+        let map = match dictionary {
+            DictionaryName::Stopwords => &self.stopwords,
+            DictionaryName::Plurals => &self.plurals,
+            DictionaryName::Compounds => &self.compounds,
+        };
+        map.iter()
+            .filter(|(_, disabled)| **disabled)
+            .map(|(lang, _)| lang)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DictionarySettings {
+    #[serde(default)]
+    pub disable_standard_entries: DisableStandardEntries,
+}
+
+/// The custom entries in the form the query pipeline uses, keyed by language.
+#[derive(Debug, Default)]
+pub struct CustomDictionaries {
+    pub stop_words: HashMap<String, CustomStopWords>,
+    pub plurals: HashMap<String, CustomPlurals>,
+    pub compounds: HashMap<String, CustomCompounds>,
+}
+
+impl CustomDictionaries {
+    fn compile(
+        entries: &HashMap<DictionaryName, BTreeMap<String, DictionaryEntry>>,
+        settings: &DictionarySettings,
+    ) -> Self {
+        let keep = KeepDiacritics::default();
+        let mut custom = CustomDictionaries::default();
+        let entries_of = |dictionary| {
+            entries
+                .get(&dictionary)
+                .into_iter()
+                .flat_map(BTreeMap::values)
+        };
+
+        for entry in entries_of(DictionaryName::Stopwords) {
+            let Some(word) = &entry.word else { continue };
+            let lang = custom.stop_words.entry(entry.language.clone()).or_default();
+            match entry.state {
+                EntryState::Enabled => lang.added.insert(fold(word, &keep)),
+                EntryState::Disabled => lang.disabled.insert(fold(word, &keep)),
+            };
+        }
+        for entry in entries_of(DictionaryName::Plurals) {
+            let Some(words) = &entry.words else { continue };
+            if entry.state == EntryState::Disabled {
+                continue;
+            }
+            let forms: Vec<String> = words.iter().map(|w| fold(w, &keep)).collect();
+            let lang = custom.plurals.entry(entry.language.clone()).or_default();
+            for form in &forms {
+                lang.forms.insert(form.clone(), forms.clone());
+            }
+        }
+        for entry in entries_of(DictionaryName::Compounds) {
+            let (Some(word), Some(parts)) = (&entry.word, &entry.decomposition) else {
+                continue;
+            };
+            if entry.state == EntryState::Disabled {
+                continue;
+            }
+            let lang = custom.compounds.entry(entry.language.clone()).or_default();
+            let parts = parts.iter().map(|p| p.to_lowercase()).collect();
+            lang.decompositions.insert(fold(word, &keep), parts);
+        }
+
+        let disabled = &settings.disable_standard_entries;
+        for lang in disabled.languages(DictionaryName::Stopwords) {
+            custom
+                .stop_words
+                .entry(lang.clone())
+                .or_default()
+                .disable_standard = true;
+        }
+        for lang in disabled.languages(DictionaryName::Plurals) {
+            custom
+                .plurals
+                .entry(lang.clone())
+                .or_default()
+                .disable_standard = true;
+        }
+        for lang in disabled.languages(DictionaryName::Compounds) {
+            custom
+                .compounds
+                .entry(lang.clone())
+                .or_default()
+                .disable_standard = true;
+        }
+        custom
+    }
+}
+
+/// File-backed store of the custom dictionaries and their settings.
+pub struct DictionaryStore {
+    dir: PathBuf,
+    entries: RwLock<HashMap<DictionaryName, BTreeMap<String, DictionaryEntry>>>,
+    settings: RwLock<DictionarySettings>,
+    compiled: RwLock<Arc<CustomDictionaries>>,
+}
+
+impl DictionaryStore {
+    /// Load the dictionaries from `dir`; missing or unreadable files are empty.
+    pub fn load(dir: &Path) -> Self {
+        let mut entries = HashMap::new();
+        for dictionary in DictionaryName::ALL {
+            let path = dir.join(format!("{}.json", dictionary.as_str()));
+            let list: Vec<DictionaryEntry> = read_json(&path).unwrap_or_default();
+            entries.insert(
+                dictionary,
+                list.into_iter().map(|e| (e.object_id.clone(), e)).collect(),
+            );
+        }
+        let settings: DictionarySettings =
+            read_json(&dir.join("settings.json")).unwrap_or_default();
+        let compiled = Arc::new(CustomDictionaries::compile(&entries, &settings));
+        DictionaryStore {
+            dir: dir.to_path_buf(),
+            entries: RwLock::new(entries),
+            settings: RwLock::new(settings),
+            compiled: RwLock::new(compiled),
+        }
+    }
+
+    /// The custom entries merged into queries.
+    pub fn custom(&self) -> Arc<CustomDictionaries> {
+        Arc::clone(&self.compiled.read().unwrap())
+    }
+
+    pub fn settings(&self) -> DictionarySettings {
+        self.settings.read().unwrap().clone()
+    }
+
+    pub fn set_settings(&self, settings: DictionarySettings) -> Result<()> {
+        write_json(&self.dir.join("settings.json"), &settings)?;
+        *self.settings.write().unwrap() = settings;
+        self.recompile();
+        Ok(())
+    }
+
+    /// Apply a batch of additions and deletions to `dictionary`, after
+    /// removing every entry when `clear_existing` is set. Nothing is changed
+    /// if an entry is invalid.
+    pub fn batch(
+        &self,
+        dictionary: DictionaryName,
+        clear_existing: bool,
+        requests: &[DictionaryBatchRequest],
+    ) -> Result<()> {
+        for request in requests {
+            if let DictionaryBatchRequest::AddEntry(entry) = request {
+                entry.validate(dictionary)?;
+            }
+        }
+        {
+            let mut entries = self.entries.write().unwrap();
+            let dict = entries.entry(dictionary).or_default();
+            if clear_existing {
+                dict.clear();
+            }
+            for request in requests {
+                match request {
+                    DictionaryBatchRequest::AddEntry(entry) => {
+                        dict.insert(entry.object_id.clone(), entry.clone());
+                    }
+                    DictionaryBatchRequest::DeleteEntry { object_id } => {
+                        dict.remove(object_id);
+                    }
+                }
+            }
+            let list: Vec<&DictionaryEntry> = dict.values().collect();
+            write_json(
+                &self.dir.join(format!("{}.json", dictionary.as_str())),
+                &list,
+            )?;
+        }
+        self.recompile();
+        Ok(())
+    }
+
+    /// Entries of `dictionary` with a word containing `query`, optionally in
+    /// one `language`, ordered by objectID.
+    pub fn search(
+        &self,
+        dictionary: DictionaryName,
+        query: &str,
+        language: Option<&str>,
+    ) -> Vec<DictionaryEntry> {
+        let query = fold(query.trim(), &KeepDiacritics::default());
+        let entries = self.entries.read().unwrap();
+        entries
+            .get(&dictionary)
+            .into_iter()
+            .flat_map(BTreeMap::values)
+            .filter(|e| language.map(|lang| e.language == lang).unwrap_or(true))
+            .filter(|e| query.is_empty() || e.matches(&query))
+            .cloned()
+            .collect()
+    }
+
+    /// Replay a dictionaries oplog entry, as written by the HTTP handlers.
+    pub fn apply_op(&self, op_type: &str, payload: &serde_json::Value) -> Result<()> {
+        match op_type {
+            "dictionary_batch" => {
+                let name = payload
+                    .get("dictionary")
+                    .and_then(|v| v.as_str())
+                    .and_then(DictionaryName::parse)
+                    .ok_or_else(|| FlapjackError::InvalidDocument("unknown dictionary".into()))?;
+                let clear_existing = payload
+                    .get("clearExistingDictionaryEntries")
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false);
+                let requests: Vec<DictionaryBatchRequest> =
+                    serde_json::from_value(payload.get("requests").cloned().unwrap_or_default())?;
+                self.batch(name, clear_existing, &requests)
+            }
+            "dictionary_settings" => self.set_settings(serde_json::from_value(payload.clone())?),
+            _ => Err(FlapjackError::InvalidDocument(format!(
+                "unknown dictionaries op {}",
+                op_type
+            ))),
+        }
+    }
+
+    fn recompile(&self) {
+        let compiled = {
+            let entries = self.entries.read().unwrap();
+            let settings = self.settings.read().unwrap();
+            CustomDictionaries::compile(&entries, &settings)
+        };
+        *self.compiled.write().unwrap() = Arc::new(compiled);
+    }
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(value) => Some(value),
+        Err(e) => {
+            tracing::warn!("Failed to parse {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, serde_json::to_string_pretty(value)?)?;
+    Ok(())
+}
@@ -1,7 +1,10 @@
 use crate::error::{FlapjackError, Result};
+use crate::index::expiry;
 use crate::index::facet_translation::{extract_facet_paths, is_hierarchical_facet};
 use crate::index::schema::Schema;
 use crate::index::settings::IndexSettings;
+use crate::index::vectors::VECTORS_ATTRIBUTE;
+use crate::tokenizer::camel_case::CAMEL_CASE_MARKER;
 use crate::types::{Document, DocumentId, FieldValue};
 use serde_json::{Map, Value};
 use std::collections::BTreeMap;
@@ -15,6 +18,7 @@ pub fn json_to_tantivy_doc(
     json_filter_field: Field,
     json_exact_field: Field,
     facets_field: Field,
+    vectors_field: Option<Field>,
 ) -> Result<TantivyDocument> {
     let mut tantivy_doc = TantivyDocument::new();
 
@@ -36,6 +40,14 @@ pub fn json_to_tantivy_doc(
         if key == "_id" || key == "objectID" {
             continue;
         }
+        if let (Some(field), VECTORS_ATTRIBUTE, Value::Array(values)) =
+            (vectors_field, key.as_str(), val)
+        {
+            for value in values.iter().filter_map(Value::as_f64) {
+                tantivy_doc.add_f64(field, value);
+            }
+            continue;
+        }
         json_fields.insert(key.clone(), val.clone());
     }
 
@@ -74,6 +86,9 @@ pub struct DocumentConverter {
     facets_field: Field,
     geo_lat_field: Option<Field>,
     geo_lng_field: Option<Field>,
+    /// Absent from indexes built before schema version 3, which keep the
+    /// `_vectors` in the filter field until they are migrated.
+    vectors_field: Option<Field>,
 }
 
 impl DocumentConverter {
@@ -95,6 +110,7 @@ impl DocumentConverter {
             .map_err(|_| FlapjackError::FieldNotFound("_facets".to_string()))?;
         let geo_lat_field = tantivy_schema.get_field("_geo_lat").ok();
         let geo_lng_field = tantivy_schema.get_field("_geo_lng").ok();
+        let vectors_field = tantivy_schema.get_field("_vectors").ok();
 
         Ok(DocumentConverter {
             id_field,
@@ -104,6 +120,7 @@ impl DocumentConverter {
             facets_field,
             geo_lat_field,
             geo_lng_field,
+            vectors_field,
         })
     }
 
@@ -134,9 +151,26 @@ impl DocumentConverter {
             }
         }
 
-        let (search_json, mut filter_json) = split_by_type(&json_fields);
+        // Embeddings are only stored: indexing every dimension as a number
+        // would bloat the filter field and match numeric filters
+        if let (Some(field), Value::Object(ref mut map)) = (self.vectors_field, &mut json_fields) {
+            if let Some(Value::Array(values)) = map.remove(VECTORS_ATTRIBUTE) {
+                for value in values.iter().filter_map(Value::as_f64) {
+                    tantivy_doc.add_f64(field, value);
+                }
+            }
+        }
+
+        let (mut search_json, mut filter_json) = split_by_type(&json_fields);
         if let Value::Object(ref mut filter_map) = filter_json {
             filter_map.insert("objectID".to_string(), Value::String(doc.id.clone()));
+            if let Some(at) = expiry::expires_at(doc, settings) {
+                filter_map.insert(expiry::EXPIRES_AT.to_string(), Value::from(at));
+            }
+        }
+        if let Some(s) = settings {
+            append_compound_parts(&mut search_json, &s.decompounded_attributes);
+            mark_camel_case_attributes(&mut search_json, &s.camel_case_attributes);
         }
 
         tantivy_doc.add_object(self.json_search_field, json_to_btree(&search_json)?);
@@ -213,7 +247,23 @@ impl DocumentConverter {
             .ok_or_else(|| FlapjackError::MissingField("_json_filter".to_string()))?;
 
         let owned: OwnedValue = json_value.into();
-        let fields = owned_value_to_fields(&owned)?;
+        let mut fields = owned_value_to_fields(&owned)?;
+
+        if let Some(field) = self.vectors_field {
+            let vector: Vec<FieldValue> = tantivy_doc
+                .get_all(field)
+                .filter_map(|v| {
+                    let owned: OwnedValue = v.into();
+                    match owned {
+                        OwnedValue::F64(f) => Some(FieldValue::Float(f)),
+                        _ => None,
+                    }
+                })
+                .collect();
+            if !vector.is_empty() {
+                fields.insert(VECTORS_ATTRIBUTE.to_string(), FieldValue::Array(vector));
+            }
+        }
 
         Ok(Document { id: doc_id, fields })
     }
@@ -453,6 +503,60 @@ fn extract_geoloc(value: &Value) -> Option<(f64, f64)> {
     }
 }
 
+/// Append the parts of compound words to the searchable text of each
+/// `decompoundedAttributes` attribute, so "Kinderschuhe" also indexes
+/// "kinder" and "schuhe". Only the indexed copy is changed.
+fn append_compound_parts(search_json: &mut Value, attributes: &BTreeMap<String, Vec<String>>) {
+    for (lang, attrs) in attributes {
+        for attr in attrs {
+            append_parts(search_json, attr, |text| {
+                crate::query::decompound::compound_parts(text, lang)
+            });
+        }
+    }
+}
+
+/// Prefix the searchable text of each `camelCaseAttributes` attribute with
+/// [`CAMEL_CASE_MARKER`], so the analyzers also index "getUserProfile" as
+/// "get", "user" and "profile". Only the indexed copy is changed.
+fn mark_camel_case_attributes(search_json: &mut Value, attributes: &[String]) {
+    for attr in attributes {
+        let mut value = Some(&mut *search_json);
+        for key in attr.split('.') {
+            value = value.and_then(|v| v.get_mut(key));
+        }
+        match value {
+            Some(Value::String(text)) => text.insert(0, CAMEL_CASE_MARKER),
+            Some(Value::Array(items)) => {
+                for item in items {
+                    if let Value::String(text) = item {
+                        text.insert(0, CAMEL_CASE_MARKER);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Append `parts_of` the text at the dotted path `attr`, if it is a string.
+fn append_parts<F>(search_json: &mut Value, attr: &str, parts_of: F)
+where
+    F: for<'a> Fn(&'a str) -> Vec<&'a str>,
+{
+    let mut value = Some(search_json);
+    for key in attr.split('.') {
+        value = value.and_then(|v| v.get_mut(key));
+    }
+    if let Some(Value::String(text)) = value {
+        let parts = parts_of(text).join(" ");
+        if !parts.is_empty() {
+            text.push(' ');
+            text.push_str(&parts);
+        }
+    }
+}
+
 fn split_by_type(value: &Value) -> (Value, Value) {
     match value {
         Value::Object(map) => {
@@ -0,0 +1,213 @@
+//! Logical index dumps.
+//!
+//! A dump is NDJSON: a header line, then the settings, synonyms, rules and
+//! API key metadata of an index, then one line per document in the form of
+//! [`Document::to_json`]. Unlike a snapshot it does not depend on the on-disk
+//! format, so it can be imported through the write queue into an index of any
+//! later version, e.g. to upgrade across a change of the internal schema.
+//! Entries of types unknown to this version are skipped.
+
+use crate::error::{FlapjackError, Result};
+use crate::index::bulk;
+use crate::index::rules::Rule;
+use crate::index::settings::IndexSettings;
+use crate::index::synonyms::Synonym;
+use crate::types::{DocFailure, Document, TaskInfo};
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// `format` of the header line of a dump.
+pub const DUMP_FORMAT: &str = "flapjack-dump";
+
+/// Latest dump version; dumps of later versions are refused.
+pub const DUMP_VERSION: u32 = 1;
+
+/// A line of a dump.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum DumpEntry {
+    #[serde(rename_all = "camelCase")]
+    Header {
+        format: String,
+        version: u32,
+        index: String,
+        flapjack_version: String,
+        created_at: String,
+    },
+    Settings {
+        settings: IndexSettings,
+    },
+    Synonym {
+        synonym: Synonym,
+    },
+    Rule {
+        rule: Rule,
+    },
+    /// API key metadata, without the key value.
+    Key {
+        key: serde_json::Value,
+    },
+    Document {
+        #[serde(with = "flat_document")]
+        document: Document,
+    },
+    #[serde(other)]
+    Unknown,
+}
+
+impl DumpEntry {
+    pub fn header(index: &str) -> Self {
+        DumpEntry::Header {
+            format: DUMP_FORMAT.to_string(),
+            version: DUMP_VERSION,
+            index: index.to_string(),
+            flapjack_version: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+}
+
+/// What an imported dump held besides its documents.
+#[derive(Debug, Clone)]
+pub struct DumpImport {
+    /// The task indexing the documents.
+    pub task: TaskInfo,
+    pub settings: bool,
+    pub synonyms: usize,
+    pub rules: usize,
+    /// API key metadata, for the caller to recreate keys from.
+    pub keys: Vec<serde_json::Value>,
+}
+
+/// Write `entry` as a line of a dump.
+pub fn write_entry(writer: &mut impl Write, entry: &DumpEntry) -> Result<()> {
+    serde_json::to_writer(&mut *writer, entry)?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// Parse the dump in `reader`, calling `f` with each entry after the header.
+/// A line that does not parse is passed as a [`DocFailure`] with its line
+/// number. Returns early with a read error, an error of `f`, or when the dump
+/// does not start with a header of a supported version.
+pub fn for_each_entry<R: Read>(
+    reader: R,
+    mut f: impl FnMut(std::result::Result<DumpEntry, DocFailure>) -> Result<()>,
+) -> Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    let mut number = 0;
+    let mut header = false;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return if header { Ok(()) } else { Err(not_a_dump()) };
+        }
+        number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry = serde_json::from_str::<DumpEntry>(&line);
+        if !header {
+            match entry {
+                Ok(DumpEntry::Header {
+                    format, version, ..
+                }) if format == DUMP_FORMAT => {
+                    if version > DUMP_VERSION {
+                        return Err(FlapjackError::InvalidDocument(format!(
+                            "Dump version {} is newer than the supported version {}",
+                            version, DUMP_VERSION
+                        )));
+                    }
+                    header = true;
+                    continue;
+                }
+                _ => return Err(not_a_dump()),
+            }
+        }
+        f(entry.map_err(|e| {
+            bulk::failure(
+                number,
+                String::new(),
+                &FlapjackError::InvalidDocument(e.to_string()),
+            )
+        }))?;
+    }
+}
+
+fn not_a_dump() -> FlapjackError {
+    FlapjackError::InvalidDocument(format!(
+        "Not a dump: the first line must be a {} header",
+        DUMP_FORMAT
+    ))
+}
+
+/// Documents are stored in the form of [`Document::to_json`].
+mod flat_document {
+    use crate::types::Document;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(doc: &Document, serializer: S) -> Result<S::Ok, S::Error> {
+        doc.to_json().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Document, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Document::from_json(&value).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_round_trip() {
+        let doc =
+            Document::from_json(&json!({"objectID": "1", "title": "lamp", "price": 9})).unwrap();
+        let mut out = Vec::new();
+        write_entry(&mut out, &DumpEntry::header("products")).unwrap();
+        write_entry(
+            &mut out,
+            &DumpEntry::Settings {
+                settings: IndexSettings::default(),
+            },
+        )
+        .unwrap();
+        write_entry(&mut out, &DumpEntry::Document { document: doc }).unwrap();
+        out.extend_from_slice(b"{\"type\":\"futureThing\",\"x\":1}\n{\"type\":\"document\"}\n");
+
+        let mut entries = Vec::new();
+        for_each_entry(out.as_slice(), |entry| {
+            entries.push(entry);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(matches!(entries[0], Ok(DumpEntry::Settings { .. })));
+        match &entries[1] {
+            Ok(DumpEntry::Document { document }) => {
+                assert_eq!(document.id, "1");
+                assert_eq!(document.to_json()["price"], 9);
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        assert!(matches!(entries[2], Ok(DumpEntry::Unknown)));
+        assert!(entries[3]
+            .as_ref()
+            .unwrap_err()
+            .message
+            .starts_with("line 5:"));
+    }
+
+    #[test]
+    fn test_rejects_other_input() {
+        let ok = |_| Ok(());
+        assert!(for_each_entry("{\"objectID\":\"1\"}\n".as_bytes(), ok).is_err());
+        assert!(for_each_entry("".as_bytes(), ok).is_err());
+        let newer = json!({"type": "header", "format": DUMP_FORMAT, "version": DUMP_VERSION + 1,
+            "index": "a", "flapjackVersion": "9", "createdAt": ""});
+        assert!(for_each_entry(format!("{}\n", newer).as_bytes(), ok).is_err());
+    }
+}
@@ -0,0 +1,1007 @@
+//! Encryption at rest.
+//!
+//! With a master key configured, tantivy files (through
+//! [`EncryptedDirectory`]), oplog records, snapshot archives and the API
+//! keys file are encrypted with AES-256-GCM. Every stream is sealed with its
+//! own key, derived from the master key and a random salt with HKDF-SHA256,
+//! so that the number of streams sealed under one master key is not bounded
+//! by the birthday limit of random GCM nonces. Data is sealed in chunks of up
+//! to 64 KiB, each with a nonce made of the chunk counter and a last-chunk
+//! flag, so that reordered, truncated or extended streams fail to decrypt:
+//!
+//! ```text
+//! stream := "FJCRYPT1" key_id[8] salt[16] chunk*
+//! chunk  := len:u32be (high bit set on the last chunk) ciphertext[len]
+//! ```
+//!
+//! The key id is derived from the key, so that data sealed before a key
+//! rotation is decrypted with the key that sealed it. The first key of the
+//! keyring encrypts and all of them decrypt. Files written before encryption
+//! was enabled are still read as plaintext.
+
+use crate::error::{FlapjackError, Result};
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+use std::sync::{Arc, Mutex, OnceLock};
+use tantivy::directory::error::{DeleteError, LockError, OpenReadError, OpenWriteError};
+use tantivy::directory::{
+    AntiCallToken, Directory, DirectoryLock, FileHandle, Lock, MmapDirectory, OwnedBytes,
+    TerminatingWrite, WatchCallback, WatchHandle, WritePtr,
+};
+use tantivy::{HasLen, Index as TantivyIndex, IndexSettings, TantivyError};
+
+const MAGIC: &[u8; 8] = b"FJCRYPT1";
+const KEY_ID_LEN: usize = 8;
+const SALT_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + KEY_ID_LEN + SALT_LEN;
+/// HKDF `info` of the per-stream keys.
+const STREAM_KEY_INFO: &[u8] = b"flapjack stream key";
+const CHUNK_LEN: usize = 64 * 1024;
+const TAG_LEN: usize = 16;
+const LAST_CHUNK: u32 = 1 << 31;
+/// Bytes of a full chunk on disk: its length and its sealed plaintext.
+const CHUNK_RECORD_LEN: usize = 4 + CHUNK_LEN + TAG_LEN;
+/// Decrypted chunks kept per open index file.
+const CACHED_CHUNKS: usize = 4;
+
+static KEYRING: OnceLock<std::result::Result<Option<Arc<Keyring>>, String>> = OnceLock::new();
+
+/// The keyring from the environment, loaded on first use; `None` when
+/// encryption at rest is off.
+///
+/// An invalid key configuration fails every call rather than falling back
+/// to plaintext; the server calls this at startup to refuse to start.
+pub fn keyring() -> Result<Option<Arc<Keyring>>> {
+    KEYRING
+        .get_or_init(|| match Keyring::from_env() {
+            Ok(keyring) => Ok(keyring.map(Arc::new)),
+            Err(FlapjackError::Config(message)) => Err(message),
+            Err(e) => Err(e.to_string()),
+        })
+        .clone()
+        .map_err(FlapjackError::Config)
+}
+
+/// Use `keyring` instead of the one from the environment. Returns false if
+/// a keyring was already loaded.
+pub fn init(keyring: Keyring) -> bool {
+    KEYRING.set(Ok(Some(Arc::new(keyring)))).is_ok()
+}
+
+struct MasterKey {
+    id: [u8; KEY_ID_LEN],
+    key: [u8; 32],
+}
+
+impl MasterKey {
+    /// The cipher of the stream whose header holds `salt`: HKDF-SHA256 of the
+    /// master key, expanded to a single 32-byte block.
+    fn stream_cipher(&self, salt: &[u8]) -> Aes256Gcm {
+        type HmacSha256 = Hmac<Sha256>;
+        let prk = <HmacSha256 as Mac>::new_from_slice(salt)
+            .expect("HMAC accepts any key length")
+            .chain_update(self.key)
+            .finalize()
+            .into_bytes();
+        let okm = <HmacSha256 as Mac>::new_from_slice(&prk)
+            .expect("HMAC accepts any key length")
+            .chain_update(STREAM_KEY_INFO)
+            .chain_update([1u8])
+            .finalize()
+            .into_bytes();
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&okm))
+    }
+}
+
+/// Master keys: the first one encrypts, all of them decrypt.
+pub struct Keyring {
+    keys: Vec<MasterKey>,
+}
+
+impl fmt::Debug for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.keys.iter().map(|k| hex::encode(k.id)))
+            .finish()
+    }
+}
+
+impl Keyring {
+    /// A keyring encrypting with the first of `keys`.
+    pub fn new(keys: &[[u8; 32]]) -> Result<Self> {
+        if keys.is_empty() {
+            return Err(FlapjackError::Config("No master key given".to_string()));
+        }
+        Ok(Keyring {
+            keys: keys
+                .iter()
+                .map(|key| MasterKey {
+                    id: key_id(key),
+                    key: *key,
+                })
+                .collect(),
+        })
+    }
+
+    /// Keys from the file at `FLAPJACK_MASTER_KEY_FILE`, or the single key in
+    /// `FLAPJACK_MASTER_KEY`.
+    pub fn from_env() -> Result<Option<Self>> {
+        let text = match (
+            std::env::var("FLAPJACK_MASTER_KEY_FILE"),
+            std::env::var("FLAPJACK_MASTER_KEY"),
+        ) {
+            (Ok(path), _) => fs::read_to_string(&path).map_err(|e| {
+                FlapjackError::Config(format!("FLAPJACK_MASTER_KEY_FILE {}: {}", path, e))
+            })?,
+            (Err(_), Ok(key)) => key,
+            _ => return Ok(None),
+        };
+        Self::parse(&text).map(Some)
+    }
+
+    /// Parse one 32-byte key per line, hex or base64 encoded, newest first.
+    /// Blank lines and `#` comments are skipped.
+    pub fn parse(text: &str) -> Result<Self> {
+        let keys = text
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                hex::decode(line)
+                    .ok()
+                    .or_else(|| BASE64.decode(line).ok())
+                    .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+                    .ok_or_else(|| {
+                        FlapjackError::Config(
+                            "A master key must be 32 bytes, hex or base64 encoded".to_string(),
+                        )
+                    })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Self::new(&keys)
+    }
+
+    /// Id of the key new data is encrypted with, hex encoded.
+    pub fn current_key_id(&self) -> String {
+        hex::encode(self.keys[0].id)
+    }
+
+    /// Whether `data` starts a stream encrypted with the current key.
+    pub fn is_current(&self, data: &[u8]) -> bool {
+        is_encrypted(data) && data[MAGIC.len()..MAGIC.len() + KEY_ID_LEN] == self.keys[0].id
+    }
+
+    /// Whether `data` starts a stream encrypted with a key of the keyring.
+    pub fn has_key_for(&self, data: &[u8]) -> bool {
+        is_encrypted(data)
+            && self
+                .keys
+                .iter()
+                .any(|k| data[MAGIC.len()..MAGIC.len() + KEY_ID_LEN] == k.id)
+    }
+
+    /// Start a stream into `writer` encrypted with the current key.
+    pub fn encryptor<W: Write>(&self, mut writer: W) -> io::Result<Encryptor<W>> {
+        let key = &self.keys[0];
+        let mut header = [0u8; HEADER_LEN];
+        header[..MAGIC.len()].copy_from_slice(MAGIC);
+        header[MAGIC.len()..MAGIC.len() + KEY_ID_LEN].copy_from_slice(&key.id);
+        header[MAGIC.len() + KEY_ID_LEN..].copy_from_slice(&rand::random::<[u8; SALT_LEN]>());
+        writer.write_all(&header)?;
+        Ok(Encryptor {
+            writer,
+            cipher: key.stream_cipher(&header[MAGIC.len() + KEY_ID_LEN..]),
+            header,
+            counter: 0,
+            buffer: Vec::with_capacity(CHUNK_LEN),
+        })
+    }
+
+    /// The cipher a stream with `header` was encrypted with.
+    fn cipher_for(&self, header: &[u8; HEADER_LEN]) -> io::Result<Aes256Gcm> {
+        if !header.starts_with(MAGIC) {
+            return Err(invalid_data("not an encrypted stream"));
+        }
+        let id = &header[MAGIC.len()..MAGIC.len() + KEY_ID_LEN];
+        let key = self.keys.iter().find(|k| k.id == id).ok_or_else(|| {
+            invalid_data(format!(
+                "encrypted with key {}, which is not in the keyring",
+                hex::encode(id)
+            ))
+        })?;
+        Ok(key.stream_cipher(&header[MAGIC.len() + KEY_ID_LEN..]))
+    }
+
+    /// Read the stream header from `reader` and decrypt what follows.
+    pub fn decryptor<R: Read>(&self, mut reader: R) -> io::Result<Decryptor<R>> {
+        let mut header = [0u8; HEADER_LEN];
+        read_exact_or_truncated(&mut reader, &mut header)?;
+        Ok(Decryptor {
+            reader,
+            cipher: self.cipher_for(&header)?,
+            header,
+            counter: 0,
+            plaintext: Vec::new(),
+            pos: 0,
+            done: false,
+        })
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> Vec<u8> {
+        let mut encryptor = self
+            .encryptor(Vec::with_capacity(plaintext.len() + HEADER_LEN + 32))
+            .expect("writing to a Vec");
+        encryptor.write_all(plaintext).expect("writing to a Vec");
+        encryptor.finish().expect("writing to a Vec")
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut plaintext = Vec::with_capacity(data.len());
+        self.decryptor(data)?.read_to_end(&mut plaintext)?;
+        Ok(plaintext)
+    }
+}
+
+fn key_id(key: &[u8; 32]) -> [u8; KEY_ID_LEN] {
+    let digest = Sha256::new()
+        .chain_update(b"flapjack master key id")
+        .chain_update(key)
+        .finalize();
+    let mut id = [0u8; KEY_ID_LEN];
+    id.copy_from_slice(&digest[..KEY_ID_LEN]);
+    id
+}
+
+/// Stream keys are never reused, so the nonce only has to tell the chunks
+/// of a stream apart.
+fn chunk_nonce(counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..4].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn read_exact_or_truncated<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<()> {
+    reader.read_exact(buf).map_err(|e| match e.kind() {
+        io::ErrorKind::UnexpectedEof => io::Error::new(e.kind(), "encrypted stream is truncated"),
+        _ => e,
+    })
+}
+
+/// Fill as much of `buf` as `reader` has; returns the number of bytes read.
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+/// Whether `data` starts with an encrypted stream header.
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN && data.starts_with(MAGIC)
+}
+
+/// Whether the file at `path` is encrypted.
+pub fn is_encrypted_file(path: &Path) -> Result<bool> {
+    let mut head = [0u8; HEADER_LEN];
+    let n = read_up_to(&mut File::open(path)?, &mut head)?;
+    Ok(is_encrypted(&head[..n]))
+}
+
+/// `data` decrypted with `keyring` if it is encrypted, as it is otherwise.
+pub fn decrypt_if_encrypted(data: &[u8], keyring: Option<&Keyring>) -> Result<Vec<u8>> {
+    if !is_encrypted(data) {
+        return Ok(data.to_vec());
+    }
+    let keyring = keyring.ok_or_else(not_configured)?;
+    Ok(keyring.decrypt(data)?)
+}
+
+fn not_configured() -> FlapjackError {
+    FlapjackError::Config("Data is encrypted but no master key is configured".to_string())
+}
+
+/// Read the file at `path`, decrypting it if it is encrypted.
+pub fn read_file(path: &Path) -> Result<Vec<u8>> {
+    decrypt_if_encrypted(&fs::read(path)?, keyring()?.as_deref())
+}
+
+/// Write `data` to `path`, encrypted when a master key is configured.
+pub fn write_file(path: &Path, data: &[u8]) -> Result<()> {
+    match keyring()? {
+        Some(keyring) => fs::write(path, keyring.encrypt(data))?,
+        None => fs::write(path, data)?,
+    }
+    Ok(())
+}
+
+/// Decrypt an encrypted file in place.
+pub fn decrypt_file(path: &Path, keyring: &Keyring) -> Result<()> {
+    let plaintext = keyring.decrypt(&fs::read(path)?)?;
+    fs::write(path, plaintext)?;
+    Ok(())
+}
+
+/// Streams the chunks sealed with the current key into a writer. Call
+/// [`Encryptor::finish`] to seal the last chunk; a stream without it fails
+/// to decrypt.
+pub struct Encryptor<W: Write> {
+    writer: W,
+    cipher: Aes256Gcm,
+    header: [u8; HEADER_LEN],
+    counter: u32,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> Encryptor<W> {
+    fn seal_chunk(&mut self, last: bool) -> io::Result<()> {
+        let nonce = chunk_nonce(self.counter, last);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &self.buffer,
+                    aad: &self.header,
+                },
+            )
+            .map_err(|_| invalid_data("encryption failed"))?;
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .filter(|counter| *counter < LAST_CHUNK)
+            .ok_or_else(|| invalid_data("too much data for one encrypted stream"))?;
+        let len = ciphertext.len() as u32 | if last { LAST_CHUNK } else { 0 };
+        self.writer.write_all(&len.to_be_bytes())?;
+        self.writer.write_all(&ciphertext)?;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Seal the last chunk and return the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.seal_chunk(true)?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> Write for Encryptor<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full chunk is sealed once more data follows, since the last one
+        // is only known on finish
+        if self.buffer.len() == CHUNK_LEN && !buf.is_empty() {
+            self.seal_chunk(false)?;
+        }
+        let n = buf.len().min(CHUNK_LEN - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads a stream written by an [`Encryptor`], failing on tampered,
+/// truncated or extended data.
+pub struct Decryptor<R: Read> {
+    reader: R,
+    cipher: Aes256Gcm,
+    header: [u8; HEADER_LEN],
+    counter: u32,
+    plaintext: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R: Read> Decryptor<R> {
+    fn open_chunk(&mut self) -> io::Result<()> {
+        let mut len = [0u8; 4];
+        read_exact_or_truncated(&mut self.reader, &mut len)?;
+        let len = u32::from_be_bytes(len);
+        let last = len & LAST_CHUNK != 0;
+        let len = (len & !LAST_CHUNK) as usize;
+        if len > CHUNK_LEN + TAG_LEN {
+            return Err(invalid_data("corrupted encrypted chunk"));
+        }
+        let mut ciphertext = vec![0u8; len];
+        read_exact_or_truncated(&mut self.reader, &mut ciphertext)?;
+
+        let nonce = chunk_nonce(self.counter, last);
+        self.plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &self.header,
+                },
+            )
+            .map_err(|_| invalid_data("encrypted data is corrupted or was tampered with"))?;
+        self.pos = 0;
+        self.counter += 1;
+        if last {
+            self.done = true;
+            if read_up_to(&mut self.reader, &mut [0u8; 1])? != 0 {
+                return Err(invalid_data("unexpected data after the encrypted stream"));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Decryptor<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.plaintext.len() {
+            if self.done || buf.is_empty() {
+                return Ok(0);
+            }
+            self.open_chunk()?;
+        }
+        let n = buf.len().min(self.plaintext.len() - self.pos);
+        buf[..n].copy_from_slice(&self.plaintext[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+type Sniffed<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+/// A reader decrypting what it reads if it is encrypted, and passing
+/// plaintext through.
+pub enum MaybeEncrypted<R: Read> {
+    Plain(Sniffed<R>),
+    Encrypted(Decryptor<Sniffed<R>>),
+}
+
+impl<R: Read> MaybeEncrypted<R> {
+    pub fn new(mut reader: R, keyring: Option<&Keyring>) -> io::Result<Self> {
+        let mut head = vec![0u8; MAGIC.len()];
+        let n = read_up_to(&mut reader, &mut head)?;
+        head.truncate(n);
+        let encrypted = head == MAGIC;
+        let reader = io::Cursor::new(head).chain(reader);
+        if !encrypted {
+            return Ok(MaybeEncrypted::Plain(reader));
+        }
+        let keyring = keyring.ok_or_else(|| invalid_data(not_configured().to_string()))?;
+        Ok(MaybeEncrypted::Encrypted(keyring.decryptor(reader)?))
+    }
+}
+
+impl<R: Read> Read for MaybeEncrypted<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            MaybeEncrypted::Plain(reader) => reader.read(buf),
+            MaybeEncrypted::Encrypted(reader) => reader.read(buf),
+        }
+    }
+}
+
+/// A tantivy [`Directory`] over an [`MmapDirectory`] that encrypts the
+/// files written through it. Encrypted files are decrypted chunk by chunk as
+/// they are read (see [`EncryptedFileHandle`]); unencrypted ones, written
+/// before encryption was enabled, are mapped as they are.
+#[derive(Clone)]
+pub struct EncryptedDirectory {
+    inner: MmapDirectory,
+    keyring: Arc<Keyring>,
+}
+
+impl fmt::Debug for EncryptedDirectory {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptedDirectory({:?})", self.inner)
+    }
+}
+
+impl EncryptedDirectory {
+    pub fn open(path: &Path, keyring: Arc<Keyring>) -> tantivy::Result<Self> {
+        Ok(EncryptedDirectory {
+            inner: MmapDirectory::open(path)?,
+            keyring,
+        })
+    }
+}
+
+impl Directory for EncryptedDirectory {
+    fn get_file_handle(
+        &self,
+        path: &Path,
+    ) -> std::result::Result<Arc<dyn FileHandle>, OpenReadError> {
+        let wrap = |e| OpenReadError::wrap_io_error(e, path.to_path_buf());
+        let handle = self.inner.get_file_handle(path)?;
+        if handle.len() < HEADER_LEN
+            || handle.read_bytes(0..MAGIC.len()).map_err(wrap)?.as_slice() != MAGIC
+        {
+            return Ok(handle);
+        }
+        Ok(Arc::new(
+            EncryptedFileHandle::open(handle, &self.keyring).map_err(wrap)?,
+        ))
+    }
+
+    fn delete(&self, path: &Path) -> std::result::Result<(), DeleteError> {
+        self.inner.delete(path)
+    }
+
+    fn exists(&self, path: &Path) -> std::result::Result<bool, OpenReadError> {
+        self.inner.exists(path)
+    }
+
+    fn open_write(&self, path: &Path) -> std::result::Result<WritePtr, OpenWriteError> {
+        let writer = self.inner.open_write(path)?;
+        let encryptor = self
+            .keyring
+            .encryptor(writer)
+            .map_err(|e| OpenWriteError::wrap_io_error(e, path.to_path_buf()))?;
+        Ok(BufWriter::new(Box::new(EncryptedFile(Some(encryptor)))))
+    }
+
+    fn atomic_read(&self, path: &Path) -> std::result::Result<Vec<u8>, OpenReadError> {
+        let data = self.inner.atomic_read(path)?;
+        if !is_encrypted(&data) {
+            return Ok(data);
+        }
+        self.keyring
+            .decrypt(&data)
+            .map_err(|e| OpenReadError::wrap_io_error(e, path.to_path_buf()))
+    }
+
+    fn atomic_write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        self.inner.atomic_write(path, &self.keyring.encrypt(data))
+    }
+
+    fn acquire_lock(&self, lock: &Lock) -> std::result::Result<DirectoryLock, LockError> {
+        self.inner.acquire_lock(lock)
+    }
+
+    fn watch(&self, watch_callback: WatchCallback) -> tantivy::Result<WatchHandle> {
+        self.inner.watch(watch_callback)
+    }
+
+    fn sync_directory(&self) -> io::Result<()> {
+        self.inner.sync_directory()
+    }
+}
+
+/// An encrypted file read through an [`EncryptedDirectory`]. Every chunk but
+/// the last holds [`CHUNK_LEN`] bytes, so a read only decrypts the chunks its
+/// range covers; the last few decrypted are kept for the reads that follow.
+struct EncryptedFileHandle {
+    inner: Arc<dyn FileHandle>,
+    cipher: Aes256Gcm,
+    header: [u8; HEADER_LEN],
+    chunks: usize,
+    len: usize,
+    cache: Mutex<VecDeque<(usize, Arc<[u8]>)>>,
+}
+
+impl EncryptedFileHandle {
+    fn open(inner: Arc<dyn FileHandle>, keyring: &Keyring) -> io::Result<Self> {
+        if inner.len() < HEADER_LEN + 4 {
+            return Err(invalid_data("encrypted stream is truncated"));
+        }
+        let mut header = [0u8; HEADER_LEN];
+        header.copy_from_slice(inner.read_bytes(0..HEADER_LEN)?.as_slice());
+        let cipher = keyring.cipher_for(&header)?;
+        let chunks = (inner.len() - HEADER_LEN).div_ceil(CHUNK_RECORD_LEN);
+        // Only the last chunk is flagged, so a file cut or extended at a
+        // chunk boundary fails here
+        let last = chunk_record(inner.as_ref(), chunks - 1, chunks)?;
+        let len = (chunks - 1) * CHUNK_LEN + last.len() - 4 - TAG_LEN;
+        Ok(EncryptedFileHandle {
+            inner,
+            cipher,
+            header,
+            chunks,
+            len,
+            cache: Mutex::new(VecDeque::with_capacity(CACHED_CHUNKS)),
+        })
+    }
+
+    /// The plaintext of chunk `index`.
+    fn chunk(&self, index: usize) -> io::Result<Arc<[u8]>> {
+        if let Some((_, chunk)) = self.cache.lock().unwrap().iter().find(|(i, _)| *i == index) {
+            return Ok(Arc::clone(chunk));
+        }
+        let record = chunk_record(self.inner.as_ref(), index, self.chunks)?;
+        let nonce = chunk_nonce(index as u32, index + 1 == self.chunks);
+        let plaintext: Arc<[u8]> = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &record.as_slice()[4..],
+                    aad: &self.header,
+                },
+            )
+            .map_err(|_| invalid_data("encrypted data is corrupted or was tampered with"))?
+            .into();
+        let mut cache = self.cache.lock().unwrap();
+        if cache.len() == CACHED_CHUNKS {
+            cache.pop_front();
+        }
+        cache.push_back((index, Arc::clone(&plaintext)));
+        Ok(plaintext)
+    }
+}
+
+/// The length and ciphertext of chunk `index` of the `chunks` in `file`,
+/// checking that the length matches its place in the file.
+fn chunk_record(file: &dyn FileHandle, index: usize, chunks: usize) -> io::Result<OwnedBytes> {
+    let start = HEADER_LEN + index * CHUNK_RECORD_LEN;
+    let end = (start + CHUNK_RECORD_LEN).min(file.len());
+    let record = file.read_bytes(start..end)?;
+    let mut len = [0u8; 4];
+    len.copy_from_slice(&record.as_slice()[..4]);
+    let len = u32::from_be_bytes(len);
+    let last = len & LAST_CHUNK != 0;
+    if last != (index + 1 == chunks)
+        || (len & !LAST_CHUNK) as usize != end - start - 4
+        || end - start < 4 + TAG_LEN
+    {
+        return Err(invalid_data("corrupted encrypted chunk"));
+    }
+    Ok(record)
+}
+
+impl FileHandle for EncryptedFileHandle {
+    fn read_bytes(&self, range: Range<usize>) -> io::Result<OwnedBytes> {
+        if range.start > range.end || range.end > self.len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "read past the end of an encrypted file",
+            ));
+        }
+        if range.is_empty() {
+            return Ok(OwnedBytes::empty());
+        }
+        let first = range.start / CHUNK_LEN;
+        let last = (range.end - 1) / CHUNK_LEN;
+        if first == last {
+            let offset = first * CHUNK_LEN;
+            return Ok(
+                OwnedBytes::new(self.chunk(first)?).slice(range.start - offset..range.end - offset)
+            );
+        }
+        let mut bytes = Vec::with_capacity(range.len());
+        for index in first..=last {
+            let chunk = self.chunk(index)?;
+            let offset = index * CHUNK_LEN;
+            let from = range.start.saturating_sub(offset);
+            let to = (range.end - offset).min(chunk.len());
+            bytes.extend_from_slice(&chunk[from..to]);
+        }
+        Ok(OwnedBytes::new(bytes))
+    }
+}
+
+impl HasLen for EncryptedFileHandle {
+    fn len(&self) -> usize {
+        self.len
+    }
+}
+
+impl fmt::Debug for EncryptedFileHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EncryptedFileHandle({:?})", self.inner)
+    }
+}
+
+/// A file written through an [`EncryptedDirectory`], sealed on terminate.
+struct EncryptedFile(Option<Encryptor<WritePtr>>);
+
+impl Write for EncryptedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &mut self.0 {
+            Some(encryptor) => encryptor.write(buf),
+            None => Err(io::Error::other("write after terminate")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.0 {
+            Some(encryptor) => encryptor.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TerminatingWrite for EncryptedFile {
+    fn terminate_ref(&mut self, token: AntiCallToken) -> io::Result<()> {
+        match self.0.take() {
+            Some(encryptor) => encryptor.finish()?.terminate_ref(token),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Create a tantivy index in `path`, encrypted when a master key is
+/// configured.
+pub(crate) fn create_index(
+    path: &Path,
+    schema: tantivy::schema::Schema,
+) -> tantivy::Result<TantivyIndex> {
+    match keyring().map_err(|e| TantivyError::SystemError(e.to_string()))? {
+        Some(keyring) => {
+            let directory = EncryptedDirectory::open(path, keyring)?;
+            if TantivyIndex::exists(&directory)? {
+                return Err(TantivyError::IndexAlreadyExists);
+            }
+            TantivyIndex::create(directory, schema, IndexSettings::default())
+        }
+        None => TantivyIndex::create_in_dir(path, schema),
+    }
+}
+
+/// Open the tantivy index in `path`, through an [`EncryptedDirectory`] when a
+/// master key is configured.
+pub(crate) fn open_index(path: &Path) -> tantivy::Result<TantivyIndex> {
+    match keyring().map_err(|e| TantivyError::SystemError(e.to_string()))? {
+        Some(keyring) => TantivyIndex::open(EncryptedDirectory::open(path, keyring)?),
+        None => TantivyIndex::open_in_dir(path),
+    }
+}
+
+/// Whether `name` is a file tantivy writes: `meta.json`, `.managed.json` or
+/// a segment file.
+pub fn is_index_file(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    name == "meta.json"
+        || name == ".managed.json"
+        || (bytes.len() > 33 && bytes[32] == b'.' && bytes[..32].iter().all(u8::is_ascii_hexdigit))
+}
+
+/// Rewrite the tantivy files, vector files and oplog segments of the index in
+/// `dir` that are unencrypted or encrypted with a retired key, with the
+/// current key of `keyring`. The index must not be open. Returns the number
+/// of rewritten files.
+pub fn reencrypt_index_dir(keyring: &Keyring, dir: &Path) -> Result<usize> {
+    let mut rewritten = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file()
+            && is_index_file(&name)
+            && reencrypt_file(keyring, &entry.path())?
+        {
+            rewritten += 1;
+        }
+    }
+    rewritten += crate::index::vectors::reencrypt(dir, keyring)?;
+    let oplog_dir = dir.join("oplog");
+    if oplog_dir.is_dir() {
+        rewritten += crate::index::oplog::reencrypt_segments(&oplog_dir, keyring)?;
+    }
+    Ok(rewritten)
+}
+
+fn reencrypt_file(keyring: &Keyring, path: &Path) -> Result<bool> {
+    let mut file = File::open(path)?;
+    let mut head = [0u8; HEADER_LEN];
+    let n = read_up_to(&mut file, &mut head)?;
+    if keyring.is_current(&head[..n]) {
+        return Ok(false);
+    }
+    file.seek(SeekFrom::Start(0))?;
+
+    let staging = path.with_file_name(format!(
+        ".{}.reencrypt",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    ));
+    let copied = (|| -> Result<()> {
+        let mut reader = MaybeEncrypted::new(file, Some(keyring))?;
+        let mut writer = keyring.encryptor(BufWriter::new(File::create(&staging)?))?;
+        io::copy(&mut reader, &mut writer)?;
+        writer
+            .finish()?
+            .into_inner()
+            .map_err(|e| e.into_error())?
+            .sync_all()?;
+        Ok(())
+    })();
+    if let Err(e) = copied {
+        let _ = fs::remove_file(&staging);
+        return Err(e);
+    }
+    fs::rename(&staging, path)?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tantivy::collector::Count;
+    use tantivy::doc;
+    use tantivy::query::AllQuery;
+    use tantivy::schema::{Schema, STORED, TEXT};
+    use tempfile::TempDir;
+
+    fn keyring(keys: &[u8]) -> Keyring {
+        let keys: Vec<[u8; 32]> = keys.iter().map(|b| [*b; 32]).collect();
+        Keyring::new(&keys).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip_across_chunks() {
+        let keyring = keyring(&[1]);
+        for len in [0, 10, CHUNK_LEN, CHUNK_LEN + 1, 3 * CHUNK_LEN + 7] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let sealed = keyring.encrypt(&plaintext);
+            assert!(is_encrypted(&sealed));
+            assert!(keyring.is_current(&sealed));
+            assert_eq!(keyring.decrypt(&sealed).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_rejects_tampered_truncated_and_extended_streams() {
+        let keyring = keyring(&[1]);
+        let sealed = keyring.encrypt(&vec![7u8; 2 * CHUNK_LEN + 100]);
+
+        let mut tampered = sealed.clone();
+        tampered[HEADER_LEN + 10] ^= 1;
+        assert!(keyring.decrypt(&tampered).is_err());
+
+        // Cut at a chunk boundary: every chunk left is intact
+        let first_chunk = HEADER_LEN + 4 + CHUNK_LEN + TAG_LEN;
+        assert!(keyring.decrypt(&sealed[..first_chunk]).is_err());
+        assert!(keyring.decrypt(&sealed[..sealed.len() - 1]).is_err());
+
+        let mut extended = sealed.clone();
+        extended.push(0);
+        assert!(keyring.decrypt(&extended).is_err());
+    }
+
+    #[test]
+    fn test_streams_are_sealed_with_their_own_key() {
+        let keyring = keyring(&[1]);
+        let first = keyring.encrypt(b"same plaintext");
+        let second = keyring.encrypt(b"same plaintext");
+        assert_ne!(first[..HEADER_LEN], second[..HEADER_LEN]);
+        assert_ne!(first[HEADER_LEN..], second[HEADER_LEN..]);
+
+        // The salt selects the stream key
+        let mut resalted = first.clone();
+        resalted[HEADER_LEN - 1] ^= 1;
+        assert!(keyring.decrypt(&resalted).is_err());
+    }
+
+    #[test]
+    fn test_rotation_keeps_old_data_readable() {
+        let old = keyring(&[1]);
+        let rotated = keyring(&[2, 1]);
+        let sealed = old.encrypt(b"secret");
+
+        assert!(!rotated.is_current(&sealed));
+        assert_eq!(rotated.decrypt(&sealed).unwrap(), b"secret");
+        assert!(keyring(&[2]).decrypt(&sealed).is_err());
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        let hex_key = "01".repeat(32);
+        let base64_key = BASE64.encode([2u8; 32]);
+        let keyring =
+            Keyring::parse(&format!("# newest first\n{}\n\n{}\n", hex_key, base64_key)).unwrap();
+        assert_eq!(keyring.keys.len(), 2);
+        assert_eq!(keyring.current_key_id(), hex::encode(key_id(&[1u8; 32])));
+
+        assert!(Keyring::parse("tooshort").is_err());
+        assert!(Keyring::parse("# nothing\n").is_err());
+    }
+
+    #[test]
+    fn test_plaintext_passes_through() {
+        let mut plain = Vec::new();
+        MaybeEncrypted::new(&b"{\"a\":1}"[..], None)
+            .unwrap()
+            .read_to_end(&mut plain)
+            .unwrap();
+        assert_eq!(plain, b"{\"a\":1}");
+
+        let sealed = keyring(&[1]).encrypt(b"{\"a\":1}");
+        assert!(MaybeEncrypted::new(sealed.as_slice(), None).is_err());
+        assert_eq!(decrypt_if_encrypted(b"abc", None).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_file_handle_decrypts_the_chunks_read() {
+        let keyring = keyring(&[1]);
+        for len in [0, 10, CHUNK_LEN, 3 * CHUNK_LEN + 7] {
+            let plaintext: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let sealed: Arc<dyn FileHandle> =
+                Arc::new(OwnedBytes::new(keyring.encrypt(&plaintext)));
+            let handle = EncryptedFileHandle::open(sealed, &keyring).unwrap();
+            assert_eq!(handle.len(), len);
+            assert_eq!(
+                handle.read_bytes(0..len).unwrap().as_slice(),
+                &plaintext[..]
+            );
+            if len > CHUNK_LEN {
+                let range = CHUNK_LEN - 3..2 * CHUNK_LEN + 5;
+                assert_eq!(
+                    handle.read_bytes(range.clone()).unwrap().as_slice(),
+                    &plaintext[range]
+                );
+            }
+            assert!(handle.read_bytes(0..len + 1).is_err());
+        }
+
+        let sealed = keyring.encrypt(&vec![7u8; 2 * CHUNK_LEN + 100]);
+        let first_chunk = HEADER_LEN + CHUNK_RECORD_LEN;
+        let cut: Arc<dyn FileHandle> = Arc::new(OwnedBytes::new(sealed[..first_chunk].to_vec()));
+        assert!(EncryptedFileHandle::open(cut, &keyring).is_err());
+
+        let mut tampered = sealed.clone();
+        tampered[first_chunk + 10] ^= 1;
+        let handle =
+            EncryptedFileHandle::open(Arc::new(OwnedBytes::new(tampered)), &keyring).unwrap();
+        assert!(handle.read_bytes(0..10).is_ok());
+        assert!(handle.read_bytes(CHUNK_LEN..CHUNK_LEN + 10).is_err());
+    }
+
+    fn count_docs(dir: &Path, keyring: Arc<Keyring>) -> usize {
+        let index = TantivyIndex::open(EncryptedDirectory::open(dir, keyring).unwrap()).unwrap();
+        index
+            .reader()
+            .unwrap()
+            .searcher()
+            .search(&AllQuery, &Count)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_encrypted_directory() {
+        let tmp = TempDir::new().unwrap();
+        let keys = Arc::new(keyring(&[1]));
+        let mut schema = Schema::builder();
+        let title = schema.add_text_field("title", TEXT | STORED);
+        let directory = EncryptedDirectory::open(tmp.path(), Arc::clone(&keys)).unwrap();
+        let index =
+            TantivyIndex::create(directory, schema.build(), IndexSettings::default()).unwrap();
+        let mut writer = index.writer(15_000_000).unwrap();
+        writer
+            .add_document(doc!(title => "confidential record"))
+            .unwrap();
+        writer.commit().unwrap();
+        drop(writer);
+
+        for entry in fs::read_dir(tmp.path()).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+            if is_index_file(&name) {
+                assert!(
+                    is_encrypted_file(&path).unwrap(),
+                    "{} is not encrypted",
+                    name
+                );
+            }
+        }
+        assert_eq!(count_docs(tmp.path(), Arc::clone(&keys)), 1);
+
+        let rotated = Arc::new(keyring(&[2, 1]));
+        assert!(reencrypt_index_dir(&rotated, tmp.path()).unwrap() > 0);
+        assert_eq!(reencrypt_index_dir(&rotated, tmp.path()).unwrap(), 0);
+        assert_eq!(count_docs(tmp.path(), Arc::new(keyring(&[2]))), 1);
+    }
+}
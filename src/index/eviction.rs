@@ -0,0 +1,71 @@
+//! Eviction of idle tenants from memory.
+//!
+//! Every loaded tenant keeps its index reader, and once written to, a write
+//! queue whose writer holds a slot of the memory budget. With one index per
+//! customer the loaded set has to be bounded: tenants idle for longer than
+//! `idle_timeout` are evicted, and beyond `max_loaded_tenants` the least
+//! recently used ones are. Evicted tenants are reloaded from disk by the next
+//! [`IndexManager::get_or_load`](crate::IndexManager::get_or_load).
+
+use serde::Serialize;
+use std::env;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct EvictionConfig {
+    /// Loaded tenants kept before evicting the least recently used; 0 for no
+    /// limit.
+    pub max_loaded_tenants: usize,
+    /// Time without searches or writes after which a tenant is evicted.
+    pub idle_timeout: Option<Duration>,
+    /// How often the background loop looks for tenants to evict.
+    pub check_interval: Duration,
+}
+
+impl Default for EvictionConfig {
+    fn default() -> Self {
+        EvictionConfig {
+            max_loaded_tenants: 0,
+            idle_timeout: None,
+            check_interval: Duration::from_secs(30),
+        }
+    }
+}
+
+impl EvictionConfig {
+    pub fn from_env() -> Self {
+        EvictionConfig {
+            max_loaded_tenants: env::var("FLAPJACK_MAX_LOADED_TENANTS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .unwrap_or(0),
+            idle_timeout: env::var("FLAPJACK_TENANT_IDLE_SECS")
+                .ok()
+                .and_then(|s| s.parse().ok())
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+            check_interval: Duration::from_secs(
+                env::var("FLAPJACK_EVICTION_INTERVAL_SECS")
+                    .ok()
+                    .and_then(|s| s.parse().ok())
+                    .filter(|secs| *secs > 0)
+                    .unwrap_or(30),
+            ),
+        }
+    }
+
+    /// Whether any eviction is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.max_loaded_tenants > 0 || self.idle_timeout.is_some()
+    }
+}
+
+/// Loaded tenants and evictions so far, reported on `/health`.
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct EvictionStats {
+    pub loaded_tenants: usize,
+    pub max_loaded_tenants: usize,
+    pub idle_timeout_secs: u64,
+    pub idle_evictions: u64,
+    pub lru_evictions: u64,
+}
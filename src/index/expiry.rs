@@ -0,0 +1,149 @@
+//! Document expiry.
+//!
+//! A document expires at its `_expiresAt` attribute, or else at the index's
+//! `expiresAtAttribute` plus `timeToLive` seconds. Either is a Unix timestamp
+//! in seconds or an RFC 3339 date. The resolved time is indexed as a number
+//! under `_expiresAt` in the filter field, so searches exclude expired
+//! documents as soon as they expire, and the sweeper of the
+//! [`IndexManager`](crate::IndexManager) deletes them through the write queue,
+//! which records the deletes in the oplog for replicas.
+
+use crate::index::settings::IndexSettings;
+use crate::types::{Document, FieldValue, Filter};
+use std::time::Duration;
+
+/// Attribute holding a document's expiry, and the filter field it is indexed
+/// under.
+pub const EXPIRES_AT: &str = "_expiresAt";
+
+/// Expired documents deleted per tenant and sweep.
+pub const SWEEP_BATCH: usize = 1000;
+
+/// How often expired documents are swept, from
+/// `FLAPJACK_EXPIRY_SWEEP_INTERVAL_SECS` (default 60).
+pub fn sweep_interval() -> Duration {
+    Duration::from_secs(
+        std::env::var("FLAPJACK_EXPIRY_SWEEP_INTERVAL_SECS")
+            .ok()
+            .and_then(|s| s.parse().ok())
+            .filter(|secs| *secs > 0)
+            .unwrap_or(60),
+    )
+}
+
+/// The current time as a Unix timestamp in seconds.
+pub fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// When `doc` expires, in Unix seconds, if it does.
+pub fn expires_at(doc: &Document, settings: Option<&IndexSettings>) -> Option<i64> {
+    if let Some(at) = doc.fields.get(EXPIRES_AT).and_then(timestamp) {
+        return Some(at);
+    }
+    let settings = settings?;
+    let attribute = settings.expires_at_attribute.as_deref()?;
+    let at = lookup(doc, attribute).and_then(timestamp)?;
+    Some(at.saturating_add(settings.time_to_live.unwrap_or(0) as i64))
+}
+
+/// Whether an index may hold documents that expire.
+pub fn is_configured(settings: Option<&IndexSettings>) -> bool {
+    settings.is_some_and(|s| s.expires_at_attribute.is_some())
+}
+
+/// Documents expired at `now`.
+pub fn expired_filter(now: i64) -> Filter {
+    Filter::LessThanOrEqual {
+        field: EXPIRES_AT.to_string(),
+        value: FieldValue::Integer(now),
+    }
+}
+
+/// Documents with an expiry, expired or not.
+pub fn expiring_filter() -> Filter {
+    Filter::GreaterThanOrEqual {
+        field: EXPIRES_AT.to_string(),
+        value: FieldValue::Integer(0),
+    }
+}
+
+/// `filter` restricted to the documents not expired at `now`.
+pub fn exclude_expired(filter: Option<&Filter>, now: i64) -> Filter {
+    let unexpired = Filter::Not(Box::new(expired_filter(now)));
+    match filter {
+        Some(filter) => Filter::And(vec![filter.clone(), unexpired]),
+        None => unexpired,
+    }
+}
+
+fn lookup<'a>(doc: &'a Document, path: &str) -> Option<&'a FieldValue> {
+    let mut parts = path.split('.');
+    let mut value = doc.fields.get(parts.next()?)?;
+    for part in parts {
+        match value {
+            FieldValue::Object(map) => value = map.get(part)?,
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+fn timestamp(value: &FieldValue) -> Option<i64> {
+    match value {
+        FieldValue::Integer(secs) | FieldValue::Date(secs) => Some(*secs),
+        FieldValue::Float(secs) => Some(*secs as i64),
+        FieldValue::Text(date) => chrono::DateTime::parse_from_rfc3339(date)
+            .ok()
+            .map(|d| d.timestamp()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn doc(value: serde_json::Value) -> Document {
+        Document::from_json(&value).unwrap()
+    }
+
+    #[test]
+    fn test_expires_at() {
+        let settings = IndexSettings {
+            expires_at_attribute: Some("job.postedAt".to_string()),
+            time_to_live: Some(60),
+            ..Default::default()
+        };
+        assert_eq!(
+            expires_at(&doc(json!({"objectID": "1", "_expiresAt": 100})), None),
+            Some(100)
+        );
+        assert_eq!(
+            expires_at(
+                &doc(json!({"objectID": "1", "_expiresAt": "1970-01-01T00:01:40Z"})),
+                None
+            ),
+            Some(100)
+        );
+        assert_eq!(
+            expires_at(
+                &doc(json!({"objectID": "1", "job": {"postedAt": 1000}})),
+                Some(&settings)
+            ),
+            Some(1060)
+        );
+        assert_eq!(
+            expires_at(
+                &doc(json!({"objectID": "1", "job": {"postedAt": 1000}})),
+                None
+            ),
+            None
+        );
+        assert_eq!(
+            expires_at(&doc(json!({"objectID": "1", "_expiresAt": "soon"})), None),
+            None
+        );
+    }
+}
@@ -1,21 +1,37 @@
 use crate::error::{FlapjackError, Result};
+use crate::index::aliases::{AliasStore, ALIASES_DIR};
+use crate::index::bulk::{self, BulkFormat, Record, BULK_CHUNK_SIZE};
+use crate::index::dictionaries::{DictionaryStore, DICTIONARIES_DIR};
+use crate::index::dump::{self, DumpEntry, DumpImport};
+use crate::index::encryption;
+use crate::index::eviction::{EvictionConfig, EvictionStats};
+use crate::index::expiry;
+use crate::index::migration;
 use crate::index::oplog::OpLog;
 use crate::index::relevance::RelevanceConfig;
 use crate::index::rules::RuleStore;
 use crate::index::settings::IndexSettings;
+use crate::index::snapshot::PinnedCommit;
 use crate::index::synonyms::SynonymStore;
 use crate::index::task_queue::TaskQueue;
 use crate::index::utils::copy_dir_recursive;
-use crate::index::write_queue::{create_write_queue, WriteAction, WriteOp, WriteQueue};
+use crate::index::vectors::{document_vector, VectorChange};
+use crate::index::write_queue::{
+    create_write_queue, SwapIndex, WriteAction, WriteOp, WriteQueue, MAX_REJECTED_DOCUMENTS,
+};
 use crate::index::Index;
-use crate::query::{QueryExecutor, QueryParser};
+use crate::query::{FilterCompiler, QueryExecutor, QueryParser};
+use crate::tokenizer::{CjkSegmenter, IndexedSeparators, KeepDiacritics};
 use crate::types::{
     Document, FacetRequest, Filter, SearchResult, Sort, TaskInfo, TaskStatus, TenantId,
 };
+use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
 use tokio::task::JoinHandle;
 
 const MAX_TASKS_PER_TENANT: usize = 1000;
@@ -54,6 +70,10 @@ pub struct IndexManager {
     settings_cache: DashMap<TenantId, Arc<IndexSettings>>,
     rules_cache: DashMap<TenantId, Arc<RuleStore>>,
     synonyms_cache: DashMap<TenantId, Arc<SynonymStore>>,
+    /// Custom stop words, plurals and compounds shared by all tenants.
+    dictionaries: DictionaryStore,
+    /// Alias names resolved to the index they point to.
+    aliases: Arc<AliasStore>,
     pub facet_cache: Arc<
         DashMap<
             String,
@@ -65,6 +85,23 @@ pub struct IndexManager {
         >,
     >,
     pub facet_cache_cap: std::sync::atomic::AtomicUsize,
+    /// When each loaded tenant was last searched or written to.
+    last_access: DashMap<TenantId, Instant>,
+    /// Whether each loaded tenant holds documents with `_expiresAt`.
+    expiring: DashMap<TenantId, bool>,
+    /// The task of each running schema migration.
+    migrations: DashMap<TenantId, String>,
+    /// Held for writing while a tenant's files are rewritten in place, as by
+    /// [`Self::reencrypt_tenant`]; loading the tenant fails meanwhile.
+    file_locks: DashMap<TenantId, Arc<tokio::sync::RwLock<()>>>,
+    /// Held by a tenant's write queue while its writer is open, so a queue
+    /// started during an eviction waits for the closing one's writer.
+    writer_slots: DashMap<TenantId, Arc<tokio::sync::Mutex<()>>>,
+    eviction_config: RwLock<EvictionConfig>,
+    /// Wakes the eviction loop when a load exceeds `max_loaded_tenants`.
+    eviction_wakeup: tokio::sync::Notify,
+    idle_evictions: AtomicU64,
+    lru_evictions: AtomicU64,
 }
 
 const DEFAULT_FACET_CACHE_CAP: usize = 500;
@@ -74,6 +111,7 @@ impl IndexManager {
     ///
     /// Each tenant's index will be stored in `{base_path}/{tenant_id}/`.
     pub fn new<P: AsRef<Path>>(base_path: P) -> Arc<Self> {
+        migration::recover_interrupted(base_path.as_ref());
         Arc::new_cyclic(|weak| {
             let tasks = Arc::new(DashMap::new());
             IndexManager {
@@ -88,12 +126,38 @@ impl IndexManager {
                 settings_cache: DashMap::new(),
                 rules_cache: DashMap::new(),
                 synonyms_cache: DashMap::new(),
+                dictionaries: DictionaryStore::load(&base_path.as_ref().join(DICTIONARIES_DIR)),
+                aliases: Arc::new(AliasStore::load(&base_path.as_ref().join(ALIASES_DIR))),
                 facet_cache: Arc::new(DashMap::new()),
                 facet_cache_cap: std::sync::atomic::AtomicUsize::new(DEFAULT_FACET_CACHE_CAP),
+                last_access: DashMap::new(),
+                expiring: DashMap::new(),
+                migrations: DashMap::new(),
+                file_locks: DashMap::new(),
+                writer_slots: DashMap::new(),
+                eviction_config: RwLock::new(EvictionConfig::from_env()),
+                eviction_wakeup: tokio::sync::Notify::new(),
+                idle_evictions: AtomicU64::new(0),
+                lru_evictions: AtomicU64::new(0),
             }
         })
     }
 
+    /// The custom dictionaries merged into every tenant's queries.
+    pub fn dictionaries(&self) -> &DictionaryStore {
+        &self.dictionaries
+    }
+
+    /// The index aliases, shared with the auth middleware.
+    pub fn aliases(&self) -> &Arc<AliasStore> {
+        &self.aliases
+    }
+
+    /// The index `name` points to when it is an alias, else `name` itself.
+    pub fn resolve_alias(&self, name: &str) -> String {
+        self.aliases.resolve(name)
+    }
+
     /// Get the oplog for a tenant (for external access)
     pub fn get_oplog(&self, tenant_id: &str) -> Option<Arc<OpLog>> {
         self.oplogs.get(tenant_id).map(|r| Arc::clone(&r))
@@ -110,6 +174,7 @@ impl IndexManager {
     }
 
     pub fn get_settings(&self, tenant_id: &str) -> Option<Arc<IndexSettings>> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         if let Some(cached) = self.settings_cache.get(tenant_id) {
             return Some(Arc::clone(&cached));
         }
@@ -126,6 +191,7 @@ impl IndexManager {
     }
 
     pub fn get_rules(&self, tenant_id: &str) -> Option<Arc<RuleStore>> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         if let Some(cached) = self.rules_cache.get(tenant_id) {
             return Some(Arc::clone(&cached));
         }
@@ -142,6 +208,7 @@ impl IndexManager {
     }
 
     pub fn get_synonyms(&self, tenant_id: &str) -> Option<Arc<SynonymStore>> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         if let Some(cached) = self.synonyms_cache.get(tenant_id) {
             return Some(Arc::clone(&cached));
         }
@@ -219,15 +286,24 @@ impl IndexManager {
     }
 
     pub fn create_tenant(&self, tenant_id: &str) -> Result<()> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         if self.loaded.contains_key(tenant_id) {
+            self.touch(tenant_id);
             return Ok(());
         }
 
+        let file_lock = self.file_lock(tenant_id);
+        let _reading = file_lock
+            .try_read()
+            .map_err(|_| FlapjackError::TenantBusy(tenant_id.to_string()))?;
         let path = self.base_path.join(tenant_id);
         if path.exists() {
             let index = Arc::new(Index::open(&path)?);
+            self.apply_analyzer_settings(tenant_id, &index);
             let _ = index.searchable_paths();
+            self.expiring.remove(tenant_id);
             self.loaded.insert(tenant_id.to_string(), index);
+            self.touch(tenant_id);
             return Ok(());
         }
 
@@ -235,6 +311,7 @@ impl IndexManager {
         let schema = crate::index::schema::Schema::builder().build();
         let index = Arc::new(Index::create(&path, schema)?);
         self.loaded.insert(tenant_id.to_string(), index);
+        self.touch(tenant_id);
 
         let settings_path = path.join("settings.json");
         if !settings_path.exists() {
@@ -245,11 +322,19 @@ impl IndexManager {
         Ok(())
     }
 
+    /// Open a tenant's index, loading it on first use. Aliases are resolved
+    /// to the index they point to.
     pub fn get_or_load(&self, tenant_id: &str) -> Result<Arc<Index>> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         if let Some(index) = self.loaded.get(tenant_id) {
+            self.touch(tenant_id);
             return Ok(Arc::clone(&index));
         }
 
+        let file_lock = self.file_lock(tenant_id);
+        let _reading = file_lock
+            .try_read()
+            .map_err(|_| FlapjackError::TenantBusy(tenant_id.to_string()))?;
         let path = self.base_path.join(tenant_id);
         if !path.exists() {
             return Err(FlapjackError::TenantNotFound(tenant_id.to_string()));
@@ -276,13 +361,46 @@ impl IndexManager {
                 }
             }
         };
+        self.apply_analyzer_settings(tenant_id, &index);
         self.recover_from_oplog(tenant_id, &index, &path)?;
         let _ = index.searchable_paths();
+        self.expiring.remove(tenant_id);
         self.loaded
             .insert(tenant_id.to_string(), Arc::clone(&index));
+        self.touch(tenant_id);
         Ok(index)
     }
 
+    /// The lock guarding a tenant's files against rewrites while it loads.
+    fn file_lock(&self, tenant_id: &str) -> Arc<tokio::sync::RwLock<()>> {
+        Arc::clone(&self.file_locks.entry(tenant_id.to_string()).or_default())
+    }
+
+    /// Record an access to a tenant, waking the eviction loop if the loaded
+    /// tenants exceed `max_loaded_tenants`.
+    fn touch(&self, tenant_id: &str) {
+        match self.last_access.get_mut(tenant_id) {
+            Some(mut at) => *at = Instant::now(),
+            None => {
+                self.last_access
+                    .insert(tenant_id.to_string(), Instant::now());
+                let max = self.eviction_config.read().unwrap().max_loaded_tenants;
+                if max > 0 && self.loaded.len() > max {
+                    self.eviction_wakeup.notify_one();
+                }
+            }
+        }
+    }
+
+    /// Configure the index analyzers from the tenant settings.
+    fn apply_analyzer_settings(&self, tenant_id: &str, index: &Index) {
+        if let Some(settings) = self.get_settings(tenant_id) {
+            index.set_keep_diacritics(&settings.keep_diacritics_on_characters);
+            index.set_index_languages(&settings.index_languages);
+            index.set_separators_to_index(&settings.separators_to_index);
+        }
+    }
+
     fn recover_from_oplog(
         &self,
         tenant_id: &str,
@@ -353,6 +471,11 @@ impl IndexManager {
             );
             None
         };
+        if let Some(ref s) = settings {
+            index.set_keep_diacritics(&s.keep_diacritics_on_characters);
+            index.set_index_languages(&s.index_languages);
+            index.set_separators_to_index(&s.separators_to_index);
+        }
 
         // Phase 3: Replay document ops
         let mut writer = index.writer()?;
@@ -360,6 +483,7 @@ impl IndexManager {
         let id_field = schema.get_field("_id").unwrap();
         let mut replayed = 0usize;
         let mut failed = 0usize;
+        let mut vector_changes = Vec::new();
 
         for entry in &ops {
             match entry.op_type.as_str() {
@@ -372,6 +496,12 @@ impl IndexManager {
                                 Ok(doc) => {
                                     match index.converter().to_tantivy(&doc, settings.as_ref()) {
                                         Ok(tantivy_doc) => {
+                                            vector_changes.push(match document_vector(&doc) {
+                                                Ok(Some(vector)) => {
+                                                    VectorChange::Set(doc.id.clone(), vector)
+                                                }
+                                                _ => VectorChange::Remove(doc.id.clone()),
+                                            });
                                             writer.add_document(tantivy_doc)?;
                                             replayed += 1;
                                         }
@@ -403,6 +533,7 @@ impl IndexManager {
                     if let Some(obj_id) = entry.payload.get("objectID").and_then(|v| v.as_str()) {
                         let term = tantivy::Term::from_field_text(id_field, obj_id);
                         writer.delete_term(term);
+                        vector_changes.push(VectorChange::Remove(obj_id.to_string()));
                         replayed += 1;
                     }
                 }
@@ -412,6 +543,7 @@ impl IndexManager {
                 }
                 "clear" => {
                     writer.delete_all_documents()?;
+                    vector_changes.push(VectorChange::Clear);
                     replayed += 1;
                 }
                 _ => {
@@ -429,6 +561,7 @@ impl IndexManager {
             writer.commit()?;
             index.reader().reload()?;
             index.invalidate_searchable_paths_cache();
+            index.update_vectors(vector_changes)?;
             let final_seq = ops.last().map(|o| o.seq).unwrap_or(committed_seq);
             let _ = std::fs::write(&committed_seq_path, final_seq.to_string());
             if failed > 0 {
@@ -558,6 +691,65 @@ impl IndexManager {
         rule_contexts: Option<&[String]>,
         restrict_searchable_attrs: Option<&[String]>,
     ) -> Result<SearchResult> {
+        self.search_full_with_settings(
+            tenant_id,
+            query_text,
+            filter,
+            sort,
+            limit,
+            offset,
+            facets,
+            distinct,
+            max_values_per_facet,
+            remove_stop_words_override,
+            ignore_plurals_override,
+            query_languages_override,
+            query_type_override,
+            typo_tolerance_override,
+            advanced_syntax_override,
+            remove_words_override,
+            optional_filter_specs,
+            enable_synonyms,
+            enable_rules,
+            rule_contexts,
+            restrict_searchable_attrs,
+            None,
+            None,
+        )
+    }
+
+    /// Like [`Self::search_full_with_stop_words`], but searches with
+    /// `settings_override` instead of the tenant's stored settings when given
+    /// (used by A/B test variants that overlay settings), and ranks by
+    /// similarity to `vector` when given: alone for an empty query, fused
+    /// with the keyword ranking otherwise.
+    pub fn search_full_with_settings(
+        &self,
+        tenant_id: &str,
+        query_text: &str,
+        filter: Option<&Filter>,
+        sort: Option<&Sort>,
+        limit: usize,
+        offset: usize,
+        facets: Option<&[FacetRequest]>,
+        distinct: Option<u32>,
+        max_values_per_facet: Option<usize>,
+        remove_stop_words_override: Option<&crate::query::stopwords::RemoveStopWordsValue>,
+        ignore_plurals_override: Option<&crate::query::plurals::IgnorePluralsValue>,
+        query_languages_override: Option<&Vec<String>>,
+        query_type_override: Option<&str>,
+        typo_tolerance_override: Option<bool>,
+        advanced_syntax_override: Option<bool>,
+        remove_words_override: Option<&str>,
+        optional_filter_specs: Option<&[(String, String, f32)]>,
+        enable_synonyms: Option<bool>,
+        enable_rules: Option<bool>,
+        rule_contexts: Option<&[String]>,
+        restrict_searchable_attrs: Option<&[String]>,
+        settings_override: Option<Arc<IndexSettings>>,
+        vector: Option<&[f32]>,
+    ) -> Result<SearchResult> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         let t0 = std::time::Instant::now();
         let index = self.get_or_load(tenant_id)?;
         let t1 = t0.elapsed();
@@ -565,10 +757,20 @@ impl IndexManager {
         let t2 = t0.elapsed();
         let searcher = reader.searcher();
 
-        let settings = self.get_settings(tenant_id);
+        let settings = settings_override
+            .clone()
+            .or_else(|| self.get_settings(tenant_id));
         if let Some(ref s) = settings {
             tracing::debug!("[SEARCH] Loaded settings query_type={}", s.query_type);
         }
+        // Expired documents are hidden until the sweeper deletes them
+        let unexpired;
+        let filter = if self.may_expire(tenant_id, &index, settings.as_deref()) {
+            unexpired = expiry::exclude_expired(filter, expiry::now());
+            Some(&unexpired)
+        } else {
+            filter
+        };
         let relevance_config = RelevanceConfig {
             searchable_attributes: settings
                 .as_ref()
@@ -582,33 +784,59 @@ impl IndexManager {
                 .map(|s| s.query_type.as_str())
                 .unwrap_or("prefixLast")
         });
+        // Stop words and plurals follow queryLanguages, else indexLanguages
+        let languages: &[String] = match query_languages_override {
+            Some(langs) => langs,
+            None => settings
+                .as_ref()
+                .map(|s| {
+                    if s.query_languages.is_empty() {
+                        s.index_languages.as_slice()
+                    } else {
+                        s.query_languages.as_slice()
+                    }
+                })
+                .unwrap_or(&[]),
+        };
+        let keep_diacritics = settings
+            .as_ref()
+            .map(|s| KeepDiacritics::parse(&s.keep_diacritics_on_characters))
+            .unwrap_or_default();
+        let custom = self.dictionaries.custom();
         let effective_stop_words =
             remove_stop_words_override.or(settings.as_ref().map(|s| &s.remove_stop_words));
         let query_text_stopped = match effective_stop_words {
-            Some(sw) => crate::query::stopwords::remove_stop_words(query_text, sw, qt),
+            Some(sw) => crate::query::stopwords::remove_stop_words_with_custom(
+                query_text,
+                sw,
+                languages,
+                qt,
+                &custom.stop_words,
+            ),
             None => query_text.to_string(),
         };
         let plural_map: Option<std::collections::HashMap<String, Vec<String>>> = {
             let effective_ignore_plurals =
                 ignore_plurals_override.or(settings.as_ref().map(|s| &s.ignore_plurals));
-            let effective_query_languages = query_languages_override
-                .map(|v| v.as_slice())
-                .or(settings.as_ref().map(|s| s.query_languages.as_slice()))
-                .unwrap_or(&[]);
             match effective_ignore_plurals {
                 Some(ip) if *ip != crate::query::plurals::IgnorePluralsValue::Disabled => {
-                    let langs = crate::query::plurals::resolve_plural_languages(
-                        ip,
-                        effective_query_languages,
-                    );
-                    if crate::query::plurals::should_expand_english(&langs) {
+                    let langs = crate::query::plurals::resolve_plural_languages(ip, languages);
+                    if langs.iter().any(|l| {
+                        crate::query::plurals::supports_language(l)
+                            || custom.plurals.contains_key(l)
+                    }) {
                         let words: Vec<&str> = query_text_stopped.split_whitespace().collect();
                         let mut map = std::collections::HashMap::new();
                         for w in words {
-                            let lower = w.to_lowercase();
-                            let forms = crate::query::plurals::expand_plurals(&lower);
+                            // Keyed like the parser's tokens, which are folded
+                            let folded = crate::tokenizer::diacritics::fold(w, &keep_diacritics);
+                            let forms = crate::query::plurals::expand_plurals_with_custom(
+                                &folded,
+                                &langs,
+                                &custom.plurals,
+                            );
                             if forms.len() > 1 {
-                                map.insert(lower, forms);
+                                map.insert(folded, forms);
                             }
                         }
                         if map.is_empty() {
@@ -723,6 +951,25 @@ impl IndexManager {
         // query + synonyms don't return enough results (see loop below).
         let mut expanded_queries = expanded_queries;
 
+        // Compound query words ("kinderschuhe") also search as their parts
+        if settings
+            .as_ref()
+            .map(|s| s.decompound_query)
+            .unwrap_or(true)
+        {
+            for eq in expanded_queries.clone() {
+                if let Some(alt) = crate::query::decompound::decompound_query_with_custom(
+                    &eq,
+                    languages,
+                    &custom.compounds,
+                ) {
+                    if !expanded_queries.contains(&alt) {
+                        expanded_queries.push(alt);
+                    }
+                }
+            }
+        }
+
         let default_sort_owned = if sort.is_none() && query_text.trim().is_empty() {
             Some(Sort::ByField {
                 field: "objectID".to_string(),
@@ -751,7 +998,29 @@ impl IndexManager {
         .with_typo_tolerance(typo_enabled)
         .with_min_word_size_for_1_typo(min_word_1_typo)
         .with_advanced_syntax(adv_syntax)
+        .with_keep_diacritics(keep_diacritics)
+        .with_cjk_segmenter(
+            settings
+                .as_ref()
+                .and_then(|s| CjkSegmenter::for_languages(&s.index_languages)),
+        )
+        .with_separators_to_index(
+            settings
+                .as_ref()
+                .map(|s| IndexedSeparators::parse(&s.separators_to_index))
+                .unwrap_or_default(),
+        )
         .with_plural_map(plural_map);
+        let parser = match &settings {
+            Some(s) => parser
+                .with_disable_typo_tolerance_on_attributes(
+                    s.disable_typo_tolerance_on_attributes.clone(),
+                )
+                .with_disable_typo_tolerance_on_words(s.disable_typo_tolerance_on_words.clone())
+                .with_disable_prefix_on_attributes(s.disable_prefix_on_attributes.clone())
+                .with_allow_typos_on_numeric_tokens(s.allow_typos_on_numeric_tokens),
+            None => parser,
+        };
 
         // Time-based facet cache: key excludes query_text so consecutive
         // typeahead keystrokes share cached facets (distribution is stable
@@ -868,6 +1137,8 @@ impl IndexManager {
             let executor = QueryExecutor::new(index.converter(), schema.clone())
                 .with_settings(settings.clone())
                 .with_query(expanded_query.clone())
+                .with_query_terms(parser.extract_terms(&query))
+                .with_proximity_paths(searchable_paths.clone())
                 .with_max_values_per_facet(max_values_per_facet);
 
             let expanded_parsed =
@@ -993,6 +1264,52 @@ impl IndexManager {
         });
 
         let mut total = first_query_total.unwrap_or(0);
+        // With `semanticSearch` set, the vector only backs up sparse keyword
+        // results instead of being fused with them
+        let min_lexical_hits = settings
+            .as_ref()
+            .and_then(|s| s.semantic_search.as_ref())
+            .map(|s| s.min_lexical_hits);
+        let semantic_fallback =
+            min_lexical_hits.is_some() && vector.is_some() && !query_text.trim().is_empty();
+        let sparse = total < min_lexical_hits.unwrap_or(0);
+        if let Some(vector) = vector.filter(|_| !semantic_fallback || sparse) {
+            let executor = QueryExecutor::new(index.converter(), schema.clone())
+                .with_settings(settings.clone());
+            let (semantic, semantic_total) = executor.execute_vector(
+                &searcher,
+                &index.vectors(),
+                vector,
+                filter,
+                effective_limit,
+            )?;
+            // Without a query the vector ranking is the whole result;
+            // otherwise it is fused with the keyword ranking, or appended
+            // to it as a fallback.
+            let merged = if query_text.trim().is_empty() {
+                total = semantic_total;
+                semantic
+            } else if semantic_fallback {
+                for doc in semantic {
+                    if seen_ids.insert(doc.document.id.clone()) {
+                        all_results.push(doc);
+                        total += 1;
+                    }
+                }
+                all_results
+            } else {
+                // Records found by both searches count once
+                total += semantic
+                    .iter()
+                    .filter(|doc| !seen_ids.contains(&doc.document.id))
+                    .count();
+                crate::query::executor::reciprocal_rank_fusion(all_results, semantic)
+            };
+            all_results = match distinct.filter(|d| *d > 0) {
+                Some(d) => executor.apply_distinct(merged, total, d)?.0,
+                None => merged,
+            };
+        }
         let result_count = all_results.len();
         let start = offset.min(result_count);
         let end = (start + limit).min(result_count);
@@ -1022,7 +1339,8 @@ impl IndexManager {
             (page_results, Vec::new(), Vec::new())
         };
 
-        // removeWordsIfNoResults: retry with fewer words if we got 0 results
+        // removeWordsIfNoResults: retry with fewer words if we got 0 results,
+        // including from the semantic fallback
         let remove_strategy = remove_words_override
             .or(settings
                 .as_ref()
@@ -1046,7 +1364,7 @@ impl IndexManager {
                     _ => vec![],
                 };
                 for fallback_q in fallback_queries {
-                    if let Ok(retry) = self.search_full_with_stop_words(
+                    if let Ok(retry) = self.search_full_with_settings(
                         tenant_id,
                         &fallback_q,
                         filter,
@@ -1068,6 +1386,8 @@ impl IndexManager {
                         enable_rules,
                         rule_contexts,
                         restrict_searchable_attrs,
+                        settings_override.clone(),
+                        vector,
                     ) {
                         if retry.total > 0 {
                             return Ok(retry);
@@ -1091,6 +1411,59 @@ impl IndexManager {
         })
     }
 
+    /// The write queue of a tenant, started on first use.
+    ///
+    /// A queue is only started on the loaded index: if the tenant was
+    /// evicted since `index` was loaded, it is reopened. On error the task
+    /// `task_id` is marked as failed.
+    fn write_queue(
+        &self,
+        tenant_id: &str,
+        index: &Arc<Index>,
+        task_id: &str,
+    ) -> Result<WriteQueue> {
+        let mut index = Arc::clone(index);
+        loop {
+            match self.write_queues.entry(tenant_id.to_string()) {
+                Entry::Occupied(queue) => return Ok(queue.get().clone()),
+                Entry::Vacant(slot)
+                    if self
+                        .loaded
+                        .get(tenant_id)
+                        .is_some_and(|loaded| Arc::ptr_eq(&loaded, &index)) =>
+                {
+                    let oplog = self.get_or_create_oplog(tenant_id);
+                    let writer_slot =
+                        Arc::clone(&self.writer_slots.entry(tenant_id.to_string()).or_default());
+                    let (queue, handle) = create_write_queue(
+                        tenant_id.to_string(),
+                        index,
+                        Arc::clone(&self.writers),
+                        Arc::clone(&self.tasks),
+                        self.base_path.clone(),
+                        oplog,
+                        Arc::clone(&self.facet_cache),
+                        writer_slot,
+                    );
+                    self.write_task_handles
+                        .insert(tenant_id.to_string(), handle);
+                    return Ok(slot.insert(queue).clone());
+                }
+                Entry::Vacant(_) => {}
+            }
+            index = match self.get_or_load(tenant_id) {
+                Ok(index) => index,
+                Err(e) => {
+                    self.tasks.alter(task_id, |_, mut t| {
+                        t.status = TaskStatus::Failed(e.to_string());
+                        t
+                    });
+                    return Err(e);
+                }
+            };
+        }
+    }
+
     /// Add documents to a tenant's index.
     ///
     /// Creates a writer, adds documents, and commits immediately.
@@ -1109,7 +1482,14 @@ impl IndexManager {
         docs: Vec<Document>,
         upsert: bool,
     ) -> Result<TaskInfo> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         let index = self.get_or_load(tenant_id)?;
+        if docs
+            .iter()
+            .any(|doc| doc.fields.contains_key(expiry::EXPIRES_AT))
+        {
+            self.expiring.insert(tenant_id.to_string(), true);
+        }
 
         let numeric_id = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
@@ -1122,25 +1502,7 @@ impl IndexManager {
 
         self.evict_old_tasks(tenant_id, MAX_TASKS_PER_TENANT);
 
-        let tx = self
-            .write_queues
-            .entry(tenant_id.to_string())
-            .or_insert_with(|| {
-                let oplog = self.get_or_create_oplog(tenant_id);
-                let (queue, handle) = create_write_queue(
-                    tenant_id.to_string(),
-                    Arc::clone(&index),
-                    Arc::clone(&self.writers),
-                    Arc::clone(&self.tasks),
-                    self.base_path.clone(),
-                    oplog,
-                    Arc::clone(&self.facet_cache),
-                );
-                self.write_task_handles
-                    .insert(tenant_id.to_string(), handle);
-                queue
-            })
-            .clone();
+        let tx = self.write_queue(tenant_id, &index, &task_id)?;
 
         let actions = if upsert {
             docs.into_iter().map(WriteAction::Upsert).collect()
@@ -1151,6 +1513,253 @@ impl IndexManager {
             .try_send(WriteOp {
                 task_id: task_id.clone(),
                 actions,
+                partial: false,
+            })
+            .is_err()
+        {
+            self.tasks.alter(&task_id, |_, mut t| {
+                t.status = TaskStatus::Failed("Queue full".to_string());
+                t
+            });
+            return Err(FlapjackError::QueueFull);
+        }
+
+        Ok(task)
+    }
+
+    /// Import a stream of NDJSON or CSV records into a tenant's index as a
+    /// single task.
+    ///
+    /// Documents are sent to the write queue in chunks of `BULK_CHUNK_SIZE`,
+    /// waiting for room in the queue, so reading is slowed to the indexing
+    /// rate instead of buffering the stream. Records that cannot be parsed
+    /// are counted as rejected with their line number. Returns once every
+    /// chunk is queued; the task succeeds when the last one is committed.
+    ///
+    /// Blocks the calling thread: run it with `spawn_blocking`.
+    pub fn import_records(
+        &self,
+        tenant_id: &str,
+        reader: impl std::io::Read,
+        format: &BulkFormat,
+    ) -> Result<TaskInfo> {
+        self.import_stream(tenant_id, |push| {
+            bulk::for_each_record(reader, format, push)
+        })
+    }
+
+    /// Index the records `stream` pushes as one task, as described for
+    /// [`Self::import_records`].
+    fn import_stream(
+        &self,
+        tenant_id: &str,
+        stream: impl FnOnce(&mut dyn FnMut(Record) -> Result<()>) -> Result<()>,
+    ) -> Result<TaskInfo> {
+        let tenant_id = &self.resolve_alias(tenant_id);
+        let index = self.get_or_load(tenant_id)?;
+
+        let numeric_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let task_id = format!("task_{}_{}", tenant_id, uuid::Uuid::new_v4());
+        let task = TaskInfo::new(task_id.clone(), numeric_id, 0);
+        self.tasks.insert(task_id.clone(), task.clone());
+        self.tasks.insert(numeric_id.to_string(), task);
+
+        self.evict_old_tasks(tenant_id, MAX_TASKS_PER_TENANT);
+
+        let tx = self.write_queue(tenant_id, &index, &task_id)?;
+        let update = |f: &dyn Fn(&mut TaskInfo)| {
+            for key in [task_id.clone(), numeric_id.to_string()] {
+                self.tasks.alter(&key, |_, mut t| {
+                    f(&mut t);
+                    t
+                });
+            }
+        };
+        let send = |chunk: Vec<Document>, partial: bool| {
+            update(&|t: &mut TaskInfo| t.received_documents += chunk.len());
+            if chunk
+                .iter()
+                .any(|doc| doc.fields.contains_key(expiry::EXPIRES_AT))
+            {
+                self.expiring.insert(tenant_id.to_string(), true);
+            }
+            tx.blocking_send(WriteOp {
+                task_id: task_id.clone(),
+                actions: chunk.into_iter().map(WriteAction::Upsert).collect(),
+                partial,
+            })
+            .map_err(|_| FlapjackError::QueueFull)
+        };
+
+        let mut chunk = Vec::with_capacity(BULK_CHUNK_SIZE);
+        let mut push = |record: Record| {
+            match record {
+                Ok(doc) => chunk.push(doc),
+                Err(failure) => update(&|t: &mut TaskInfo| {
+                    t.received_documents += 1;
+                    t.rejected_count += 1;
+                    if t.rejected_documents.len() < MAX_REJECTED_DOCUMENTS {
+                        t.rejected_documents.push(failure.clone());
+                    }
+                }),
+            }
+            if chunk.len() >= BULK_CHUNK_SIZE {
+                send(std::mem::take(&mut chunk), true)?;
+            }
+            Ok(())
+        };
+        let streamed = stream(&mut push).and_then(|()| send(std::mem::take(&mut chunk), false));
+
+        if let Err(e) = streamed {
+            // Chunks already queued are still indexed
+            update(&|t: &mut TaskInfo| t.status = TaskStatus::Failed(e.to_string()));
+            return Err(e);
+        }
+        self.get_task(&task_id)
+    }
+
+    /// Write a logical dump of a tenant: its settings, synonyms and rules,
+    /// the API key metadata in `keys`, then every document. Returns the
+    /// number of documents written.
+    pub fn export_dump(
+        &self,
+        tenant_id: &str,
+        keys: Vec<serde_json::Value>,
+        mut writer: impl std::io::Write,
+    ) -> Result<usize> {
+        let tenant_id = &self.resolve_alias(tenant_id);
+        let index = self.get_or_load(tenant_id)?;
+
+        dump::write_entry(&mut writer, &DumpEntry::header(tenant_id))?;
+        if let Some(settings) = self.get_settings(tenant_id) {
+            let settings = (*settings).clone();
+            dump::write_entry(&mut writer, &DumpEntry::Settings { settings })?;
+        }
+        if let Some(synonyms) = self.get_synonyms(tenant_id) {
+            for synonym in synonyms.all() {
+                dump::write_entry(&mut writer, &DumpEntry::Synonym { synonym })?;
+            }
+        }
+        if let Some(rules) = self.get_rules(tenant_id) {
+            for rule in rules.all() {
+                dump::write_entry(&mut writer, &DumpEntry::Rule { rule })?;
+            }
+        }
+        for key in keys {
+            dump::write_entry(&mut writer, &DumpEntry::Key { key })?;
+        }
+
+        let searcher = index.reader().searcher();
+        let mut count = 0;
+        for document in index.live_documents(&searcher) {
+            let document = document?;
+            dump::write_entry(&mut writer, &DumpEntry::Document { document })?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Import a logical dump into a tenant, creating it if needed. The
+    /// settings, synonyms and rules of the dump replace the tenant's and are
+    /// recorded in the oplog; documents are upserted through the write queue
+    /// as one task, as for [`Self::import_records`]. The tenant's other
+    /// documents are kept, so restore into an empty tenant for an exact
+    /// copy. API keys are left to the caller.
+    ///
+    /// Blocks the calling thread: run it with `spawn_blocking`.
+    pub fn import_dump(&self, tenant_id: &str, reader: impl std::io::Read) -> Result<DumpImport> {
+        let tenant_id = &self.resolve_alias(tenant_id);
+        self.create_tenant(tenant_id)?;
+        let tenant_path = self.base_path.join(tenant_id);
+
+        let mut settings = false;
+        let mut synonyms = SynonymStore::new();
+        let mut rules = RuleStore::new();
+        let mut keys = Vec::new();
+        let task = self.import_stream(tenant_id, |push| {
+            dump::for_each_entry(reader, |entry| {
+                match entry {
+                    // Saved as they come, before the documents they apply to
+                    Ok(DumpEntry::Settings { settings: s }) => {
+                        s.save(tenant_path.join("settings.json"))?;
+                        self.invalidate_settings_cache(tenant_id);
+                        self.invalidate_facet_cache(tenant_id);
+                        self.append_oplog(tenant_id, "settings", serde_json::to_value(&s)?);
+                        settings = true;
+                    }
+                    Ok(DumpEntry::Synonym { synonym }) => synonyms.insert(synonym),
+                    Ok(DumpEntry::Rule { rule }) => rules.insert(rule),
+                    Ok(DumpEntry::Key { key }) => keys.push(key),
+                    Ok(DumpEntry::Document { document }) => push(Ok(document))?,
+                    Ok(DumpEntry::Header { .. } | DumpEntry::Unknown) => {}
+                    Err(failure) => push(Err(failure))?,
+                }
+                Ok(())
+            })?;
+
+            // Saved even when empty, so none of the tenant's are left over
+            synonyms.save(tenant_path.join("synonyms.json"))?;
+            self.invalidate_synonyms_cache(tenant_id);
+            self.append_oplog(
+                tenant_id,
+                "save_synonyms",
+                serde_json::json!({"synonyms": synonyms.all(), "replace": true}),
+            );
+            rules.save(&tenant_path.join("rules.json"))?;
+            self.invalidate_rules_cache(tenant_id);
+            self.append_oplog(
+                tenant_id,
+                "save_rules",
+                serde_json::json!({"rules": rules.all(), "clearExisting": true}),
+            );
+            Ok(())
+        })?;
+
+        Ok(DumpImport {
+            task,
+            settings,
+            synonyms: synonyms.all().len(),
+            rules: rules.all().len(),
+            keys,
+        })
+    }
+
+    /// Re-add every document so it is re-tokenized with the current analyzer
+    /// settings, e.g. after `keepDiacriticsOnCharacters` changes.
+    ///
+    /// The reindex is enqueued on the write queue, after the writes already
+    /// queued, and streams the documents into the writer in committed
+    /// batches. The returned task counts the documents to re-add in
+    /// `received_documents` and those re-added so far in
+    /// `indexed_documents`.
+    pub fn reindex(&self, tenant_id: &str) -> Result<TaskInfo> {
+        let tenant_id = &self.resolve_alias(tenant_id);
+        let index = self.get_or_load(tenant_id)?;
+        let documents = index.reader().searcher().num_docs() as usize;
+        if documents == 0 {
+            return self.make_noop_task(tenant_id);
+        }
+
+        let numeric_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let task_id = format!("task_{}_{}", tenant_id, uuid::Uuid::new_v4());
+        let task = TaskInfo::new(task_id.clone(), numeric_id, documents);
+        self.tasks.insert(task_id.clone(), task.clone());
+        self.tasks.insert(numeric_id.to_string(), task.clone());
+
+        let tx = self.write_queue(tenant_id, &index, &task_id)?;
+
+        if tx
+            .try_send(WriteOp {
+                task_id: task_id.clone(),
+                actions: vec![WriteAction::Reindex],
+                partial: false,
             })
             .is_err()
         {
@@ -1164,9 +1773,403 @@ impl IndexManager {
         Ok(task)
     }
 
+    /// Whether a tenant's index was built with a schema older than
+    /// [`migration::SCHEMA_VERSION`].
+    pub fn needs_schema_migration(&self, tenant_id: &str) -> bool {
+        migration::needs_migration(&self.base_path.join(self.resolve_alias(tenant_id)))
+    }
+
+    /// The tenants on disk whose index needs a schema migration.
+    pub fn outdated_tenants(&self) -> Vec<TenantId> {
+        let Ok(entries) = std::fs::read_dir(&self.base_path) else {
+            return Vec::new();
+        };
+        let mut tenants: Vec<TenantId> = entries
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_string_lossy().into_owned())
+            .filter(|name| !name.starts_with('.'))
+            .filter(|name| migration::needs_migration(&self.base_path.join(name)))
+            .collect();
+        tenants.sort();
+        tenants
+    }
+
+    /// Rebuild a tenant's index with the current schema, in the background.
+    ///
+    /// The stored documents are copied into a new index under
+    /// [`migration::MIGRATIONS_DIR`] while the live index keeps serving
+    /// searches and writes. The write queue then replays the writes made
+    /// meanwhile from the oplog and swaps the directories, so writes only
+    /// wait for the swap. The returned task counts the documents to copy in
+    /// `received_documents` and those copied so far in `indexed_documents`,
+    /// and succeeds once the new index is live. While a migration of the
+    /// tenant runs, its task is returned instead of starting another.
+    pub fn migrate_schema(self: &Arc<Self>, tenant_id: &str) -> Result<TaskInfo> {
+        let tenant_id = self.resolve_alias(tenant_id);
+        let index = self.get_or_load(&tenant_id)?;
+
+        let numeric_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_millis() as i64;
+        let task_id = format!("task_{}_{}", tenant_id, uuid::Uuid::new_v4());
+        match self.migrations.entry(tenant_id.clone()) {
+            dashmap::mapref::entry::Entry::Occupied(running) => {
+                return self.get_task(running.get());
+            }
+            dashmap::mapref::entry::Entry::Vacant(slot) => {
+                slot.insert(task_id.clone());
+            }
+        }
+        let total = index.reader().searcher().num_docs() as usize;
+        let task = TaskInfo::new(task_id.clone(), numeric_id, total);
+        self.tasks.insert(task_id.clone(), task.clone());
+        self.tasks.insert(numeric_id.to_string(), task.clone());
+
+        self.evict_old_tasks(&tenant_id, MAX_TASKS_PER_TENANT);
+
+        let manager = Arc::clone(self);
+        tokio::task::spawn_blocking(move || {
+            let queued = manager
+                .build_shadow_index(&tenant_id, &task_id)
+                .and_then(|since| manager.queue_swap(&tenant_id, &task_id, since));
+            if let Err(e) = queued {
+                tracing::error!("[MIGRATE {}] migration failed: {}", tenant_id, e);
+                manager.update_task(&task_id, |t| t.status = TaskStatus::Failed(e.to_string()));
+                manager.end_migration(&tenant_id);
+            }
+        });
+        Ok(task)
+    }
+
+    /// Copy the documents of a tenant into a new index in its shadow
+    /// directory. Returns the oplog sequence number the copy includes.
+    fn build_shadow_index(&self, tenant_id: &str, task_id: &str) -> Result<u64> {
+        let live = self.get_or_load(tenant_id)?;
+        // The searcher holds at least the writes committed up to `since`;
+        // later ones are replayed from the oplog at the swap
+        let since = migration::committed_seq(&self.base_path.join(tenant_id));
+        if let Some(oplog) = self.get_or_create_oplog(tenant_id) {
+            oplog.retain_after(since);
+        }
+        live.reader().reload()?;
+        let searcher = live.reader().searcher();
+        self.update_task(task_id, |t| {
+            t.status = TaskStatus::Processing;
+            t.received_documents = searcher.num_docs() as usize;
+            t.indexed_documents = 0;
+        });
+
+        let shadow_path = migration::shadow_dir(&self.base_path, tenant_id);
+        if shadow_path.exists() {
+            std::fs::remove_dir_all(&shadow_path)?;
+        }
+        std::fs::create_dir_all(&shadow_path)?;
+        let shadow = Index::create(
+            &shadow_path,
+            crate::index::schema::Schema::builder().build(),
+        )?;
+        self.apply_analyzer_settings(tenant_id, &shadow);
+        let settings = self.get_settings(tenant_id);
+        let copied =
+            migration::copy_documents(&live, &searcher, &shadow, settings.as_deref(), |copied| {
+                self.update_task(task_id, |t| t.indexed_documents = copied)
+            })?;
+        tracing::info!(
+            "[MIGRATE {}] copied {} documents up to seq {}",
+            tenant_id,
+            copied,
+            since
+        );
+        Ok(since)
+    }
+
+    /// Queue the swap of a tenant's shadow index behind its pending writes.
+    fn queue_swap(self: &Arc<Self>, tenant_id: &str, task_id: &str, since: u64) -> Result<()> {
+        let index = self.get_or_load(tenant_id)?;
+        let manager = Arc::clone(self);
+        let tenant = tenant_id.to_string();
+        let swap: SwapIndex = Box::new(move || {
+            let swapped = manager.swap_shadow_index(&tenant, since);
+            manager.end_migration(&tenant);
+            swapped
+        });
+        self.write_queue(tenant_id, &index, task_id)?
+            .blocking_send(WriteOp {
+                task_id: task_id.to_string(),
+                actions: vec![WriteAction::SwapIndex(swap)],
+                partial: false,
+            })
+            .map_err(|_| FlapjackError::QueueFull)
+    }
+
+    /// Put a tenant's shadow index in place of its live one. Runs in the
+    /// write queue with the writer closed: the writes after `since` are
+    /// replayed from the oplog into the shadow index, the oplog is moved and
+    /// the other tenant files copied over, then the directories are swapped
+    /// and the new index loaded. The oplog keeps the writes after `since`
+    /// until the migration ends; if it was reopened meanwhile and lost them,
+    /// the swap fails. On error the live directory is left as it was.
+    fn swap_shadow_index(&self, tenant_id: &str, since: u64) -> Result<Arc<Index>> {
+        let live_path = self.base_path.join(tenant_id);
+        let shadow_path = migration::shadow_dir(&self.base_path, tenant_id);
+        let previous_path = migration::previous_dir(&self.base_path, tenant_id);
+        if !live_path.exists() {
+            return Err(FlapjackError::TenantNotFound(tenant_id.to_string()));
+        }
+
+        // Reading through the tenant's oplog also flushes its buffered ops
+        // for the replay
+        let replayable = match self.get_or_create_oplog(tenant_id) {
+            Some(oplog) => match oplog.read_since(since)?.first() {
+                Some(op) => op.seq == since + 1,
+                None => oplog.current_seq() == since,
+            },
+            None => false,
+        };
+        if !replayable {
+            // Copying again here would hold up the writes for as long as
+            // the first copy took
+            return Err(FlapjackError::Io(format!(
+                "oplog does not reach back to seq {}, migrate the tenant again",
+                since
+            )));
+        }
+
+        if previous_path.exists() {
+            std::fs::remove_dir_all(&previous_path)?;
+        }
+        migration::copy_tenant_state(&live_path, &shadow_path)?;
+        std::fs::write(shadow_path.join("committed_seq"), since.to_string())?;
+        let oplog_dir = live_path.join("oplog");
+        let moved_oplog = oplog_dir.exists();
+        if moved_oplog {
+            std::fs::rename(&oplog_dir, shadow_path.join("oplog"))?;
+        }
+        let restore_oplog = || {
+            if moved_oplog {
+                if let Err(e) = std::fs::rename(shadow_path.join("oplog"), &oplog_dir) {
+                    tracing::error!("[MIGRATE {}] failed to restore the oplog: {}", tenant_id, e);
+                }
+            }
+        };
+
+        let replayed = Index::open(&shadow_path).and_then(|shadow| {
+            let shadow = Arc::new(shadow);
+            self.apply_analyzer_settings(tenant_id, &shadow);
+            self.recover_from_oplog(tenant_id, &shadow, &shadow_path)
+        });
+        if let Err(e) = replayed {
+            restore_oplog();
+            return Err(e);
+        }
+
+        if let Err(e) = std::fs::rename(&live_path, &previous_path) {
+            restore_oplog();
+            return Err(e.into());
+        }
+        let swapped = std::fs::rename(&shadow_path, &live_path)
+            .map_err(FlapjackError::from)
+            .and_then(|_| Index::open(&live_path));
+        let index = match swapped {
+            Ok(index) => Arc::new(index),
+            Err(e) => {
+                if live_path.exists() {
+                    let _ = std::fs::rename(&live_path, &shadow_path);
+                }
+                if let Err(e) = std::fs::rename(&previous_path, &live_path) {
+                    tracing::error!("[MIGRATE {}] failed to restore the index: {}", tenant_id, e);
+                }
+                restore_oplog();
+                return Err(e);
+            }
+        };
+
+        self.apply_analyzer_settings(tenant_id, &index);
+        let _ = index.searchable_paths();
+        self.loaded
+            .insert(tenant_id.to_string(), Arc::clone(&index));
+        self.invalidate_facet_cache(tenant_id);
+        self.expiring.remove(tenant_id);
+        tracing::info!(
+            "[MIGRATE {}] now on schema version {}",
+            tenant_id,
+            migration::SCHEMA_VERSION
+        );
+        Ok(index)
+    }
+
+    /// Forget a finished migration and remove its directories. They are
+    /// kept when the tenant's directory is missing, for
+    /// [`migration::recover_interrupted`] to restore it.
+    fn end_migration(&self, tenant_id: &str) {
+        self.migrations.remove(tenant_id);
+        if let Some(oplog) = self.oplogs.get(tenant_id) {
+            oplog.release();
+        }
+        let dir = migration::migration_dir(&self.base_path, tenant_id);
+        if dir.exists() && self.base_path.join(tenant_id).exists() {
+            if let Err(e) = std::fs::remove_dir_all(&dir) {
+                tracing::warn!("[MIGRATE {}] failed to remove {:?}: {}", tenant_id, dir, e);
+            }
+        }
+    }
+
+    /// Update a task under both its ids.
+    fn update_task(&self, task_id: &str, f: impl Fn(&mut TaskInfo)) {
+        let numeric_id = self.tasks.get(task_id).map(|t| t.numeric_id.to_string());
+        for key in std::iter::once(task_id.to_string()).chain(numeric_id) {
+            self.tasks.alter(&key, |_, mut t| {
+                f(&mut t);
+                t
+            });
+        }
+    }
+
+    /// Migrate the tenants of [`Self::outdated_tenants`] one after the
+    /// other, so that a single index is rebuilt at a time.
+    pub async fn run_schema_migrations(self: Arc<Self>) {
+        let manager = Arc::clone(&self);
+        let tenants = tokio::task::spawn_blocking(move || manager.outdated_tenants())
+            .await
+            .unwrap_or_default();
+        for tenant_id in tenants {
+            let task = match self.migrate_schema(&tenant_id) {
+                Ok(task) => task,
+                Err(e) => {
+                    tracing::error!("[MIGRATE {}] cannot migrate: {}", tenant_id, e);
+                    continue;
+                }
+            };
+            tracing::info!(
+                "[MIGRATE {}] migrating to schema version {} as task {}",
+                tenant_id,
+                migration::SCHEMA_VERSION,
+                task.numeric_id
+            );
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+                match self.get_task(&task.id).map(|t| t.status) {
+                    Ok(TaskStatus::Enqueued | TaskStatus::Processing) => {}
+                    _ => break,
+                }
+            }
+        }
+    }
+
+    /// Whether a tenant may hold documents that expire: its settings define
+    /// `expiresAtAttribute`, or documents with `_expiresAt` were written to
+    /// it or found when it was loaded.
+    fn may_expire(&self, tenant_id: &str, index: &Index, settings: Option<&IndexSettings>) -> bool {
+        if expiry::is_configured(settings) {
+            return true;
+        }
+        if let Some(known) = self.expiring.get(tenant_id) {
+            return *known;
+        }
+        let found = count_matching(index, &expiry::expiring_filter()).unwrap_or_else(|e| {
+            tracing::warn!(
+                "[EXPIRY {}] failed to look for expiring documents: {}",
+                tenant_id,
+                e
+            );
+            0
+        }) > 0;
+        self.expiring.insert(tenant_id.to_string(), found);
+        found
+    }
+
+    /// Enqueue the deletion of up to [`expiry::SWEEP_BATCH`] expired
+    /// documents of a loaded tenant through its write queue, so the deletes
+    /// are recorded in the oplog and replicated. The sweep does not count as
+    /// an access, so idle tenants are still evicted, and a tenant that is not
+    /// loaded is skipped. Returns the number of documents enqueued.
+    pub fn sweep_expired(&self, tenant_id: &str) -> Result<usize> {
+        let tenant_id = &self.resolve_alias(tenant_id);
+        let Some(index) = self.loaded.get(tenant_id).map(|e| Arc::clone(&e)) else {
+            return Ok(0);
+        };
+        let searcher = index.reader().searcher();
+        let schema = index.inner().schema();
+        let query = FilterCompiler::new(schema.clone())
+            .compile(&expiry::expired_filter(expiry::now()), None)?;
+        let top_docs = searcher.search(
+            query.as_ref(),
+            &tantivy::collector::TopDocs::with_limit(expiry::SWEEP_BATCH),
+        )?;
+        let mut ids = Vec::with_capacity(top_docs.len());
+        for (_, addr) in top_docs {
+            let tantivy_doc: tantivy::TantivyDocument = searcher.doc(addr)?;
+            let doc = index
+                .converter()
+                .from_tantivy(tantivy_doc, &schema, String::new())?;
+            ids.push(doc.id);
+        }
+        let count = ids.len();
+        if count > 0 {
+            self.enqueue_deletes(tenant_id, &index, ids)?;
+            tracing::info!(
+                "[EXPIRY {}] deleting {} expired documents",
+                tenant_id,
+                count
+            );
+        }
+        Ok(count)
+    }
+
+    /// Sweep the expired documents of every loaded tenant that may hold
+    /// some. Unloaded tenants are swept once loaded again; until then their
+    /// expired documents are already hidden from searches. Returns the number
+    /// of documents enqueued for deletion.
+    pub fn sweep_expired_documents(&self) -> usize {
+        let tenants: Vec<TenantId> = self.loaded.iter().map(|e| e.key().clone()).collect();
+        let mut swept = 0;
+        for tenant_id in tenants {
+            let Some(index) = self.loaded.get(&tenant_id).map(|e| Arc::clone(&e)) else {
+                continue;
+            };
+            let settings = self.get_settings(&tenant_id);
+            if !self.may_expire(&tenant_id, &index, settings.as_deref()) {
+                continue;
+            }
+            match self.sweep_expired(&tenant_id) {
+                Ok(count) => swept += count,
+                Err(e) => tracing::warn!("[EXPIRY {}] sweep failed: {}", tenant_id, e),
+            }
+        }
+        swept
+    }
+
+    /// Run [`Self::sweep_expired_documents`] every
+    /// [`expiry::sweep_interval`].
+    pub async fn run_expiry_loop(self: Arc<Self>) {
+        let interval = expiry::sweep_interval();
+        loop {
+            tokio::time::sleep(interval).await;
+            let manager = Arc::clone(&self);
+            if let Err(e) =
+                tokio::task::spawn_blocking(move || manager.sweep_expired_documents()).await
+            {
+                tracing::warn!("[EXPIRY] sweep task failed: {}", e);
+            }
+        }
+    }
+
     pub fn delete_documents(&self, tenant_id: &str, object_ids: Vec<String>) -> Result<TaskInfo> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         let index = self.get_or_load(tenant_id)?;
+        self.enqueue_deletes(tenant_id, &index, object_ids)
+    }
 
+    /// Enqueue the deletion of `object_ids` on the write queue of the loaded
+    /// `index`.
+    fn enqueue_deletes(
+        &self,
+        tenant_id: &str,
+        index: &Arc<Index>,
+        object_ids: Vec<String>,
+    ) -> Result<TaskInfo> {
         let numeric_id = std::time::SystemTime::now()
             .duration_since(std::time::UNIX_EPOCH)
             .unwrap()
@@ -1178,31 +2181,14 @@ impl IndexManager {
 
         self.evict_old_tasks(tenant_id, MAX_TASKS_PER_TENANT);
 
-        let tx = self
-            .write_queues
-            .entry(tenant_id.to_string())
-            .or_insert_with(|| {
-                let oplog = self.get_or_create_oplog(tenant_id);
-                let (queue, handle) = create_write_queue(
-                    tenant_id.to_string(),
-                    Arc::clone(&index),
-                    Arc::clone(&self.writers),
-                    Arc::clone(&self.tasks),
-                    self.base_path.clone(),
-                    oplog,
-                    Arc::clone(&self.facet_cache),
-                );
-                self.write_task_handles
-                    .insert(tenant_id.to_string(), handle);
-                queue
-            })
-            .clone();
+        let tx = self.write_queue(tenant_id, index, &task_id)?;
 
         let actions = object_ids.into_iter().map(WriteAction::Delete).collect();
         if tx
             .try_send(WriteOp {
                 task_id: task_id.clone(),
                 actions,
+                partial: false,
             })
             .is_err()
         {
@@ -1221,6 +2207,7 @@ impl IndexManager {
     /// This reclaims disk space from deleted documents. The operation is
     /// enqueued on the write queue so it serialises with other writes.
     pub fn compact_index(&self, tenant_id: &str) -> Result<TaskInfo> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         let index = self.get_or_load(tenant_id)?;
 
         let numeric_id = std::time::SystemTime::now()
@@ -1232,30 +2219,13 @@ impl IndexManager {
         self.tasks.insert(task_id.clone(), task.clone());
         self.tasks.insert(numeric_id.to_string(), task.clone());
 
-        let tx = self
-            .write_queues
-            .entry(tenant_id.to_string())
-            .or_insert_with(|| {
-                let oplog = self.get_or_create_oplog(tenant_id);
-                let (queue, handle) = create_write_queue(
-                    tenant_id.to_string(),
-                    Arc::clone(&index),
-                    Arc::clone(&self.writers),
-                    Arc::clone(&self.tasks),
-                    self.base_path.clone(),
-                    oplog,
-                    Arc::clone(&self.facet_cache),
-                );
-                self.write_task_handles
-                    .insert(tenant_id.to_string(), handle);
-                queue
-            })
-            .clone();
+        let tx = self.write_queue(tenant_id, &index, &task_id)?;
 
         if tx
             .try_send(WriteOp {
                 task_id: task_id.clone(),
                 actions: vec![WriteAction::Compact],
+                partial: false,
             })
             .is_err()
         {
@@ -1298,6 +2268,7 @@ impl IndexManager {
         self.settings_cache.remove(tenant_id);
         self.rules_cache.remove(tenant_id);
         self.synonyms_cache.remove(tenant_id);
+        self.last_access.remove(tenant_id);
         Ok(())
     }
 
@@ -1311,6 +2282,7 @@ impl IndexManager {
         self.writers.remove(tenant_id);
         self.oplogs.remove(tenant_id);
         self.loaded.remove(tenant_id);
+        self.last_access.remove(tenant_id);
 
         let path = self.base_path.join(tenant_id);
         if path.exists() {
@@ -1381,6 +2353,197 @@ impl IndexManager {
         self.loaded.len()
     }
 
+    pub fn eviction_config(&self) -> EvictionConfig {
+        self.eviction_config.read().unwrap().clone()
+    }
+
+    pub fn set_eviction_config(&self, config: EvictionConfig) {
+        *self.eviction_config.write().unwrap() = config;
+        self.eviction_wakeup.notify_one();
+    }
+
+    pub fn eviction_stats(&self) -> EvictionStats {
+        let config = self.eviction_config();
+        EvictionStats {
+            loaded_tenants: self.loaded.len(),
+            max_loaded_tenants: config.max_loaded_tenants,
+            idle_timeout_secs: config.idle_timeout.map(|d| d.as_secs()).unwrap_or(0),
+            idle_evictions: self.idle_evictions.load(Ordering::Relaxed),
+            lru_evictions: self.lru_evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Pin the last commit of a tenant for an incremental snapshot, so that
+    /// its segment files outlive merges until the pin is dropped. A loaded
+    /// tenant is pinned without counting as an access; an unloaded one is
+    /// loaded, since only the loaded index's writer merges segments.
+    pub fn pin_commit(&self, tenant_id: &str) -> Result<PinnedCommit> {
+        let tenant_id = &self.resolve_alias(tenant_id);
+        let index = match self.loaded.get(tenant_id).map(|e| Arc::clone(&e)) {
+            Some(index) => index,
+            None => self.get_or_load(tenant_id)?,
+        };
+        PinnedCommit::pin(&index, &self.base_path.join(tenant_id))
+    }
+
+    /// Release a tenant's memory: flush and close its write queue (which
+    /// frees the writer's budget slot), then drop the index and the cached
+    /// settings, rules and synonyms. The next [`Self::get_or_load`] reopens
+    /// it from disk. Tenants with pending tasks, or written to while the
+    /// queue closes, are skipped; returns whether the tenant was evicted.
+    pub async fn evict_tenant(&self, tenant_id: &str) -> bool {
+        // Checked under the queue's entry lock, which writes take to queue
+        let handle = {
+            let entry = self.write_queues.entry(tenant_id.to_string());
+            if !self.loaded.contains_key(tenant_id) || self.pending_task_count(tenant_id) > 0 {
+                return false;
+            }
+            if let Entry::Occupied(queue) = entry {
+                queue.remove();
+            }
+            self.write_task_handles.remove(tenant_id)
+        };
+        if let Some((_, handle)) = handle {
+            match handle.await {
+                Ok(Err(e)) => tracing::error!("[EVICT {}] write queue failed: {}", tenant_id, e),
+                Err(e) => tracing::error!("[EVICT {}] write queue panicked: {}", tenant_id, e),
+                Ok(Ok(())) => {}
+            }
+        }
+        // A write meanwhile started a new queue on the loaded index; once
+        // the index is dropped, writes reopen it instead
+        let entry = self.write_queues.entry(tenant_id.to_string());
+        if matches!(entry, Entry::Occupied(_)) {
+            return false;
+        }
+        self.writers.remove(tenant_id);
+        self.oplogs.remove(tenant_id);
+        self.loaded.remove(tenant_id);
+        self.last_access.remove(tenant_id);
+        self.settings_cache.remove(tenant_id);
+        self.rules_cache.remove(tenant_id);
+        self.synonyms_cache.remove(tenant_id);
+        self.invalidate_facet_cache(tenant_id);
+        tracing::info!("[EVICT {}] unloaded", tenant_id);
+        true
+    }
+
+    /// Run `rewrite`, which replaces the files of `tenant_id`, with the
+    /// tenant evicted and kept from being loaded, or its oplog from being
+    /// written, until it returns. Fails with [`FlapjackError::TenantBusy`]
+    /// when the tenant has pending tasks or is written to while its queue
+    /// closes.
+    pub async fn rewrite_tenant<T, F>(&self, tenant_id: &str, rewrite: F) -> Result<T>
+    where
+        F: std::future::Future<Output = Result<T>>,
+    {
+        let file_lock = self.file_lock(tenant_id);
+        let _rewriting = file_lock.write().await;
+        if self.loaded.contains_key(tenant_id) && !self.evict_tenant(tenant_id).await {
+            return Err(FlapjackError::TenantBusy(tenant_id.to_string()));
+        }
+        self.oplogs.remove(tenant_id);
+        rewrite.await
+    }
+
+    /// Re-encrypt a tenant's index files and oplog with the current master
+    /// key, after a key rotation or when encryption is enabled on existing
+    /// data. The tenant is evicted first and reopened by the next request;
+    /// until the files are rewritten, loading it fails with
+    /// [`FlapjackError::TenantBusy`]. Returns the number of rewritten files.
+    pub async fn reencrypt_tenant(&self, tenant_id: &str) -> Result<usize> {
+        let keyring = encryption::keyring()?
+            .ok_or_else(|| FlapjackError::Config("No master key is configured".to_string()))?;
+        let path = self.base_path.join(tenant_id);
+        if !path.exists() {
+            return Err(FlapjackError::TenantNotFound(tenant_id.to_string()));
+        }
+        // Waits for running loads, then keeps the tenant from being
+        // reopened, or its oplog from being written, during the rewrite
+        let file_lock = self.file_lock(tenant_id);
+        let _rewriting = file_lock.write().await;
+        if self.loaded.contains_key(tenant_id) && !self.evict_tenant(tenant_id).await {
+            return Err(FlapjackError::Io(format!(
+                "{} has pending tasks, retry once they are done",
+                tenant_id
+            )));
+        }
+        // The shared dictionaries have an oplog but are never loaded
+        self.oplogs.remove(tenant_id);
+        let rewritten =
+            tokio::task::spawn_blocking(move || encryption::reencrypt_index_dir(&keyring, &path))
+                .await
+                .map_err(|e| FlapjackError::Io(format!("Re-encryption task: {}", e)))??;
+        tracing::info!(
+            "[ENCRYPTION {}] re-encrypted {} files",
+            tenant_id,
+            rewritten
+        );
+        Ok(rewritten)
+    }
+
+    /// Evict the tenants idle for longer than the idle timeout, then the
+    /// least recently used ones beyond `max_loaded_tenants`. Returns the
+    /// number of evicted tenants.
+    pub async fn evict_idle_tenants(&self) -> usize {
+        let config = self.eviction_config();
+        let mut by_age: Vec<(TenantId, Instant)> = self
+            .loaded
+            .iter()
+            .map(|entry| {
+                let at = self
+                    .last_access
+                    .get(entry.key())
+                    .map(|at| *at)
+                    .unwrap_or_else(Instant::now);
+                (entry.key().clone(), at)
+            })
+            .collect();
+        by_age.sort_by_key(|(_, at)| *at);
+
+        let mut evicted = 0;
+        let mut remaining = by_age.len();
+        for (tenant_id, at) in by_age {
+            let idle = config
+                .idle_timeout
+                .is_some_and(|timeout| at.elapsed() >= timeout);
+            let over_limit = config.max_loaded_tenants > 0 && remaining > config.max_loaded_tenants;
+            if !idle && !over_limit {
+                break;
+            }
+            // Accessed since the snapshot above
+            if self.last_access.get(&tenant_id).is_some_and(|a| *a > at) {
+                continue;
+            }
+            if self.evict_tenant(&tenant_id).await {
+                remaining -= 1;
+                evicted += 1;
+                let counter = if idle {
+                    &self.idle_evictions
+                } else {
+                    &self.lru_evictions
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        evicted
+    }
+
+    /// Run [`Self::evict_idle_tenants`] every `check_interval`, and as soon
+    /// as a load exceeds `max_loaded_tenants`.
+    pub async fn run_eviction_loop(self: Arc<Self>) {
+        loop {
+            let interval = self.eviction_config().check_interval;
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = self.eviction_wakeup.notified() => {}
+            }
+            if self.eviction_config().is_enabled() {
+                self.evict_idle_tenants().await;
+            }
+        }
+    }
+
     pub async fn add_documents_insert_sync(
         &self,
         tenant_id: &str,
@@ -1524,6 +2687,11 @@ impl IndexManager {
             .oplogs
             .entry(tenant_id.to_string())
             .or_try_insert_with(|| {
+                let file_lock = self.file_lock(tenant_id);
+                let _reading = file_lock.try_read().map_err(|_| {
+                    tracing::error!("[OPLOG {}] not opened during a rewrite", tenant_id);
+                    FlapjackError::TenantBusy(tenant_id.to_string())
+                })?;
                 let oplog_dir = self.base_path.join(tenant_id).join("oplog");
                 let node_id =
                     std::env::var("FLAPJACK_NODE_ID").unwrap_or_else(|_| "unknown".to_string());
@@ -1548,7 +2716,10 @@ impl IndexManager {
         }
     }
 
+    /// A document by object id. Expired documents are hidden, as from
+    /// searches, until the sweeper deletes them.
     pub fn get_document(&self, tenant_id: &str, object_id: &str) -> Result<Option<Document>> {
+        let tenant_id = &self.resolve_alias(tenant_id);
         let index = self.get_or_load(tenant_id)?;
         let reader = index.reader();
         let searcher = reader.searcher();
@@ -1575,6 +2746,20 @@ impl IndexManager {
             index
                 .converter()
                 .from_tantivy(retrieved_doc, &schema, object_id.to_string())?;
+        let settings = self.get_settings(tenant_id);
+        if expiry::expires_at(&document, settings.as_deref()).is_some_and(|at| at <= expiry::now())
+        {
+            return Ok(None);
+        }
         Ok(Some(document))
     }
 }
+
+/// Number of documents of `index` matching `filter`.
+fn count_matching(index: &Index, filter: &Filter) -> Result<usize> {
+    let query = FilterCompiler::new(index.inner().schema()).compile(filter, None)?;
+    Ok(index
+        .reader()
+        .searcher()
+        .search(query.as_ref(), &tantivy::collector::Count)?)
+}
@@ -0,0 +1,225 @@
+//! Schema versions and online schema migration.
+//!
+//! [`Schema::to_tantivy`](crate::index::schema::Schema::to_tantivy) fixes the
+//! internal fields and their tokenizers, and the analyzers decide the tokens
+//! indexed in them, so changing either makes existing indexes stale. Every
+//! index directory is stamped with the [`SCHEMA_VERSION`] it was created
+//! with. The
+//! [`IndexManager`](crate::IndexManager) migrates an outdated tenant by
+//! copying its stored documents into a new index in a shadow directory under
+//! [`MIGRATIONS_DIR`] while the live index keeps serving. Its write queue then
+//! replays the writes made meanwhile from the oplog and swaps the
+//! directories.
+
+use crate::error::Result;
+use crate::index::encryption;
+use crate::index::settings::IndexSettings;
+use crate::index::vectors::{document_vector, VectorChange, VECTORS_FILE, VECTORS_LOG_FILE};
+use crate::index::Index;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Version of the internal schema and of the analyzers writing into it. Bump
+/// it with any change to the fixed fields built by
+/// [`Schema::to_tantivy`](crate::index::schema::Schema::to_tantivy) or to the
+/// tokens the analyzers emit for the same text.
+///
+/// 2: diacritic folding, CJK segmentation and `separatorsToIndex`.
+/// 3: `_vectors` stored apart from the indexed attributes.
+/// 4: camelCase parts indexed at the position of their word.
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// File in an index directory holding its schema version.
+pub const SCHEMA_VERSION_FILE: &str = "schema_version";
+
+/// Directory under the data directory holding the shadow indexes of running
+/// migrations, hidden from index listings.
+pub const MIGRATIONS_DIR: &str = ".migrations";
+
+/// Documents copied into a shadow index between commits and progress
+/// updates.
+pub const MIGRATION_BATCH: usize = 10_000;
+
+/// The schema version stamped in `dir`, if any.
+pub fn read_schema_version(dir: &Path) -> Option<u32> {
+    fs::read_to_string(dir.join(SCHEMA_VERSION_FILE))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Stamp `dir` with the current [`SCHEMA_VERSION`].
+pub fn write_schema_version(dir: &Path) -> Result<()> {
+    fs::write(dir.join(SCHEMA_VERSION_FILE), SCHEMA_VERSION.to_string())?;
+    Ok(())
+}
+
+/// Whether the index in `dir` was built with an older schema. Indexes
+/// without a stamp predate versioning and count as version 0.
+pub fn needs_migration(dir: &Path) -> bool {
+    dir.join("meta.json").exists() && read_schema_version(dir).unwrap_or(0) < SCHEMA_VERSION
+}
+
+/// Directory of the migration of `tenant_id`.
+pub fn migration_dir(base_path: &Path, tenant_id: &str) -> PathBuf {
+    base_path.join(MIGRATIONS_DIR).join(tenant_id)
+}
+
+/// Where the new index of `tenant_id` is built.
+pub fn shadow_dir(base_path: &Path, tenant_id: &str) -> PathBuf {
+    migration_dir(base_path, tenant_id).join("index")
+}
+
+/// Where the replaced directory of `tenant_id` is moved by the swap.
+pub fn previous_dir(base_path: &Path, tenant_id: &str) -> PathBuf {
+    migration_dir(base_path, tenant_id).join("previous")
+}
+
+/// The oplog sequence number the index in `dir` has committed.
+pub fn committed_seq(dir: &Path) -> u64 {
+    fs::read_to_string(dir.join("committed_seq"))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Copy the live documents of `searcher`, a searcher of `from`, into `to`,
+/// committing every [`MIGRATION_BATCH`] documents, along with their vectors,
+/// and calling `progress` with the number copied so far. Returns the number of documents copied.
+pub fn copy_documents(
+    from: &Index,
+    searcher: &tantivy::Searcher,
+    to: &Index,
+    settings: Option<&IndexSettings>,
+    progress: impl Fn(usize),
+) -> Result<usize> {
+    let mut writer = to.writer()?;
+    let mut vector_changes = Vec::new();
+    let mut copied = 0;
+    for doc in from.live_documents(searcher) {
+        let doc = doc?;
+        if let Some(vector) = document_vector(&doc)? {
+            vector_changes.push(VectorChange::Set(doc.id.clone(), vector));
+        }
+        writer.add_document(to.converter().to_tantivy(&doc, settings)?)?;
+        copied += 1;
+        if copied % MIGRATION_BATCH == 0 {
+            writer.commit()?;
+            to.update_vectors(std::mem::take(&mut vector_changes))?;
+            progress(copied);
+        }
+    }
+    writer.commit()?;
+    to.reader().reload()?;
+    to.update_vectors(vector_changes)?;
+    progress(copied);
+    Ok(copied)
+}
+
+/// Copy the settings, synonyms, rules and other state of the tenant in
+/// `live` into `shadow`: everything but the index files, the vectors and
+/// schema stamp rebuilt with the index, and the oplog, which the swap moves.
+pub fn copy_tenant_state(live: &Path, shadow: &Path) -> Result<()> {
+    for entry in fs::read_dir(live)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if encryption::is_index_file(&name)
+            || name.starts_with(".tantivy")
+            || name == VECTORS_FILE
+            || name == VECTORS_LOG_FILE
+            || name == SCHEMA_VERSION_FILE
+            || name == "oplog"
+        {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            crate::index::utils::copy_dir_recursive(&entry.path(), &shadow.join(&name))?;
+        } else {
+            fs::copy(entry.path(), shadow.join(&name))?;
+        }
+    }
+    Ok(())
+}
+
+/// Undo the swaps interrupted by a crash, before any migration runs: a
+/// tenant moved aside and not yet replaced gets its directory back, and a
+/// tenant whose oplog was moved into its shadow directory gets its oplog
+/// back. Shadow indexes left over are removed; their migrations start over.
+pub fn recover_interrupted(base_path: &Path) {
+    let Ok(entries) = fs::read_dir(base_path.join(MIGRATIONS_DIR)) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let tenant_id = entry.file_name().to_string_lossy().into_owned();
+        let live = base_path.join(&tenant_id);
+        let previous = previous_dir(base_path, &tenant_id);
+        let oplog = shadow_dir(base_path, &tenant_id).join("oplog");
+        let mut restored = Ok(());
+        if !live.exists() && previous.exists() {
+            tracing::warn!(
+                "[MIGRATE {}] restoring the index of an interrupted swap",
+                tenant_id
+            );
+            restored = fs::rename(&previous, &live);
+        }
+        if restored.is_ok() && live.exists() && oplog.exists() && !live.join("oplog").exists() {
+            restored = fs::rename(&oplog, live.join("oplog"));
+        }
+        if let Err(e) = restored {
+            tracing::error!("[MIGRATE {}] failed to restore: {}", tenant_id, e);
+            continue;
+        }
+        if let Err(e) = fs::remove_dir_all(entry.path()) {
+            tracing::warn!(
+                "[MIGRATE {}] failed to remove {:?}: {}",
+                tenant_id,
+                entry.path(),
+                e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_stamp() {
+        let temp = TempDir::new().unwrap();
+        let _index = Index::create_in_dir(temp.path()).unwrap();
+        assert_eq!(read_schema_version(temp.path()), Some(SCHEMA_VERSION));
+        assert!(!needs_migration(temp.path()));
+
+        // Unstamped indexes predate versioning
+        fs::remove_file(temp.path().join(SCHEMA_VERSION_FILE)).unwrap();
+        assert!(needs_migration(temp.path()));
+
+        fs::write(temp.path().join(SCHEMA_VERSION_FILE), "1").unwrap();
+        assert!(needs_migration(temp.path()));
+
+        // Directories without an index have nothing to migrate
+        let empty = TempDir::new().unwrap();
+        assert!(!needs_migration(empty.path()));
+    }
+
+    #[test]
+    fn test_recover_interrupted() {
+        let temp = TempDir::new().unwrap();
+        let previous = previous_dir(temp.path(), "products");
+        fs::create_dir_all(&previous).unwrap();
+        fs::write(previous.join("settings.json"), "{}").unwrap();
+        fs::create_dir_all(shadow_dir(temp.path(), "products").join("oplog")).unwrap();
+        fs::create_dir_all(shadow_dir(temp.path(), "done")).unwrap();
+        fs::create_dir_all(temp.path().join("done")).unwrap();
+
+        recover_interrupted(temp.path());
+        assert!(temp.path().join("products/settings.json").exists());
+        assert!(temp.path().join("products/oplog").exists());
+        assert!(temp.path().join("done").exists());
+        assert!(!temp.path().join(MIGRATIONS_DIR).join("done").exists());
+        assert!(!temp.path().join(MIGRATIONS_DIR).join("products").exists());
+    }
+}
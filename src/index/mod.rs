@@ -1,8 +1,16 @@
+pub mod aliases;
+pub mod bulk;
+pub mod dictionaries;
 pub mod document;
+pub mod dump;
+pub mod encryption;
+pub mod eviction;
+pub mod expiry;
 pub mod facet_translation;
 pub mod manager;
 pub mod memory;
 pub mod memory_observer;
+pub mod migration;
 pub mod oplog;
 pub mod relevance;
 pub mod rules;
@@ -12,20 +20,28 @@ pub mod schema;
 pub mod settings;
 #[cfg(feature = "s3-snapshots")]
 pub mod snapshot;
+#[cfg(feature = "s3-snapshots")]
+pub mod snapshot_store;
 pub mod synonyms;
 pub mod task_queue;
 mod utils;
+pub mod vectors;
 pub mod write_queue;
 pub mod writer;
 
 use crate::error::Result;
+use crate::tokenizer::{
+    CamelCaseFilter, CjkSegmenter, DiacriticFoldingFilter, IndexedSeparators, KeepDiacritics,
+    SegmentedCjkTokenizer,
+};
 use crate::types::Document;
 use document::DocumentConverter;
 use memory::{MemoryBudget, MemoryBudgetConfig};
 use schema::Schema;
-use std::path::Path;
-use std::sync::{Arc, OnceLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock, RwLock, RwLockReadGuard};
 use tantivy::Index as TantivyIndex;
+use vectors::{VectorChange, VectorIndex};
 pub use writer::ManagedIndexWriter;
 
 static GLOBAL_BUDGET: OnceLock<Arc<MemoryBudget>> = OnceLock::new();
@@ -36,6 +52,50 @@ pub fn get_global_budget() -> Arc<MemoryBudget> {
     )
 }
 
+/// Register the analyzers referenced by the schema: `edge_ngram_lower` for
+/// prefix search and `simple` for exact terms. Both segment CJK text with the
+/// index's segmenter, keep the indexed separators inside words, split the
+/// camelCase words of the `camelCaseAttributes`, lowercase and fold
+/// diacritics, sharing `analyzers` with the index.
+fn register_tokenizers(inner: &TantivyIndex, analyzers: &AnalyzerState) {
+    let cjk = SegmentedCjkTokenizer::new(
+        Arc::clone(&analyzers.cjk_segmenter),
+        Arc::clone(&analyzers.separators_to_index),
+    );
+    let folding = DiacriticFoldingFilter::new(Arc::clone(&analyzers.keep_diacritics));
+
+    let edge_ngram_tokenizer = tantivy::tokenizer::TextAnalyzer::builder(cjk.clone())
+        .filter(CamelCaseFilter)
+        .filter(tantivy::tokenizer::LowerCaser)
+        .filter(folding.clone())
+        .filter(tantivy::tokenizer::EdgeNgramFilter::new(2, 20).unwrap())
+        .build();
+
+    inner
+        .tokenizers()
+        .register("edge_ngram_lower", edge_ngram_tokenizer);
+
+    let simple_tokenizer = tantivy::tokenizer::TextAnalyzer::builder(cjk)
+        .filter(CamelCaseFilter)
+        .filter(tantivy::tokenizer::LowerCaser)
+        .filter(folding)
+        .build();
+
+    inner.tokenizers().register("simple", simple_tokenizer);
+}
+
+/// Settings-driven analyzer configuration shared with the registered
+/// tokenizers, so changes apply without re-registering them.
+#[derive(Default)]
+struct AnalyzerState {
+    /// Characters left unfolded (`keepDiacriticsOnCharacters`).
+    keep_diacritics: Arc<RwLock<KeepDiacritics>>,
+    /// Word segmenter for CJK text (`indexLanguages`).
+    cjk_segmenter: Arc<RwLock<Option<CjkSegmenter>>>,
+    /// Punctuation kept inside words (`separatorsToIndex`).
+    separators_to_index: Arc<RwLock<IndexedSeparators>>,
+}
+
 pub fn reset_global_budget_for_test() {
     if let Some(budget) = GLOBAL_BUDGET.get() {
         budget.reset_for_test();
@@ -70,6 +130,11 @@ pub struct Index {
     converter: Arc<DocumentConverter>,
     budget: Arc<MemoryBudget>,
     searchable_paths_cache: std::sync::RwLock<Option<Vec<String>>>,
+    /// Analyzer configuration from the tenant settings.
+    analyzers: AnalyzerState,
+    /// Nearest-neighbour index over the records' `_vectors`.
+    vectors: RwLock<VectorIndex>,
+    vectors_dir: PathBuf,
 }
 
 impl Index {
@@ -98,24 +163,11 @@ impl Index {
         budget: Arc<MemoryBudget>,
     ) -> Result<Self> {
         let tantivy_schema = schema.to_tantivy();
-        let inner = TantivyIndex::create_in_dir(path, tantivy_schema.clone())?;
-
-        let edge_ngram_tokenizer =
-            tantivy::tokenizer::TextAnalyzer::builder(crate::tokenizer::CjkAwareTokenizer)
-                .filter(tantivy::tokenizer::LowerCaser)
-                .filter(tantivy::tokenizer::EdgeNgramFilter::new(2, 20).unwrap())
-                .build();
-
-        inner
-            .tokenizers()
-            .register("edge_ngram_lower", edge_ngram_tokenizer);
-
-        let simple_tokenizer =
-            tantivy::tokenizer::TextAnalyzer::builder(crate::tokenizer::CjkAwareTokenizer)
-                .filter(tantivy::tokenizer::LowerCaser)
-                .build();
-
-        inner.tokenizers().register("simple", simple_tokenizer);
+        let vectors_dir = path.as_ref().to_path_buf();
+        let inner = encryption::create_index(path.as_ref(), tantivy_schema.clone())?;
+        migration::write_schema_version(path.as_ref())?;
+        let analyzers = AnalyzerState::default();
+        register_tokenizers(&inner, &analyzers);
 
         let reader = inner
             .reader_builder()
@@ -123,14 +175,18 @@ impl Index {
             .try_into()?;
 
         let converter = Arc::new(DocumentConverter::new(&schema, &tantivy_schema)?);
-        Ok(Index {
+        Index {
             inner,
             reader,
             schema,
             converter,
             budget,
             searchable_paths_cache: std::sync::RwLock::new(None),
-        })
+            analyzers,
+            vectors: RwLock::new(VectorIndex::new()),
+            vectors_dir,
+        }
+        .with_vectors()
     }
 
     /// Open an existing index at `path`.
@@ -140,24 +196,10 @@ impl Index {
 
     /// Open an existing index with an explicit memory budget.
     pub fn open_with_budget<P: AsRef<Path>>(path: P, budget: Arc<MemoryBudget>) -> Result<Self> {
-        let inner = TantivyIndex::open_in_dir(path)?;
-
-        let edge_ngram_tokenizer =
-            tantivy::tokenizer::TextAnalyzer::builder(crate::tokenizer::CjkAwareTokenizer)
-                .filter(tantivy::tokenizer::LowerCaser)
-                .filter(tantivy::tokenizer::EdgeNgramFilter::new(2, 20).unwrap())
-                .build();
-
-        inner
-            .tokenizers()
-            .register("edge_ngram_lower", edge_ngram_tokenizer);
-
-        let simple_tokenizer =
-            tantivy::tokenizer::TextAnalyzer::builder(crate::tokenizer::CjkAwareTokenizer)
-                .filter(tantivy::tokenizer::LowerCaser)
-                .build();
-
-        inner.tokenizers().register("simple", simple_tokenizer);
+        let vectors_dir = path.as_ref().to_path_buf();
+        let inner = encryption::open_index(path.as_ref())?;
+        let analyzers = AnalyzerState::default();
+        register_tokenizers(&inner, &analyzers);
 
         let reader = inner
             .reader_builder()
@@ -167,14 +209,18 @@ impl Index {
         let tantivy_schema = inner.schema();
         let schema = Schema::from_tantivy(tantivy_schema.clone())?;
         let converter = Arc::new(DocumentConverter::new(&schema, &tantivy_schema)?);
-        Ok(Index {
+        Index {
             inner,
             reader,
             schema,
             converter,
             budget,
             searchable_paths_cache: std::sync::RwLock::new(None),
-        })
+            analyzers,
+            vectors: RwLock::new(VectorIndex::new()),
+            vectors_dir,
+        }
+        .with_vectors()
     }
 
     /// Create an index writer with the default buffer size (20 MB).
@@ -243,6 +289,33 @@ impl Index {
         &self.budget
     }
 
+    /// Iterate over the live documents of `searcher`, a searcher of this
+    /// index, converted back to [`Document`]s.
+    ///
+    /// Deletions are checked with `is_deleted` rather than iterating
+    /// `doc_ids_alive()`, whose boxed iterator is not `Send`, so the
+    /// iteration can be held across an `.await`.
+    pub fn live_documents<'a>(
+        &'a self,
+        searcher: &'a tantivy::Searcher,
+    ) -> impl Iterator<Item = Result<Document>> + Send + 'a {
+        let schema = self.inner.schema();
+        searcher
+            .segment_readers()
+            .iter()
+            .enumerate()
+            .flat_map(|(segment_ord, segment)| {
+                (0..segment.max_doc())
+                    .filter(move |doc_id| !segment.is_deleted(*doc_id))
+                    .map(move |doc_id| tantivy::DocAddress::new(segment_ord as u32, doc_id))
+            })
+            .map(move |addr| {
+                let tantivy_doc: tantivy::TantivyDocument = searcher.doc(addr)?;
+                self.converter
+                    .from_tantivy(tantivy_doc, &schema, String::new())
+            })
+    }
+
     /// Add JSON documents, commit, and refresh the reader in one call.
     ///
     /// This is the easiest way to index documents. Each JSON object must
@@ -265,8 +338,14 @@ impl Index {
         let json_filter_field = schema.get_field("_json_filter").unwrap();
         let json_exact_field = schema.get_field("_json_exact").unwrap();
         let facets_field = schema.get_field("_facets").unwrap();
+        let vectors_field = schema.get_field("_vectors").ok();
 
+        let mut vector_changes = Vec::new();
         for json_doc in docs {
+            let doc = Document::from_json(json_doc)?;
+            if let Some(vector) = vectors::document_vector(&doc)? {
+                vector_changes.push(VectorChange::Set(doc.id, vector));
+            }
             let tantivy_doc = json_to_tantivy_doc(
                 json_doc,
                 id_field,
@@ -274,6 +353,7 @@ impl Index {
                 json_filter_field,
                 json_exact_field,
                 facets_field,
+                vectors_field,
             )?;
             writer.add_document(tantivy_doc)?;
         }
@@ -281,7 +361,47 @@ impl Index {
         writer.commit()?;
         self.reader.reload()?;
         self.invalidate_searchable_paths_cache();
-        Ok(())
+        self.update_vectors(vector_changes)
+    }
+
+    /// Load the vector index stored next to this index. An unreadable one
+    /// is rebuilt from the `_vectors` of the live documents and saved.
+    fn with_vectors(self) -> Result<Self> {
+        let vectors = match VectorIndex::open(&self.vectors_dir) {
+            Ok(vectors) => vectors,
+            Err(e) => {
+                tracing::warn!(
+                    "[VECTORS] rebuilding the vectors in {}: {}",
+                    self.vectors_dir.display(),
+                    e
+                );
+                let mut vectors = VectorIndex::new();
+                let searcher = self.reader.searcher();
+                for doc in self.live_documents(&searcher) {
+                    let doc = doc?;
+                    if let Some(vector) = vectors::document_vector(&doc)? {
+                        vectors.insert(&doc.id, &vector)?;
+                    }
+                }
+                vectors.compact(&self.vectors_dir)?;
+                vectors
+            }
+        };
+        *self.vectors.write().unwrap() = vectors;
+        Ok(self)
+    }
+
+    /// The nearest-neighbour index over the records' `_vectors`.
+    pub fn vectors(&self) -> RwLockReadGuard<'_, VectorIndex> {
+        self.vectors.read().unwrap()
+    }
+
+    /// Apply committed vector changes and log them next to the index.
+    pub fn update_vectors(&self, changes: Vec<VectorChange>) -> Result<()> {
+        self.vectors
+            .write()
+            .unwrap()
+            .commit(&self.vectors_dir, changes)
     }
 
     /// Return the list of field paths that contain indexed text.
@@ -326,6 +446,25 @@ impl Index {
         result
     }
 
+    /// Set the characters exempt from diacritic folding for documents indexed
+    /// from now on. Existing documents must be re-added to pick up a change
+    /// (see [`manager::IndexManager::reindex`]).
+    pub fn set_keep_diacritics(&self, chars: &str) {
+        *self.analyzers.keep_diacritics.write().unwrap() = KeepDiacritics::parse(chars);
+    }
+
+    /// Select the CJK word segmenter from `indexLanguages` for documents
+    /// indexed from now on; without "zh" or "ja" each CJK character is a word.
+    pub fn set_index_languages(&self, languages: &[String]) {
+        *self.analyzers.cjk_segmenter.write().unwrap() = CjkSegmenter::for_languages(languages);
+    }
+
+    /// Keep the `separatorsToIndex` characters inside words for documents
+    /// indexed from now on, so "c++" is indexed as "c++" rather than "c".
+    pub fn set_separators_to_index(&self, chars: &str) {
+        *self.analyzers.separators_to_index.write().unwrap() = IndexedSeparators::parse(chars);
+    }
+
     /// Clear the cached searchable paths so the next call recomputes them.
     pub fn invalidate_searchable_paths_cache(&self) {
         let mut cache = self.searchable_paths_cache.write().unwrap();
@@ -1,9 +1,11 @@
+use crate::index::encryption::{self, Keyring};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use serde::{Deserialize, Serialize};
 use std::fs::{self, File, OpenOptions};
-use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 const SEGMENT_MAX_BYTES: u64 = 10 * 1024 * 1024;
 
@@ -17,6 +19,134 @@ pub struct OpLogEntry {
     pub payload: serde_json::Value,
 }
 
+/// Serialize `entry` as one oplog line: its JSON, or with a keyring, the
+/// JSON encrypted and base64 encoded.
+pub fn encode_line(entry: &OpLogEntry, keyring: Option<&Keyring>) -> crate::error::Result<String> {
+    let json =
+        serde_json::to_string(entry).map_err(|e| crate::error::FlapjackError::Io(e.to_string()))?;
+    Ok(match keyring {
+        Some(keyring) => BASE64.encode(keyring.encrypt(json.as_bytes())),
+        None => json,
+    })
+}
+
+/// Parse a complete oplog line written by [`encode_line`]. Plaintext lines
+/// that do not parse are `None`. Encrypted lines that cannot be decrypted,
+/// for lack of their key or because they fail authentication, are an error:
+/// skipping them would let replay and replication move past lost writes.
+pub fn decode_line(
+    line: &str,
+    keyring: Option<&Keyring>,
+) -> crate::error::Result<Option<OpLogEntry>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.starts_with('{') {
+        return Ok(serde_json::from_str(line).ok());
+    }
+    let unreadable = |reason: String| {
+        crate::error::FlapjackError::Io(format!("Unreadable encrypted oplog entry: {}", reason))
+    };
+    let sealed = BASE64.decode(line).map_err(|e| unreadable(e.to_string()))?;
+    if !encryption::is_encrypted(&sealed) {
+        return Err(unreadable("not an encrypted stream".to_string()));
+    }
+    let keyring = keyring.ok_or_else(|| {
+        crate::error::FlapjackError::Config(
+            "Oplog is encrypted but no master key is configured".to_string(),
+        )
+    })?;
+    if !keyring.has_key_for(&sealed) {
+        return Err(crate::error::FlapjackError::Config(
+            "Oplog entry is encrypted with a key that is not in the keyring".to_string(),
+        ));
+    }
+    let json = keyring
+        .decrypt(&sealed)
+        .map_err(|e| unreadable(e.to_string()))?;
+    serde_json::from_slice(&json)
+        .map(Some)
+        .map_err(|e| unreadable(e.to_string()))
+}
+
+/// The entries of the segment at `path`. A last line without its newline is
+/// still being written, or was cut short by a crash, and is left out.
+fn read_segment(path: &Path, keyring: Option<&Keyring>) -> crate::error::Result<Vec<OpLogEntry>> {
+    let text = fs::read_to_string(path)?;
+    let complete = text.rfind('\n').map_or("", |end| &text[..end]);
+    let mut entries = Vec::new();
+    for line in complete.lines() {
+        if let Some(entry) = decode_line(line, keyring)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Cut a last line left without its newline by a crash off the segment at
+/// `path`, so that the next entry starts on a line of its own.
+fn truncate_torn_line(path: &Path) -> crate::error::Result<()> {
+    let data = fs::read(path)?;
+    if data.is_empty() || data.ends_with(b"\n") {
+        return Ok(());
+    }
+    let keep = data.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+    tracing::warn!("[OPLOG] dropping the torn last line of {}", path.display());
+    OpenOptions::new()
+        .write(true)
+        .open(path)?
+        .set_len(keep as u64)?;
+    Ok(())
+}
+
+/// Rewrite the segments in `dir` holding entries that are unencrypted or
+/// encrypted with a retired key, with the current key of `keyring`. The
+/// oplog must not be open. Returns the number of rewritten segments.
+pub fn reencrypt_segments(dir: &Path, keyring: &Keyring) -> crate::error::Result<usize> {
+    let mut rewritten = 0;
+    for path in segment_paths(dir)? {
+        truncate_torn_line(&path)?;
+        let text = fs::read_to_string(&path)?;
+        let current = text.lines().filter(|l| !l.trim().is_empty()).all(|line| {
+            BASE64
+                .decode(line.trim())
+                .is_ok_and(|sealed| keyring.is_current(&sealed))
+        });
+        if current {
+            continue;
+        }
+        let mut lines = String::with_capacity(text.len());
+        for line in text.lines() {
+            if let Some(entry) = decode_line(line, Some(keyring))? {
+                lines.push_str(&encode_line(&entry, Some(keyring))?);
+                lines.push('\n');
+            }
+        }
+        let staging = path.with_extension("jsonl.reencrypt");
+        fs::write(&staging, lines)?;
+        fs::rename(&staging, &path)?;
+        rewritten += 1;
+    }
+    Ok(rewritten)
+}
+
+/// The `segment_*.jsonl` files in `dir`, in order.
+fn segment_paths(dir: &Path) -> crate::error::Result<Vec<PathBuf>> {
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| {
+            e.file_name()
+                .to_str()
+                .map(|n| n.starts_with("segment_") && n.ends_with(".jsonl"))
+                .unwrap_or(false)
+        })
+        .map(|e| e.path())
+        .collect();
+    paths.sort();
+    Ok(paths)
+}
+
 struct ActiveSegment {
     writer: BufWriter<File>,
     path: PathBuf,
@@ -30,13 +160,30 @@ pub struct OpLog {
     node_id: String,
     current_seq: AtomicU64,
     segment: Mutex<ActiveSegment>,
+    /// Encrypts the entries when encryption at rest is on.
+    keyring: Option<Arc<Keyring>>,
+    /// Entries after this sequence number are kept by
+    /// [`OpLog::truncate_before`], e.g. for a schema migration to replay.
+    retained_after: Mutex<Option<u64>>,
 }
 
 impl OpLog {
     pub fn open(dir: &Path, tenant_id: &str, node_id: &str) -> crate::error::Result<Self> {
+        Self::open_with_keyring(dir, tenant_id, node_id, encryption::keyring()?)
+    }
+
+    pub fn open_with_keyring(
+        dir: &Path,
+        tenant_id: &str,
+        node_id: &str,
+        keyring: Option<Arc<Keyring>>,
+    ) -> crate::error::Result<Self> {
         fs::create_dir_all(dir)?;
+        if let Some(last) = segment_paths(dir)?.last() {
+            truncate_torn_line(last)?;
+        }
 
-        let (max_seq, max_seg_id) = Self::scan_existing(dir)?;
+        let (max_seq, max_seg_id) = Self::scan_existing(dir, keyring.as_deref())?;
         let next_seg_id = if max_seg_id > 0 { max_seg_id } else { 1 };
         let seg_path = dir.join(format!("segment_{:04}.jsonl", next_seg_id));
         let seg_size = seg_path.metadata().map(|m| m.len()).unwrap_or(0);
@@ -57,10 +204,12 @@ impl OpLog {
                 size: seg_size,
                 id: next_seg_id,
             }),
+            keyring,
+            retained_after: Mutex::new(None),
         })
     }
 
-    fn scan_existing(dir: &Path) -> crate::error::Result<(u64, u32)> {
+    fn scan_existing(dir: &Path, keyring: Option<&Keyring>) -> crate::error::Result<(u64, u32)> {
         let mut max_seq: u64 = 0;
         let mut max_seg_id: u32 = 0;
 
@@ -91,15 +240,8 @@ impl OpLog {
         }
 
         if let Some(last) = entries.last() {
-            let f = File::open(last.path())?;
-            let reader = BufReader::new(f);
-            for line in reader.lines() {
-                let line = line?;
-                if let Ok(entry) = serde_json::from_str::<OpLogEntry>(&line) {
-                    if entry.seq > max_seq {
-                        max_seq = entry.seq;
-                    }
-                }
+            for entry in read_segment(&last.path(), keyring)? {
+                max_seq = max_seq.max(entry.seq);
             }
         }
 
@@ -110,6 +252,18 @@ impl OpLog {
         self.current_seq.load(Ordering::SeqCst)
     }
 
+    /// Keep the entries after `seq` whatever the retention, until
+    /// [`OpLog::release`].
+    pub fn retain_after(&self, seq: u64) {
+        *self.retained_after.lock().unwrap() = Some(seq);
+    }
+
+    /// Let [`OpLog::truncate_before`] remove the entries kept by
+    /// [`OpLog::retain_after`] again.
+    pub fn release(&self) {
+        *self.retained_after.lock().unwrap() = None;
+    }
+
     pub fn append(&self, op_type: &str, payload: serde_json::Value) -> crate::error::Result<u64> {
         let seq = self.current_seq.fetch_add(1, Ordering::SeqCst) + 1;
         let entry = OpLogEntry {
@@ -124,8 +278,7 @@ impl OpLog {
             payload,
         };
 
-        let line = serde_json::to_string(&entry)
-            .map_err(|e| crate::error::FlapjackError::Io(e.to_string()))?;
+        let line = encode_line(&entry, self.keyring.as_deref())?;
 
         let mut seg = self.segment.lock().unwrap();
         seg.writer.write_all(line.as_bytes())?;
@@ -158,8 +311,7 @@ impl OpLog {
                 op_type: op_type.clone(),
                 payload: payload.clone(),
             };
-            let line = serde_json::to_string(&entry)
-                .map_err(|e| crate::error::FlapjackError::Io(e.to_string()))?;
+            let line = encode_line(&entry, self.keyring.as_deref())?;
             seg.writer.write_all(line.as_bytes())?;
             seg.writer.write_all(b"\n")?;
             seg.size += line.len() as u64 + 1;
@@ -207,20 +359,9 @@ impl OpLog {
         }
 
         for entry in entries {
-            let f = File::open(entry.path())?;
-            let reader = BufReader::new(f);
-            for line in reader.lines() {
-                let line = line?;
-                if line.trim().is_empty() {
-                    continue;
-                }
-                match serde_json::from_str::<OpLogEntry>(&line) {
-                    Ok(op) => {
-                        if op.seq > since_seq {
-                            results.push(op);
-                        }
-                    }
-                    Err(_) => continue,
+            for op in read_segment(&entry.path(), self.keyring.as_deref())? {
+                if op.seq > since_seq {
+                    results.push(op);
                 }
             }
         }
@@ -229,6 +370,10 @@ impl OpLog {
     }
 
     pub fn truncate_before(&self, before_seq: u64) -> crate::error::Result<u64> {
+        let before_seq = match *self.retained_after.lock().unwrap() {
+            Some(retained) => before_seq.min(retained + 1),
+            None => before_seq,
+        };
         let mut removed = 0u64;
         let seg = self.segment.lock().unwrap();
         let current_seg_name = seg.path.file_name().unwrap().to_str().unwrap().to_string();
@@ -250,17 +395,11 @@ impl OpLog {
             if name == current_seg_name {
                 continue;
             }
-            let f = File::open(entry.path())?;
-            let reader = BufReader::new(f);
-            let mut max_seq_in_file = 0u64;
-            for line in reader.lines() {
-                let line = line?;
-                if let Ok(op) = serde_json::from_str::<OpLogEntry>(&line) {
-                    if op.seq > max_seq_in_file {
-                        max_seq_in_file = op.seq;
-                    }
-                }
-            }
+            let max_seq_in_file = read_segment(&entry.path(), self.keyring.as_deref())?
+                .iter()
+                .map(|op| op.seq)
+                .max()
+                .unwrap_or(0);
             if max_seq_in_file > 0 && max_seq_in_file < before_seq {
                 fs::remove_file(entry.path())?;
                 removed += 1;
@@ -367,4 +506,108 @@ mod tests {
         assert_eq!(remaining.len(), 5);
         assert_eq!(remaining[0].seq, 6);
     }
+
+    #[test]
+    fn test_retained_entries_survive_truncation() {
+        let tmp = TempDir::new().unwrap();
+        let oplog = OpLog::open(tmp.path(), "t1", "node1").unwrap();
+        for i in 0..5 {
+            oplog.append("upsert", serde_json::json!({"i": i})).unwrap();
+        }
+        oplog
+            .rotate_segment_locked(&mut oplog.segment.lock().unwrap())
+            .unwrap();
+        for i in 5..10 {
+            oplog.append("upsert", serde_json::json!({"i": i})).unwrap();
+        }
+
+        oplog.retain_after(3);
+        assert_eq!(oplog.truncate_before(6).unwrap(), 0);
+        assert_eq!(oplog.read_since(3).unwrap()[0].seq, 4);
+
+        oplog.release();
+        assert_eq!(oplog.truncate_before(6).unwrap(), 1);
+        assert_eq!(oplog.read_since(0).unwrap()[0].seq, 6);
+    }
+
+    #[test]
+    fn test_encrypted_entries() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let segment = dir.join("segment_0001.jsonl");
+
+        {
+            // Written before encryption was enabled
+            let oplog = OpLog::open_with_keyring(&dir, "t1", "node1", None).unwrap();
+            oplog
+                .append("upsert", serde_json::json!({"objectID": "plain"}))
+                .unwrap();
+        }
+        {
+            let keyring = Arc::new(Keyring::new(&[[1; 32]]).unwrap());
+            let oplog = OpLog::open_with_keyring(&dir, "t1", "node1", Some(keyring)).unwrap();
+            assert_eq!(oplog.current_seq(), 1);
+            oplog
+                .append("upsert", serde_json::json!({"objectID": "secret"}))
+                .unwrap();
+        }
+        assert!(!fs::read_to_string(&segment).unwrap().contains("secret"));
+        assert!(OpLog::open_with_keyring(&dir, "t1", "node1", None).is_err());
+
+        let rotated = Keyring::new(&[[2; 32], [1; 32]]).unwrap();
+        assert_eq!(reencrypt_segments(&dir, &rotated).unwrap(), 1);
+        assert_eq!(reencrypt_segments(&dir, &rotated).unwrap(), 0);
+        assert!(!fs::read_to_string(&segment).unwrap().contains("plain"));
+
+        let keyring = Arc::new(Keyring::new(&[[2; 32]]).unwrap());
+        let oplog = OpLog::open_with_keyring(&dir, "t1", "node1", Some(keyring)).unwrap();
+        let all = oplog.read_since(0).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].payload["objectID"], "plain");
+        assert_eq!(all[1].payload["objectID"], "secret");
+    }
+
+    #[test]
+    fn test_tampered_entries_fail_and_torn_lines_are_dropped() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let segment = dir.join("segment_0001.jsonl");
+        let keyring = Arc::new(Keyring::new(&[[1; 32]]).unwrap());
+        let open = || OpLog::open_with_keyring(&dir, "t1", "node1", Some(keyring.clone()));
+        {
+            let oplog = open().unwrap();
+            for id in ["a", "b"] {
+                oplog
+                    .append("upsert", serde_json::json!({"objectID": id}))
+                    .unwrap();
+            }
+        }
+        let written = fs::read_to_string(&segment).unwrap();
+
+        // A crash in the middle of the last line
+        fs::write(&segment, &written[..written.len() - 10]).unwrap();
+        let oplog = open().unwrap();
+        assert_eq!(oplog.current_seq(), 1);
+        oplog
+            .append("upsert", serde_json::json!({"objectID": "c"}))
+            .unwrap();
+        let all = oplog.read_since(0).unwrap();
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[1].payload["objectID"], "c");
+        drop(oplog);
+
+        // A complete line that fails authentication is not skipped
+        let mut lines: Vec<String> = fs::read_to_string(&segment)
+            .unwrap()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let mut sealed = BASE64.decode(&lines[0]).unwrap();
+        let last = sealed.len() - 1;
+        sealed[last] ^= 1;
+        lines[0] = BASE64.encode(sealed);
+        fs::write(&segment, lines.join("\n") + "\n").unwrap();
+        assert!(open().is_err());
+        assert!(decode_line(&lines[0], Some(&keyring)).is_err());
+    }
 }
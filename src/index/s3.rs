@@ -1,13 +1,23 @@
-use crate::error::Result;
+//! [`SnapshotStore`] on S3 or an S3-compatible service such as MinIO.
+
+use crate::error::{FlapjackError, Result};
+use crate::index::snapshot_store::SnapshotStore;
+use async_trait::async_trait;
 use s3::bucket::Bucket;
 use s3::creds::Credentials;
 use s3::Region;
+use std::path::Path;
 
 #[derive(Clone)]
 pub struct S3Config {
     pub bucket_name: String,
     pub region: String,
     pub endpoint: Option<String>,
+    /// Address the bucket as `endpoint/bucket` rather than `bucket.endpoint`,
+    /// as most S3-compatible servers expect.
+    pub path_style: bool,
+    /// Access and secret key; the standard AWS credential chain when unset.
+    pub credentials: Option<(String, String)>,
 }
 
 impl S3Config {
@@ -15,10 +25,23 @@ impl S3Config {
         let bucket_name = std::env::var("FLAPJACK_S3_BUCKET").ok()?;
         let region = std::env::var("FLAPJACK_S3_REGION").unwrap_or_else(|_| "us-east-1".into());
         let endpoint = std::env::var("FLAPJACK_S3_ENDPOINT").ok();
+        let path_style = std::env::var("FLAPJACK_S3_PATH_STYLE")
+            .ok()
+            .map(|v| v == "true" || v == "1")
+            .unwrap_or(endpoint.is_some());
+        let credentials = match (
+            std::env::var("FLAPJACK_S3_ACCESS_KEY"),
+            std::env::var("FLAPJACK_S3_SECRET_KEY"),
+        ) {
+            (Ok(access_key), Ok(secret_key)) => Some((access_key, secret_key)),
+            _ => None,
+        };
         Some(Self {
             bucket_name,
             region,
             endpoint,
+            path_style,
+            credentials,
         })
     }
 
@@ -31,91 +54,138 @@ impl S3Config {
             None => self
                 .region
                 .parse()
-                .map_err(|e| crate::error::FlapjackError::S3(format!("Invalid region: {}", e)))?,
+                .map_err(|e| FlapjackError::S3(format!("Invalid region: {}", e)))?,
         };
-        let creds = Credentials::default()
-            .map_err(|e| crate::error::FlapjackError::S3(format!("S3 credentials: {}", e)))?;
+        let creds = match &self.credentials {
+            Some((access_key, secret_key)) => {
+                Credentials::new(Some(access_key), Some(secret_key), None, None, None)
+            }
+            None => Credentials::default(),
+        }
+        .map_err(|e| FlapjackError::S3(format!("S3 credentials: {}", e)))?;
         let bucket = Bucket::new(&self.bucket_name, region, creds)
-            .map_err(|e| crate::error::FlapjackError::S3(format!("S3 bucket: {}", e)))?;
-        Ok(bucket)
+            .map_err(|e| FlapjackError::S3(format!("S3 bucket: {}", e)))?;
+        Ok(if self.path_style {
+            bucket.with_path_style()
+        } else {
+            bucket
+        })
     }
 }
 
-pub async fn upload_snapshot(config: &S3Config, index_name: &str, data: &[u8]) -> Result<String> {
-    let bucket = config.bucket_internal()?;
-    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
-    let key = format!("snapshots/{}/{}.tar.gz", index_name, timestamp);
-
-    bucket
-        .put_object(&key, data)
-        .await
-        .map_err(|e| crate::error::FlapjackError::S3(format!("S3 upload: {}", e)))?;
-
-    tracing::info!("Uploaded snapshot s3://{}/{}", config.bucket_name, key);
-    Ok(key)
+pub struct S3Store {
+    config: S3Config,
+    bucket: Box<Bucket>,
 }
 
-pub async fn download_snapshot(config: &S3Config, key: &str) -> Result<Vec<u8>> {
-    let bucket = config.bucket_internal()?;
-    let response = bucket
-        .get_object(key)
-        .await
-        .map_err(|e| crate::error::FlapjackError::S3(format!("S3 download: {}", e)))?;
-    if response.status_code() != 200 {
-        return Err(crate::error::FlapjackError::S3(format!(
-            "S3 download failed: HTTP {}",
-            response.status_code()
-        )));
+impl S3Store {
+    pub fn new(config: S3Config) -> Result<Self> {
+        let bucket = config.bucket_internal()?;
+        Ok(S3Store { config, bucket })
     }
-    Ok(response.to_vec())
 }
 
-pub async fn download_latest_snapshot(
-    config: &S3Config,
-    index_name: &str,
-) -> Result<(String, Vec<u8>)> {
-    let keys = list_snapshots(config, index_name).await?;
-    let latest = keys.last().ok_or_else(|| {
-        crate::error::FlapjackError::S3(format!("No snapshots found for {}", index_name))
-    })?;
-    let data = download_snapshot(config, latest).await?;
-    Ok((latest.clone(), data))
+fn check_status(action: &str, key: &str, status: u16) -> Result<()> {
+    if (200..300).contains(&status) {
+        Ok(())
+    } else {
+        Err(FlapjackError::S3(format!(
+            "S3 {} of {} failed: HTTP {}",
+            action, key, status
+        )))
+    }
 }
 
-pub async fn list_snapshots(config: &S3Config, index_name: &str) -> Result<Vec<String>> {
-    let bucket = config.bucket_internal()?;
-    let prefix = format!("snapshots/{}/", index_name);
-    let results = bucket
-        .list(prefix, None)
-        .await
-        .map_err(|e| crate::error::FlapjackError::S3(format!("S3 list: {}", e)))?;
-    let mut keys: Vec<String> = results
-        .into_iter()
-        .flat_map(|r| r.contents)
-        .map(|obj| obj.key)
-        .collect();
-    keys.sort();
-    Ok(keys)
-}
+#[async_trait]
+impl SnapshotStore for S3Store {
+    fn location(&self) -> String {
+        format!("s3://{}", self.config.bucket_name)
+    }
 
-pub async fn delete_snapshot(config: &S3Config, key: &str) -> Result<()> {
-    let bucket = config.bucket_internal()?;
-    bucket
-        .delete_object(key)
-        .await
-        .map_err(|e| crate::error::FlapjackError::S3(format!("S3 delete: {}", e)))?;
-    Ok(())
-}
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let response = self
+            .bucket
+            .put_object(key, &data)
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 upload: {}", e)))?;
+        check_status("upload", key, response.status_code())
+    }
 
-pub async fn enforce_retention(config: &S3Config, index_name: &str, keep: usize) -> Result<usize> {
-    let keys = list_snapshots(config, index_name).await?;
-    if keys.len() <= keep {
-        return Ok(0);
+    /// Streamed as a multipart upload for large files.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()> {
+        let mut reader = tokio::fs::File::open(path).await?;
+        let response = self
+            .bucket
+            .put_object_stream(&mut reader, key)
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 upload: {}", e)))?;
+        check_status("upload", key, response.status_code())
     }
-    let to_delete = &keys[..keys.len() - keep];
-    for key in to_delete {
-        delete_snapshot(config, key).await?;
-        tracing::info!("Deleted old snapshot: {}", key);
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let response = self
+            .bucket
+            .get_object(key)
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 download: {}", e)))?;
+        check_status("download", key, response.status_code())?;
+        Ok(response.to_vec())
+    }
+
+    async fn get_to_file(&self, key: &str, path: &Path) -> Result<()> {
+        let mut writer = tokio::fs::File::create(path).await?;
+        let status = self
+            .bucket
+            .get_object_to_writer(key, &mut writer)
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 download: {}", e)))?;
+        check_status("download", key, status)
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let results = self
+            .bucket
+            .list(prefix.to_string(), None)
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 list: {}", e)))?;
+        let mut keys: Vec<String> = results
+            .into_iter()
+            .flat_map(|r| r.contents)
+            .map(|obj| obj.key)
+            .collect();
+        keys.sort();
+        Ok(keys)
+    }
+
+    async fn list_dirs(&self, prefix: &str) -> Result<Vec<String>> {
+        let results = self
+            .bucket
+            .list(prefix.to_string(), Some("/".to_string()))
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 list: {}", e)))?;
+        let mut names: Vec<String> = results
+            .iter()
+            .flat_map(|r| r.common_prefixes.iter().flatten())
+            .filter_map(|p| {
+                p.prefix
+                    .strip_prefix(prefix)
+                    .and_then(|s| s.strip_suffix('/'))
+                    .map(|s| s.to_string())
+            })
+            .collect();
+        names.sort();
+        Ok(names)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self
+            .bucket
+            .delete_object(key)
+            .await
+            .map_err(|e| FlapjackError::S3(format!("S3 delete: {}", e)))?;
+        match response.status_code() {
+            404 => Ok(()),
+            status => check_status("delete", key, status),
+        }
     }
-    Ok(to_delete.len())
 }
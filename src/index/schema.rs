@@ -112,6 +112,9 @@ impl Schema {
         builder.add_f64_field("_geo_lat", f64_opts.clone());
         builder.add_f64_field("_geo_lng", f64_opts);
 
+        // The `_vectors` of a record, kept for retrieval only
+        builder.add_f64_field("_vectors", tantivy::schema::STORED);
+
         builder.build()
     }
 
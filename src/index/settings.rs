@@ -1,7 +1,8 @@
+use crate::index::vectors::VECTORS_ATTRIBUTE;
 use crate::query::plurals::IgnorePluralsValue;
 use crate::query::stopwords::RemoveStopWordsValue;
 use serde::{Deserialize, Serialize, Serializer};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 
 fn default_hits_per_page() -> u32 {
@@ -147,12 +148,113 @@ pub struct IndexSettings {
     )]
     pub query_languages: Vec<String>,
 
+    /// Languages the records are written in. Stop words and plurals use them
+    /// when `queryLanguages` is not set.
+    #[serde(
+        rename = "indexLanguages",
+        default,
+        skip_serializing_if = "vec_is_empty"
+    )]
+    pub index_languages: Vec<String>,
+
     #[serde(
         rename = "ignorePlurals",
         default,
         skip_serializing_if = "ignore_plurals_is_default"
     )]
     pub ignore_plurals: IgnorePluralsValue,
+
+    /// Characters that keep their diacritics; every other accented letter is
+    /// folded to its base form when indexing and searching (e.g. "øé").
+    #[serde(
+        rename = "keepDiacriticsOnCharacters",
+        default,
+        skip_serializing_if = "String::is_empty"
+    )]
+    pub keep_diacritics_on_characters: String,
+
+    /// Attributes whose compound words are also indexed as their parts, per
+    /// language (e.g. `{"de": ["name"]}`).
+    #[serde(
+        rename = "decompoundedAttributes",
+        default,
+        skip_serializing_if = "BTreeMap::is_empty"
+    )]
+    pub decompounded_attributes: BTreeMap<String, Vec<String>>,
+
+    /// Split compound words in the query into their parts.
+    #[serde(rename = "decompoundQuery")]
+    pub decompound_query: bool,
+
+    /// Attributes matched without typos, such as SKUs or part numbers.
+    #[serde(
+        rename = "disableTypoToleranceOnAttributes",
+        default,
+        skip_serializing_if = "vec_is_empty"
+    )]
+    pub disable_typo_tolerance_on_attributes: Vec<String>,
+
+    /// Query words matched without typos.
+    #[serde(
+        rename = "disableTypoToleranceOnWords",
+        default,
+        skip_serializing_if = "vec_is_empty"
+    )]
+    pub disable_typo_tolerance_on_words: Vec<String>,
+
+    /// Attributes where the last query word must match a whole word.
+    #[serde(
+        rename = "disablePrefixOnAttributes",
+        default,
+        skip_serializing_if = "vec_is_empty"
+    )]
+    pub disable_prefix_on_attributes: Vec<String>,
+
+    /// Whether query words made only of digits may match with typos.
+    #[serde(rename = "allowTyposOnNumericTokens")]
+    pub allow_typos_on_numeric_tokens: bool,
+
+    /// Word distance (1-7) below which the proximity criterion considers
+    /// query terms equally close.
+    #[serde(rename = "minProximity")]
+    pub min_proximity: u32,
+
+    /// Attributes whose camelCase words are also indexed as their parts, so
+    /// "user profile" finds "getUserProfile".
+    #[serde(
+        rename = "camelCaseAttributes",
+        default,
+        skip_serializing_if = "vec_is_empty"
+    )]
+    pub camel_case_attributes: Vec<String>,
+
+    /// Use the query `vector` as a fallback for sparse keyword results
+    /// rather than fusing the two rankings.
+    #[serde(rename = "semanticSearch", skip_serializing_if = "Option::is_none")]
+    pub semantic_search: Option<SemanticSearch>,
+
+    /// Date attribute at which documents without `_expiresAt` expire, as a
+    /// Unix timestamp in seconds or an RFC 3339 date.
+    #[serde(rename = "expiresAtAttribute", skip_serializing_if = "Option::is_none")]
+    pub expires_at_attribute: Option<String>,
+
+    /// Seconds after `expiresAtAttribute` at which documents expire, e.g. to
+    /// remove job postings 30 days after their publication date.
+    #[serde(rename = "timeToLive", skip_serializing_if = "Option::is_none")]
+    pub time_to_live: Option<u64>,
+}
+
+/// The `semanticSearch` setting: when a query with a `vector` has fewer than
+/// `minLexicalHits` keyword hits, its nearest neighbours are appended after
+/// them. `removeWordsIfNoResults` only applies when that finds nothing either.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SemanticSearch {
+    #[serde(rename = "minLexicalHits", default = "default_min_lexical_hits")]
+    pub min_lexical_hits: usize,
+}
+
+fn default_min_lexical_hits() -> usize {
+    1
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -206,7 +308,20 @@ impl Default for IndexSettings {
             distinct: None,
             remove_stop_words: RemoveStopWordsValue::Disabled,
             query_languages: Vec::new(),
+            index_languages: Vec::new(),
             ignore_plurals: IgnorePluralsValue::Disabled,
+            keep_diacritics_on_characters: String::new(),
+            decompounded_attributes: BTreeMap::new(),
+            decompound_query: true,
+            disable_typo_tolerance_on_attributes: Vec::new(),
+            disable_typo_tolerance_on_words: Vec::new(),
+            disable_prefix_on_attributes: Vec::new(),
+            allow_typos_on_numeric_tokens: true,
+            min_proximity: 1,
+            camel_case_attributes: Vec::new(),
+            semantic_search: None,
+            expires_at_attribute: None,
+            time_to_live: None,
         }
     }
 }
@@ -256,6 +371,8 @@ impl IndexSettings {
         }
     }
 
+    /// Whether `field` is returned in hits. `_vectors` is only returned when
+    /// `attributesToRetrieve` names it.
     pub fn should_retrieve(&self, field: &str) -> bool {
         if let Some(unretrievable) = &self.unretrievable_attributes {
             if unretrievable.contains(&field.to_string()) {
@@ -264,13 +381,13 @@ impl IndexSettings {
         }
 
         if let Some(retrievable) = &self.attributes_to_retrieve {
-            if retrievable.contains(&"*".to_string()) {
+            if retrievable.contains(&field.to_string()) {
                 return true;
             }
-            return retrievable.contains(&field.to_string());
+            return field != VECTORS_ATTRIBUTE && retrievable.contains(&"*".to_string());
         }
 
-        true
+        field != VECTORS_ATTRIBUTE
     }
 }
 
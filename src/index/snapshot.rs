@@ -1,59 +1,384 @@
-use crate::error::Result;
+use crate::error::{FlapjackError, Result};
+use crate::index::encryption::{self, MaybeEncrypted};
+use crate::index::oplog::{self, OpLog, OpLogEntry};
+use crate::index::Index;
 use flate2::read::GzDecoder;
 use flate2::write::GzEncoder;
 use flate2::Compression;
-use std::fs::File;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
 use std::path::Path;
 use tar::{Archive, Builder};
 
-pub fn export_to_tarball(index_path: &Path, dest_file: &Path) -> Result<u64> {
-    let file = File::create(dest_file)?;
-    let encoder = GzEncoder::new(file, Compression::fast());
-    let mut archive = Builder::new(encoder);
-
-    archive.append_dir_all(".", index_path)?;
-
-    let encoder = archive.into_inner()?;
-    encoder.finish()?;
+/// Name of the archive entry listing the SHA-256 of every archived file.
+pub const CHECKSUMS_FILE: &str = "snapshot-checksums.json";
 
+pub fn export_to_tarball(index_path: &Path, dest_file: &Path) -> Result<u64> {
+    export_to_writer(index_path, File::create(dest_file)?)?;
     let size = std::fs::metadata(dest_file)?.len();
     Ok(size)
 }
 
 pub fn import_from_tarball(tarball_path: &Path, dest_dir: &Path) -> Result<()> {
-    std::fs::create_dir_all(dest_dir)?;
+    import_from_reader(File::open(tarball_path)?, dest_dir)
+}
 
-    let file = File::open(tarball_path)?;
-    let decoder = GzDecoder::new(file);
-    let mut archive = Archive::new(decoder);
+pub fn export_to_bytes(index_path: &Path) -> Result<Vec<u8>> {
+    export_to_writer(index_path, Vec::new())
+}
 
-    archive.unpack(dest_dir)?;
+pub fn import_from_bytes(data: &[u8], dest_dir: &Path) -> Result<()> {
+    import_from_reader(data, dest_dir)
+}
+
+/// Stream `index_path` as a tar.gz archive into `writer`, file by file, and
+/// end it with a [`CHECKSUMS_FILE`] of the archived files. The archive is
+/// encrypted when a master key is configured. Returns the writer.
+pub fn export_to_writer<W: Write>(index_path: &Path, writer: W) -> Result<W> {
+    match encryption::keyring()? {
+        Some(keyring) => Ok(write_archive(index_path, keyring.encryptor(writer)?)?.finish()?),
+        None => write_archive(index_path, writer),
+    }
+}
 
+fn write_archive<W: Write>(index_path: &Path, writer: W) -> Result<W> {
+    let mut archive = Builder::new(GzEncoder::new(writer, Compression::fast()));
+    let mut checksums = BTreeMap::new();
+    append_dir(&mut archive, index_path, index_path, &mut checksums)?;
+
+    let data = serde_json::to_vec_pretty(&checksums)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    archive.append_data(&mut header, CHECKSUMS_FILE, data.as_slice())?;
+    Ok(archive.into_inner()?.finish()?)
+}
+
+fn append_dir<W: Write>(
+    archive: &mut Builder<W>,
+    root: &Path,
+    dir: &Path,
+    checksums: &mut BTreeMap<String, String>,
+) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let path = entry.path();
+        let relative = relative_path(root, &path);
+        if entry.file_type()?.is_dir() {
+            archive.append_dir(&relative, &path)?;
+            append_dir(archive, root, &path, checksums)?;
+            continue;
+        }
+        if relative == CHECKSUMS_FILE || relative.ends_with(".lock") {
+            continue;
+        }
+        let file = File::open(&path)?;
+        let metadata = file.metadata()?;
+        let mut header = tar::Header::new_gnu();
+        header.set_metadata(&metadata);
+        let mut reader = HashingReader {
+            inner: file.take(metadata.len()),
+            hasher: Sha256::new(),
+        };
+        archive.append_data(&mut header, &relative, &mut reader)?;
+        checksums.insert(relative, hex::encode(reader.hasher.finalize()));
+    }
     Ok(())
 }
 
-pub fn export_to_bytes(index_path: &Path) -> Result<Vec<u8>> {
-    let mut buffer = Vec::new();
-    {
-        let encoder = GzEncoder::new(&mut buffer, Compression::fast());
-        let mut archive = Builder::new(encoder);
-        archive.append_dir_all(".", index_path)?;
-        let encoder = archive.into_inner()?;
-        encoder.finish()?;
+struct HashingReader<R> {
+    inner: R,
+    hasher: Sha256,
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
     }
-    Ok(buffer)
 }
 
-pub fn import_from_bytes(data: &[u8], dest_dir: &Path) -> Result<()> {
-    std::fs::create_dir_all(dest_dir)?;
+/// Unpack a tar.gz archive streamed from `reader` next to `dest_dir`, check
+/// it against its [`CHECKSUMS_FILE`], then swap it in place of `dest_dir`.
+/// Archives without checksums, from older exports, are taken as they are.
+pub fn import_from_reader<R: Read>(reader: R, dest_dir: &Path) -> Result<()> {
+    let staging = dest_dir.with_file_name(format!(".import-{}", uuid::Uuid::new_v4()));
+    let unpacked = unpack(reader, &staging).and_then(|()| verify_checksums(&staging));
+    if let Err(e) = unpacked {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    if dest_dir.exists() {
+        fs::remove_dir_all(dest_dir)?;
+    }
+    fs::rename(&staging, dest_dir)?;
+    Ok(())
+}
 
-    let decoder = GzDecoder::new(data);
-    let mut archive = Archive::new(decoder);
+fn unpack<R: Read>(reader: R, dest_dir: &Path) -> Result<()> {
+    let reader = MaybeEncrypted::new(reader, encryption::keyring()?.as_deref())?;
+    let mut archive = Archive::new(GzDecoder::new(reader));
     archive.unpack(dest_dir)?;
+    // Reading past the end of the tar checks the gzip trailer, which catches
+    // an archive cut short between two entries
+    std::io::copy(&mut archive.into_inner(), &mut std::io::sink())?;
+    Ok(())
+}
+
+fn verify_checksums(dir: &Path) -> Result<()> {
+    let path = dir.join(CHECKSUMS_FILE);
+    if !path.exists() {
+        return Ok(());
+    }
+    let expected: BTreeMap<String, String> = serde_json::from_slice(&fs::read(&path)?)?;
+    for (relative, checksum) in &expected {
+        let mut hasher = Sha256::new();
+        let mut file = File::open(dir.join(relative))
+            .map_err(|_| FlapjackError::Io(format!("snapshot is missing {}", relative)))?;
+        std::io::copy(&mut file, &mut hasher)?;
+        if hex::encode(hasher.finalize()) != *checksum {
+            return Err(FlapjackError::Io(format!(
+                "snapshot checksum mismatch for {}",
+                relative
+            )));
+        }
+    }
+    fs::remove_file(path)?;
+    Ok(())
+}
+
+fn relative_path(root: &Path, path: &Path) -> String {
+    path.strip_prefix(root)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The files of a tenant at one point in time, for incremental snapshots.
+///
+/// Tantivy segment files are never modified once written, so they are
+/// stored once under `segments/` and shared by every manifest listing them.
+/// The other files (`meta.json`, settings, oplog, ...) change in place and
+/// are stored again under `files/{created_at_ms}/` with each manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    pub index_name: String,
+    pub created_at_ms: u64,
+    /// Last oplog seq committed to the index when the files were listed;
+    /// later ops are replayed from the oplog on restore.
+    pub committed_seq: u64,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    /// Path relative to the tenant directory, `/`-separated.
+    pub path: String,
+    pub size: u64,
+    /// Storage key relative to the tenant's snapshot prefix.
+    pub key: String,
+}
+
+impl SnapshotManifest {
+    /// Files that are not already stored by `previous`.
+    pub fn new_files(&self, previous: Option<&SnapshotManifest>) -> Vec<&ManifestFile> {
+        let stored: HashSet<&str> = previous
+            .map(|p| p.files.iter().map(|f| f.key.as_str()).collect())
+            .unwrap_or_default();
+        self.files
+            .iter()
+            .filter(|f| !stored.contains(f.key.as_str()))
+            .collect()
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.files.iter().map(|f| f.size).sum()
+    }
+}
+
+/// The last commit of a tenant, pinned for an incremental snapshot.
+///
+/// Tantivy only garbage collects the files of segments whose metadata is no
+/// longer alive, so while the pin lives, merges cannot delete the segments
+/// of the commit, and the snapshot lists exactly the files of its
+/// `meta.json` rather than whatever the directory holds mid-merge.
+pub struct PinnedCommit {
+    meta: tantivy::IndexMeta,
+    meta_json: Vec<u8>,
+    committed_seq: u64,
+}
+
+impl PinnedCommit {
+    /// Pin the last commit of `index`, stored at `index_path`.
+    pub fn pin(index: &Index, index_path: &Path) -> Result<Self> {
+        // Read before the commit: a commit landing in between only means
+        // replaying a few ops that are already in the index.
+        let committed_seq = read_committed_seq(index_path);
+        let meta = index.inner().load_metas()?;
+        let meta_json = serde_json::to_vec_pretty(&meta)?;
+        Ok(PinnedCommit {
+            meta,
+            meta_json,
+            committed_seq,
+        })
+    }
+
+    /// The `meta.json` of the commit.
+    pub fn meta_json(&self) -> &[u8] {
+        &self.meta_json
+    }
+
+    fn segment_files(&self) -> BTreeSet<String> {
+        self.meta
+            .segments
+            .iter()
+            .flat_map(|segment| segment.list_files())
+            .map(|path| path.to_string_lossy().into_owned())
+            .collect()
+    }
+}
+
+/// Whether `name` is a tantivy segment file: the 32 hex digits of the
+/// segment id, then the component extension.
+pub fn is_segment_file(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    bytes.len() > 33 && bytes[32] == b'.' && bytes[..32].iter().all(u8::is_ascii_hexdigit)
+}
+
+/// List the files of the tenant at `index_path` for an incremental snapshot
+/// of `commit` taken at `created_at_ms`: the segment files and `meta.json`
+/// of the commit, and the other files of the tenant.
+pub fn build_manifest(
+    index_path: &Path,
+    index_name: &str,
+    created_at_ms: u64,
+    commit: &PinnedCommit,
+) -> Result<SnapshotManifest> {
+    let mut files = Vec::new();
+    for name in commit.segment_files() {
+        let size = match fs::metadata(index_path.join(&name)) {
+            Ok(metadata) => metadata.len(),
+            // Components a segment has no data for are not written
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        files.push(ManifestFile {
+            key: format!("segments/{}", name),
+            path: name,
+            size,
+        });
+    }
+    files.push(ManifestFile {
+        path: "meta.json".to_string(),
+        size: commit.meta_json.len() as u64,
+        key: format!("files/{}/meta.json", created_at_ms),
+    });
+    collect_files(index_path, index_path, created_at_ms, &mut files)?;
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(SnapshotManifest {
+        index_name: index_name.to_string(),
+        created_at_ms,
+        committed_seq: commit.committed_seq,
+        files,
+    })
+}
 
+fn collect_files(
+    root: &Path,
+    dir: &Path,
+    created_at_ms: u64,
+    files: &mut Vec<ManifestFile>,
+) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            collect_files(root, &path, created_at_ms, files)?;
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.ends_with(".lock") {
+            continue;
+        }
+        // The index files come from the pinned commit; `.managed.json` only
+        // tracks the files tantivy may garbage collect
+        if dir == root && (is_segment_file(&name) || name == "meta.json" || name == ".managed.json")
+        {
+            continue;
+        }
+        let relative = relative_path(root, &path);
+        files.push(ManifestFile {
+            key: format!("files/{}/{}", created_at_ms, relative),
+            path: relative,
+            size: entry.metadata()?.len(),
+        });
+    }
     Ok(())
 }
 
+fn read_committed_seq(index_path: &Path) -> u64 {
+    fs::read_to_string(index_path.join("committed_seq"))
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// All entries of the oplog in `dir`, in seq order.
+pub fn read_oplog(dir: &Path) -> Result<Vec<OpLogEntry>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    OpLog::open(dir, "", "")?.read_since(0)
+}
+
+/// Prepare a restored snapshot for point-in-time recovery: rewrite its oplog
+/// with its own entries and `later_ops` (taken from a later snapshot or the
+/// live tenant) up to `target_ms`. The ops past `committed_seq` are then
+/// replayed by the oplog recovery when the tenant is loaded. Returns their
+/// number.
+pub fn truncate_oplog_at(
+    index_path: &Path,
+    later_ops: Vec<OpLogEntry>,
+    target_ms: u64,
+) -> Result<usize> {
+    let committed_seq = read_committed_seq(index_path);
+    let oplog_dir = index_path.join("oplog");
+    let mut ops: BTreeMap<u64, OpLogEntry> = later_ops.into_iter().map(|e| (e.seq, e)).collect();
+    ops.extend(read_oplog(&oplog_dir)?.into_iter().map(|e| (e.seq, e)));
+
+    if let Some(&first) = ops.range(committed_seq + 1..).next().map(|(seq, _)| seq) {
+        if first != committed_seq + 1 {
+            return Err(FlapjackError::Io(format!(
+                "oplog no longer holds ops {}..{} after the snapshot; pick a time closer to a snapshot",
+                committed_seq + 1,
+                first
+            )));
+        }
+    }
+    ops.retain(|_, e| e.timestamp_ms <= target_ms);
+
+    if oplog_dir.exists() {
+        fs::remove_dir_all(&oplog_dir)?;
+    }
+    fs::create_dir_all(&oplog_dir)?;
+    let keyring = encryption::keyring()?;
+    let mut writer = BufWriter::new(File::create(oplog_dir.join("segment_0001.jsonl"))?);
+    for entry in ops.values() {
+        writer.write_all(oplog::encode_line(entry, keyring.as_deref())?.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(ops.range(committed_seq + 1..).count())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -100,5 +425,161 @@ mod tests {
             fs::read_to_string(restored.path().join("data.json")).unwrap(),
             r#"{"key": "value"}"#
         );
+        assert!(!restored.path().join(CHECKSUMS_FILE).exists());
+    }
+
+    #[test]
+    fn test_import_rejects_corrupted_archive() {
+        let mut archive = Builder::new(GzEncoder::new(Vec::new(), Compression::fast()));
+        let entries: [(&str, &[u8]); 2] = [
+            ("data.json", b"{}"),
+            (CHECKSUMS_FILE, br#"{"data.json": "00"}"#),
+        ];
+        for (name, data) in entries {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            archive.append_data(&mut header, name, data).unwrap();
+        }
+        let bytes = archive.into_inner().unwrap().finish().unwrap();
+
+        let dest = TempDir::new().unwrap();
+        fs::write(dest.path().join("existing.txt"), "kept").unwrap();
+        assert!(import_from_bytes(&bytes, dest.path()).is_err());
+        // The index being replaced is left untouched
+        assert_eq!(
+            fs::read_to_string(dest.path().join("existing.txt")).unwrap(),
+            "kept"
+        );
+        assert!(!dest.path().join("data.json").exists());
+    }
+
+    const SEGMENT: &str = "0123456789abcdef0123456789abcdef";
+
+    fn write_tenant(dir: &Path, committed_seq: u64) {
+        fs::write(dir.join(".tantivy-writer.lock"), "").unwrap();
+        fs::write(dir.join("committed_seq"), committed_seq.to_string()).unwrap();
+    }
+
+    fn entry(seq: u64, timestamp_ms: u64) -> OpLogEntry {
+        OpLogEntry {
+            seq,
+            timestamp_ms,
+            node_id: "node1".into(),
+            tenant_id: "t1".into(),
+            op_type: "upsert".into(),
+            payload: serde_json::json!({"objectID": seq.to_string()}),
+        }
+    }
+
+    fn segment_keys(manifest: &SnapshotManifest) -> Vec<&str> {
+        manifest
+            .files
+            .iter()
+            .map(|f| f.key.as_str())
+            .filter(|k| k.starts_with("segments/"))
+            .collect()
+    }
+
+    #[test]
+    fn test_manifest_lists_the_pinned_commit() {
+        let src = TempDir::new().unwrap();
+        let index = Index::create_in_dir(src.path()).unwrap();
+        index
+            .add_documents_simple(&[serde_json::json!({"objectID": "1", "title": "a"})])
+            .unwrap();
+        write_tenant(src.path(), 3);
+        let oplog = OpLog::open(&src.path().join("oplog"), "t1", "node1").unwrap();
+        oplog.append("upsert", serde_json::json!({})).unwrap();
+
+        let commit = PinnedCommit::pin(&index, src.path()).unwrap();
+        let first = build_manifest(src.path(), "t1", 1000, &commit).unwrap();
+        assert_eq!(first.committed_seq, 3);
+        let keys: Vec<&str> = first.files.iter().map(|f| f.key.as_str()).collect();
+        assert!(!segment_keys(&first).is_empty());
+        for key in [
+            "files/1000/committed_seq",
+            "files/1000/meta.json",
+            "files/1000/oplog/segment_0001.jsonl",
+        ] {
+            assert!(keys.contains(&key), "{:?}", keys);
+        }
+        assert!(!keys
+            .iter()
+            .any(|k| k.ends_with(".lock") || k.ends_with(".managed.json")));
+        assert_eq!(first.new_files(None).len(), first.files.len());
+
+        // Merged away after the pin, the segments of the commit are kept
+        index
+            .add_documents_simple(&[serde_json::json!({"objectID": "2", "title": "b"})])
+            .unwrap();
+        let mut writer = index.writer().unwrap();
+        let segment_ids = index.inner().searchable_segment_ids().unwrap();
+        writer.merge(&segment_ids).wait().unwrap();
+        index.reader().reload().unwrap();
+        writer.garbage_collect_files().wait().unwrap();
+        for file in &first.files {
+            assert!(src.path().join(&file.path).exists(), "{}", file.path);
+        }
+
+        // The next snapshot only stores the merged segment
+        drop(commit);
+        writer.garbage_collect_files().wait().unwrap();
+        let commit = PinnedCommit::pin(&index, src.path()).unwrap();
+        let second = build_manifest(src.path(), "t1", 2000, &commit).unwrap();
+        let new_keys: Vec<&str> = second
+            .new_files(Some(&first))
+            .into_iter()
+            .map(|f| f.key.as_str())
+            .collect();
+        assert!(new_keys.contains(&"files/2000/meta.json"));
+        for key in segment_keys(&second) {
+            assert!(new_keys.contains(&key));
+            assert!(!segment_keys(&first).contains(&key));
+        }
+        for key in segment_keys(&first) {
+            let name = key.trim_start_matches("segments/");
+            assert!(!src.path().join(name).exists(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_is_segment_file() {
+        assert!(is_segment_file(&format!("{}.store", SEGMENT)));
+        assert!(is_segment_file(&format!("{}.42.del", SEGMENT)));
+        assert!(!is_segment_file("meta.json"));
+        assert!(!is_segment_file(".managed.json"));
+        assert!(!is_segment_file(&format!("{}x.idx", &SEGMENT[..31])));
+    }
+
+    #[test]
+    fn test_truncate_oplog_at_keeps_ops_up_to_target() {
+        let dir = TempDir::new().unwrap();
+        write_tenant(dir.path(), 2);
+        let oplog_dir = dir.path().join("oplog");
+        fs::create_dir_all(&oplog_dir).unwrap();
+        let own: String = [entry(1, 100), entry(2, 200), entry(3, 300)]
+            .iter()
+            .map(|e| serde_json::to_string(e).unwrap() + "\n")
+            .collect();
+        fs::write(oplog_dir.join("segment_0001.jsonl"), own).unwrap();
+
+        let later = vec![entry(3, 300), entry(4, 400), entry(5, 500)];
+        let replayed = truncate_oplog_at(dir.path(), later, 450).unwrap();
+        assert_eq!(replayed, 2);
+        let seqs: Vec<u64> = read_oplog(&oplog_dir)
+            .unwrap()
+            .iter()
+            .map(|e| e.seq)
+            .collect();
+        assert_eq!(seqs, vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_truncate_oplog_at_rejects_gaps() {
+        let dir = TempDir::new().unwrap();
+        write_tenant(dir.path(), 2);
+        let later = vec![entry(5, 500), entry(6, 600)];
+        assert!(truncate_oplog_at(dir.path(), later, 550).is_err());
     }
 }
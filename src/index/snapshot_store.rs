@@ -0,0 +1,548 @@
+//! Where snapshots are kept: a [`SnapshotStore`] is a flat key space of
+//! objects, implemented by [`LocalStore`] for a local or mounted directory
+//! and by [`S3Store`](crate::index::s3::S3Store) for S3 and S3-compatible
+//! services. The backup and restore operations below work on any of them.
+//!
+//! Full snapshots are stored as `snapshots/{index}/{timestamp}.tar.gz`,
+//! incremental ones under `incremental/{index}/` (see
+//! [`SnapshotManifest`]). With encryption at rest, files of incremental
+//! snapshots that are not encrypted on disk are encrypted when stored and
+//! decrypted when restored.
+
+use crate::error::{FlapjackError, Result};
+use crate::index::encryption;
+use crate::index::s3::{S3Config, S3Store};
+use crate::index::snapshot::{self, ManifestFile, PinnedCommit, SnapshotManifest};
+use async_trait::async_trait;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[async_trait]
+pub trait SnapshotStore: Send + Sync {
+    /// Where the objects are kept, for logs.
+    fn location(&self) -> String;
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()>;
+
+    /// Store the file at `path` without reading it into memory.
+    async fn put_file(&self, key: &str, path: &Path) -> Result<()>;
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Write the object to `path` without holding it in memory.
+    async fn get_to_file(&self, key: &str, path: &Path) -> Result<()>;
+
+    /// Keys starting with `prefix`, sorted.
+    async fn list(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Names of the "directories" directly under `prefix`, which ends with
+    /// `/`.
+    async fn list_dirs(&self, prefix: &str) -> Result<Vec<String>>;
+
+    /// Delete an object; deleting a missing one is not an error.
+    async fn delete(&self, key: &str) -> Result<()>;
+
+    /// Keep the `keep` most recent full snapshots of an index. Returns the
+    /// number deleted.
+    async fn enforce_retention(&self, index_name: &str, keep: usize) -> Result<usize> {
+        let keys = self.list(&format!("snapshots/{}/", index_name)).await?;
+        if keys.len() <= keep {
+            return Ok(0);
+        }
+        let to_delete = &keys[..keys.len() - keep];
+        for key in to_delete {
+            self.delete(key).await?;
+            tracing::info!("Deleted old snapshot: {}", key);
+        }
+        Ok(to_delete.len())
+    }
+}
+
+/// The configured snapshot store: the directory in `FLAPJACK_SNAPSHOT_DIR`,
+/// or else the S3 bucket in `FLAPJACK_S3_BUCKET`.
+pub fn from_env() -> Option<Arc<dyn SnapshotStore>> {
+    if let Ok(dir) = std::env::var("FLAPJACK_SNAPSHOT_DIR") {
+        return Some(Arc::new(LocalStore::new(dir)));
+    }
+    let config = S3Config::from_env()?;
+    match S3Store::new(config) {
+        Ok(store) => Some(Arc::new(store)),
+        Err(e) => {
+            tracing::error!("Snapshot store: {}", e);
+            None
+        }
+    }
+}
+
+/// Snapshots in a local or mounted directory, one file per key.
+pub struct LocalStore {
+    root: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        LocalStore { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if key
+            .split('/')
+            .any(|part| part.is_empty() || part == "." || part == "..")
+        {
+            return Err(FlapjackError::Io(format!("Invalid snapshot key: {}", key)));
+        }
+        Ok(self.root.join(key))
+    }
+
+    /// Written next to the object then renamed, so that readers never see a
+    /// partial object.
+    fn partial_path(path: &Path) -> PathBuf {
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(".partial");
+        path.with_file_name(name)
+    }
+
+    async fn create_parent(path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SnapshotStore for LocalStore {
+    fn location(&self) -> String {
+        self.root.display().to_string()
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>) -> Result<()> {
+        let path = self.path(key)?;
+        Self::create_parent(&path).await?;
+        let partial = Self::partial_path(&path);
+        tokio::fs::write(&partial, data).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn put_file(&self, key: &str, source: &Path) -> Result<()> {
+        let path = self.path(key)?;
+        Self::create_parent(&path).await?;
+        let partial = Self::partial_path(&path);
+        tokio::fs::copy(source, &partial).await?;
+        tokio::fs::rename(&partial, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Vec<u8>> {
+        Ok(tokio::fs::read(self.path(key)?).await?)
+    }
+
+    async fn get_to_file(&self, key: &str, dest: &Path) -> Result<()> {
+        tokio::fs::copy(self.path(key)?, dest).await?;
+        Ok(())
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<String>> {
+        let root = self.root.clone();
+        // Only the directory holding the prefix needs walking
+        let dir = match prefix.rfind('/') {
+            Some(end) => self.path(&prefix[..end])?,
+            None => root.clone(),
+        };
+        let prefix = prefix.to_string();
+        tokio::task::spawn_blocking(move || {
+            let mut keys = Vec::new();
+            if dir.exists() {
+                walk(&root, &dir, &mut keys)?;
+            }
+            keys.retain(|key| key.starts_with(&prefix) && !key.ends_with(".partial"));
+            keys.sort();
+            Ok(keys)
+        })
+        .await
+        .map_err(|e| FlapjackError::Io(format!("Snapshot listing task: {}", e)))?
+    }
+
+    async fn list_dirs(&self, prefix: &str) -> Result<Vec<String>> {
+        let dir = self.path(prefix.trim_end_matches('/'))?;
+        let mut names = Vec::new();
+        let Ok(mut entries) = tokio::fs::read_dir(&dir).await else {
+            return Ok(names);
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_dir() {
+                if let Ok(name) = entry.file_name().into_string() {
+                    names.push(name);
+                }
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.path(key)?).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn walk(root: &Path, dir: &Path, keys: &mut Vec<String>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            walk(root, &path, keys)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let parts: Vec<String> = relative
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            keys.push(parts.join("/"));
+        }
+    }
+    Ok(())
+}
+
+/// Indexes with full or incremental snapshots in the store.
+pub async fn list_indexes(store: &dyn SnapshotStore) -> Result<Vec<String>> {
+    let mut names = store.list_dirs("snapshots/").await?;
+    names.extend(store.list_dirs("incremental/").await?);
+    names.sort();
+    names.dedup();
+    Ok(names)
+}
+
+/// Export `index_path` to a tar.gz next to it and store that, so that the
+/// archive is never held in memory. Returns the key and the archive size.
+pub async fn upload_snapshot(
+    store: &dyn SnapshotStore,
+    index_name: &str,
+    index_path: &Path,
+) -> Result<(String, u64)> {
+    let tarball = staging_file(index_path)?;
+    let (source, dest) = (index_path.to_path_buf(), tarball.path().to_path_buf());
+    let size = tokio::task::spawn_blocking(move || snapshot::export_to_tarball(&source, &dest))
+        .await
+        .map_err(|e| FlapjackError::Io(format!("Export task: {}", e)))??;
+
+    let timestamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+    let key = format!("snapshots/{}/{}.tar.gz", index_name, timestamp);
+    store.put_file(&key, tarball.path()).await?;
+
+    tracing::info!(
+        "Uploaded snapshot {}/{} ({} bytes)",
+        store.location(),
+        key,
+        size
+    );
+    Ok((key, size))
+}
+
+/// Full snapshots of an index, oldest first.
+pub async fn list_snapshots(store: &dyn SnapshotStore, index_name: &str) -> Result<Vec<String>> {
+    store.list(&format!("snapshots/{}/", index_name)).await
+}
+
+/// Key of the most recent full snapshot of an index.
+pub async fn latest_snapshot(store: &dyn SnapshotStore, index_name: &str) -> Result<String> {
+    let keys = list_snapshots(store, index_name).await?;
+    keys.last()
+        .cloned()
+        .ok_or_else(|| FlapjackError::Io(format!("No snapshots found for {}", index_name)))
+}
+
+/// Copy the snapshot at `key` to disk, then import it into `dest_dir` once
+/// its checksums match. Returns the archive size.
+pub async fn restore_snapshot(
+    store: &dyn SnapshotStore,
+    key: &str,
+    dest_dir: &Path,
+) -> Result<u64> {
+    let tarball = staging_file(dest_dir)?;
+    store.get_to_file(key, tarball.path()).await?;
+    let size = tokio::fs::metadata(tarball.path()).await?.len();
+
+    let (source, dest) = (tarball.path().to_path_buf(), dest_dir.to_path_buf());
+    tokio::task::spawn_blocking(move || snapshot::import_from_tarball(&source, &dest))
+        .await
+        .map_err(|e| FlapjackError::Io(format!("Import task: {}", e)))??;
+    Ok(size)
+}
+
+/// A temporary file in the directory holding `index_path`, which is on the
+/// same disk as the index and usually larger than `/tmp`.
+fn staging_file(index_path: &Path) -> Result<tempfile::NamedTempFile> {
+    let dir = index_path.parent().unwrap_or_else(|| Path::new("."));
+    std::fs::create_dir_all(dir)?;
+    Ok(tempfile::Builder::new()
+        .prefix(".snapshot-")
+        .suffix(".tar.gz")
+        .tempfile_in(dir)?)
+}
+
+/// Result of [`upload_incremental_snapshot`].
+#[derive(Debug, Clone)]
+pub struct IncrementalUpload {
+    pub manifest_key: String,
+    pub files: usize,
+    pub uploaded_files: usize,
+    pub uploaded_bytes: u64,
+}
+
+/// Result of [`restore_point_in_time`].
+#[derive(Debug, Clone)]
+pub struct PointInTimeRestore {
+    pub manifest_key: String,
+    pub snapshot_at_ms: u64,
+    /// Oplog ops after the snapshot that are replayed when the tenant loads.
+    pub replayed_ops: usize,
+}
+
+fn incremental_prefix(index_name: &str) -> String {
+    format!("incremental/{}/", index_name)
+}
+
+/// Store the files of `commit` and of the rest of `index_path` that the
+/// latest incremental snapshot does not already store, then a manifest
+/// listing all of them under
+/// `incremental/{index}/manifests/{created_at_ms}.json`. `commit` keeps its
+/// segment files from being merged away until the upload is done.
+pub async fn upload_incremental_snapshot(
+    store: &dyn SnapshotStore,
+    index_name: &str,
+    index_path: &Path,
+    commit: &PinnedCommit,
+) -> Result<IncrementalUpload> {
+    let previous = match list_manifests(store, index_name).await?.last() {
+        Some((_, key)) => Some(download_manifest(store, key).await?),
+        None => None,
+    };
+    let created_at_ms = chrono::Utc::now().timestamp_millis() as u64;
+    let manifest = snapshot::build_manifest(index_path, index_name, created_at_ms, commit)?;
+
+    let prefix = incremental_prefix(index_name);
+    let new_files = manifest.new_files(previous.as_ref());
+    let keyring = encryption::keyring()?;
+    let mut uploaded_bytes = 0;
+    for file in &new_files {
+        let key = format!("{}{}", prefix, file.key);
+        let path = index_path.join(&file.path);
+        if file.path == "meta.json" {
+            // Stored as encrypted as the index files it lists
+            let data = match &keyring {
+                Some(keyring) if encryption::is_encrypted_file(&path)? => {
+                    keyring.encrypt(commit.meta_json())
+                }
+                _ => commit.meta_json().to_vec(),
+            };
+            store.put(&key, data).await?;
+            uploaded_bytes += file.size;
+            continue;
+        }
+        match &keyring {
+            Some(keyring) if !encryption::is_encrypted_file(&path)? => {
+                let data = tokio::fs::read(&path).await?;
+                store.put(&key, keyring.encrypt(&data)).await?;
+            }
+            _ => store.put_file(&key, &path).await?,
+        }
+        uploaded_bytes += file.size;
+    }
+
+    // Stored last, so that a manifest only ever lists stored files
+    let manifest_key = format!("{}manifests/{}.json", prefix, created_at_ms);
+    store
+        .put(&manifest_key, serde_json::to_vec(&manifest)?)
+        .await?;
+
+    tracing::info!(
+        "Uploaded incremental snapshot {}/{} ({} of {} files, {} bytes)",
+        store.location(),
+        manifest_key,
+        new_files.len(),
+        manifest.files.len(),
+        uploaded_bytes
+    );
+    Ok(IncrementalUpload {
+        manifest_key,
+        files: manifest.files.len(),
+        uploaded_files: new_files.len(),
+        uploaded_bytes,
+    })
+}
+
+/// Incremental snapshot manifests of an index as `(created_at_ms, key)`,
+/// oldest first.
+pub async fn list_manifests(
+    store: &dyn SnapshotStore,
+    index_name: &str,
+) -> Result<Vec<(u64, String)>> {
+    let prefix = format!("{}manifests/", incremental_prefix(index_name));
+    let mut manifests: Vec<(u64, String)> = store
+        .list(&prefix)
+        .await?
+        .into_iter()
+        .filter_map(|key| {
+            let created_at_ms = key
+                .strip_prefix(&prefix)?
+                .strip_suffix(".json")?
+                .parse()
+                .ok()?;
+            Some((created_at_ms, key))
+        })
+        .collect();
+    manifests.sort();
+    Ok(manifests)
+}
+
+pub async fn download_manifest(store: &dyn SnapshotStore, key: &str) -> Result<SnapshotManifest> {
+    let data = store.get(key).await?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+async fn download_files(
+    store: &dyn SnapshotStore,
+    prefix: &str,
+    files: impl IntoIterator<Item = &ManifestFile>,
+    dest_dir: &Path,
+) -> Result<()> {
+    for file in files {
+        let path = dest_dir.join(&file.path);
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        store
+            .get_to_file(&format!("{}{}", prefix, file.key), &path)
+            .await?;
+        // Tantivy files are read encrypted, the others were encrypted for
+        // the upload
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if !encryption::is_index_file(&name) && encryption::is_encrypted_file(&path)? {
+            let keyring = encryption::keyring()?.ok_or_else(|| {
+                FlapjackError::Config(
+                    "Snapshot is encrypted but no master key is configured".to_string(),
+                )
+            })?;
+            encryption::decrypt_file(&path, &keyring)?;
+        }
+    }
+    Ok(())
+}
+
+/// Restore `index_path` to its state at `target_ms`: fetch the latest
+/// incremental snapshot taken at or before that time, and leave the oplog
+/// ops up to `target_ms` to be replayed when the tenant is loaded. The ops
+/// after the snapshot come from the next snapshot's oplog, or from the
+/// tenant's live oplog when there is none. Without a target, the latest
+/// snapshot is restored as it was taken.
+///
+/// The tenant must be unloaded by the caller; its directory is replaced.
+pub async fn restore_point_in_time(
+    store: &dyn SnapshotStore,
+    index_name: &str,
+    index_path: &Path,
+    target_ms: Option<u64>,
+) -> Result<PointInTimeRestore> {
+    let manifests = list_manifests(store, index_name).await?;
+    let base = manifests
+        .iter()
+        .rposition(|(created_at_ms, _)| *created_at_ms <= target_ms.unwrap_or(u64::MAX))
+        .ok_or_else(|| {
+            FlapjackError::Io(match target_ms {
+                Some(ms) => format!(
+                    "No incremental snapshot of {} at or before {}",
+                    index_name, ms
+                ),
+                None => format!("No incremental snapshots found for {}", index_name),
+            })
+        })?;
+    let manifest_key = manifests[base].1.clone();
+    let manifest = download_manifest(store, &manifest_key).await?;
+
+    let prefix = incremental_prefix(index_name);
+    let staging = index_path.with_file_name(format!(".restore-{}", index_name));
+    if staging.exists() {
+        std::fs::remove_dir_all(&staging)?;
+    }
+    download_files(store, &prefix, &manifest.files, &staging).await?;
+
+    let later_ops = match (target_ms, manifests.get(base + 1)) {
+        (None, _) => Vec::new(),
+        (Some(_), Some((_, next_key))) => {
+            let next = download_manifest(store, next_key).await?;
+            let oplog = tempfile::TempDir::new()?;
+            download_files(
+                store,
+                &prefix,
+                next.files.iter().filter(|f| f.path.starts_with("oplog/")),
+                oplog.path(),
+            )
+            .await?;
+            snapshot::read_oplog(&oplog.path().join("oplog"))?
+        }
+        (Some(_), None) => snapshot::read_oplog(&index_path.join("oplog"))?,
+    };
+    let replayed_ops = snapshot::truncate_oplog_at(
+        &staging,
+        later_ops,
+        target_ms.unwrap_or(manifest.created_at_ms),
+    )?;
+
+    if index_path.exists() {
+        std::fs::remove_dir_all(index_path)?;
+    }
+    std::fs::rename(&staging, index_path)?;
+
+    tracing::info!(
+        "Restored {} from {} with {} ops to replay",
+        index_name,
+        manifest_key,
+        replayed_ops
+    );
+    Ok(PointInTimeRestore {
+        manifest_key,
+        snapshot_at_ms: manifest.created_at_ms,
+        replayed_ops,
+    })
+}
+
+/// Keep the `keep` most recent incremental snapshots of an index: delete
+/// older manifests with their files, except segments still listed by a kept
+/// manifest.
+pub async fn enforce_incremental_retention(
+    store: &dyn SnapshotStore,
+    index_name: &str,
+    keep: usize,
+) -> Result<usize> {
+    let manifests = list_manifests(store, index_name).await?;
+    if manifests.len() <= keep {
+        return Ok(0);
+    }
+    let (expired, kept) = manifests.split_at(manifests.len() - keep);
+    let mut retained = std::collections::HashSet::new();
+    for (_, key) in kept {
+        retained.extend(
+            download_manifest(store, key)
+                .await?
+                .files
+                .into_iter()
+                .map(|f| f.key),
+        );
+    }
+
+    let prefix = incremental_prefix(index_name);
+    for (_, key) in expired {
+        for file in download_manifest(store, key).await?.files {
+            // Also skips the segments already deleted with an older manifest
+            if retained.insert(file.key.clone()) {
+                store.delete(&format!("{}{}", prefix, file.key)).await?;
+            }
+        }
+        store.delete(key).await?;
+        tracing::info!("Deleted old incremental snapshot: {}", key);
+    }
+    Ok(expired.len())
+}
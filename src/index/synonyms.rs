@@ -144,6 +144,10 @@ impl SynonymStore {
         self.synonyms.remove(object_id)
     }
 
+    pub fn all(&self) -> Vec<Synonym> {
+        self.synonyms.values().cloned().collect()
+    }
+
     pub fn clear(&mut self) {
         self.synonyms.clear();
     }
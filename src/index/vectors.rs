@@ -0,0 +1,932 @@
+//! Approximate nearest-neighbour search over the records' `_vectors`.
+//!
+//! Clients supply the embedding of each record as a `_vectors` array of
+//! numbers, so no model runs on the server. The vectors are kept in an HNSW
+//! graph (cosine similarity) stored as `vectors.bin` next to the tantivy
+//! files, and updated by the write queue after each commit. A commit only
+//! appends its changes to `vectors.log`, which is replayed on open and folded
+//! into `vectors.bin` once it grows long. Deleted and replaced
+//! vectors stay in the graph as tombstones for navigation until they
+//! outnumber the live ones, when the graph is rebuilt.
+//!
+//! With a master key configured, `vectors.bin` is encrypted as a whole and
+//! each commit appends its changes to `vectors.log` as one sealed record.
+
+use crate::error::{FlapjackError, Result};
+use crate::index::encryption::{self, Keyring};
+use crate::types::{Document, FieldValue};
+use std::cmp::Reverse;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+/// File of the vector index in the index directory.
+pub const VECTORS_FILE: &str = "vectors.bin";
+
+/// Changes committed since [`VECTORS_FILE`] was written.
+pub const VECTORS_LOG_FILE: &str = "vectors.log";
+
+/// Attribute holding the embedding of a record.
+pub const VECTORS_ATTRIBUTE: &str = "_vectors";
+
+const MAGIC: &[u8; 6] = b"FJVEC1";
+/// Neighbours kept per node on the upper layers; twice as many on layer 0.
+const MAX_NEIGHBORS: usize = 16;
+const EF_CONSTRUCTION: usize = 100;
+const MIN_EF_SEARCH: usize = 64;
+const MAX_LEVEL: usize = 16;
+/// Tombstones tolerated before the graph is rebuilt.
+const MIN_TOMBSTONES_FOR_REBUILD: usize = 1000;
+/// Logged changes tolerated before they are folded into [`VECTORS_FILE`];
+/// also at least half as many as there are live vectors, so that saving the
+/// graph costs a constant amount per change.
+const MIN_LOGGED_FOR_COMPACTION: usize = 1000;
+
+/// A change to the vector index from a committed write.
+#[derive(Debug, Clone, PartialEq)]
+pub enum VectorChange {
+    Set(String, Vec<f32>),
+    Remove(String),
+    Clear,
+}
+
+struct Node {
+    id: String,
+    /// Normalized, so that cosine similarity is a dot product.
+    vector: Vec<f32>,
+    /// Neighbours on each layer the node belongs to.
+    neighbors: Vec<Vec<u32>>,
+    deleted: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Candidate {
+    distance: f32,
+    node: u32,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then(self.node.cmp(&other.node))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// HNSW graph of the vectors of one index, keyed by objectID.
+#[derive(Default)]
+pub struct VectorIndex {
+    dimensions: usize,
+    nodes: Vec<Node>,
+    ids: HashMap<String, u32>,
+    entry: Option<u32>,
+    /// Changes in the log, not yet in the saved graph.
+    logged: usize,
+}
+
+impl VectorIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of live vectors.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// Dimensions of the stored vectors; `None` while the index is empty.
+    pub fn dimensions(&self) -> Option<usize> {
+        (!self.is_empty()).then_some(self.dimensions)
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.ids.contains_key(id)
+    }
+
+    /// Add or replace the vector of `id`.
+    pub fn insert(&mut self, id: &str, vector: &[f32]) -> Result<()> {
+        let vector = normalize(vector).ok_or_else(|| {
+            FlapjackError::InvalidDocument(format!(
+                "{} of {} must be a non-zero vector",
+                VECTORS_ATTRIBUTE, id
+            ))
+        })?;
+        if self.is_empty() {
+            self.clear();
+            self.dimensions = vector.len();
+        } else if vector.len() != self.dimensions {
+            return Err(FlapjackError::InvalidDocument(format!(
+                "{} of {} has {} dimensions, the index uses {}",
+                VECTORS_ATTRIBUTE,
+                id,
+                vector.len(),
+                self.dimensions
+            )));
+        }
+        if let Some(&existing) = self.ids.get(id) {
+            if self.nodes[existing as usize].vector == vector {
+                return Ok(());
+            }
+            self.nodes[existing as usize].deleted = true;
+        }
+
+        let node = self.nodes.len() as u32;
+        let level = level_for(id);
+        self.nodes.push(Node {
+            id: id.to_string(),
+            vector,
+            neighbors: vec![Vec::new(); level + 1],
+            deleted: false,
+        });
+        self.ids.insert(id.to_string(), node);
+
+        let Some(entry) = self.entry else {
+            self.entry = Some(node);
+            return Ok(());
+        };
+        let query = self.nodes[node as usize].vector.clone();
+        let top = self.nodes[entry as usize].neighbors.len() - 1;
+        let mut closest = entry;
+        for layer in (level + 1..=top).rev() {
+            closest = self.search_layer(&query, closest, 1, layer)[0].node;
+        }
+        for layer in (0..=level.min(top)).rev() {
+            let found = self.search_layer(&query, closest, EF_CONSTRUCTION, layer);
+            closest = found[0].node;
+            let neighbors: Vec<u32> = found
+                .iter()
+                .map(|c| c.node)
+                .take(max_neighbors(layer))
+                .collect();
+            for &neighbor in &neighbors {
+                self.connect(neighbor, node, layer);
+            }
+            self.nodes[node as usize].neighbors[layer] = neighbors;
+        }
+        if level > top {
+            self.entry = Some(node);
+        }
+        Ok(())
+    }
+
+    /// Remove the vector of `id`; false if it had none.
+    pub fn remove(&mut self, id: &str) -> bool {
+        match self.ids.remove(id) {
+            Some(node) => {
+                self.nodes[node as usize].deleted = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self {
+            logged: self.logged,
+            ..Self::default()
+        };
+    }
+
+    /// Apply committed changes. Invalid vectors are skipped with a warning,
+    /// since the write queue already rejected them.
+    pub fn apply(&mut self, changes: Vec<VectorChange>) {
+        for change in changes {
+            match change {
+                VectorChange::Set(id, vector) => {
+                    if let Err(e) = self.insert(&id, &vector) {
+                        tracing::warn!("[VECTORS] skipping vector of {}: {}", id, e);
+                    }
+                }
+                VectorChange::Remove(id) => {
+                    self.remove(&id);
+                }
+                VectorChange::Clear => self.clear(),
+            }
+        }
+        let tombstones = self.nodes.len() - self.len();
+        if tombstones >= MIN_TOMBSTONES_FOR_REBUILD && tombstones > self.len() {
+            self.rebuild();
+        }
+    }
+
+    /// The `k` live vectors most similar to `query` whose objectID passes
+    /// `accept`, most similar first, with their cosine similarity.
+    pub fn search<F>(&self, query: &[f32], k: usize, accept: F) -> Result<Vec<(String, f32)>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let Some(entry) = self.entry.filter(|_| k > 0 && !self.is_empty()) else {
+            return Ok(Vec::new());
+        };
+        if query.len() != self.dimensions {
+            return Err(FlapjackError::InvalidQuery(format!(
+                "vector has {} dimensions, the index uses {}",
+                query.len(),
+                self.dimensions
+            )));
+        }
+        let query = normalize(query)
+            .ok_or_else(|| FlapjackError::InvalidQuery("vector must not be zero".to_string()))?;
+
+        let top = self.nodes[entry as usize].neighbors.len() - 1;
+        let mut closest = entry;
+        for layer in (1..=top).rev() {
+            closest = self.search_layer(&query, closest, 1, layer)[0].node;
+        }
+        let found = self.search_layer(&query, closest, k.max(MIN_EF_SEARCH), 0);
+        let mut hits: Vec<Candidate> = found
+            .into_iter()
+            .filter(|c| self.is_match(c.node, &accept))
+            .take(k)
+            .collect();
+
+        // Selective filters can leave too few candidates in the graph's
+        // neighbourhood of the query: scan the accepted vectors instead
+        if hits.len() < k && hits.len() < self.count_matching(&accept) {
+            hits = (0..self.nodes.len() as u32)
+                .filter(|&node| self.is_match(node, &accept))
+                .map(|node| Candidate {
+                    distance: self.distance(&query, node),
+                    node,
+                })
+                .collect();
+            hits.sort();
+            hits.truncate(k);
+        }
+        Ok(hits
+            .into_iter()
+            .map(|c| (self.nodes[c.node as usize].id.clone(), 1.0 - c.distance))
+            .collect())
+    }
+
+    /// Number of live vectors whose objectID passes `accept`.
+    pub fn count_matching<F>(&self, accept: F) -> usize
+    where
+        F: Fn(&str) -> bool,
+    {
+        self.ids.keys().filter(|id| accept(id.as_str())).count()
+    }
+
+    fn is_match<F: Fn(&str) -> bool>(&self, node: u32, accept: &F) -> bool {
+        let node = &self.nodes[node as usize];
+        !node.deleted && accept(node.id.as_str())
+    }
+
+    fn distance(&self, query: &[f32], node: u32) -> f32 {
+        1.0 - dot(query, &self.nodes[node as usize].vector)
+    }
+
+    /// The `ef` nodes of `layer` closest to `query` found from `entry`,
+    /// closest first. Tombstones are traversed like live nodes.
+    fn search_layer(&self, query: &[f32], entry: u32, ef: usize, layer: usize) -> Vec<Candidate> {
+        let start = Candidate {
+            distance: self.distance(query, entry),
+            node: entry,
+        };
+        let mut visited = HashSet::from([entry]);
+        let mut candidates = BinaryHeap::from([Reverse(start)]);
+        let mut results = BinaryHeap::from([start]);
+
+        while let Some(Reverse(current)) = candidates.pop() {
+            let furthest = results.peek().map(|c| c.distance).unwrap_or(f32::MAX);
+            if current.distance > furthest && results.len() >= ef {
+                break;
+            }
+            let neighbors = self.nodes[current.node as usize]
+                .neighbors
+                .get(layer)
+                .map(Vec::as_slice)
+                .unwrap_or_default();
+            for &neighbor in neighbors {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let candidate = Candidate {
+                    distance: self.distance(query, neighbor),
+                    node: neighbor,
+                };
+                let furthest = results.peek().map(|c| c.distance).unwrap_or(f32::MAX);
+                if results.len() < ef || candidate.distance < furthest {
+                    candidates.push(Reverse(candidate));
+                    results.push(candidate);
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+        results.into_sorted_vec()
+    }
+
+    /// Link `node` from `from` on `layer`, keeping only the closest
+    /// neighbours of `from`.
+    fn connect(&mut self, from: u32, node: u32, layer: usize) {
+        let mut neighbors = std::mem::take(&mut self.nodes[from as usize].neighbors[layer]);
+        neighbors.push(node);
+        if neighbors.len() > max_neighbors(layer) {
+            let origin = self.nodes[from as usize].vector.clone();
+            neighbors.sort_by(|a, b| {
+                self.distance(&origin, *a)
+                    .total_cmp(&self.distance(&origin, *b))
+            });
+            neighbors.truncate(max_neighbors(layer));
+        }
+        self.nodes[from as usize].neighbors[layer] = neighbors;
+    }
+
+    /// Rebuild the graph from the live vectors, dropping the tombstones.
+    fn rebuild(&mut self) {
+        let nodes = std::mem::take(&mut self.nodes);
+        self.clear();
+        for node in nodes.into_iter().filter(|n| !n.deleted) {
+            // Already validated and normalized
+            let _ = self.insert(&node.id, &node.vector);
+        }
+    }
+
+    /// Open the index stored in `dir`: [`VECTORS_FILE`] with the changes of
+    /// [`VECTORS_LOG_FILE`] replayed on top. A log cut short by a crash is
+    /// folded in right away, so that later changes are not appended after
+    /// the partial record.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let keyring = encryption::keyring()?;
+        let mut index = Self::load_with(&dir.join(VECTORS_FILE), keyring.as_deref())?;
+        let log = read_log(&dir.join(VECTORS_LOG_FILE), keyring.as_deref())?;
+        index.logged = log.changes.len();
+        index.apply(log.changes);
+        if !log.complete {
+            index.compact(dir)?;
+        }
+        Ok(index)
+    }
+
+    /// Apply committed changes and persist them in `dir`. The changes are
+    /// appended to the log, so a commit writes only what it changed; the
+    /// graph is saved once the log grows long relative to the index.
+    pub fn commit(&mut self, dir: &Path, changes: Vec<VectorChange>) -> Result<()> {
+        if changes.is_empty() {
+            return Ok(());
+        }
+        let keyring = encryption::keyring()?;
+        append_log(&dir.join(VECTORS_LOG_FILE), &changes, keyring.as_deref())?;
+        self.logged += changes.len();
+        self.apply(changes);
+        if self.logged >= MIN_LOGGED_FOR_COMPACTION.max(self.len() / 2) {
+            self.compact(dir)?;
+        }
+        Ok(())
+    }
+
+    /// Save the graph to [`VECTORS_FILE`] and drop the log it now covers.
+    /// Replaying the changes is idempotent, so a crash between the two
+    /// steps loses nothing.
+    pub fn compact(&mut self, dir: &Path) -> Result<()> {
+        self.compact_with(dir, encryption::keyring()?.as_deref())
+    }
+
+    fn compact_with(&mut self, dir: &Path, keyring: Option<&Keyring>) -> Result<()> {
+        self.save_with(&dir.join(VECTORS_FILE), keyring)?;
+        match std::fs::remove_file(dir.join(VECTORS_LOG_FILE)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+        self.logged = 0;
+        Ok(())
+    }
+
+    /// Load the index from `path`; a missing file is an empty index.
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::load_with(path, encryption::keyring()?.as_deref())
+    }
+
+    fn load_with(path: &Path, keyring: Option<&Keyring>) -> Result<Self> {
+        let bytes = match std::fs::read(path) {
+            Ok(bytes) => encryption::decrypt_if_encrypted(&bytes, keyring)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let corrupt = || FlapjackError::Io(format!("corrupt vector index {}", path.display()));
+        let mut reader = ByteReader { bytes: &bytes };
+        if reader.take(MAGIC.len()).ok_or_else(corrupt)? != MAGIC {
+            return Err(corrupt());
+        }
+        let dimensions = reader.u32().ok_or_else(corrupt)? as usize;
+        let entry = reader.u32().ok_or_else(corrupt)?;
+        let count = reader.u32().ok_or_else(corrupt)?;
+
+        let mut index = VectorIndex {
+            dimensions,
+            entry: (entry != u32::MAX).then_some(entry),
+            ..Self::default()
+        };
+        for node in 0..count {
+            let id_len = reader.u32().ok_or_else(corrupt)? as usize;
+            let id = String::from_utf8(reader.take(id_len).ok_or_else(corrupt)?.to_vec())
+                .map_err(|_| corrupt())?;
+            let deleted = reader.take(1).ok_or_else(corrupt)?[0] != 0;
+            let vector = (0..dimensions)
+                .map(|_| reader.u32().map(f32::from_bits))
+                .collect::<Option<Vec<f32>>>()
+                .ok_or_else(corrupt)?;
+            let layers = reader.u32().ok_or_else(corrupt)? as usize;
+            let mut neighbors = Vec::with_capacity(layers);
+            for _ in 0..layers {
+                let len = reader.u32().ok_or_else(corrupt)? as usize;
+                let list = (0..len)
+                    .map(|_| reader.u32().filter(|n| *n < count))
+                    .collect::<Option<Vec<u32>>>()
+                    .ok_or_else(corrupt)?;
+                neighbors.push(list);
+            }
+            if !deleted {
+                index.ids.insert(id.clone(), node);
+            }
+            index.nodes.push(Node {
+                id,
+                vector,
+                neighbors,
+                deleted,
+            });
+        }
+        if index.entry.is_some_and(|e| e >= count) {
+            return Err(corrupt());
+        }
+        Ok(index)
+    }
+
+    /// Write the index to `path`, replacing it atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        self.save_with(path, encryption::keyring()?.as_deref())
+    }
+
+    fn save_with(&self, path: &Path, keyring: Option<&Keyring>) -> Result<()> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&(self.dimensions as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.entry.unwrap_or(u32::MAX).to_le_bytes());
+        bytes.extend_from_slice(&(self.nodes.len() as u32).to_le_bytes());
+        for node in &self.nodes {
+            bytes.extend_from_slice(&(node.id.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(node.id.as_bytes());
+            bytes.push(node.deleted as u8);
+            for value in &node.vector {
+                bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+            bytes.extend_from_slice(&(node.neighbors.len() as u32).to_le_bytes());
+            for list in &node.neighbors {
+                bytes.extend_from_slice(&(list.len() as u32).to_le_bytes());
+                for neighbor in list {
+                    bytes.extend_from_slice(&neighbor.to_le_bytes());
+                }
+            }
+        }
+        if let Some(keyring) = keyring {
+            bytes = keyring.encrypt(&bytes);
+        }
+        let tmp = path.with_extension("bin.tmp");
+        std::fs::write(&tmp, &bytes)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Rewrite the vector files in `dir` that are unencrypted or encrypted with a
+/// retired key: the log is folded into [`VECTORS_FILE`], which is saved with
+/// the current key of `keyring`. Returns the number of rewritten files.
+pub fn reencrypt(dir: &Path, keyring: &Keyring) -> Result<usize> {
+    let bin = dir.join(VECTORS_FILE);
+    let log_path = dir.join(VECTORS_LOG_FILE);
+    let bin_current = match std::fs::read(&bin) {
+        Ok(bytes) => keyring.is_current(&bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+        Err(e) => return Err(e.into()),
+    };
+    let log = read_log(&log_path, Some(keyring))?;
+    if bin_current && log.current {
+        return Ok(0);
+    }
+    let mut index = VectorIndex::load_with(&bin, Some(keyring))?;
+    let rewritten = usize::from(!bin_current) + usize::from(log_path.exists());
+    index.apply(log.changes);
+    index.compact_with(dir, Some(keyring))?;
+    Ok(rewritten)
+}
+
+const LOG_SET: u8 = b'S';
+const LOG_REMOVE: u8 = b'R';
+const LOG_CLEAR: u8 = b'C';
+/// Changes sealed with the keyring: `len:u32le` then the encrypted records.
+const LOG_SEALED: u8 = b'E';
+
+/// Append `changes` to the log at `path` in a single write, as one sealed
+/// record when `keyring` is given.
+fn append_log(path: &Path, changes: &[VectorChange], keyring: Option<&Keyring>) -> Result<()> {
+    use std::io::Write;
+    let mut bytes = Vec::new();
+    for change in changes {
+        match change {
+            VectorChange::Set(id, vector) => {
+                bytes.push(LOG_SET);
+                bytes.extend_from_slice(&(id.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(id.as_bytes());
+                bytes.extend_from_slice(&(vector.len() as u32).to_le_bytes());
+                for value in vector {
+                    bytes.extend_from_slice(&value.to_bits().to_le_bytes());
+                }
+            }
+            VectorChange::Remove(id) => {
+                bytes.push(LOG_REMOVE);
+                bytes.extend_from_slice(&(id.len() as u32).to_le_bytes());
+                bytes.extend_from_slice(id.as_bytes());
+            }
+            VectorChange::Clear => bytes.push(LOG_CLEAR),
+        }
+    }
+    if let Some(keyring) = keyring {
+        let sealed = keyring.encrypt(&bytes);
+        bytes = Vec::with_capacity(sealed.len() + 5);
+        bytes.push(LOG_SEALED);
+        bytes.extend_from_slice(&(sealed.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&sealed);
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    file.write_all(&bytes)?;
+    Ok(())
+}
+
+/// The changes read from a log.
+struct LogContents {
+    changes: Vec<VectorChange>,
+    /// The log ends on a complete record.
+    complete: bool,
+    /// Every record is sealed with the current key of the keyring.
+    current: bool,
+}
+
+/// The changes logged at `path`. A missing log holds no changes. Only a
+/// partial final record, as left by a crash, is skipped: a complete sealed
+/// record that fails to decrypt is an error.
+fn read_log(path: &Path, keyring: Option<&Keyring>) -> Result<LogContents> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(LogContents {
+                changes: Vec::new(),
+                complete: true,
+                current: true,
+            })
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let corrupt = || FlapjackError::Io(format!("corrupt vector log {}", path.display()));
+    let mut reader = ByteReader { bytes: &bytes };
+    let mut log = LogContents {
+        changes: Vec::new(),
+        complete: true,
+        current: true,
+    };
+    while !reader.bytes.is_empty() {
+        if reader.bytes[0] == LOG_SEALED {
+            let Some(sealed) = read_sealed(&mut reader) else {
+                tracing::warn!("[VECTORS] ignoring the partial tail of {}", path.display());
+                log.complete = false;
+                break;
+            };
+            let keyring = keyring.ok_or_else(|| {
+                FlapjackError::Config(
+                    "Vector log is encrypted but no master key is configured".to_string(),
+                )
+            })?;
+            log.current &= keyring.is_current(sealed);
+            let records = keyring.decrypt(sealed)?;
+            let mut records = ByteReader { bytes: &records };
+            while !records.bytes.is_empty() {
+                log.changes
+                    .push(read_change(&mut records).ok_or_else(corrupt)?);
+            }
+            continue;
+        }
+        log.current = false;
+        match read_change(&mut reader) {
+            Some(change) => log.changes.push(change),
+            None => {
+                tracing::warn!("[VECTORS] ignoring the partial tail of {}", path.display());
+                log.complete = false;
+                break;
+            }
+        }
+    }
+    Ok(log)
+}
+
+fn read_sealed<'a>(reader: &mut ByteReader<'a>) -> Option<&'a [u8]> {
+    reader.take(1)?;
+    let len = reader.u32()? as usize;
+    reader.take(len)
+}
+
+fn read_change(reader: &mut ByteReader<'_>) -> Option<VectorChange> {
+    match reader.take(1)?[0] {
+        LOG_SET => {
+            let id = read_id(reader)?;
+            let dimensions = reader.u32()? as usize;
+            let vector = (0..dimensions)
+                .map(|_| reader.u32().map(f32::from_bits))
+                .collect::<Option<Vec<f32>>>()?;
+            Some(VectorChange::Set(id, vector))
+        }
+        LOG_REMOVE => read_id(reader).map(VectorChange::Remove),
+        LOG_CLEAR => Some(VectorChange::Clear),
+        _ => None,
+    }
+}
+
+fn read_id(reader: &mut ByteReader<'_>) -> Option<String> {
+    let len = reader.u32()? as usize;
+    String::from_utf8(reader.take(len)?.to_vec()).ok()
+}
+
+/// The `_vectors` of `doc`, or `None` if it has none.
+pub fn document_vector(doc: &Document) -> Result<Option<Vec<f32>>> {
+    let invalid = || {
+        FlapjackError::InvalidDocument(format!(
+            "{} of {} must be a non-empty array of numbers",
+            VECTORS_ATTRIBUTE, doc.id
+        ))
+    };
+    match doc.fields.get(VECTORS_ATTRIBUTE) {
+        None => Ok(None),
+        Some(FieldValue::Array(values)) if !values.is_empty() => values
+            .iter()
+            .map(|v| match v {
+                FieldValue::Integer(i) => Some(*i as f32),
+                FieldValue::Float(f) if f.is_finite() => Some(*f as f32),
+                _ => None,
+            })
+            .collect::<Option<Vec<f32>>>()
+            .map(Some)
+            .ok_or_else(invalid),
+        Some(_) => Err(invalid()),
+    }
+}
+
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> ByteReader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if self.bytes.len() < len {
+            return None;
+        }
+        let (head, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(head)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+fn max_neighbors(layer: usize) -> usize {
+    if layer == 0 {
+        2 * MAX_NEIGHBORS
+    } else {
+        MAX_NEIGHBORS
+    }
+}
+
+/// Layer of a node, drawn from the HNSW exponential distribution with a
+/// hash of the objectID so that rebuilds give the same graph.
+fn level_for(id: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    let uniform = ((hasher.finish() >> 11) as f64 / (1u64 << 53) as f64).max(f64::MIN_POSITIVE);
+    let level = -uniform.ln() / (MAX_NEIGHBORS as f64).ln();
+    (level as usize).min(MAX_LEVEL)
+}
+
+fn normalize(vector: &[f32]) -> Option<Vec<f32>> {
+    let norm = dot(vector, vector).sqrt();
+    if vector.is_empty() || !norm.is_normal() {
+        return None;
+    }
+    Some(vector.iter().map(|v| v / norm).collect())
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(hits: &[(String, f32)]) -> Vec<&str> {
+        hits.iter().map(|(id, _)| id.as_str()).collect()
+    }
+
+    #[test]
+    fn nearest_neighbours_by_cosine() {
+        let mut index = VectorIndex::new();
+        index.insert("x", &[1.0, 0.0]).unwrap();
+        index.insert("y", &[0.0, 2.0]).unwrap();
+        index.insert("xy", &[1.0, 1.0]).unwrap();
+
+        let hits = index.search(&[3.0, 0.2], 2, |_| true).unwrap();
+        assert_eq!(ids(&hits), vec!["x", "xy"]);
+        assert!(hits[0].1 > 0.99);
+
+        let hits = index.search(&[3.0, 0.2], 2, |id| id != "x").unwrap();
+        assert_eq!(ids(&hits), vec!["xy", "y"]);
+        assert_eq!(index.count_matching(|id| id.starts_with('x')), 2);
+    }
+
+    #[test]
+    fn finds_exact_neighbours_in_larger_graphs() {
+        let mut index = VectorIndex::new();
+        let vector = |i: usize| -> Vec<f32> {
+            let angle = i as f32 * 0.01;
+            vec![angle.cos(), angle.sin(), (i % 7) as f32 * 0.001]
+        };
+        for i in 0..500 {
+            index.insert(&i.to_string(), &vector(i)).unwrap();
+        }
+        let hits = index.search(&vector(250), 3, |_| true).unwrap();
+        assert_eq!(hits[0].0, "250");
+        let mut neighbours: Vec<&str> = ids(&hits)[1..].to_vec();
+        neighbours.sort();
+        assert_eq!(neighbours, vec!["249", "251"]);
+    }
+
+    #[test]
+    fn replace_remove_and_dimensions() {
+        let mut index = VectorIndex::new();
+        assert_eq!(index.dimensions(), None);
+        index.insert("a", &[1.0, 0.0, 0.0]).unwrap();
+        index.insert("b", &[0.0, 1.0, 0.0]).unwrap();
+        assert_eq!(index.dimensions(), Some(3));
+        assert!(index.insert("c", &[1.0, 0.0]).is_err());
+        assert!(index.insert("c", &[0.0, 0.0, 0.0]).is_err());
+        assert!(index.search(&[1.0, 0.0], 1, |_| true).is_err());
+
+        index.insert("a", &[0.0, 0.0, 1.0]).unwrap();
+        assert_eq!(index.len(), 2);
+        let hits = index.search(&[0.0, 0.0, 1.0], 1, |_| true).unwrap();
+        assert_eq!(ids(&hits), vec!["a"]);
+
+        assert!(index.remove("a"));
+        assert!(!index.remove("a"));
+        let hits = index.search(&[0.0, 0.0, 1.0], 5, |_| true).unwrap();
+        assert_eq!(ids(&hits), vec!["b"]);
+
+        // Once empty, any dimensions are accepted again
+        index.apply(vec![VectorChange::Clear]);
+        index.insert("c", &[1.0, 0.0]).unwrap();
+        assert_eq!(index.dimensions(), Some(2));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join(VECTORS_FILE);
+        assert!(VectorIndex::load(&path).unwrap().is_empty());
+
+        let mut index = VectorIndex::new();
+        for i in 0..50 {
+            let v = [i as f32, 1.0, (50 - i) as f32];
+            index.insert(&format!("doc{}", i), &v).unwrap();
+        }
+        index.remove("doc3");
+        index.save(&path).unwrap();
+
+        let loaded = VectorIndex::load(&path).unwrap();
+        assert_eq!(loaded.len(), 49);
+        assert!(!loaded.contains("doc3"));
+        let query = [10.0, 1.0, 40.0];
+        assert_eq!(
+            loaded.search(&query, 5, |_| true).unwrap(),
+            index.search(&query, 5, |_| true).unwrap()
+        );
+
+        std::fs::write(&path, b"FJVEC1\x02").unwrap();
+        assert!(VectorIndex::load(&path).is_err());
+    }
+
+    #[test]
+    fn commits_are_logged_and_compacted() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let mut index = VectorIndex::open(dir.path()).unwrap();
+        index
+            .commit(
+                dir.path(),
+                vec![
+                    VectorChange::Set("a".to_string(), vec![1.0, 0.0]),
+                    VectorChange::Set("b".to_string(), vec![0.0, 1.0]),
+                ],
+            )
+            .unwrap();
+        index
+            .commit(dir.path(), vec![VectorChange::Remove("a".to_string())])
+            .unwrap();
+        assert!(!dir.path().join(VECTORS_FILE).exists());
+
+        let reopened = VectorIndex::open(dir.path()).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert!(reopened.contains("b"));
+
+        // A partial record from a crash is dropped and the log folded in
+        let log = dir.path().join(VECTORS_LOG_FILE);
+        let mut bytes = std::fs::read(&log).unwrap();
+        bytes.extend_from_slice(&[LOG_SET, 9]);
+        std::fs::write(&log, bytes).unwrap();
+        let reopened = VectorIndex::open(dir.path()).unwrap();
+        assert_eq!(reopened.len(), 1);
+        assert!(!log.exists());
+        assert!(dir.path().join(VECTORS_FILE).exists());
+
+        // Once the log outgrows the index, it is folded into the graph
+        let mut index = reopened;
+        let changes = (0..MIN_LOGGED_FOR_COMPACTION)
+            .map(|i| VectorChange::Set(format!("doc{}", i), vec![i as f32, 1.0]))
+            .collect();
+        index.commit(dir.path(), changes).unwrap();
+        assert!(!log.exists());
+        assert_eq!(VectorIndex::open(dir.path()).unwrap().len(), 1001);
+    }
+
+    #[test]
+    fn sealed_log_records_and_reencryption() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let log = dir.path().join(VECTORS_LOG_FILE);
+        let old = Keyring::new(&[[1; 32]]).unwrap();
+        let changes = vec![VectorChange::Set("a".to_string(), vec![0.25, 0.5])];
+        append_log(&log, &changes, Some(&old)).unwrap();
+        append_log(&log, &[VectorChange::Remove("b".to_string())], Some(&old)).unwrap();
+        let bytes = std::fs::read(&log).unwrap();
+        assert_eq!(bytes[0], LOG_SEALED);
+        assert!(!bytes
+            .windows(4)
+            .any(|w| w == 0.25f32.to_bits().to_le_bytes()));
+        let contents = read_log(&log, Some(&old)).unwrap();
+        assert_eq!(contents.changes.len(), 2);
+        assert!(contents.complete && contents.current);
+        assert!(read_log(&log, None).is_err());
+
+        // A torn final record is skipped, a tampered complete one is not
+        std::fs::write(&log, &bytes[..bytes.len() - 1]).unwrap();
+        let contents = read_log(&log, Some(&old)).unwrap();
+        assert_eq!(contents.changes, changes);
+        assert!(!contents.complete);
+        let mut tampered = bytes.clone();
+        let tag = tampered.len() - 3;
+        tampered[tag] ^= 1;
+        std::fs::write(&log, &tampered).unwrap();
+        assert!(read_log(&log, Some(&old)).is_err());
+
+        std::fs::write(&log, &bytes).unwrap();
+        let rotated = Keyring::new(&[[2; 32], [1; 32]]).unwrap();
+        assert_eq!(reencrypt(dir.path(), &rotated).unwrap(), 1);
+        assert!(!log.exists());
+        let bin = std::fs::read(dir.path().join(VECTORS_FILE)).unwrap();
+        assert!(rotated.is_current(&bin));
+        let index = VectorIndex::load_with(&dir.path().join(VECTORS_FILE), Some(&rotated)).unwrap();
+        assert!(index.contains("a"));
+        assert_eq!(reencrypt(dir.path(), &rotated).unwrap(), 0);
+    }
+
+    #[test]
+    fn reads_document_vectors() {
+        let doc = |value: serde_json::Value| {
+            Document::from_json(&serde_json::json!({"objectID": "1", "_vectors": value})).unwrap()
+        };
+        assert_eq!(
+            document_vector(&doc(serde_json::json!([1, 0.5]))).unwrap(),
+            Some(vec![1.0, 0.5])
+        );
+        assert!(document_vector(&doc(serde_json::json!([]))).is_err());
+        assert!(document_vector(&doc(serde_json::json!(["a"]))).is_err());
+        assert!(document_vector(&doc(serde_json::json!({"default": [1]}))).is_err());
+        let plain = Document::from_json(&serde_json::json!({"objectID": "1"})).unwrap();
+        assert_eq!(document_vector(&plain).unwrap(), None);
+    }
+}
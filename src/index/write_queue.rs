@@ -1,5 +1,6 @@
 //! Async write queue with hybrid batching for Flapjack.
 
+use crate::index::vectors::{self, VectorChange};
 use crate::types::{DocFailure, Document, TaskInfo, TaskStatus};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -11,15 +12,35 @@ pub enum WriteAction {
     Upsert(Document),
     Delete(String),
     Compact,
+    /// Re-add every live document so it is re-tokenized with the current
+    /// analyzer settings, committing in batches of [`REINDEX_BATCH`].
+    Reindex,
+    /// Replace the queue's index, e.g. by a schema migration, once the
+    /// pending writes are committed. The writer is closed first so the
+    /// index directory can be moved; on error the old index is kept.
+    SwapIndex(SwapIndex),
 }
 
+pub type SwapIndex =
+    Box<dyn FnOnce() -> crate::error::Result<Arc<crate::index::Index>> + Send + 'static>;
+
 pub struct WriteOp {
     pub task_id: String,
     pub actions: Vec<WriteAction>,
+    /// More ops of the same task follow, as for a streamed bulk import: the
+    /// task's counts accumulate and it only succeeds with its last op.
+    pub partial: bool,
 }
 
+/// Rejected documents kept per task; `rejected_count` has the full count.
+pub const MAX_REJECTED_DOCUMENTS: usize = 100;
+
 pub type WriteQueue = mpsc::Sender<WriteOp>;
 
+/// Documents re-added by a [`WriteAction::Reindex`] between commits and
+/// progress updates.
+pub const REINDEX_BATCH: usize = 10_000;
+
 pub fn create_write_queue(
     tenant_id: String,
     index: Arc<crate::index::Index>,
@@ -39,6 +60,7 @@ pub fn create_write_queue(
             )>,
         >,
     >,
+    writer_slot: Arc<tokio::sync::Mutex<()>>,
 ) -> (
     WriteQueue,
     tokio::task::JoinHandle<crate::error::Result<()>>,
@@ -63,6 +85,7 @@ pub fn create_write_queue(
             base_path,
             oplog,
             facet_cache,
+            writer_slot,
         )
         .await
     });
@@ -72,7 +95,7 @@ pub fn create_write_queue(
 
 async fn process_writes(
     tenant_id: String,
-    index: Arc<crate::index::Index>,
+    mut index: Arc<crate::index::Index>,
     _writers: Arc<
         dashmap::DashMap<String, Arc<tokio::sync::Mutex<crate::index::ManagedIndexWriter>>>,
     >,
@@ -90,23 +113,13 @@ async fn process_writes(
             )>,
         >,
     >,
+    writer_slot: Arc<tokio::sync::Mutex<()>>,
 ) -> crate::error::Result<()> {
-    let mut writer = match index.writer() {
-        Ok(w) => {
-            tracing::info!("Write queue started for tenant {}", tenant_id);
-            w
-        }
-        Err(e) => {
-            tracing::error!("Failed to create writer for tenant {}: {}", tenant_id, e);
-            return Err(e);
-        }
-    };
-
-    // Merge segments when >30% of docs are deleted, so disk space is
-    // gradually reclaimed without aggressive write amplification.
-    let mut merge_policy = tantivy::merge_policy::LogMergePolicy::default();
-    merge_policy.set_del_docs_ratio_before_merge(0.3);
-    writer.set_merge_policy(Box::new(merge_policy));
+    // Wait for the writer of a queue still closing after an eviction to
+    // release the index lock; held until this queue's writer is dropped
+    let _slot = writer_slot.lock_owned().await;
+    let mut writer = open_writer(&index, &tenant_id)?;
+    tracing::info!("Write queue started for tenant {}", tenant_id);
     let mut pending = Vec::new();
     let mut deadline = Instant::now() + Duration::from_millis(100);
 
@@ -151,6 +164,69 @@ async fn process_writes(
                     continue;
                 }
 
+                if matches!(op.actions.first(), Some(WriteAction::Reindex)) {
+                    if !pending.is_empty() {
+                        commit_batch(
+                            &index,
+                            &tasks,
+                            &mut pending,
+                            &mut writer,
+                            &tenant_id,
+                            &base_path,
+                            &oplog,
+                            &facet_cache,
+                        )
+                        .await?;
+                    }
+                    reindex_documents(
+                        &index,
+                        &tasks,
+                        &op.task_id,
+                        &mut writer,
+                        &tenant_id,
+                        &base_path,
+                        &facet_cache,
+                    )
+                    .await?;
+                    deadline = Instant::now() + Duration::from_millis(100);
+                    continue;
+                }
+
+                if matches!(op.actions.first(), Some(WriteAction::SwapIndex(_))) {
+                    if !pending.is_empty() {
+                        commit_batch(
+                            &index,
+                            &tasks,
+                            &mut pending,
+                            &mut writer,
+                            &tenant_id,
+                            &base_path,
+                            &oplog,
+                            &facet_cache,
+                        )
+                        .await?;
+                    }
+                    let Some(WriteAction::SwapIndex(swap)) = op.actions.into_iter().next() else {
+                        unreachable!()
+                    };
+                    drop(writer);
+                    let status = match swap() {
+                        Ok(swapped) => {
+                            tracing::info!("[WQ {}] index swapped", tenant_id);
+                            index = swapped;
+                            TaskStatus::Succeeded
+                        }
+                        Err(e) => {
+                            tracing::error!("[WQ {}] index swap failed: {}", tenant_id, e);
+                            TaskStatus::Failed(e.to_string())
+                        }
+                    };
+                    writer = open_writer(&index, &tenant_id)?;
+                    set_status(&tasks, &op.task_id, status);
+                    deadline = Instant::now() + Duration::from_millis(100);
+                    continue;
+                }
+
                 pending.push(op);
                 if pending.len() >= 10 {
                     tracing::warn!(
@@ -219,6 +295,27 @@ async fn process_writes(
     Ok(())
 }
 
+/// Open the queue's writer on `index`.
+fn open_writer(
+    index: &crate::index::Index,
+    tenant_id: &str,
+) -> crate::error::Result<crate::index::ManagedIndexWriter> {
+    let mut writer = match index.writer() {
+        Ok(w) => w,
+        Err(e) => {
+            tracing::error!("Failed to create writer for tenant {}: {}", tenant_id, e);
+            return Err(e);
+        }
+    };
+
+    // Merge segments when >30% of docs are deleted, so disk space is
+    // gradually reclaimed without aggressive write amplification.
+    let mut merge_policy = tantivy::merge_policy::LogMergePolicy::default();
+    merge_policy.set_del_docs_ratio_before_merge(0.3);
+    writer.set_merge_policy(Box::new(merge_policy));
+    Ok(writer)
+}
+
 async fn commit_batch(
     index: &Arc<crate::index::Index>,
     tasks: &Arc<dashmap::DashMap<String, TaskInfo>>,
@@ -243,28 +340,28 @@ async fn commit_batch(
     let schema = index.inner().schema();
     let id_field = schema.get_field("_id").unwrap();
 
-    let settings_path = base_path.join(tenant_id).join("settings.json");
-    let settings = if settings_path.exists() {
-        Some(crate::index::settings::IndexSettings::load(&settings_path)?)
-    } else {
-        None
-    };
+    let settings = load_settings(index, tenant_id, base_path)?;
 
     for op in ops.drain(..) {
         tasks.alter(&op.task_id, |_, mut task| {
-            task.status = TaskStatus::Processing;
+            if task.status == TaskStatus::Enqueued {
+                task.status = TaskStatus::Processing;
+            }
             task
         });
 
         let mut valid_docs = Vec::new();
         let mut rejected = Vec::new();
         let mut deleted_ids: Vec<String> = Vec::new();
+        let mut vector_changes = Vec::new();
+        let mut vector_dims = index.vectors().dimensions();
 
         for action in op.actions {
             match action {
                 WriteAction::Delete(object_id) => {
                     let term = tantivy::Term::from_field_text(id_field, &object_id);
                     writer.delete_term(term);
+                    vector_changes.push(VectorChange::Remove(object_id.clone()));
                     deleted_ids.push(object_id);
                 }
                 WriteAction::Add(doc) => {
@@ -280,8 +377,20 @@ async fn commit_batch(
                         });
                         continue;
                     }
+                    let vector_change = match checked_vector(&doc, &mut vector_dims) {
+                        Ok(change) => change,
+                        Err(e) => {
+                            rejected.push(DocFailure {
+                                doc_id: doc.id,
+                                error: classify_error(&e),
+                                message: e.to_string(),
+                            });
+                            continue;
+                        }
+                    };
                     match index.converter().to_tantivy(&doc, settings.as_ref()) {
                         Ok(tantivy_doc) => {
+                            vector_changes.push(vector_change);
                             valid_docs.push((doc.id.clone(), doc_json, tantivy_doc));
                         }
                         Err(e) => {
@@ -306,11 +415,23 @@ async fn commit_batch(
                         });
                         continue;
                     }
+                    let vector_change = match checked_vector(&doc, &mut vector_dims) {
+                        Ok(change) => change,
+                        Err(e) => {
+                            rejected.push(DocFailure {
+                                doc_id: doc.id,
+                                error: classify_error(&e),
+                                message: e.to_string(),
+                            });
+                            continue;
+                        }
+                    };
                     let term = tantivy::Term::from_field_text(id_field, &doc.id);
                     writer.delete_term(term);
 
                     match index.converter().to_tantivy(&doc, settings.as_ref()) {
                         Ok(tantivy_doc) => {
+                            vector_changes.push(vector_change);
                             valid_docs.push((doc.id.clone(), doc_json, tantivy_doc));
                         }
                         Err(e) => {
@@ -322,7 +443,7 @@ async fn commit_batch(
                         }
                     }
                 }
-                WriteAction::Compact => {
+                WriteAction::Compact | WriteAction::Reindex | WriteAction::SwapIndex(_) => {
                     // Handled in the process_writes loop, should not reach here
                 }
             }
@@ -379,6 +500,7 @@ async fn commit_batch(
         }
         index.reader().reload()?;
         index.invalidate_searchable_paths_cache();
+        index.update_vectors(vector_changes)?;
         facet_cache.retain(|k, _| !k.starts_with(&format!("{}:", tenant_id)));
 
         if let Some(ref ol) = oplog {
@@ -405,29 +527,115 @@ async fn commit_batch(
             op.task_id.clone()
         };
 
-        let total_rejected = rejected.len();
-        rejected.truncate(100);
-        let rejected_final = rejected.clone();
-
-        tasks.alter(&op.task_id, |_, mut task| {
-            task.status = TaskStatus::Succeeded;
-            task.indexed_documents = valid_docs.len() + deleted_ids.len();
-            task.rejected_documents = rejected_final.clone();
-            task.rejected_count = total_rejected;
-            task
-        });
-        tasks.alter(&numeric_id, |_, mut task| {
-            task.status = TaskStatus::Succeeded;
-            task.indexed_documents = valid_docs.len() + deleted_ids.len();
-            task.rejected_documents = rejected_final.clone();
-            task.rejected_count = total_rejected;
+        let indexed = valid_docs.len() + deleted_ids.len();
+        let record = |mut task: TaskInfo| {
+            // A bulk import that failed while streaming stays failed
+            if !op.partial && !matches!(task.status, TaskStatus::Failed(_)) {
+                task.status = TaskStatus::Succeeded;
+            }
+            task.indexed_documents += indexed;
+            task.rejected_count += rejected.len();
+            let room = MAX_REJECTED_DOCUMENTS.saturating_sub(task.rejected_documents.len());
+            task.rejected_documents
+                .extend(rejected.iter().take(room).cloned());
             task
-        });
+        };
+        tasks.alter(&op.task_id, |_, task| record(task));
+        if numeric_id != op.task_id {
+            tasks.alter(&numeric_id, |_, task| record(task));
+        }
     }
 
     Ok(())
 }
 
+/// The tenant's settings, with their analyzer settings applied to `index`.
+fn load_settings(
+    index: &crate::index::Index,
+    tenant_id: &str,
+    base_path: &std::path::Path,
+) -> crate::error::Result<Option<crate::index::settings::IndexSettings>> {
+    let settings_path = base_path.join(tenant_id).join("settings.json");
+    let settings = if settings_path.exists() {
+        Some(crate::index::settings::IndexSettings::load(&settings_path)?)
+    } else {
+        None
+    };
+    if let Some(ref s) = settings {
+        index.set_keep_diacritics(&s.keep_diacritics_on_characters);
+        index.set_index_languages(&s.index_languages);
+        index.set_separators_to_index(&s.separators_to_index);
+    }
+    Ok(settings)
+}
+
+/// Re-add the live documents of the index as of now with the current
+/// settings, committing every [`REINDEX_BATCH`] documents so the writer's
+/// memory stays bounded. The task's `indexed_documents` counts the
+/// documents re-added so far; the writes queued meanwhile wait.
+async fn reindex_documents(
+    index: &Arc<crate::index::Index>,
+    tasks: &Arc<dashmap::DashMap<String, TaskInfo>>,
+    task_id: &str,
+    writer: &mut crate::index::ManagedIndexWriter,
+    tenant_id: &str,
+    base_path: &std::path::Path,
+    facet_cache: &Arc<
+        dashmap::DashMap<
+            String,
+            Arc<(
+                std::time::Instant,
+                usize,
+                std::collections::HashMap<String, Vec<crate::types::FacetCount>>,
+            )>,
+        >,
+    >,
+) -> crate::error::Result<()> {
+    set_status(tasks, task_id, TaskStatus::Processing);
+
+    let result: crate::error::Result<usize> = async {
+        let settings = load_settings(index, tenant_id, base_path)?;
+        let schema = index.inner().schema();
+        let id_field = schema.get_field("_id").unwrap();
+        let searcher = index.reader().searcher();
+        let mut reindexed = 0;
+        for doc in index.live_documents(&searcher) {
+            let doc = doc?;
+            writer.delete_term(tantivy::Term::from_field_text(id_field, &doc.id));
+            writer.add_document(index.converter().to_tantivy(&doc, settings.as_ref())?)?;
+            reindexed += 1;
+            if reindexed % REINDEX_BATCH == 0 {
+                writer.commit()?;
+                index.reader().reload()?;
+                alter_task(tasks, task_id, |t| t.indexed_documents = reindexed);
+                // Let the other queues and the searches run between batches
+                tokio::task::yield_now().await;
+            }
+        }
+        writer.commit()?;
+        index.reader().reload()?;
+        Ok(reindexed)
+    }
+    .await;
+
+    index.invalidate_searchable_paths_cache();
+    facet_cache.retain(|k, _| !k.starts_with(&format!("{}:", tenant_id)));
+    match &result {
+        Ok(reindexed) => {
+            tracing::info!("[WQ {}] reindexed {} documents", tenant_id, reindexed);
+            alter_task(tasks, task_id, |t| {
+                t.indexed_documents = *reindexed;
+                t.status = TaskStatus::Succeeded;
+            });
+        }
+        Err(e) => {
+            tracing::error!("[WQ {}] reindex failed: {}", tenant_id, e);
+            set_status(tasks, task_id, TaskStatus::Failed(e.to_string()));
+        }
+    }
+    result.map(|_| ())
+}
+
 /// Force-merge all segments into one and garbage-collect stale files.
 fn compact_segments(
     index: &Arc<crate::index::Index>,
@@ -475,29 +683,44 @@ fn compact_segments(
         Ok(())
     })();
 
+    let status = match &result {
+        Ok(()) => TaskStatus::Succeeded,
+        Err(e) => TaskStatus::Failed(e.to_string()),
+    };
+    set_status(tasks, task_id, status);
+
+    result
+}
+
+/// Set the status of a task under both its ids.
+fn set_status(tasks: &dashmap::DashMap<String, TaskInfo>, task_id: &str, status: TaskStatus) {
+    alter_task(tasks, task_id, |t| t.status = status.clone());
+}
+
+/// Update a task under both its ids.
+fn alter_task(
+    tasks: &dashmap::DashMap<String, TaskInfo>,
+    task_id: &str,
+    f: impl Fn(&mut TaskInfo),
+) {
     let numeric_id = if let Some(task_ref) = tasks.get(task_id) {
         task_ref.numeric_id.to_string()
     } else {
         task_id.to_string()
     };
-
-    let status = match &result {
-        Ok(()) => TaskStatus::Succeeded,
-        Err(e) => TaskStatus::Failed(e.to_string()),
-    };
     tasks.alter(task_id, |_, mut t| {
-        t.status = status.clone();
+        f(&mut t);
         t
     });
-    tasks.alter(&numeric_id, |_, mut t| {
-        t.status = status;
-        t
-    });
-
-    result
+    if numeric_id != task_id {
+        tasks.alter(&numeric_id, |_, mut t| {
+            f(&mut t);
+            t
+        });
+    }
 }
 
-fn classify_error(e: &crate::error::FlapjackError) -> String {
+pub(crate) fn classify_error(e: &crate::error::FlapjackError) -> String {
     match e {
         crate::error::FlapjackError::FieldNotFound(_) => "field_not_found".to_string(),
         crate::error::FlapjackError::TypeMismatch { .. } => "type_mismatch".to_string(),
@@ -506,3 +729,32 @@ fn classify_error(e: &crate::error::FlapjackError) -> String {
         _ => "validation_error".to_string(),
     }
 }
+
+/// The vector change for an added or replaced document, rejecting a
+/// `_vectors` whose dimensions differ from the index (or from the first
+/// vector of the batch when the index has none yet).
+fn checked_vector(doc: &Document, dims: &mut Option<usize>) -> crate::error::Result<VectorChange> {
+    let Some(vector) = vectors::document_vector(doc)? else {
+        return Ok(VectorChange::Remove(doc.id.clone()));
+    };
+    if vector.iter().all(|v| *v == 0.0) {
+        return Err(crate::error::FlapjackError::InvalidDocument(format!(
+            "{} must be a non-zero vector",
+            vectors::VECTORS_ATTRIBUTE
+        )));
+    }
+    match *dims {
+        Some(expected) if expected != vector.len() => {
+            Err(crate::error::FlapjackError::InvalidDocument(format!(
+                "{} has {} dimensions, the index uses {}",
+                vectors::VECTORS_ATTRIBUTE,
+                vector.len(),
+                expected
+            )))
+        }
+        _ => {
+            *dims = Some(vector.len());
+            Ok(VectorChange::Set(doc.id.clone(), vector))
+        }
+    }
+}
@@ -55,7 +55,7 @@
 //! | Feature | Dependencies | Use case |
 //! |---------|-------------|----------|
 //! | `axum-support` | axum | [`FlapjackError`] implements `IntoResponse` |
-//! | `s3-snapshots` | rust-s3, flate2, tar | S3 backup/restore via [`index::s3`] and [`index::snapshot`] |
+//! | `s3-snapshots` | rust-s3, flate2, tar, async-trait | Backup/restore to a directory or S3 via [`index::snapshot_store`] and [`index::snapshot`] |
 //! | `openapi` | utoipa | OpenAPI schema generation |
 //!
 //! All features are enabled by default. Use `default-features = false` for a
@@ -0,0 +1,344 @@
+//! Compound-word decomposition for German, Dutch, Swedish, Finnish, Danish
+//! and Norwegian.
+//!
+//! "Kinderschuhe" is split into "kinder" + "schuhe" using the word list and
+//! the linking elements ("Arbeit-s-platz") in `package/compound-words.json`.
+//! Records are decompounded at index time for the attributes listed in
+//! `decompoundedAttributes`, and queries when `decompoundQuery` is enabled, so
+//! "Kinderschuhe" and "Schuhe für Kinder" find each other. Query words are
+//! also split with the custom compounds of the dictionaries API.
+
+use crate::tokenizer::diacritics::{fold, fold_with_offsets, KeepDiacritics};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// Shortest word accepted as a part, so that "ei" or "ob" don't shred words.
+const MIN_PART_CHARS: usize = 3;
+
+#[derive(Deserialize)]
+struct RawCompoundRules {
+    linking: Vec<String>,
+    endings: Vec<String>,
+    words: Vec<String>,
+}
+
+struct CompoundRules {
+    /// Elements allowed between two parts, like the "s" in "Arbeitsplatz".
+    linking: Vec<String>,
+    /// Inflections allowed after the last part, like the "e" in "Handschuhe".
+    endings: Vec<String>,
+    words: HashSet<String>,
+    /// Longest word, in characters.
+    max_chars: usize,
+}
+
+static COMPOUND_RULES: OnceLock<HashMap<String, CompoundRules>> = OnceLock::new();
+
+fn load_compound_rules() -> HashMap<String, CompoundRules> {
+    let json_str = include_str!("../../package/compound-words.json");
+    let raw: HashMap<String, RawCompoundRules> =
+        serde_json::from_str(json_str).expect("invalid compound-words.json");
+
+    let keep = KeepDiacritics::default();
+    let fold_all =
+        |list: Vec<String>| -> Vec<String> { list.iter().map(|w| fold(w, &keep)).collect() };
+    raw.into_iter()
+        .map(|(lang, raw)| {
+            let words: HashSet<String> = fold_all(raw.words).into_iter().collect();
+            let max_chars = words.iter().map(|w| w.chars().count()).max().unwrap_or(0);
+            let rules = CompoundRules {
+                linking: fold_all(raw.linking),
+                endings: fold_all(raw.endings),
+                words,
+                max_chars,
+            };
+            (lang, rules)
+        })
+        .collect()
+}
+
+fn get_compound_rules() -> &'static HashMap<String, CompoundRules> {
+    COMPOUND_RULES.get_or_init(load_compound_rules)
+}
+
+pub fn supports_language(lang: &str) -> bool {
+    get_compound_rules().contains_key(lang)
+}
+
+/// Byte spans of the parts of `word`, a folded word, using the fewest parts
+/// and the fewest linking/ending characters. `None` unless there are at least
+/// two parts. Linking elements are left out of the spans; the ending of the
+/// last part is kept.
+fn split(word: &str, rules: &CompoundRules) -> Option<Vec<(usize, usize)>> {
+    let bounds: Vec<usize> = word
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(word.len()))
+        .collect();
+    let n = bounds.len() - 1;
+    let char_at = |byte: usize| bounds.binary_search(&byte).ok();
+
+    // best[i]: (parts, affix chars, end of the word part, start of the next
+    // part) for the best split of the suffix starting at character i
+    let mut best: Vec<Option<(usize, usize, usize, usize)>> = vec![None; n + 1];
+    best[n] = Some((0, 0, n, n));
+    for i in (0..n).rev() {
+        let longest = n.min(i + rules.max_chars);
+        for j in (i + MIN_PART_CHARS..=longest).rev() {
+            if !rules.words.contains(&word[bounds[i]..bounds[j]]) {
+                continue;
+            }
+            let rest = &word[bounds[j]..];
+            let mut candidates = Vec::new();
+            if rest.is_empty() {
+                candidates.push((1, 0, j, n));
+            }
+            for ending in &rules.endings {
+                if rest == ending {
+                    candidates.push((1, ending.chars().count(), j, n));
+                }
+            }
+            for link in std::iter::once("").chain(rules.linking.iter().map(String::as_str)) {
+                if !rest.starts_with(link) || rest.len() == link.len() {
+                    continue;
+                }
+                let Some(k) = char_at(bounds[j] + link.len()) else {
+                    continue;
+                };
+                if let Some((parts, affix, _, _)) = best[k] {
+                    candidates.push((parts + 1, affix + link.chars().count(), j, k));
+                }
+            }
+            for candidate in candidates {
+                let better = match best[i] {
+                    Some((parts, affix, _, _)) => (candidate.0, candidate.1) < (parts, affix),
+                    None => true,
+                };
+                if better {
+                    best[i] = Some(candidate);
+                }
+            }
+        }
+    }
+
+    let (parts, ..) = best[0]?;
+    if parts < 2 {
+        return None;
+    }
+    let mut spans = Vec::with_capacity(parts);
+    let mut i = 0;
+    while i < n {
+        let (_, _, word_end, next) = best[i]?;
+        let end = if next == n { n } else { word_end };
+        spans.push((bounds[i], bounds[end]));
+        i = next;
+    }
+    Some(spans)
+}
+
+/// The parts of `word` if it is a compound in `lang`, as slices of `word`.
+pub fn decompound<'a>(word: &'a str, lang: &str) -> Option<Vec<&'a str>> {
+    let rules = get_compound_rules().get(lang)?;
+    if word.chars().count() < 2 * MIN_PART_CHARS {
+        return None;
+    }
+    let (folded, spans) = fold_with_offsets(word, &KeepDiacritics::default());
+    let parts = split(&folded, rules)?;
+    Some(
+        parts
+            .into_iter()
+            .map(|(start, end)| &word[spans[start].0..spans[end - 1].1])
+            .collect(),
+    )
+}
+
+/// The parts of every compound word of `text`, for indexing alongside it.
+pub fn compound_parts<'a>(text: &'a str, lang: &str) -> Vec<&'a str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| decompound(word, lang))
+        .flatten()
+        .collect()
+}
+
+/// Custom compounds for one language, from the dictionaries API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CustomCompounds {
+    /// Folded compound words mapped to their parts.
+    pub decompositions: HashMap<String, Vec<String>>,
+    /// Ignore the bundled word list (`disableStandardEntries`).
+    pub disable_standard: bool,
+}
+
+/// The parts of `word` in `lang`, from its `custom` decomposition if there
+/// is one and from the bundled word list otherwise.
+pub fn decompound_with_custom(
+    word: &str,
+    lang: &str,
+    custom: &HashMap<String, CustomCompounds>,
+) -> Option<Vec<String>> {
+    if let Some(custom) = custom.get(lang) {
+        let folded = fold(word, &KeepDiacritics::default());
+        if let Some(parts) = custom.decompositions.get(&folded) {
+            return Some(parts.clone());
+        }
+        if custom.disable_standard {
+            return None;
+        }
+    }
+    decompound(word, lang).map(|parts| parts.into_iter().map(str::to_string).collect())
+}
+
+/// `query` with each compound word replaced by its parts, trying `languages`
+/// in order. `None` when no word is a compound.
+pub fn decompound_query(query: &str, languages: &[String]) -> Option<String> {
+    decompound_query_with_custom(query, languages, &HashMap::new())
+}
+
+/// [`decompound_query`] with the `custom` compounds, keyed by language.
+pub fn decompound_query_with_custom(
+    query: &str,
+    languages: &[String],
+    custom: &HashMap<String, CustomCompounds>,
+) -> Option<String> {
+    let mut changed = false;
+    let words: Vec<String> = query
+        .split_whitespace()
+        .map(|word| {
+            let parts = if word.chars().all(char::is_alphanumeric) {
+                languages
+                    .iter()
+                    .find_map(|lang| decompound_with_custom(word, lang, custom))
+            } else {
+                None
+            };
+            match parts {
+                Some(parts) => {
+                    changed = true;
+                    parts.join(" ")
+                }
+                None => word.to_string(),
+            }
+        })
+        .collect();
+    changed.then(|| words.join(" "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn german_compounds() {
+        assert_eq!(
+            decompound("Kinderschuhe", "de"),
+            Some(vec!["Kinder", "schuhe"])
+        );
+        assert_eq!(decompound("handschuhe", "de"), Some(vec!["hand", "schuhe"]));
+        assert_eq!(
+            decompound("krankenhaus", "de"),
+            Some(vec!["kranken", "haus"])
+        );
+        assert_eq!(
+            decompound("kaffeetasse", "de"),
+            Some(vec!["kaffee", "tasse"])
+        );
+    }
+
+    #[test]
+    fn linking_elements_are_dropped() {
+        assert_eq!(
+            decompound("Arbeitsplatz", "de"),
+            Some(vec!["Arbeit", "platz"])
+        );
+        assert_eq!(decompound("geburtstag", "de"), Some(vec!["geburt", "tag"]));
+        assert_eq!(
+            decompound("fietsenwinkel", "nl"),
+            Some(vec!["fiets", "winkel"])
+        );
+    }
+
+    #[test]
+    fn prefers_fewest_parts() {
+        assert_eq!(
+            decompound("fotbollsspelare", "sv"),
+            Some(vec!["fotboll", "spelare"])
+        );
+        assert_eq!(
+            decompound("voetbalschoenen", "nl"),
+            Some(vec!["voetbal", "schoenen"])
+        );
+    }
+
+    #[test]
+    fn nordic_compounds() {
+        assert_eq!(decompound("jalkapallo", "fi"), Some(vec!["jalka", "pallo"]));
+        assert_eq!(decompound("työpaikka", "fi"), Some(vec!["työ", "paikka"]));
+        assert_eq!(
+            decompound("vaskemaskine", "da"),
+            Some(vec!["vaske", "maskine"])
+        );
+        assert_eq!(
+            decompound("arbeidsplass", "no"),
+            Some(vec!["arbeid", "plass"])
+        );
+    }
+
+    #[test]
+    fn simple_words_are_not_split() {
+        assert_eq!(decompound("kinder", "de"), None);
+        assert_eq!(decompound("schuhe", "de"), None);
+        assert_eq!(decompound("verkaufen", "de"), None);
+        assert_eq!(decompound("kinderschuhe", "en"), None);
+        assert!(!supports_language("en"));
+    }
+
+    #[test]
+    fn parts_of_text() {
+        assert_eq!(
+            compound_parts("Warme Kinderschuhe, Größe 30", "de"),
+            vec!["Kinder", "schuhe"]
+        );
+        assert!(compound_parts("Schuhe für Kinder", "de").is_empty());
+    }
+
+    #[test]
+    fn decompounds_queries() {
+        let langs = vec!["de".to_string()];
+        assert_eq!(
+            decompound_query("rote kinderschuhe", &langs),
+            Some("rote kinder schuhe".to_string())
+        );
+        assert_eq!(decompound_query("schuhe", &langs), None);
+        assert_eq!(decompound_query("kinderschuhe", &[]), None);
+    }
+
+    #[test]
+    fn custom_decompositions() {
+        let custom = |disable_standard: bool| {
+            HashMap::from([(
+                "de".to_string(),
+                CustomCompounds {
+                    decompositions: HashMap::from([(
+                        "kopfhorer".to_string(),
+                        vec!["kopf".to_string(), "hörer".to_string()],
+                    )]),
+                    disable_standard,
+                },
+            )])
+        };
+        let langs = vec!["de".to_string()];
+        assert_eq!(
+            decompound_query_with_custom("Kopfhörer kaufen", &langs, &custom(false)),
+            Some("kopf hörer kaufen".to_string())
+        );
+        assert_eq!(
+            decompound_with_custom("kinderschuhe", "de", &custom(false)),
+            Some(vec!["kinder".to_string(), "schuhe".to_string()])
+        );
+        assert_eq!(
+            decompound_with_custom("kinderschuhe", "de", &custom(true)),
+            None
+        );
+    }
+}
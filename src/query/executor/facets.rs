@@ -174,18 +174,15 @@ impl QueryExecutor {
                     limit + offset
                 };
                 let top_collector = TopDocs::with_limit(prelim_limit);
-                let (count, mut top_docs, facets) =
+                let (count, top_docs, facets) =
                     searcher.search(query.as_ref(), &(Count, top_collector, facet_collector))?;
                 let fi1 = fi0.elapsed();
-                let query_terms: Vec<String> = self
-                    .query_text
-                    .split_whitespace()
-                    .map(|s| s.to_lowercase())
-                    .collect();
-                top_docs = self.apply_tier2_and_custom_ranking(searcher, top_docs, &query_terms)?;
+                let query_terms = self.query_terms();
+                let top_docs =
+                    self.apply_tier2_and_custom_ranking(searcher, top_docs, &query_terms)?;
                 let fi2 = fi0.elapsed();
                 let final_docs = top_docs.into_iter().skip(offset).take(limit).collect();
-                let docs = self.reconstruct_documents(searcher, final_docs)?;
+                let docs = self.reconstruct_ranked_documents(searcher, final_docs)?;
                 tracing::debug!(
                     "[FACET_INT] search={:?} tier2={:?} reconstruct={:?} prelim_limit={} count={}",
                     fi1,
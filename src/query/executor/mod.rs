@@ -3,6 +3,7 @@ use crate::index::document::DocumentConverter;
 use crate::index::settings::IndexSettings;
 use crate::query::filter::FilterCompiler;
 use crate::query::parser::ShortQueryPlaceholder;
+use crate::tokenizer::diacritics::{fold, KeepDiacritics};
 use crate::types::{Filter, ScoredDocument, SearchResult};
 use std::sync::Arc;
 use tantivy::query::{BooleanQuery, BoostQuery, Occur, Query as TantivyQuery, TermQuery};
@@ -17,6 +18,9 @@ mod facets;
 mod relevance;
 mod rules;
 mod sorting;
+mod vector;
+
+pub use vector::reciprocal_rank_fusion;
 
 pub struct QueryExecutor {
     pub(crate) converter: Arc<DocumentConverter>,
@@ -27,6 +31,9 @@ pub struct QueryExecutor {
     pub(crate) searchable_paths: Vec<String>,
     pub(crate) query_text: String,
     pub(crate) max_values_per_facet: Option<usize>,
+    pub(crate) keep_diacritics: KeepDiacritics,
+    pub(crate) proximity_paths: Vec<String>,
+    pub(crate) query_terms: Option<Vec<String>>,
 }
 
 impl QueryExecutor {
@@ -43,6 +50,9 @@ impl QueryExecutor {
             searchable_paths: vec![],
             query_text: String::new(),
             max_values_per_facet: None,
+            keep_diacritics: KeepDiacritics::default(),
+            proximity_paths: vec![],
+            query_terms: None,
         }
     }
 
@@ -56,16 +66,51 @@ impl QueryExecutor {
             if let Some(ref attrs) = s.searchable_attributes {
                 self.searchable_paths = attrs.clone();
             }
+            self.keep_diacritics = KeepDiacritics::parse(&s.keep_diacritics_on_characters);
         }
         self.settings = settings;
         self
     }
 
+    /// Attributes in which the proximity criterion measures word distances;
+    /// defaults to the `searchableAttributes` setting.
+    pub fn with_proximity_paths(mut self, paths: Vec<String>) -> Self {
+        self.proximity_paths = paths;
+        self
+    }
+
+    pub(crate) fn proximity_paths(&self) -> &[String] {
+        if self.proximity_paths.is_empty() {
+            &self.searchable_paths
+        } else {
+            &self.proximity_paths
+        }
+    }
+
     pub fn with_query(mut self, query_text: String) -> Self {
         self.query_text = query_text;
         self
     }
 
+    /// Query words as the query parser tokenized them, with CJK
+    /// segmentation and `separatorsToIndex` applied.
+    pub fn with_query_terms(mut self, terms: Vec<String>) -> Self {
+        self.query_terms = Some(terms);
+        self
+    }
+
+    /// Query words normalized the way the analyzers index them: the terms
+    /// given to [`Self::with_query_terms`], else the folded query words.
+    pub(crate) fn query_terms(&self) -> Vec<String> {
+        if let Some(terms) = &self.query_terms {
+            return terms.clone();
+        }
+        self.query_text
+            .split_whitespace()
+            .map(|s| fold(s, &self.keep_diacritics))
+            .collect()
+    }
+
     pub fn execute(
         &self,
         searcher: &Searcher,
@@ -249,7 +294,12 @@ impl QueryExecutor {
             let document =
                 self.converter
                     .from_tantivy(tantivy_doc, &self.tantivy_schema, String::new())?;
-            documents.push(ScoredDocument { document, score });
+            documents.push(ScoredDocument {
+                document,
+                score,
+                proximity_distance: 0,
+                semantic_score: None,
+            });
         }
         Ok(documents)
     }
@@ -2,12 +2,94 @@ use super::sorting::SortValue;
 use super::QueryExecutor;
 use crate::error::Result;
 use crate::types::ScoredDocument;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use tantivy::collector::{Count, TopDocs};
 use tantivy::postings::Postings;
 use tantivy::query::Query as TantivyQuery;
 use tantivy::schema::{document::ReferenceValue, Value};
 use tantivy::{DocSet, Searcher, TERMINATED};
 
+/// Distance counted for two query terms that are not in the same attribute,
+/// and the cap for any closer pair.
+const MAX_PROXIMITY: u32 = 8;
+
+/// A ranking criterion applied to the top documents, in the order of the
+/// `ranking` setting.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Criterion {
+    /// Word distance between the query terms.
+    Proximity,
+    /// Position of the first matched query word.
+    Attribute,
+    /// The `customRanking` attributes.
+    Custom,
+}
+
+/// A top document with the values it is ranked by.
+struct Ranked {
+    proximity: u32,
+    min_position: u32,
+    custom: Vec<SortValue>,
+    score: f32,
+    addr: tantivy::DocAddress,
+}
+
+/// Order `a` and `b` by each criterion in turn, then by doc id.
+fn compare_ranked(
+    criteria: &[Criterion],
+    ranking_specs: &[(String, bool)],
+    a: &Ranked,
+    b: &Ranked,
+) -> Ordering {
+    for criterion in criteria {
+        let cmp = match criterion {
+            Criterion::Proximity => a.proximity.cmp(&b.proximity),
+            Criterion::Attribute => a.min_position.cmp(&b.min_position),
+            Criterion::Custom => compare_custom(ranking_specs, &a.custom, &b.custom),
+        };
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+    }
+    a.addr.doc_id.cmp(&b.addr.doc_id)
+}
+
+/// Order by the `customRanking` values, missing values last.
+fn compare_custom(ranking_specs: &[(String, bool)], a: &[SortValue], b: &[SortValue]) -> Ordering {
+    for (idx, (_, asc)) in ranking_specs.iter().enumerate() {
+        let cmp = match (&a[idx], &b[idx]) {
+            (SortValue::Missing, SortValue::Missing) => Ordering::Equal,
+            (SortValue::Missing, _) => Ordering::Greater,
+            (_, SortValue::Missing) => Ordering::Less,
+            _ => {
+                if *asc {
+                    a[idx].cmp(&b[idx])
+                } else {
+                    b[idx].cmp(&a[idx])
+                }
+            }
+        };
+        if cmp != Ordering::Equal {
+            return cmp;
+        }
+    }
+    Ordering::Equal
+}
+
+/// Smallest word distance from a position in `first` to a position in
+/// `second`; pairs in reverse order count one more.
+fn pair_distance(first: &[u32], second: &[u32]) -> u32 {
+    let mut best = MAX_PROXIMITY;
+    for &a in first {
+        for &b in second {
+            let distance = if b > a { b - a } else { a - b + 1 };
+            best = best.min(distance);
+        }
+    }
+    best
+}
+
 impl QueryExecutor {
     pub(crate) fn execute_relevance_sort(
         &self,
@@ -28,20 +110,16 @@ impl QueryExecutor {
             limit + offset
         };
 
-        let (total, mut top_docs) =
+        let (total, top_docs) =
             searcher.search(query.as_ref(), &(Count, TopDocs::with_limit(prelim_limit)))?;
         let tr1 = tr0.elapsed();
 
-        let query_terms: Vec<String> = self
-            .query_text
-            .split_whitespace()
-            .map(|s| s.to_lowercase())
-            .collect();
-        top_docs = self.apply_tier2_and_custom_ranking(searcher, top_docs, &query_terms)?;
+        let query_terms = self.query_terms();
+        let top_docs = self.apply_tier2_and_custom_ranking(searcher, top_docs, &query_terms)?;
         let tr2 = tr0.elapsed();
 
         let final_docs = top_docs.into_iter().skip(offset).take(limit).collect();
-        let documents = self.reconstruct_documents(searcher, final_docs)?;
+        let documents = self.reconstruct_ranked_documents(searcher, final_docs)?;
         tracing::debug!(
             "[REL] search={:?} tier2={:?} reconstruct={:?} total_hits={}",
             tr1,
@@ -52,135 +130,184 @@ impl QueryExecutor {
         Ok((documents, total))
     }
 
+    /// Rank the top documents by the `ranking` criteria: proximity, the
+    /// position of the first matched word and the `customRanking`
+    /// attributes. Returns each document's score, address and proximity.
     pub(crate) fn apply_tier2_and_custom_ranking(
         &self,
         searcher: &Searcher,
         docs: Vec<(f32, tantivy::DocAddress)>,
         query_terms: &[String],
-    ) -> Result<Vec<(f32, tantivy::DocAddress)>> {
-        let custom_ranking = match &self.settings {
-            Some(s) => match &s.custom_ranking {
-                Some(cr) if !cr.is_empty() => cr,
-                _ => return self.apply_tier2_only(searcher, docs, query_terms),
-            },
-            None => return self.apply_tier2_only(searcher, docs, query_terms),
-        };
+    ) -> Result<Vec<(f32, tantivy::DocAddress, u32)>> {
+        let mut ranking_specs: Vec<(String, bool)> = Vec::new();
+        if let Some(custom_ranking) = self
+            .settings
+            .as_ref()
+            .and_then(|s| s.custom_ranking.as_ref())
+        {
+            for spec in custom_ranking {
+                let (direction, attr) = if let Some(attr) = spec.strip_prefix("desc(") {
+                    (false, attr.trim_end_matches(')'))
+                } else if let Some(attr) = spec.strip_prefix("asc(") {
+                    (true, attr.trim_end_matches(')'))
+                } else {
+                    continue;
+                };
 
-        let tantivy_schema = searcher.schema();
-        let json_filter_field = tantivy_schema
-            .get_field("_json_filter")
-            .map_err(|_| crate::error::FlapjackError::FieldNotFound("_json_filter".to_string()))?;
+                ranking_specs.push((attr.to_string(), direction));
+            }
+        }
 
-        let mut ranking_specs: Vec<(String, bool)> = Vec::new();
+        let criteria: Vec<Criterion> = self
+            .ranking_criteria()
+            .into_iter()
+            .filter(|c| *c != Criterion::Custom || !ranking_specs.is_empty())
+            .collect();
+        let min_positions = if criteria.contains(&Criterion::Attribute) {
+            self.min_positions(searcher, &docs, query_terms)?
+        } else {
+            HashMap::new()
+        };
 
-        for spec in custom_ranking {
-            let (direction, attr) = if let Some(attr) = spec.strip_prefix("desc(") {
-                (false, attr.trim_end_matches(')'))
-            } else if let Some(attr) = spec.strip_prefix("asc(") {
-                (true, attr.trim_end_matches(')'))
+        let mut scored: Vec<Ranked> = Vec::with_capacity(docs.len());
+        for (score, addr) in docs {
+            let proximity = if criteria.contains(&Criterion::Proximity) {
+                self.proximity_distance(searcher, addr, query_terms)?
             } else {
-                continue;
+                0
             };
-
-            ranking_specs.push((attr.to_string(), direction));
+            let min_position = min_positions
+                .get(&(addr.segment_ord, addr.doc_id))
+                .copied()
+                .unwrap_or(u32::MAX);
+            let custom = if criteria.contains(&Criterion::Custom) {
+                self.custom_ranking_values(searcher, addr, &ranking_specs)?
+            } else {
+                Vec::new()
+            };
+            scored.push(Ranked {
+                proximity,
+                min_position,
+                custom,
+                score,
+                addr,
+            });
         }
 
-        if ranking_specs.is_empty() {
-            return self.apply_tier2_only(searcher, docs, query_terms);
+        scored.sort_by(|a, b| compare_ranked(&criteria, &ranking_specs, a, b));
+
+        Ok(scored
+            .into_iter()
+            .map(|r| (r.score, r.addr, r.proximity))
+            .collect())
+    }
+
+    /// Reconstruct documents ranked by [`Self::apply_tier2_and_custom_ranking`],
+    /// reporting the proximity computed while ranking them.
+    pub(crate) fn reconstruct_ranked_documents(
+        &self,
+        searcher: &Searcher,
+        docs: Vec<(f32, tantivy::DocAddress, u32)>,
+    ) -> Result<Vec<ScoredDocument>> {
+        let proximities: Vec<u32> = docs.iter().map(|(_, _, proximity)| *proximity).collect();
+        let addresses = docs
+            .into_iter()
+            .map(|(score, addr, _)| (score, addr))
+            .collect();
+        let mut documents = self.reconstruct_documents(searcher, addresses)?;
+        for (document, proximity) in documents.iter_mut().zip(proximities) {
+            document.proximity_distance = proximity;
         }
+        Ok(documents)
+    }
 
-        let mut scored: Vec<(u32, Vec<SortValue>, f32, tantivy::DocAddress)> = Vec::new();
+    /// The ranking criteria applied to the top documents, in `ranking`
+    /// order; all of them when `ranking` is not set.
+    fn ranking_criteria(&self) -> Vec<Criterion> {
+        match self.settings.as_ref().and_then(|s| s.ranking.as_ref()) {
+            Some(ranking) => ranking
+                .iter()
+                .filter_map(|criterion| match criterion.as_str() {
+                    "proximity" => Some(Criterion::Proximity),
+                    "attribute" => Some(Criterion::Attribute),
+                    "custom" => Some(Criterion::Custom),
+                    _ => None,
+                })
+                .collect(),
+            None => vec![
+                Criterion::Proximity,
+                Criterion::Attribute,
+                Criterion::Custom,
+            ],
+        }
+    }
 
-        for (score, addr) in docs {
-            let doc: tantivy::TantivyDocument = searcher.doc(addr)?;
-
-            let min_position = self.extract_min_position(searcher, addr, query_terms)?;
-
-            let mut values = Vec::new();
-
-            for (attr_name, _) in &ranking_specs {
-                let val = if let Some(json_value) = doc.get_first(json_filter_field) {
-                    if let ReferenceValue::Object(obj) = json_value.as_value() {
-                        let mut found = SortValue::Missing;
-                        for (path, compact_val) in obj {
-                            if path == attr_name {
-                                found = match compact_val.as_value() {
-                                    ReferenceValue::Leaf(leaf) => match leaf {
-                                        tantivy::schema::document::ReferenceValueLeaf::I64(i) => {
-                                            SortValue::Integer(i)
-                                        }
-                                        tantivy::schema::document::ReferenceValueLeaf::U64(u) => {
-                                            SortValue::Integer(u as i64)
-                                        }
-                                        tantivy::schema::document::ReferenceValueLeaf::F64(f) => {
-                                            SortValue::Float(f)
-                                        }
-                                        tantivy::schema::document::ReferenceValueLeaf::Str(s) => {
-                                            SortValue::Text(s.to_string())
-                                        }
-                                        _ => SortValue::Missing,
-                                    },
+    /// The `customRanking` values of a document, read from `_json_filter`.
+    fn custom_ranking_values(
+        &self,
+        searcher: &Searcher,
+        addr: tantivy::DocAddress,
+        ranking_specs: &[(String, bool)],
+    ) -> Result<Vec<SortValue>> {
+        let json_filter_field = searcher
+            .schema()
+            .get_field("_json_filter")
+            .map_err(|_| crate::error::FlapjackError::FieldNotFound("_json_filter".to_string()))?;
+        let doc: tantivy::TantivyDocument = searcher.doc(addr)?;
+
+        let mut values = Vec::new();
+        for (attr_name, _) in ranking_specs {
+            let val = if let Some(json_value) = doc.get_first(json_filter_field) {
+                if let ReferenceValue::Object(obj) = json_value.as_value() {
+                    let mut found = SortValue::Missing;
+                    for (path, compact_val) in obj {
+                        if path == attr_name {
+                            found = match compact_val.as_value() {
+                                ReferenceValue::Leaf(leaf) => match leaf {
+                                    tantivy::schema::document::ReferenceValueLeaf::I64(i) => {
+                                        SortValue::Integer(i)
+                                    }
+                                    tantivy::schema::document::ReferenceValueLeaf::U64(u) => {
+                                        SortValue::Integer(u as i64)
+                                    }
+                                    tantivy::schema::document::ReferenceValueLeaf::F64(f) => {
+                                        SortValue::Float(f)
+                                    }
+                                    tantivy::schema::document::ReferenceValueLeaf::Str(s) => {
+                                        SortValue::Text(s.to_string())
+                                    }
                                     _ => SortValue::Missing,
-                                };
-                                break;
-                            }
+                                },
+                                _ => SortValue::Missing,
+                            };
+                            break;
                         }
-                        found
-                    } else {
-                        SortValue::Missing
                     }
+                    found
                 } else {
                     SortValue::Missing
-                };
-                values.push(val);
-            }
-            scored.push((min_position, values, score, addr));
-        }
-
-        scored.sort_by(|a, b| {
-            let pos_cmp = a.0.cmp(&b.0);
-            if pos_cmp != std::cmp::Ordering::Equal {
-                return pos_cmp;
-            }
-
-            for (idx, (_, asc)) in ranking_specs.iter().enumerate() {
-                let cmp = match (&a.1[idx], &b.1[idx]) {
-                    (SortValue::Missing, SortValue::Missing) => std::cmp::Ordering::Equal,
-                    (SortValue::Missing, _) => std::cmp::Ordering::Greater,
-                    (_, SortValue::Missing) => std::cmp::Ordering::Less,
-                    _ => {
-                        if *asc {
-                            a.1[idx].cmp(&b.1[idx])
-                        } else {
-                            b.1[idx].cmp(&a.1[idx])
-                        }
-                    }
-                };
-                if cmp != std::cmp::Ordering::Equal {
-                    return cmp;
                 }
-            }
-            a.3.doc_id.cmp(&b.3.doc_id)
-        });
-
-        Ok(scored
-            .into_iter()
-            .map(|(_, _, score, addr)| (score, addr))
-            .collect())
+            } else {
+                SortValue::Missing
+            };
+            values.push(val);
+        }
+        Ok(values)
     }
 
-    pub(crate) fn apply_tier2_only(
+    /// The first position of any query term in the top 2 searchable paths
+    /// of each document, keyed by (segment, doc id).
+    fn min_positions(
         &self,
         searcher: &Searcher,
-        docs: Vec<(f32, tantivy::DocAddress)>,
+        docs: &[(f32, tantivy::DocAddress)],
         query_terms: &[String],
-    ) -> Result<Vec<(f32, tantivy::DocAddress)>> {
-        use std::collections::HashMap;
+    ) -> Result<HashMap<(u32, u32), u32>> {
         use tantivy::schema::IndexRecordOption;
 
         let mut doc_positions: HashMap<(u32, u32), u32> = HashMap::new();
 
-        for doc in &docs {
+        for doc in docs {
             doc_positions.insert((doc.1.segment_ord, doc.1.doc_id), u32::MAX);
         }
 
@@ -216,26 +343,14 @@ impl QueryExecutor {
             }
         }
 
-        let mut scored: Vec<(u32, f32, tantivy::DocAddress)> = docs
-            .into_iter()
-            .map(|(score, addr)| {
-                let pos = doc_positions
-                    .get(&(addr.segment_ord, addr.doc_id))
-                    .copied()
-                    .unwrap_or(u32::MAX);
-                (pos, score, addr)
-            })
-            .collect();
-
-        scored.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.doc_id.cmp(&b.2.doc_id)));
-
-        Ok(scored
-            .into_iter()
-            .map(|(_, score, addr)| (score, addr))
-            .collect())
+        Ok(doc_positions)
     }
 
-    pub(crate) fn extract_min_position(
+    /// Algolia's proximity criterion: the word distances between consecutive
+    /// query terms within one attribute, each floored at `minProximity` and
+    /// capped at [`MAX_PROXIMITY`], summed and minimized over attributes.
+    /// 0 for queries with fewer than two distinct terms.
+    pub(crate) fn proximity_distance(
         &self,
         searcher: &Searcher,
         addr: tantivy::DocAddress,
@@ -243,35 +358,47 @@ impl QueryExecutor {
     ) -> Result<u32> {
         use tantivy::schema::IndexRecordOption;
 
+        let mut terms: Vec<&String> = Vec::with_capacity(query_terms.len());
+        for term in query_terms {
+            if !terms.contains(&term) {
+                terms.push(term);
+            }
+        }
+        if terms.len() < 2 {
+            return Ok(0);
+        }
+        let min_proximity = self
+            .settings
+            .as_ref()
+            .map(|s| s.min_proximity.clamp(1, 7))
+            .unwrap_or(1);
+
         let segment_reader = searcher.segment_reader(addr.segment_ord);
         let inverted_index = segment_reader.inverted_index(self.json_search_field)?;
 
-        let mut min_pos = u32::MAX;
-
-        // Only check top 2 searchable paths (same as apply_tier2_only)
-        for path in self.searchable_paths.iter().take(2) {
-            for term_text in query_terms {
+        let mut best = MAX_PROXIMITY * (terms.len() as u32 - 1);
+        for path in self.proximity_paths() {
+            let mut term_positions: Vec<Vec<u32>> = Vec::with_capacity(terms.len());
+            for term_text in &terms {
                 let full_term = format!("{}\0s{}", path, term_text);
                 let term = tantivy::Term::from_field_text(self.json_search_field, &full_term);
-
+                let mut positions = Vec::new();
                 if let Some(mut postings) =
                     inverted_index.read_postings(&term, IndexRecordOption::WithFreqsAndPositions)?
                 {
-                    // Use seek() to jump directly to target doc instead of
-                    // walking through every doc in the posting list.
-                    let doc_id = postings.seek(addr.doc_id);
-                    if doc_id == addr.doc_id {
-                        let mut positions: Vec<u32> =
-                            Vec::with_capacity(postings.term_freq() as usize);
+                    if postings.seek(addr.doc_id) == addr.doc_id {
                         postings.positions(&mut positions);
-                        if let Some(&first_pos) = positions.first() {
-                            min_pos = min_pos.min(first_pos);
-                        }
                     }
                 }
+                term_positions.push(positions);
             }
+            let distance: u32 = term_positions
+                .windows(2)
+                .map(|pair| pair_distance(&pair[0], &pair[1]).clamp(min_proximity, MAX_PROXIMITY))
+                .sum();
+            best = best.min(distance);
         }
 
-        Ok(min_pos)
+        Ok(best)
     }
 }
@@ -52,6 +52,8 @@ impl QueryExecutor {
                         ScoredDocument {
                             document,
                             score: f32::MAX,
+                            proximity_distance: 0,
+                            semantic_score: None,
                         },
                         *target_pos,
                     ));
@@ -0,0 +1,126 @@
+use super::QueryExecutor;
+use crate::error::Result;
+use crate::index::vectors::VectorIndex;
+use crate::types::{Filter, ScoredDocument};
+use std::collections::{HashMap, HashSet};
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::query::{AllQuery, TermQuery};
+use tantivy::schema::IndexRecordOption;
+use tantivy::Searcher;
+
+/// Rank constant of reciprocal rank fusion; damps the weight of the top ranks
+/// so that neither list dominates.
+const RRF_K: f32 = 60.0;
+
+impl QueryExecutor {
+    /// Nearest-neighbour search: the `k` records closest to `vector` among
+    /// those matching `filter`, scored by cosine similarity, and the number
+    /// of records with a vector that match the filter.
+    pub fn execute_vector(
+        &self,
+        searcher: &Searcher,
+        vectors: &VectorIndex,
+        vector: &[f32],
+        filter: Option<&Filter>,
+        k: usize,
+    ) -> Result<(Vec<ScoredDocument>, usize)> {
+        let allowed = match filter {
+            Some(_) => Some(self.filtered_ids(searcher, filter)?),
+            None => None,
+        };
+        let accept = |id: &str| allowed.as_ref().map(|ids| ids.contains(id)).unwrap_or(true);
+
+        let hits = vectors.search(vector, k, accept)?;
+        let total = match allowed {
+            Some(_) => vectors.count_matching(accept),
+            None => vectors.len(),
+        };
+
+        let id_field = self
+            .tantivy_schema
+            .get_field("_id")
+            .map_err(|_| crate::error::FlapjackError::FieldNotFound("_id".to_string()))?;
+        let mut documents = Vec::with_capacity(hits.len());
+        for (id, similarity) in hits {
+            let term = tantivy::Term::from_field_text(id_field, &id);
+            let query = TermQuery::new(term, IndexRecordOption::Basic);
+            // The vector index is updated after the commit, so a record may
+            // briefly be missing from the searcher.
+            let Some((_, doc_address)) = searcher.search(&query, &TopDocs::with_limit(1))?.pop()
+            else {
+                continue;
+            };
+            let document = self.converter.from_tantivy(
+                searcher.doc(doc_address)?,
+                &self.tantivy_schema,
+                id,
+            )?;
+            documents.push(ScoredDocument {
+                document,
+                score: similarity,
+                proximity_distance: 0,
+                semantic_score: Some(similarity),
+            });
+        }
+        Ok((documents, total))
+    }
+
+    /// objectIDs of the records matching `filter`.
+    fn filtered_ids(
+        &self,
+        searcher: &Searcher,
+        filter: Option<&Filter>,
+    ) -> Result<HashSet<String>> {
+        let query = self.apply_filter(Box::new(AllQuery), filter)?;
+        let addresses = searcher.search(&*query, &DocSetCollector)?;
+
+        let columns: Vec<Option<tantivy::columnar::StrColumn>> = searcher
+            .segment_readers()
+            .iter()
+            .map(|segment| segment.fast_fields().str("_id").ok().flatten())
+            .collect();
+        let mut ids = HashSet::with_capacity(addresses.len());
+        let mut id = String::new();
+        for address in addresses {
+            let Some(column) = &columns[address.segment_ord as usize] else {
+                continue;
+            };
+            if let Some(ord) = column.term_ords(address.doc_id).next() {
+                id.clear();
+                if column.ord_to_str(ord, &mut id)? {
+                    ids.insert(id.clone());
+                }
+            }
+        }
+        Ok(ids)
+    }
+}
+
+/// Merge the keyword and vector rankings with reciprocal rank fusion: each
+/// record scores `1 / (60 + rank)` in every list it appears in. Records found
+/// by the vector search keep their `semantic_score`.
+pub fn reciprocal_rank_fusion(
+    lexical: Vec<ScoredDocument>,
+    semantic: Vec<ScoredDocument>,
+) -> Vec<ScoredDocument> {
+    let mut fused: Vec<ScoredDocument> = Vec::with_capacity(lexical.len() + semantic.len());
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for list in [lexical, semantic] {
+        for (rank, mut doc) in list.into_iter().enumerate() {
+            let score = 1.0 / (RRF_K + rank as f32 + 1.0);
+            match positions.get(&doc.document.id) {
+                Some(&i) => {
+                    fused[i].score += score;
+                    fused[i].semantic_score = fused[i].semantic_score.or(doc.semantic_score);
+                }
+                None => {
+                    positions.insert(doc.document.id.clone(), fused.len());
+                    doc.score = score;
+                    fused.push(doc);
+                }
+            }
+        }
+    }
+    fused.sort_by(|a, b| b.score.total_cmp(&a.score));
+    fused
+}
@@ -1,6 +1,10 @@
+use crate::query::decompound::{decompound_with_custom, CustomCompounds};
+use crate::tokenizer::cjk_tokenizer::is_cjk;
+use crate::tokenizer::diacritics::{fold, fold_with_offsets, KeepDiacritics};
+use crate::tokenizer::CjkSegmenter;
 use crate::types::{Document, FieldValue};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -31,6 +35,10 @@ pub enum HighlightValue {
 pub struct Highlighter {
     pre_tag: String,
     post_tag: String,
+    keep_diacritics: KeepDiacritics,
+    cjk_segmenter: Option<CjkSegmenter>,
+    decompound_languages: Vec<String>,
+    custom_compounds: HashMap<String, CustomCompounds>,
 }
 
 impl Default for Highlighter {
@@ -38,13 +46,51 @@ impl Default for Highlighter {
         Self {
             pre_tag: "<em>".to_string(),
             post_tag: "</em>".to_string(),
+            keep_diacritics: KeepDiacritics::default(),
+            cjk_segmenter: None,
+            decompound_languages: Vec::new(),
+            custom_compounds: HashMap::new(),
         }
     }
 }
 
 impl Highlighter {
     pub fn new(pre_tag: String, post_tag: String) -> Self {
-        Self { pre_tag, post_tag }
+        Self {
+            pre_tag,
+            post_tag,
+            keep_diacritics: KeepDiacritics::default(),
+            cjk_segmenter: None,
+            decompound_languages: Vec::new(),
+            custom_compounds: HashMap::new(),
+        }
+    }
+
+    /// Match query words against text folded like the index
+    /// (`keepDiacriticsOnCharacters`). Highlights still wrap the original text.
+    pub fn with_keep_diacritics(mut self, keep: KeepDiacritics) -> Self {
+        self.keep_diacritics = keep;
+        self
+    }
+
+    /// Segment CJK text like the index (`indexLanguages`), so query words are
+    /// split into dictionary words and only whole words are highlighted.
+    pub fn with_cjk_segmenter(mut self, segmenter: Option<CjkSegmenter>) -> Self {
+        self.cjk_segmenter = segmenter;
+        self
+    }
+
+    /// Highlight the parts of compound query words (`decompoundQuery`) found
+    /// separately in the text.
+    pub fn with_decompound_languages(mut self, languages: Vec<String>) -> Self {
+        self.decompound_languages = languages;
+        self
+    }
+
+    /// Custom compounds from the dictionaries API, keyed by language.
+    pub fn with_custom_compounds(mut self, compounds: HashMap<String, CustomCompounds>) -> Self {
+        self.custom_compounds = compounds;
+        self
     }
 
     pub fn highlight_document(
@@ -106,21 +152,52 @@ impl Highlighter {
     }
 
     fn highlight_text(&self, text: &str, query_words: &[String]) -> HighlightResult {
-        let text_lower = text.to_lowercase();
+        // Match on folded text; `spans` maps folded byte offsets back to `text`
+        let (text_lower, spans) = fold_with_offsets(text, &self.keep_diacritics);
+        let original = |start: usize, end: usize| (spans[start].0, spans[end - 1].1);
+
+        // With a CJK segmenter, CJK query words match whole words only
+        let segmented_words;
+        let (query_words, word_bounds) = match self.cjk_segmenter {
+            Some(segmenter) => {
+                segmented_words = segment_query_words(segmenter, query_words);
+                (
+                    segmented_words.as_slice(),
+                    Some(cjk_word_bounds(segmenter, &text_lower)),
+                )
+            }
+            None => (query_words, None),
+        };
         let mut matched_words = Vec::new();
         let mut match_positions = Vec::new();
 
-        // Pre-compute lowercased query words to avoid repeated allocation
-        let query_words_lower: Vec<String> = query_words.iter().map(|w| w.to_lowercase()).collect();
+        // Pre-compute folded query words to avoid repeated allocation
+        let query_words_lower: Vec<String> = query_words
+            .iter()
+            .map(|w| fold(w, &self.keep_diacritics))
+            .collect();
 
         // 1. Exact substring matching for each query word
         for (qi, word_lower) in query_words_lower.iter().enumerate() {
+            if word_lower.is_empty() {
+                continue;
+            }
+            let whole_words = word_bounds
+                .as_ref()
+                .filter(|_| word_lower.chars().any(is_cjk));
             let mut start = 0;
             while let Some(pos) = text_lower[start..].find(word_lower.as_str()) {
                 let absolute_pos = start + pos;
+                let end = absolute_pos + word_lower.len();
+                if let Some((starts, ends)) = whole_words {
+                    if !starts.contains(&absolute_pos) || !ends.contains(&end) {
+                        start = absolute_pos + word_lower.chars().next().map_or(1, char::len_utf8);
+                        continue;
+                    }
+                }
+                start = end;
                 matched_words.push(query_words[qi].clone());
-                match_positions.push((absolute_pos, absolute_pos + word_lower.len()));
-                start = absolute_pos + word_lower.len();
+                match_positions.push(original(absolute_pos, end));
             }
         }
 
@@ -133,7 +210,42 @@ impl Highlighter {
             .all(|qw| unique_matched.contains(qw.as_str()));
 
         if !all_found_exact {
-            // 2. Split matching: for each query word >= 4 chars, try inserting a space
+            // 2. Compound matching: a compound query word matches when all of
+            //     its parts are in the text (e.g., "kinderschuhe" -> "schuhe für kinder")
+            let found: HashSet<String> = matched_words.iter().cloned().collect();
+            for word in query_words {
+                if found.contains(word) {
+                    continue;
+                }
+                let Some(parts) = self
+                    .decompound_languages
+                    .iter()
+                    .find_map(|lang| decompound_with_custom(word, lang, &self.custom_compounds))
+                else {
+                    continue;
+                };
+                let mut positions = Vec::new();
+                for part in &parts {
+                    let part_lower = fold(part, &self.keep_diacritics);
+                    let before = positions.len();
+                    let mut start = 0;
+                    while let Some(pos) = text_lower[start..].find(part_lower.as_str()) {
+                        let absolute_pos = start + pos;
+                        start = absolute_pos + part_lower.len();
+                        positions.push(original(absolute_pos, start));
+                    }
+                    if positions.len() == before {
+                        positions.clear();
+                        break;
+                    }
+                }
+                if !positions.is_empty() {
+                    matched_words.push(word.clone());
+                    match_positions.extend(positions);
+                }
+            }
+
+            // 3. Split matching: for each query word >= 4 chars, try inserting a space
             //    at each position to match split forms (e.g., "hotdog" -> "hot dog")
             for (qi, word_lower) in query_words_lower.iter().enumerate() {
                 let chars: Vec<char> = word_lower.chars().collect();
@@ -151,13 +263,14 @@ impl Highlighter {
                     while let Some(pos) = text_lower[start..].find(&split_form) {
                         let absolute_pos = start + pos;
                         matched_words.push(query_words[qi].clone());
-                        match_positions.push((absolute_pos, absolute_pos + split_form.len()));
+                        match_positions
+                            .push(original(absolute_pos, absolute_pos + split_form.len()));
                         start = absolute_pos + split_form.len();
                     }
                 }
             }
 
-            // 3. Concat matching: for adjacent query word pairs, try concatenated form
+            // 4. Concat matching: for adjacent query word pairs, try concatenated form
             //    (e.g., "ear" + "buds" -> try matching "earbuds" in text)
             if query_words_lower.len() >= 2 {
                 for i in 0..query_words_lower.len() - 1 {
@@ -167,13 +280,13 @@ impl Highlighter {
                         let absolute_pos = start + pos;
                         matched_words.push(query_words[i].clone());
                         matched_words.push(query_words[i + 1].clone());
-                        match_positions.push((absolute_pos, absolute_pos + concat.len()));
+                        match_positions.push(original(absolute_pos, absolute_pos + concat.len()));
                         start = absolute_pos + concat.len();
                     }
                 }
             }
 
-            // 4. Fuzzy matching per word boundary (most expensive — only when needed)
+            // 5. Fuzzy matching per word boundary (most expensive — only when needed)
             let text_words: Vec<(usize, &str)> = {
                 let mut words = Vec::new();
                 let mut current_start = 0;
@@ -192,7 +305,7 @@ impl Highlighter {
             };
 
             for (word_start, text_word) in &text_words {
-                let text_word_lower = text_word.to_lowercase();
+                let text_word_lower = fold(text_word, &self.keep_diacritics);
                 for (qi, query_lower) in query_words_lower.iter().enumerate() {
                     let ql_chars = query_lower.chars().count();
                     let twl_chars = text_word_lower.chars().count();
@@ -201,8 +314,12 @@ impl Highlighter {
                         let max_distance = if ql_chars >= 8 { 2 } else { 1 };
                         if distance <= max_distance && distance > 0 {
                             matched_words.push(query_words[qi].clone());
-                            let highlight_len = ql_chars.min(text_word.len());
-                            match_positions.push((*word_start, word_start + highlight_len));
+                            let highlight_end = text_word
+                                .char_indices()
+                                .nth(ql_chars)
+                                .map(|(i, _)| word_start + i)
+                                .unwrap_or(word_start + text_word.len());
+                            match_positions.push((*word_start, highlight_end));
                         } else if twl_chars > ql_chars {
                             let prefix: String = text_word_lower.chars().take(ql_chars).collect();
                             let prefix_distance = strsim::damerau_levenshtein(query_lower, &prefix);
@@ -408,13 +525,18 @@ impl Highlighter {
         }
 
         // Find the word index where the first match occurs
-        let text_lower = text.to_lowercase();
-        let query_words_lower: Vec<String> = query_words.iter().map(|w| w.to_lowercase()).collect();
+        let (text_lower, spans) = fold_with_offsets(text, &self.keep_diacritics);
+        let query_words_lower: Vec<String> = query_words
+            .iter()
+            .map(|w| fold(w, &self.keep_diacritics))
+            .filter(|w| !w.is_empty())
+            .collect();
 
         let first_match_byte = query_words_lower
             .iter()
             .filter_map(|qw| text_lower.find(qw.as_str()))
             .min()
+            .map(|pos| spans[pos].0)
             .unwrap_or(0);
 
         // Find which word index corresponds to this byte offset
@@ -511,6 +633,63 @@ pub fn parse_snippet_spec(spec: &str) -> (&str, usize) {
     }
 }
 
+/// Split the CJK parts of each query word into dictionary words.
+fn segment_query_words(segmenter: CjkSegmenter, query_words: &[String]) -> Vec<String> {
+    let mut words = Vec::new();
+    for word in query_words {
+        let mut rest = word.as_str();
+        while let Some(c) = rest.chars().next() {
+            let cjk = is_cjk(c);
+            let len = rest
+                .char_indices()
+                .find(|(_, ch)| is_cjk(*ch) != cjk)
+                .map(|(i, _)| i)
+                .unwrap_or(rest.len());
+            let (part, tail) = rest.split_at(len);
+            if cjk {
+                for (start, end) in segmenter.segment(part) {
+                    words.push(part[start..end].to_string());
+                }
+            } else {
+                words.push(part.to_string());
+            }
+            rest = tail;
+        }
+    }
+    words
+}
+
+/// Byte offsets where the words (and dictionary sub-words) of the CJK runs
+/// in `text` start and end.
+fn cjk_word_bounds(segmenter: CjkSegmenter, text: &str) -> (HashSet<usize>, HashSet<usize>) {
+    let mut starts = HashSet::new();
+    let mut ends = HashSet::new();
+    let mut chars = text.char_indices().peekable();
+    while let Some((run_start, c)) = chars.next() {
+        if !is_cjk(c) {
+            continue;
+        }
+        let mut run_end = run_start + c.len_utf8();
+        while let Some(&(i, ch)) = chars.peek() {
+            if !is_cjk(ch) {
+                break;
+            }
+            run_end = i + ch.len_utf8();
+            chars.next();
+        }
+        let run = &text[run_start..run_end];
+        for (start, end) in segmenter.segment(run) {
+            starts.insert(run_start + start);
+            ends.insert(run_start + end);
+            for (sub_start, sub_end) in segmenter.sub_words(&run[start..end]) {
+                starts.insert(run_start + start + sub_start);
+                ends.insert(run_start + start + sub_end);
+            }
+        }
+    }
+    (starts, ends)
+}
+
 pub fn extract_query_words(query_text: &str) -> Vec<String> {
     query_text
         .split_whitespace()
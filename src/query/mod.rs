@@ -1,3 +1,4 @@
+pub mod decompound;
 pub mod executor;
 pub mod filter;
 pub mod fuzzy;
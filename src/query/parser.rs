@@ -1,5 +1,10 @@
 use crate::error::Result;
+use crate::tokenizer::diacritics::{fold, KeepDiacritics};
+use crate::tokenizer::{CjkSegmenter, IndexedSeparators};
 use crate::types::Query;
+use tantivy::query::{Query as TantivyQuery, Scorer, Weight};
+use tantivy::schema::Schema as TantivySchema;
+use tantivy::DocSet;
 
 fn is_cjk(c: char) -> bool {
     matches!(c,
@@ -20,29 +25,61 @@ fn is_cjk(c: char) -> bool {
     )
 }
 
-fn split_cjk_aware(text: &str) -> Vec<String> {
+/// Split `text` into words. CJK runs are split into dictionary words when a
+/// segmenter is given, the same way the index analyzers split them, and into
+/// single characters otherwise. Indexed separators stay inside words.
+fn split_cjk_aware(
+    text: &str,
+    segmenter: Option<CjkSegmenter>,
+    separators: &IndexedSeparators,
+) -> Vec<String> {
     let mut tokens = Vec::new();
     let mut current = String::new();
+    let mut run = String::new();
+    let flush_run = |run: &mut String, tokens: &mut Vec<String>| {
+        if let Some(segmenter) = segmenter {
+            for (start, end) in segmenter.segment(run) {
+                tokens.push(run[start..end].to_string());
+            }
+        }
+        run.clear();
+    };
     for c in text.chars() {
         if is_cjk(c) {
             if !current.is_empty() {
                 tokens.push(std::mem::take(&mut current));
             }
-            tokens.push(c.to_string());
-        } else if c.is_alphanumeric() {
+            if segmenter.is_some() {
+                run.push(c);
+            } else {
+                tokens.push(c.to_string());
+            }
+            continue;
+        }
+        if !run.is_empty() {
+            flush_run(&mut run, &mut tokens);
+        }
+        if separators.is_word_char(c) {
             current.push(c);
         } else if !current.is_empty() {
             tokens.push(std::mem::take(&mut current));
         }
     }
+    if !run.is_empty() {
+        flush_run(&mut run, &mut tokens);
+    }
     if !current.is_empty() {
         tokens.push(current);
     }
     tokens
 }
-use tantivy::query::{Query as TantivyQuery, Scorer, Weight};
-use tantivy::schema::Schema as TantivySchema;
-use tantivy::DocSet;
+
+/// Whether `path` is one of `attributes` or nested inside one of them.
+fn matches_attribute(attributes: &[String], path: &str) -> bool {
+    attributes.iter().any(|attr| {
+        path == attr || (path.starts_with(attr.as_str()) && path[attr.len()..].starts_with('.'))
+    })
+}
 
 #[derive(Debug, Clone)]
 pub struct ShortQueryPlaceholder {
@@ -113,6 +150,13 @@ pub struct QueryParser {
     typo_tolerance: bool,
     min_word_size_for_1_typo: usize,
     advanced_syntax: bool,
+    keep_diacritics: KeepDiacritics,
+    cjk_segmenter: Option<CjkSegmenter>,
+    separators_to_index: IndexedSeparators,
+    disable_typo_attributes: Vec<String>,
+    disable_typo_words: Vec<String>,
+    disable_prefix_attributes: Vec<String>,
+    allow_typos_on_numeric_tokens: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -136,6 +180,13 @@ impl QueryParser {
             typo_tolerance: true,
             min_word_size_for_1_typo: 4,
             advanced_syntax: false,
+            keep_diacritics: KeepDiacritics::default(),
+            cjk_segmenter: None,
+            separators_to_index: IndexedSeparators::default(),
+            disable_typo_attributes: Vec::new(),
+            disable_typo_words: Vec::new(),
+            disable_prefix_attributes: Vec::new(),
+            allow_typos_on_numeric_tokens: true,
         }
     }
 
@@ -160,6 +211,13 @@ impl QueryParser {
             typo_tolerance: true,
             min_word_size_for_1_typo: 4,
             advanced_syntax: false,
+            keep_diacritics: KeepDiacritics::default(),
+            cjk_segmenter: None,
+            separators_to_index: IndexedSeparators::default(),
+            disable_typo_attributes: Vec::new(),
+            disable_typo_words: Vec::new(),
+            disable_prefix_attributes: Vec::new(),
+            allow_typos_on_numeric_tokens: true,
         }
     }
 
@@ -188,6 +246,50 @@ impl QueryParser {
         self
     }
 
+    /// Characters exempt from diacritic folding; must match the index analyzers.
+    pub fn with_keep_diacritics(mut self, keep: KeepDiacritics) -> Self {
+        self.keep_diacritics = keep;
+        self
+    }
+
+    /// CJK word segmenter; must match the index analyzers.
+    pub fn with_cjk_segmenter(mut self, segmenter: Option<CjkSegmenter>) -> Self {
+        self.cjk_segmenter = segmenter;
+        self
+    }
+
+    /// Punctuation kept inside words (`separatorsToIndex`); must match the
+    /// index analyzers.
+    pub fn with_separators_to_index(mut self, separators: IndexedSeparators) -> Self {
+        self.separators_to_index = separators;
+        self
+    }
+
+    /// Attributes matched without typos (`disableTypoToleranceOnAttributes`).
+    pub fn with_disable_typo_tolerance_on_attributes(mut self, attributes: Vec<String>) -> Self {
+        self.disable_typo_attributes = attributes;
+        self
+    }
+
+    /// Query words matched without typos (`disableTypoToleranceOnWords`).
+    pub fn with_disable_typo_tolerance_on_words(mut self, words: Vec<String>) -> Self {
+        self.disable_typo_words = words;
+        self
+    }
+
+    /// Attributes matched on whole words only (`disablePrefixOnAttributes`).
+    pub fn with_disable_prefix_on_attributes(mut self, attributes: Vec<String>) -> Self {
+        self.disable_prefix_attributes = attributes;
+        self
+    }
+
+    /// Whether tokens made only of digits may match with typos
+    /// (`allowTyposOnNumericTokens`).
+    pub fn with_allow_typos_on_numeric_tokens(mut self, allowed: bool) -> Self {
+        self.allow_typos_on_numeric_tokens = allowed;
+        self
+    }
+
     pub fn with_plural_map(
         mut self,
         plural_map: Option<std::collections::HashMap<String, Vec<String>>>,
@@ -211,8 +313,11 @@ impl QueryParser {
         }
 
         let has_trailing_space = query.text.ends_with(' ');
-        let text = query.text.to_lowercase().trim_end_matches('*').to_string();
-        let tokens: Vec<String> = split_cjk_aware(&text);
+        let text = fold(&query.text, &self.keep_diacritics)
+            .trim_end_matches('*')
+            .to_string();
+        let tokens: Vec<String> =
+            split_cjk_aware(&text, self.cjk_segmenter, &self.separators_to_index);
 
         tracing::trace!(
             "[PARSER] parse() called: query='{}', tokens={:?}, searchable_paths={:?}",
@@ -266,17 +371,11 @@ impl QueryParser {
                 return Ok(Box::new(tantivy::query::BooleanQuery::new(field_queries)));
             }
 
-            let marker = ShortQueryMarker {
-                token: tokens[0].to_string(),
-                paths: self.searchable_paths.clone(),
-                weights: self.weights.clone(),
-                field: self.fields[0],
-            };
             tracing::trace!(
                 "[PARSER] Creating placeholder with {} paths",
                 self.searchable_paths.len()
             );
-            return Ok(Box::new(ShortQueryPlaceholder { marker }));
+            return Ok(self.short_prefix_query(&tokens[0]));
         }
 
         tracing::trace!(
@@ -321,26 +420,20 @@ impl QueryParser {
             );
 
             if token.chars().count() <= 2 && is_prefix {
-                let marker = ShortQueryMarker {
-                    token: token.to_string(),
-                    paths: self.searchable_paths.clone(),
-                    weights: self.weights.clone(),
-                    field: self.fields[0],
-                };
-                word_queries.push((
-                    tantivy::query::Occur::Must,
-                    Box::new(ShortQueryPlaceholder { marker }),
-                ));
+                word_queries.push((tantivy::query::Occur::Must, self.short_prefix_query(token)));
                 continue;
             }
 
             let mut field_queries: Vec<(tantivy::query::Occur, Box<dyn TantivyQuery>)> = Vec::new();
 
-            let target_field = if is_prefix {
-                json_search_field
-            } else {
-                self.json_exact_field.unwrap_or(json_search_field)
-            };
+            let typos_allowed = self.typo_tolerance
+                && token.len() >= self.min_word_size_for_1_typo
+                && !self
+                    .disable_typo_words
+                    .iter()
+                    .any(|w| fold(w, &self.keep_diacritics) == *token)
+                && (self.allow_typos_on_numeric_tokens
+                    || !token.chars().all(|c| c.is_ascii_digit()));
 
             let plural_forms: Vec<String> = self
                 .plural_map
@@ -356,12 +449,19 @@ impl QueryParser {
                 .unwrap_or_default();
 
             for (path_idx, path) in self.searchable_paths.iter().enumerate() {
+                let is_prefix =
+                    is_prefix && !matches_attribute(&self.disable_prefix_attributes, path);
+                let target_field = if is_prefix {
+                    json_search_field
+                } else {
+                    self.json_exact_field.unwrap_or(json_search_field)
+                };
                 let term_text = format!("{}\0s{}", path, token);
                 let term = tantivy::Term::from_field_text(target_field, &term_text);
 
-                let distance = if self.typo_tolerance
-                    && token.len() >= self.min_word_size_for_1_typo
+                let distance = if typos_allowed
                     && path_idx < max_fuzzy_paths
+                    && !matches_attribute(&self.disable_typo_attributes, path)
                 {
                     1
                 } else {
@@ -475,16 +575,64 @@ impl QueryParser {
         Ok(Box::new(tantivy::query::BooleanQuery::new(word_queries)))
     }
 
+    /// Prefix query for a token of at most two characters: a placeholder
+    /// expanded against the index by the executor, plus whole-word matches on
+    /// attributes in `disablePrefixOnAttributes`.
+    fn short_prefix_query(&self, token: &str) -> Box<dyn TantivyQuery> {
+        let mut paths = Vec::new();
+        let mut weights = Vec::new();
+        let mut exact_queries: Vec<(tantivy::query::Occur, Box<dyn TantivyQuery>)> = Vec::new();
+        for (path_idx, path) in self.searchable_paths.iter().enumerate() {
+            let weight = self.weights.get(path_idx).copied().unwrap_or(1.0);
+            if !matches_attribute(&self.disable_prefix_attributes, path) {
+                paths.push(path.clone());
+                weights.push(weight);
+                continue;
+            }
+            let exact_field = self.json_exact_field.unwrap_or(self.fields[0]);
+            let term_text = format!("{}\0s{}", path, token);
+            let tq: Box<dyn TantivyQuery> = Box::new(tantivy::query::TermQuery::new(
+                tantivy::Term::from_field_text(exact_field, &term_text),
+                tantivy::schema::IndexRecordOption::WithFreqsAndPositions,
+            ));
+            exact_queries.push((
+                tantivy::query::Occur::Should,
+                Box::new(tantivy::query::BoostQuery::new(tq, weight)),
+            ));
+        }
+
+        let placeholder = Box::new(ShortQueryPlaceholder {
+            marker: ShortQueryMarker {
+                token: token.to_string(),
+                paths,
+                weights,
+                field: self.fields[0],
+            },
+        });
+        if exact_queries.is_empty() {
+            return placeholder;
+        }
+        exact_queries.push((tantivy::query::Occur::Should, placeholder));
+        Box::new(tantivy::query::BooleanQuery::new(exact_queries))
+    }
+
     pub fn fields(&self) -> &[tantivy::schema::Field] {
         &self.fields
     }
 
     pub fn extract_terms(&self, query: &Query) -> Vec<String> {
-        split_cjk_aware(&query.text.to_lowercase())
-            .into_iter()
-            .map(|s| s.trim_matches(|c: char| !c.is_alphanumeric()).to_string())
-            .filter(|s| !s.is_empty())
-            .collect()
+        split_cjk_aware(
+            &fold(&query.text, &self.keep_diacritics),
+            self.cjk_segmenter,
+            &self.separators_to_index,
+        )
+        .into_iter()
+        .map(|s| {
+            s.trim_matches(|c: char| !self.separators_to_index.is_word_char(c))
+                .to_string()
+        })
+        .filter(|s| !s.is_empty())
+        .collect()
     }
 
     /// Extract "quoted phrases" and -exclusion terms from query text.
@@ -559,48 +707,48 @@ impl QueryParser {
             }
         }
 
-        // Phrase queries: all words in the phrase must match (exact, on same field paths)
+        // Phrase queries: the words must be adjacent and in order, in the same
+        // attribute. Positions come from the exact (non n-gram) field.
         for phrase in phrases {
-            let words: Vec<String> = phrase
-                .to_lowercase()
-                .split_whitespace()
-                .map(|s| s.to_string())
-                .collect();
+            let words = split_cjk_aware(
+                &fold(phrase, &self.keep_diacritics),
+                self.cjk_segmenter,
+                &self.separators_to_index,
+            );
             if words.is_empty() {
                 continue;
             }
-            let mut phrase_clauses: Vec<(tantivy::query::Occur, Box<dyn TantivyQuery>)> =
-                Vec::new();
-            for word in &words {
-                let mut field_queries: Vec<(tantivy::query::Occur, Box<dyn TantivyQuery>)> =
-                    Vec::new();
-                for (path_idx, path) in self.searchable_paths.iter().enumerate() {
-                    let term_text = format!("{}\0s{}", path, word);
-                    let term = tantivy::Term::from_field_text(exact_field, &term_text);
-                    let tq: Box<dyn TantivyQuery> = Box::new(tantivy::query::TermQuery::new(
-                        term,
+            let mut field_queries: Vec<(tantivy::query::Occur, Box<dyn TantivyQuery>)> = Vec::new();
+            for (path_idx, path) in self.searchable_paths.iter().enumerate() {
+                let terms: Vec<tantivy::Term> = words
+                    .iter()
+                    .map(|word| {
+                        tantivy::Term::from_field_text(exact_field, &format!("{}\0s{}", path, word))
+                    })
+                    .collect();
+                let pq: Box<dyn TantivyQuery> = if terms.len() == 1 {
+                    Box::new(tantivy::query::TermQuery::new(
+                        terms.into_iter().next().unwrap(),
                         tantivy::schema::IndexRecordOption::WithFreqs,
-                    ));
-                    let weight = self.weights.get(path_idx).copied().unwrap_or(1.0);
-                    field_queries.push((
-                        tantivy::query::Occur::Should,
-                        Box::new(tantivy::query::BoostQuery::new(tq, weight)),
-                    ));
-                }
-                phrase_clauses.push((
-                    tantivy::query::Occur::Must,
-                    Box::new(tantivy::query::BooleanQuery::new(field_queries)),
+                    ))
+                } else {
+                    Box::new(tantivy::query::PhraseQuery::new(terms))
+                };
+                let weight = self.weights.get(path_idx).copied().unwrap_or(1.0);
+                field_queries.push((
+                    tantivy::query::Occur::Should,
+                    Box::new(tantivy::query::BoostQuery::new(pq, weight)),
                 ));
             }
             clauses.push((
                 tantivy::query::Occur::Must,
-                Box::new(tantivy::query::BooleanQuery::new(phrase_clauses)),
+                Box::new(tantivy::query::BooleanQuery::new(field_queries)),
             ));
         }
 
         // Exclusion queries: MustNot for each excluded term
         for exclusion in exclusions {
-            let word = exclusion.to_lowercase();
+            let word = fold(exclusion, &self.keep_diacritics);
             let mut field_queries: Vec<(tantivy::query::Occur, Box<dyn TantivyQuery>)> = Vec::new();
             for path in &self.searchable_paths {
                 let term_text = format!("{}\0s{}", path, word);
@@ -636,6 +784,13 @@ impl QueryParser {
             typo_tolerance: self.typo_tolerance,
             min_word_size_for_1_typo: self.min_word_size_for_1_typo,
             advanced_syntax: self.advanced_syntax,
+            keep_diacritics: self.keep_diacritics.clone(),
+            cjk_segmenter: self.cjk_segmenter,
+            separators_to_index: self.separators_to_index.clone(),
+            disable_typo_attributes: self.disable_typo_attributes.clone(),
+            disable_typo_words: self.disable_typo_words.clone(),
+            disable_prefix_attributes: self.disable_prefix_attributes.clone(),
+            allow_typos_on_numeric_tokens: self.allow_typos_on_numeric_tokens,
         }
     }
 }
@@ -1,3 +1,4 @@
+use crate::tokenizer::diacritics::{fold, KeepDiacritics};
 use std::collections::HashMap;
 use std::sync::OnceLock;
 
@@ -73,12 +74,18 @@ fn load_dictionary() -> HashMap<String, Vec<String>> {
     let raw: HashMap<String, String> =
         serde_json::from_str(json_str).expect("invalid irregular-plurals.json");
 
-    let mut bidir: HashMap<String, Vec<String>> = HashMap::new();
+    bidirectional(
+        raw.iter()
+            .map(|(singular, plural)| (singular.to_lowercase(), plural.to_lowercase())),
+    )
+}
 
-    for (singular, plural) in &raw {
-        let s = singular.to_lowercase();
-        let p = plural.to_lowercase();
+/// Map every singular to its plurals and back. Invariant words map to an
+/// empty list.
+fn bidirectional(pairs: impl Iterator<Item = (String, String)>) -> HashMap<String, Vec<String>> {
+    let mut bidir: HashMap<String, Vec<String>> = HashMap::new();
 
+    for (s, p) in pairs {
         if s == p {
             bidir.entry(s).or_default();
             continue;
@@ -186,6 +193,159 @@ pub fn expand_plurals(word: &str) -> Vec<String> {
     forms
 }
 
+/// Suffix rules and irregular forms for one language, from
+/// `package/plural-rules.json`. Entries are stored folded so they match the
+/// words produced by the query parser.
+struct LanguageRules {
+    /// `(singular suffix, plural suffix)` pairs.
+    rules: Vec<(String, String)>,
+    irregular: HashMap<String, Vec<String>>,
+}
+
+#[derive(serde::Deserialize)]
+struct RawLanguageRules {
+    rules: Vec<(String, String)>,
+    irregular: HashMap<String, String>,
+}
+
+static LANGUAGE_RULES: OnceLock<HashMap<String, LanguageRules>> = OnceLock::new();
+
+fn load_language_rules() -> HashMap<String, LanguageRules> {
+    let json_str = include_str!("../../package/plural-rules.json");
+    let raw: HashMap<String, RawLanguageRules> =
+        serde_json::from_str(json_str).expect("invalid plural-rules.json");
+
+    let keep = KeepDiacritics::default();
+    raw.into_iter()
+        .map(|(lang, raw)| {
+            let rules = raw
+                .rules
+                .iter()
+                .map(|(s, p)| (fold(s, &keep), fold(p, &keep)))
+                .collect();
+            let irregular = bidirectional(
+                raw.irregular
+                    .iter()
+                    .map(|(s, p)| (fold(s, &keep), fold(p, &keep))),
+            );
+            (lang, LanguageRules { rules, irregular })
+        })
+        .collect()
+}
+
+fn get_language_rules() -> &'static HashMap<String, LanguageRules> {
+    LANGUAGE_RULES.get_or_init(load_language_rules)
+}
+
+/// Expand `word` with a language's rules: every rule whose plural suffix ends
+/// the word yields a singular, and the rules with the longest matching
+/// singular suffix yield plurals. Spurious forms are harmless since they
+/// match no indexed term.
+fn expand_with_rules(word: &str, lang: &LanguageRules) -> Vec<String> {
+    let mut forms = vec![word.to_string()];
+
+    if let Some(others) = lang.irregular.get(word) {
+        for other in others {
+            if !forms.contains(other) {
+                forms.push(other.clone());
+            }
+        }
+        return forms;
+    }
+
+    for (singular, plural) in &lang.rules {
+        if plural == singular || !word.ends_with(plural.as_str()) {
+            continue;
+        }
+        let stem = &word[..word.len() - plural.len()];
+        if stem.chars().count() < 2 {
+            continue;
+        }
+        let candidate = format!("{}{}", stem, singular);
+        if lang.irregular.get(&candidate).is_some_and(|o| o.is_empty()) {
+            continue;
+        }
+        if !forms.contains(&candidate) {
+            forms.push(candidate);
+        }
+    }
+
+    let longest = lang
+        .rules
+        .iter()
+        .filter(|(singular, _)| word.ends_with(singular.as_str()))
+        .map(|(singular, _)| singular.len())
+        .max();
+    if let Some(longest) = longest {
+        for (singular, plural) in &lang.rules {
+            if singular.len() != longest || !word.ends_with(singular.as_str()) {
+                continue;
+            }
+            let stem = &word[..word.len() - singular.len()];
+            if stem.is_empty() {
+                continue;
+            }
+            let candidate = format!("{}{}", stem, plural);
+            if !forms.contains(&candidate) {
+                forms.push(candidate);
+            }
+        }
+    }
+
+    forms
+}
+
+/// Whether plural rules are bundled for `lang`.
+pub fn supports_language(lang: &str) -> bool {
+    lang == "en" || get_language_rules().contains_key(lang)
+}
+
+/// Custom plurals for one language, from the dictionaries API.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CustomPlurals {
+    /// Every word of an entry mapped to all the words of that entry, folded.
+    pub forms: HashMap<String, Vec<String>>,
+    /// Ignore the bundled rules and irregular forms (`disableStandardEntries`).
+    pub disable_standard: bool,
+}
+
+/// Singular and plural forms of `word` in each of `langs`. Non-English
+/// rules expect `word` to be folded the way the query parser folds tokens.
+pub fn expand_plurals_for_languages(word: &str, langs: &[String]) -> Vec<String> {
+    expand_plurals_with_custom(word, langs, &HashMap::new())
+}
+
+/// [`expand_plurals_for_languages`] where `custom` plurals, keyed by
+/// language, take precedence over the bundled forms.
+pub fn expand_plurals_with_custom(
+    word: &str,
+    langs: &[String],
+    custom: &HashMap<String, CustomPlurals>,
+) -> Vec<String> {
+    let lower = word.to_lowercase();
+    let mut forms = vec![lower.clone()];
+    for lang in langs {
+        let custom = custom.get(lang.as_str());
+        let expanded = if let Some(custom_forms) = custom.and_then(|c| c.forms.get(&lower)) {
+            custom_forms.clone()
+        } else if custom.is_some_and(|c| c.disable_standard) {
+            continue;
+        } else if lang == "en" {
+            expand_plurals(&lower)
+        } else if let Some(rules) = get_language_rules().get(lang.as_str()) {
+            expand_with_rules(&lower, rules)
+        } else {
+            continue;
+        };
+        for form in expanded {
+            if !forms.contains(&form) {
+                forms.push(form);
+            }
+        }
+    }
+    forms
+}
+
 pub fn resolve_plural_languages(
     ignore_plurals: &IgnorePluralsValue,
     query_languages: &[String],
@@ -638,4 +798,157 @@ mod tests {
             failures.join("\n")
         );
     }
+
+    fn expand_in(word: &str, lang: &str) -> Vec<String> {
+        expand_plurals_for_languages(word, &[lang.to_string()])
+    }
+
+    #[test]
+    fn test_french_regular() {
+        assert!(expand_in("chat", "fr").contains(&"chats".to_string()));
+        assert!(expand_in("chats", "fr").contains(&"chat".to_string()));
+    }
+
+    #[test]
+    fn test_french_al_aux() {
+        assert!(expand_in("cheval", "fr").contains(&"chevaux".to_string()));
+        assert!(expand_in("chevaux", "fr").contains(&"cheval".to_string()));
+    }
+
+    #[test]
+    fn test_french_eau_eaux() {
+        assert!(expand_in("bateau", "fr").contains(&"bateaux".to_string()));
+        assert!(expand_in("bateaux", "fr").contains(&"bateau".to_string()));
+        assert!(expand_in("jeu", "fr").contains(&"jeux".to_string()));
+    }
+
+    #[test]
+    fn test_french_irregular() {
+        let forms = expand_in("oeil", "fr");
+        assert!(forms.contains(&"yeux".to_string()), "oeil -> {:?}", forms);
+        assert!(expand_in("yeux", "fr").contains(&"oeil".to_string()));
+        let forms = expand_in("festival", "fr");
+        assert!(forms.contains(&"festivals".to_string()));
+        assert!(!forms.contains(&"festivaux".to_string()));
+    }
+
+    #[test]
+    fn test_french_invariant() {
+        let forms = expand_in("prix", "fr");
+        assert_eq!(forms, vec!["prix".to_string()]);
+    }
+
+    #[test]
+    fn test_german_plurals() {
+        assert!(expand_in("blume", "de").contains(&"blumen".to_string()));
+        assert!(expand_in("blumen", "de").contains(&"blume".to_string()));
+        assert!(expand_in("zeitung", "de").contains(&"zeitungen".to_string()));
+        assert!(expand_in("zeitungen", "de").contains(&"zeitung".to_string()));
+        assert!(expand_in("lehrerin", "de").contains(&"lehrerinnen".to_string()));
+    }
+
+    #[test]
+    fn test_german_umlaut_irregular() {
+        // Dictionary entries are folded like query words
+        assert!(expand_in("haus", "de").contains(&"hauser".to_string()));
+        assert!(expand_in("hauser", "de").contains(&"haus".to_string()));
+        assert!(expand_in("kind", "de").contains(&"kinder".to_string()));
+    }
+
+    #[test]
+    fn test_spanish_plurals() {
+        assert!(expand_in("casa", "es").contains(&"casas".to_string()));
+        assert!(expand_in("flores", "es").contains(&"flor".to_string()));
+        assert!(expand_in("flor", "es").contains(&"flores".to_string()));
+        assert!(expand_in("luz", "es").contains(&"luces".to_string()));
+        assert!(expand_in("luces", "es").contains(&"luz".to_string()));
+        assert!(expand_in("cancion", "es").contains(&"canciones".to_string()));
+        assert!(expand_in("padres", "es").contains(&"padre".to_string()));
+    }
+
+    #[test]
+    fn test_spanish_invariant() {
+        assert_eq!(expand_in("lunes", "es"), vec!["lunes".to_string()]);
+    }
+
+    #[test]
+    fn test_italian_plurals() {
+        assert!(expand_in("libro", "it").contains(&"libri".to_string()));
+        assert!(expand_in("libri", "it").contains(&"libro".to_string()));
+        assert!(expand_in("casa", "it").contains(&"case".to_string()));
+        assert!(expand_in("fiori", "it").contains(&"fiore".to_string()));
+        assert!(expand_in("uomo", "it").contains(&"uomini".to_string()));
+        assert_eq!(expand_in("citta", "it"), vec!["citta".to_string()]);
+    }
+
+    #[test]
+    fn test_portuguese_plurals() {
+        assert!(expand_in("carro", "pt").contains(&"carros".to_string()));
+        assert!(expand_in("animal", "pt").contains(&"animais".to_string()));
+        assert!(expand_in("animais", "pt").contains(&"animal".to_string()));
+        assert!(expand_in("homem", "pt").contains(&"homens".to_string()));
+        assert!(expand_in("cancoes", "pt").contains(&"cancao".to_string()));
+        assert!(expand_in("pao", "pt").contains(&"paes".to_string()));
+    }
+
+    #[test]
+    fn test_dutch_plurals() {
+        assert!(expand_in("boek", "nl").contains(&"boeken".to_string()));
+        assert!(expand_in("boeken", "nl").contains(&"boek".to_string()));
+        assert!(expand_in("tafel", "nl").contains(&"tafels".to_string()));
+        assert!(expand_in("huis", "nl").contains(&"huizen".to_string()));
+        assert!(expand_in("kinderen", "nl").contains(&"kind".to_string()));
+        assert!(expand_in("stad", "nl").contains(&"steden".to_string()));
+    }
+
+    #[test]
+    fn test_multiple_languages() {
+        let forms = expand_plurals_for_languages("child", &["en".to_string(), "fr".to_string()]);
+        assert!(forms.contains(&"children".to_string()));
+        assert!(forms.contains(&"childs".to_string()));
+    }
+
+    #[test]
+    fn test_unsupported_language_noop() {
+        assert_eq!(expand_in("cars", "xx"), vec!["cars".to_string()]);
+        assert!(!supports_language("xx"));
+        for lang in ["en", "fr", "de", "es", "it", "pt", "nl"] {
+            assert!(supports_language(lang), "{}", lang);
+        }
+    }
+
+    #[test]
+    fn test_custom_plurals() {
+        let custom = |entries: &[&[&str]], disable_standard: bool| {
+            let mut forms = HashMap::new();
+            for entry in entries {
+                for word in *entry {
+                    forms.insert(
+                        word.to_string(),
+                        entry.iter().map(|w| w.to_string()).collect(),
+                    );
+                }
+            }
+            HashMap::from([(
+                "en".to_string(),
+                CustomPlurals {
+                    forms,
+                    disable_standard,
+                },
+            )])
+        };
+        let en = vec!["en".to_string()];
+
+        let forms =
+            expand_plurals_with_custom("cactus", &en, &custom(&[&["cactus", "cacti"]], false));
+        assert_eq!(forms, vec!["cactus", "cacti"]);
+        let forms =
+            expand_plurals_with_custom("cacti", &en, &custom(&[&["cactus", "cacti"]], false));
+        assert_eq!(forms, vec!["cacti", "cactus"]);
+        // Words without a custom entry keep the bundled forms
+        let forms = expand_plurals_with_custom("car", &en, &custom(&[&["cactus", "cacti"]], false));
+        assert!(forms.contains(&"cars".to_string()));
+        let forms = expand_plurals_with_custom("car", &en, &custom(&[], true));
+        assert_eq!(forms, vec!["car"]);
+    }
 }
@@ -1,4 +1,6 @@
-use std::collections::HashSet;
+use crate::tokenizer::diacritics::{fold, KeepDiacritics};
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 
 pub fn english_stop_words() -> HashSet<&'static str> {
     [
@@ -209,24 +211,101 @@ impl<'de> serde::Deserialize<'de> for RemoveStopWordsValue {
     }
 }
 
+static STOP_WORDS: OnceLock<HashMap<String, HashSet<String>>> = OnceLock::new();
+
+/// Non-English lists from `package/stop-words.json`, folded like query words.
+fn load_stop_words() -> HashMap<String, HashSet<String>> {
+    let json_str = include_str!("../../package/stop-words.json");
+    let raw: HashMap<String, Vec<String>> =
+        serde_json::from_str(json_str).expect("invalid stop-words.json");
+
+    let keep = KeepDiacritics::default();
+    raw.into_iter()
+        .map(|(lang, words)| (lang, words.iter().map(|w| fold(w, &keep)).collect()))
+        .collect()
+}
+
 fn stop_words_for_lang(lang: &str) -> Option<HashSet<&'static str>> {
     match lang {
         "en" => Some(english_stop_words()),
-        _ => None,
+        _ => STOP_WORDS
+            .get_or_init(load_stop_words)
+            .get(lang)
+            .map(|words| words.iter().map(String::as_str).collect()),
+    }
+}
+
+/// Languages whose stop words `setting` removes. `true` follows the index's
+/// languages (`queryLanguages`, else `indexLanguages`), falling back to English.
+pub fn resolve_stop_word_languages(
+    setting: &RemoveStopWordsValue,
+    languages: &[String],
+) -> Vec<String> {
+    match setting {
+        RemoveStopWordsValue::Disabled => vec![],
+        RemoveStopWordsValue::Languages(langs) => langs.clone(),
+        RemoveStopWordsValue::All => {
+            if languages.is_empty() {
+                vec!["en".to_string()]
+            } else {
+                languages.to_vec()
+            }
+        }
     }
 }
 
 pub fn remove_stop_words(query: &str, setting: &RemoveStopWordsValue, query_type: &str) -> String {
-    let langs: Vec<&str> = match setting {
-        RemoveStopWordsValue::Disabled => return query.to_string(),
-        RemoveStopWordsValue::All => vec!["en"],
-        RemoveStopWordsValue::Languages(langs) => langs.iter().map(|s| s.as_str()).collect(),
-    };
+    remove_stop_words_for_languages(query, setting, &[], query_type)
+}
 
-    let mut all_stop_words = HashSet::new();
+/// Custom stop words for one language, from the dictionaries API. Words are
+/// folded like query words.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CustomStopWords {
+    /// Words removed in addition to the bundled list.
+    pub added: HashSet<String>,
+    /// Bundled words that are kept (entries with `state: "disabled"`).
+    pub disabled: HashSet<String>,
+    /// Ignore the bundled list (`disableStandardEntries`).
+    pub disable_standard: bool,
+}
+
+pub fn remove_stop_words_for_languages(
+    query: &str,
+    setting: &RemoveStopWordsValue,
+    languages: &[String],
+    query_type: &str,
+) -> String {
+    remove_stop_words_with_custom(query, setting, languages, query_type, &HashMap::new())
+}
+
+/// [`remove_stop_words_for_languages`] with the bundled lists merged with
+/// `custom` stop words, keyed by language.
+pub fn remove_stop_words_with_custom(
+    query: &str,
+    setting: &RemoveStopWordsValue,
+    languages: &[String],
+    query_type: &str,
+    custom: &HashMap<String, CustomStopWords>,
+) -> String {
+    let langs = resolve_stop_word_languages(setting, languages);
+    if langs.is_empty() {
+        return query.to_string();
+    }
+
+    let mut all_stop_words: HashSet<&str> = HashSet::new();
     for lang in &langs {
-        if let Some(sw) = stop_words_for_lang(lang) {
-            all_stop_words.extend(sw);
+        let custom = custom.get(lang.as_str());
+        if !custom.is_some_and(|c| c.disable_standard) {
+            if let Some(sw) = stop_words_for_lang(lang) {
+                all_stop_words.extend(
+                    sw.into_iter()
+                        .filter(|w| !custom.is_some_and(|c| c.disabled.contains(*w))),
+                );
+            }
+        }
+        if let Some(custom) = custom {
+            all_stop_words.extend(custom.added.iter().map(String::as_str));
         }
     }
 
@@ -254,7 +333,7 @@ pub fn remove_stop_words(query: &str, setting: &RemoveStopWordsValue, query_type
             if is_prefix_token {
                 return true;
             }
-            !all_stop_words.contains(w.to_lowercase().as_str())
+            !all_stop_words.contains(fold(w, &KeepDiacritics::default()).as_str())
         })
         .map(|(_, w)| *w)
         .collect();
@@ -393,4 +472,146 @@ mod tests {
         let r = remove_stop_words("best search ", &RemoveStopWordsValue::All, "prefixLast");
         assert_eq!(r, "best search ");
     }
+
+    fn langs(codes: &[&str]) -> RemoveStopWordsValue {
+        RemoveStopWordsValue::Languages(codes.iter().map(|c| c.to_string()).collect())
+    }
+
+    #[test]
+    fn test_french_removal() {
+        let r = remove_stop_words("le chat de la maison", &langs(&["fr"]), "prefixNone");
+        assert_eq!(r, "chat maison");
+    }
+
+    #[test]
+    fn test_german_removal() {
+        let r = remove_stop_words("der Hund und die Katze", &langs(&["de"]), "prefixNone");
+        assert_eq!(r, "Hund Katze");
+    }
+
+    #[test]
+    fn test_spanish_removal() {
+        let r = remove_stop_words("el perro de los vecinos", &langs(&["es"]), "prefixNone");
+        assert_eq!(r, "perro vecinos");
+    }
+
+    #[test]
+    fn test_italian_removal() {
+        let r = remove_stop_words("il gatto della nonna", &langs(&["it"]), "prefixNone");
+        assert_eq!(r, "gatto nonna");
+    }
+
+    #[test]
+    fn test_portuguese_removal() {
+        let r = remove_stop_words("o livro da escola", &langs(&["pt"]), "prefixNone");
+        assert_eq!(r, "livro escola");
+    }
+
+    #[test]
+    fn test_dutch_removal() {
+        let r = remove_stop_words("de fiets van het meisje", &langs(&["nl"]), "prefixNone");
+        assert_eq!(r, "fiets meisje");
+    }
+
+    #[test]
+    fn test_accents_optional() {
+        // "à" and "très" match whether or not the accent is typed
+        let r = remove_stop_words("À la plage tres loin", &langs(&["fr"]), "prefixNone");
+        assert_eq!(r, "plage loin");
+    }
+
+    #[test]
+    fn test_multiple_languages() {
+        let r = remove_stop_words("the chat de la house", &langs(&["en", "fr"]), "prefixNone");
+        assert_eq!(r, "chat house");
+    }
+
+    #[test]
+    fn test_other_language_words_kept() {
+        let r = remove_stop_words("the chat de la house", &langs(&["fr"]), "prefixNone");
+        assert_eq!(r, "the chat house");
+    }
+
+    #[test]
+    fn test_prefix_last_preserves_last_word_french() {
+        let r = remove_stop_words("chat de la", &langs(&["fr"]), "prefixLast");
+        assert_eq!(r, "chat la");
+    }
+
+    #[test]
+    fn test_all_follows_languages() {
+        let de = vec!["de".to_string()];
+        let r = remove_stop_words_for_languages(
+            "the Hund und die Katze",
+            &RemoveStopWordsValue::All,
+            &de,
+            "prefixNone",
+        );
+        assert_eq!(r, "the Hund Katze");
+        let r = remove_stop_words_for_languages(
+            "the Hund und die Katze",
+            &RemoveStopWordsValue::All,
+            &[],
+            "prefixNone",
+        );
+        assert_eq!(r, "Hund und die Katze");
+    }
+
+    #[test]
+    fn test_resolve_languages() {
+        let fr = vec!["fr".to_string()];
+        assert_eq!(
+            resolve_stop_word_languages(&RemoveStopWordsValue::All, &fr),
+            vec!["fr".to_string()]
+        );
+        assert_eq!(
+            resolve_stop_word_languages(&RemoveStopWordsValue::All, &[]),
+            vec!["en".to_string()]
+        );
+        assert_eq!(
+            resolve_stop_word_languages(&langs(&["es"]), &fr),
+            vec!["es".to_string()]
+        );
+        assert!(resolve_stop_word_languages(&RemoveStopWordsValue::Disabled, &fr).is_empty());
+    }
+
+    #[test]
+    fn test_custom_stop_words() {
+        let words = |w: &[&str]| w.iter().map(|s| s.to_string()).collect::<HashSet<_>>();
+        let custom = |added: &[&str], disabled: &[&str], disable_standard: bool| {
+            HashMap::from([(
+                "en".to_string(),
+                CustomStopWords {
+                    added: words(added),
+                    disabled: words(disabled),
+                    disable_standard,
+                },
+            )])
+        };
+        let remove = |query: &str, custom: &HashMap<String, CustomStopWords>| {
+            remove_stop_words_with_custom(
+                query,
+                &RemoveStopWordsValue::All,
+                &[],
+                "prefixNone",
+                custom,
+            )
+        };
+
+        assert_eq!(
+            remove(
+                "buy the cheap laptop",
+                &custom(&["buy", "cheap"], &[], false)
+            ),
+            "laptop"
+        );
+        assert_eq!(
+            remove("the who live", &custom(&[], &["who"], false)),
+            "who live"
+        );
+        assert_eq!(
+            remove("buy the laptop", &custom(&["buy"], &[], true)),
+            "the laptop"
+        );
+    }
 }
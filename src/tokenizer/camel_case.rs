@@ -0,0 +1,184 @@
+//! camelCase word splitting (`camelCaseAttributes`).
+//!
+//! Identifiers like "getUserProfile" are one word to the tokenizers. For the
+//! attributes listed in `camelCaseAttributes` the parts ("get", "User",
+//! "Profile") are indexed as well, at the identifier's position, so "user
+//! profile" finds the identifier and the following words keep their
+//! positions.
+
+use std::collections::VecDeque;
+use tantivy::tokenizer::{Token, TokenFilter, TokenStream, Tokenizer};
+
+/// Leads the indexed text of the `camelCaseAttributes`, as the analyzers
+/// see one value at a time without its attribute. A private-use character,
+/// so the tokenizers treat it as a separator.
+pub const CAMEL_CASE_MARKER: char = '\u{E000}';
+
+/// The camelCase parts of `word`, as slices of it. A part starts at each
+/// lowercase-to-uppercase change, and at the last capital of an uppercase
+/// run followed by lowercase ("XMLHttp" is "XML" + "Http"). Empty unless
+/// there are at least two parts.
+pub fn split_camel_case(word: &str) -> Vec<&str> {
+    camel_case_bounds(word)
+        .into_iter()
+        .map(|(start, end)| &word[start..end])
+        .collect()
+}
+
+/// The byte ranges of the parts [`split_camel_case`] returns.
+fn camel_case_bounds(word: &str) -> Vec<(usize, usize)> {
+    let chars: Vec<(usize, char)> = word.char_indices().collect();
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (offset, c) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_is_lower = chars.get(i + 1).is_some_and(|&(_, n)| n.is_lowercase());
+        let boundary = c.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_is_lower));
+        if boundary {
+            parts.push((start, offset));
+            start = offset;
+        }
+    }
+    if parts.is_empty() {
+        return parts;
+    }
+    parts.push((start, word.len()));
+    parts
+}
+
+/// Adds the camelCase parts of each token of a text starting with
+/// [`CAMEL_CASE_MARKER`] after it, at the same position. Must see the
+/// tokens before they are lowercased.
+#[derive(Clone, Default)]
+pub struct CamelCaseFilter;
+
+impl TokenFilter for CamelCaseFilter {
+    type Tokenizer<T: Tokenizer> = CamelCaseWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> Self::Tokenizer<T> {
+        CamelCaseWrapper { inner: tokenizer }
+    }
+}
+
+#[derive(Clone)]
+pub struct CamelCaseWrapper<T> {
+    inner: T,
+}
+
+impl<T: Tokenizer> Tokenizer for CamelCaseWrapper<T> {
+    type TokenStream<'a> = CamelCaseTokenStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        CamelCaseTokenStream {
+            inner: self.inner.token_stream(text),
+            split: text.starts_with(CAMEL_CASE_MARKER),
+            parts: VecDeque::new(),
+            token: Token::default(),
+        }
+    }
+}
+
+pub struct CamelCaseTokenStream<T> {
+    inner: T,
+    split: bool,
+    /// Parts of the current token still to emit, with their byte offsets.
+    parts: VecDeque<(usize, usize)>,
+    token: Token,
+}
+
+impl<T: TokenStream> TokenStream for CamelCaseTokenStream<T> {
+    fn advance(&mut self) -> bool {
+        if let Some((start, end)) = self.parts.pop_front() {
+            let word = self.inner.token();
+            self.token.text.clear();
+            self.token.text.push_str(&word.text[start..end]);
+            self.token.offset_from = word.offset_from + start;
+            self.token.offset_to = word.offset_from + end;
+            return true;
+        }
+        if !self.inner.advance() {
+            return false;
+        }
+        self.token.clone_from(self.inner.token());
+        if self.split {
+            self.parts.extend(camel_case_bounds(&self.token.text));
+        }
+        true
+    }
+
+    fn token(&self) -> &Token {
+        &self.token
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_camel_case() {
+        assert_eq!(
+            split_camel_case("getUserProfile"),
+            vec!["get", "User", "Profile"]
+        );
+        assert_eq!(split_camel_case("UserProfile"), vec!["User", "Profile"]);
+        assert_eq!(
+            split_camel_case("XMLHttpRequest"),
+            vec!["XML", "Http", "Request"]
+        );
+        assert_eq!(split_camel_case("parse2Json"), vec!["parse2", "Json"]);
+    }
+
+    #[test]
+    fn plain_words_are_not_split() {
+        assert!(split_camel_case("profile").is_empty());
+        assert!(split_camel_case("Profile").is_empty());
+        assert!(split_camel_case("HTML").is_empty());
+        assert!(split_camel_case("").is_empty());
+    }
+
+    fn tokens(text: &str) -> Vec<(String, usize)> {
+        let mut analyzer = tantivy::tokenizer::TextAnalyzer::builder(
+            tantivy::tokenizer::SimpleTokenizer::default(),
+        )
+        .filter(CamelCaseFilter)
+        .filter(tantivy::tokenizer::LowerCaser)
+        .build();
+        let mut stream = analyzer.token_stream(text);
+        let mut tokens = Vec::new();
+        while let Some(token) = stream.next() {
+            tokens.push((token.text.clone(), token.position));
+        }
+        tokens
+    }
+
+    #[test]
+    fn parts_share_the_position_of_their_word() {
+        let text = format!("{}call getUserProfile now", CAMEL_CASE_MARKER);
+        assert_eq!(
+            tokens(&text),
+            vec![
+                ("call".to_string(), 0),
+                ("getuserprofile".to_string(), 1),
+                ("get".to_string(), 1),
+                ("user".to_string(), 1),
+                ("profile".to_string(), 1),
+                ("now".to_string(), 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn unmarked_text_is_not_split() {
+        assert_eq!(
+            tokens("call getUserProfile"),
+            vec![("call".to_string(), 0), ("getuserprofile".to_string(), 1)]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! Dictionary-based word segmentation for Chinese and Japanese.
+//!
+//! Without a segmenter every CJK character is its own token. With
+//! `indexLanguages: ["zh"]` or `["ja"]`, runs of CJK characters are split into
+//! the most probable sequence of words from an embedded word-frequency
+//! dictionary (`package/segmentation-{zh,ja}.json`). Characters the dictionary
+//! doesn't cover still come out one per word, so the unigram behaviour remains
+//! the fallback.
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CjkSegmenter {
+    Chinese,
+    Japanese,
+}
+
+struct Dictionary {
+    /// Log probability of each word.
+    log_prob: HashMap<String, f64>,
+    /// Longest entry, in characters.
+    max_chars: usize,
+    /// Log probability of a character the dictionary doesn't know.
+    unknown: f64,
+}
+
+static CHINESE: OnceLock<Dictionary> = OnceLock::new();
+static JAPANESE: OnceLock<Dictionary> = OnceLock::new();
+
+fn load_dictionary(json_str: &str) -> Dictionary {
+    let raw: HashMap<String, u64> =
+        serde_json::from_str(json_str).expect("invalid segmentation dictionary");
+    let log_total = (raw.values().sum::<u64>().max(1) as f64).ln();
+    let max_chars = raw.keys().map(|w| w.chars().count()).max().unwrap_or(1);
+    let log_prob = raw
+        .into_iter()
+        .filter(|(_, freq)| *freq > 0)
+        .map(|(word, freq)| (word, (freq as f64).ln() - log_total))
+        .collect();
+    Dictionary {
+        log_prob,
+        max_chars,
+        unknown: -log_total,
+    }
+}
+
+impl CjkSegmenter {
+    /// The segmenter for the first of `languages` that has one.
+    pub fn for_languages(languages: &[String]) -> Option<Self> {
+        languages.iter().find_map(|lang| match lang.as_str() {
+            "zh" => Some(CjkSegmenter::Chinese),
+            "ja" => Some(CjkSegmenter::Japanese),
+            _ => None,
+        })
+    }
+
+    fn dictionary(&self) -> &'static Dictionary {
+        match self {
+            CjkSegmenter::Chinese => CHINESE.get_or_init(|| {
+                load_dictionary(include_str!("../../package/segmentation-zh.json"))
+            }),
+            CjkSegmenter::Japanese => JAPANESE.get_or_init(|| {
+                load_dictionary(include_str!("../../package/segmentation-ja.json"))
+            }),
+        }
+    }
+
+    /// Byte spans of the words of `run`, a run of CJK characters, chosen to
+    /// maximise the product of the word probabilities.
+    pub fn segment(&self, run: &str) -> Vec<(usize, usize)> {
+        let dict = self.dictionary();
+        let bounds: Vec<usize> = run
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(run.len()))
+            .collect();
+        let n = bounds.len() - 1;
+
+        // best[i]: score of the best segmentation of the suffix starting at
+        // character i, and the end of its first word
+        let mut best = vec![(0.0_f64, n); n + 1];
+        for i in (0..n).rev() {
+            let mut choice = (f64::NEG_INFINITY, i + 1);
+            for j in i + 1..=n.min(i + dict.max_chars) {
+                let score = match dict.log_prob.get(&run[bounds[i]..bounds[j]]) {
+                    Some(score) => *score,
+                    None if j == i + 1 => dict.unknown,
+                    None => continue,
+                };
+                if score + best[j].0 > choice.0 {
+                    choice = (score + best[j].0, j);
+                }
+            }
+            best[i] = choice;
+        }
+
+        let mut spans = Vec::new();
+        let mut i = 0;
+        while i < n {
+            let j = best[i].1;
+            spans.push((bounds[i], bounds[j]));
+            i = j;
+        }
+        spans
+    }
+
+    /// Byte spans of the dictionary words strictly inside `word`, so that
+    /// "北京大学" can also be found by "大学".
+    pub fn sub_words(&self, word: &str) -> Vec<(usize, usize)> {
+        let dict = self.dictionary();
+        let bounds: Vec<usize> = word
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(word.len()))
+            .collect();
+        let n = bounds.len() - 1;
+        let mut spans = Vec::new();
+        for len in 2..n {
+            for i in 0..=n - len {
+                let (start, end) = (bounds[i], bounds[i + len]);
+                if dict.log_prob.contains_key(&word[start..end]) {
+                    spans.push((start, end));
+                }
+            }
+        }
+        spans
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(segmenter: CjkSegmenter, text: &str) -> Vec<&str> {
+        segmenter
+            .segment(text)
+            .into_iter()
+            .map(|(s, e)| &text[s..e])
+            .collect()
+    }
+
+    #[test]
+    fn segments_chinese() {
+        assert_eq!(
+            words(CjkSegmenter::Chinese, "我在北京大学读书"),
+            vec!["我", "在", "北京大学", "读书"]
+        );
+        assert_eq!(
+            words(CjkSegmenter::Chinese, "蓝牙无线耳机"),
+            vec!["蓝牙", "无线", "耳机"]
+        );
+    }
+
+    #[test]
+    fn segments_japanese() {
+        assert_eq!(
+            words(
+                CjkSegmenter::Japanese,
+                "私は東京の大学で日本語を勉強しています"
+            ),
+            vec![
+                "私",
+                "は",
+                "東京",
+                "の",
+                "大学",
+                "で",
+                "日本語",
+                "を",
+                "勉強して",
+                "います"
+            ]
+        );
+        assert_eq!(
+            words(CjkSegmenter::Japanese, "ラーメンを食べます"),
+            vec!["ラーメン", "を", "食べます"]
+        );
+    }
+
+    #[test]
+    fn unknown_characters_fall_back_to_unigrams() {
+        assert_eq!(words(CjkSegmenter::Chinese, "龘靐"), vec!["龘", "靐"]);
+        assert!(CjkSegmenter::Chinese.segment("").is_empty());
+    }
+
+    #[test]
+    fn sub_words_of_compounds() {
+        let word = "北京大学";
+        let subs: Vec<&str> = CjkSegmenter::Chinese
+            .sub_words(word)
+            .into_iter()
+            .map(|(s, e)| &word[s..e])
+            .collect();
+        assert_eq!(subs, vec!["北京", "大学"]);
+    }
+
+    #[test]
+    fn selected_by_index_languages() {
+        let langs = |l: &[&str]| l.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            CjkSegmenter::for_languages(&langs(&["en", "ja"])),
+            Some(CjkSegmenter::Japanese)
+        );
+        assert_eq!(
+            CjkSegmenter::for_languages(&langs(&["zh"])),
+            Some(CjkSegmenter::Chinese)
+        );
+        assert_eq!(CjkSegmenter::for_languages(&langs(&["en", "ko"])), None);
+    }
+}
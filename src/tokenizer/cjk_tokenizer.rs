@@ -1,9 +1,36 @@
+use super::cjk_segmenter::CjkSegmenter;
+use super::separators::IndexedSeparators;
+use std::sync::{Arc, RwLock};
 use tantivy::tokenizer::{Token, TokenStream, Tokenizer};
 
 #[derive(Clone, Default)]
 pub struct CjkAwareTokenizer;
 
-fn is_cjk(c: char) -> bool {
+/// [`CjkAwareTokenizer`] that splits CJK runs into dictionary words instead
+/// of single characters when the owning index has a segmenter configured.
+///
+/// Words containing other dictionary words also emit those at the same
+/// position, so "北京大学" is found by both "北京大学" and "大学". Characters
+/// in `separatorsToIndex` are kept inside words, so "c++" stays one word.
+#[derive(Clone, Default)]
+pub struct SegmentedCjkTokenizer {
+    segmenter: Arc<RwLock<Option<CjkSegmenter>>>,
+    separators: Arc<RwLock<IndexedSeparators>>,
+}
+
+impl SegmentedCjkTokenizer {
+    pub fn new(
+        segmenter: Arc<RwLock<Option<CjkSegmenter>>>,
+        separators: Arc<RwLock<IndexedSeparators>>,
+    ) -> Self {
+        SegmentedCjkTokenizer {
+            segmenter,
+            separators,
+        }
+    }
+}
+
+pub(crate) fn is_cjk(c: char) -> bool {
     matches!(c,
         '\u{4E00}'..='\u{9FFF}' |
         '\u{3400}'..='\u{4DBF}' |
@@ -54,112 +81,169 @@ impl Tokenizer for CjkAwareTokenizer {
     type TokenStream<'a> = CjkAwareTokenStream;
 
     fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
-        let mut tokens = Vec::new();
-        let mut position = 0;
-        let mut chars = text.char_indices().peekable();
-
-        let mut pending_concat: Option<(usize, String)> = None;
-        let mut pending_parts: usize = 0;
-        let mut saw_separator = false;
-
-        while let Some(&(byte_offset, c)) = chars.peek() {
-            if is_cjk(c) {
-                if let Some((concat_start, concat_text)) = pending_concat.take() {
-                    if pending_parts >= 2 && concat_text.len() >= 3 {
-                        tokens.push(Token {
-                            offset_from: concat_start,
-                            offset_to: byte_offset,
-                            position,
-                            text: concat_text,
-                            ..Default::default()
-                        });
-                        position += 1;
-                    }
+        CjkAwareTokenStream {
+            tokens: tokenize(text, None, &IndexedSeparators::default()),
+            index: 0,
+        }
+    }
+}
+
+impl Tokenizer for SegmentedCjkTokenizer {
+    type TokenStream<'a> = CjkAwareTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        let segmenter = *self.segmenter.read().unwrap();
+        let separators = self.separators.read().unwrap();
+        CjkAwareTokenStream {
+            tokens: tokenize(text, segmenter, &separators),
+            index: 0,
+        }
+    }
+}
+
+fn tokenize(
+    text: &str,
+    segmenter: Option<CjkSegmenter>,
+    separators: &IndexedSeparators,
+) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    let mut chars = text.char_indices().peekable();
+
+    let mut pending_concat: Option<(usize, String)> = None;
+    let mut pending_parts: usize = 0;
+    let mut saw_separator = false;
+
+    while let Some(&(byte_offset, c)) = chars.peek() {
+        if is_cjk(c) {
+            if let Some((concat_start, concat_text)) = pending_concat.take() {
+                if pending_parts >= 2 && concat_text.len() >= 3 {
+                    tokens.push(Token {
+                        offset_from: concat_start,
+                        offset_to: byte_offset,
+                        position,
+                        text: concat_text,
+                        ..Default::default()
+                    });
+                    position += 1;
                 }
-                pending_parts = 0;
-                saw_separator = false;
-
-                let len = c.len_utf8();
-                tokens.push(Token {
-                    offset_from: byte_offset,
-                    offset_to: byte_offset + len,
-                    position,
-                    text: c.to_string(),
-                    ..Default::default()
-                });
-                position += 1;
-                chars.next();
-            } else if c.is_alphanumeric() {
-                let start = byte_offset;
+            }
+            pending_parts = 0;
+            saw_separator = false;
+
+            if let Some(segmenter) = segmenter {
                 let mut end = byte_offset;
-                let mut word = String::new();
                 while let Some(&(bi, ci)) = chars.peek() {
-                    if ci.is_alphanumeric() && !is_cjk(ci) {
-                        end = bi + ci.len_utf8();
-                        word.push(ci);
-                        chars.next();
-                    } else {
+                    if !is_cjk(ci) {
                         break;
                     }
+                    end = bi + ci.len_utf8();
+                    chars.next();
                 }
-
-                tokens.push(Token {
-                    offset_from: start,
-                    offset_to: end,
-                    position,
-                    text: text[start..end].to_string(),
-                    ..Default::default()
-                });
-                position += 1;
-
-                if saw_separator {
-                    if let Some((_, ref mut concat_text)) = pending_concat {
-                        concat_text.push_str(&word);
-                        pending_parts += 1;
-                    }
-                } else if pending_concat.is_none() {
-                    pending_concat = Some((start, word.clone()));
-                    pending_parts = 1;
-                }
-                saw_separator = false;
-            } else if is_intra_word_separator(c) {
-                saw_separator = true;
-                chars.next();
-            } else {
-                if let Some((concat_start, concat_text)) = pending_concat.take() {
-                    if pending_parts >= 2 && concat_text.len() >= 3 {
+                let run = &text[byte_offset..end];
+                for (word_start, word_end) in segmenter.segment(run) {
+                    let word = &run[word_start..word_end];
+                    tokens.push(Token {
+                        offset_from: byte_offset + word_start,
+                        offset_to: byte_offset + word_end,
+                        position,
+                        text: word.to_string(),
+                        ..Default::default()
+                    });
+                    for (sub_start, sub_end) in segmenter.sub_words(word) {
                         tokens.push(Token {
-                            offset_from: concat_start,
-                            offset_to: byte_offset,
+                            offset_from: byte_offset + word_start + sub_start,
+                            offset_to: byte_offset + word_start + sub_end,
                             position,
-                            text: concat_text,
+                            text: word[sub_start..sub_end].to_string(),
                             ..Default::default()
                         });
-                        position += 1;
                     }
+                    position += 1;
                 }
-                pending_parts = 0;
-                saw_separator = false;
-                chars.next();
+                continue;
             }
-        }
 
-        if let Some((concat_start, concat_text)) = pending_concat.take() {
-            if pending_parts >= 2 && concat_text.len() >= 3 {
-                tokens.push(Token {
-                    offset_from: concat_start,
-                    offset_to: text.len(),
-                    position,
-                    text: concat_text,
-                    ..Default::default()
-                });
-                #[allow(unused_assignments)]
-                {
+            let len = c.len_utf8();
+            tokens.push(Token {
+                offset_from: byte_offset,
+                offset_to: byte_offset + len,
+                position,
+                text: c.to_string(),
+                ..Default::default()
+            });
+            position += 1;
+            chars.next();
+        } else if separators.is_word_char(c) {
+            let start = byte_offset;
+            let mut end = byte_offset;
+            let mut word = String::new();
+            while let Some(&(bi, ci)) = chars.peek() {
+                if separators.is_word_char(ci) && !is_cjk(ci) {
+                    end = bi + ci.len_utf8();
+                    word.push(ci);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+
+            tokens.push(Token {
+                offset_from: start,
+                offset_to: end,
+                position,
+                text: text[start..end].to_string(),
+                ..Default::default()
+            });
+            position += 1;
+
+            if saw_separator {
+                if let Some((_, ref mut concat_text)) = pending_concat {
+                    concat_text.push_str(&word);
+                    pending_parts += 1;
+                }
+            } else if pending_concat.is_none() {
+                pending_concat = Some((start, word.clone()));
+                pending_parts = 1;
+            }
+            saw_separator = false;
+        } else if is_intra_word_separator(c) {
+            saw_separator = true;
+            chars.next();
+        } else {
+            if let Some((concat_start, concat_text)) = pending_concat.take() {
+                if pending_parts >= 2 && concat_text.len() >= 3 {
+                    tokens.push(Token {
+                        offset_from: concat_start,
+                        offset_to: byte_offset,
+                        position,
+                        text: concat_text,
+                        ..Default::default()
+                    });
                     position += 1;
                 }
             }
+            pending_parts = 0;
+            saw_separator = false;
+            chars.next();
         }
+    }
 
-        CjkAwareTokenStream { tokens, index: 0 }
+    if let Some((concat_start, concat_text)) = pending_concat.take() {
+        if pending_parts >= 2 && concat_text.len() >= 3 {
+            tokens.push(Token {
+                offset_from: concat_start,
+                offset_to: text.len(),
+                position,
+                text: concat_text,
+                ..Default::default()
+            });
+            #[allow(unused_assignments)]
+            {
+                position += 1;
+            }
+        }
     }
+
+    tokens
 }
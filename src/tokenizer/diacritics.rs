@@ -0,0 +1,214 @@
+//! Unicode normalization and diacritic folding.
+//!
+//! "Café", "cafe\u{301}" and "CAFE" all fold to "cafe", so accented and
+//! unaccented spellings match each other. The same folding is applied by the
+//! indexing analyzers, the query parser and the highlighter. Characters listed
+//! in the `keepDiacriticsOnCharacters` setting are left as they are.
+//!
+//! Only scripts below U+3000 are folded: kana voicing marks and Hangul
+//! syllables decompose canonically too, but stripping them changes the word.
+
+use std::sync::{Arc, RwLock};
+use tantivy::tokenizer::{Token, TokenFilter, TokenStream, Tokenizer};
+use unicode_normalization::char::{decompose_canonical, is_combining_mark};
+use unicode_normalization::UnicodeNormalization;
+
+/// Characters exempt from folding, lowercased and NFC-normalized.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeepDiacritics(Vec<char>);
+
+impl KeepDiacritics {
+    /// Parse a `keepDiacriticsOnCharacters` value such as `"øé"`.
+    pub fn parse(chars: &str) -> Self {
+        let mut keep: Vec<char> = chars
+            .nfc()
+            .flat_map(char::to_lowercase)
+            .filter(|c| !c.is_whitespace() && *c != ',')
+            .collect();
+        keep.sort_unstable();
+        keep.dedup();
+        KeepDiacritics(keep)
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.0.binary_search(&c).is_ok()
+    }
+}
+
+/// Lowercase and fold `text`.
+pub fn fold(text: &str, keep: &KeepDiacritics) -> String {
+    let mut out = String::with_capacity(text.len());
+    fold_into(text, keep, &mut out);
+    out
+}
+
+/// Lowercase and fold `text`, recording for every byte of the folded string
+/// the byte span of the original character(s) it came from.
+pub fn fold_with_offsets(text: &str, keep: &KeepDiacritics) -> (String, Vec<(usize, usize)>) {
+    let mut out = String::with_capacity(text.len());
+    let mut spans = Vec::with_capacity(text.len());
+    for_each_cluster(text, |start, end, cluster| {
+        fold_cluster(cluster, keep, &mut out);
+        spans.resize(out.len(), (start, end));
+    });
+    (out, spans)
+}
+
+fn fold_into(text: &str, keep: &KeepDiacritics, out: &mut String) {
+    if text.is_ascii() {
+        out.extend(text.chars().map(|c| c.to_ascii_lowercase()));
+        return;
+    }
+    for_each_cluster(text, |_, _, cluster| fold_cluster(cluster, keep, out));
+}
+
+/// Call `f` with each base character and its trailing combining marks.
+fn for_each_cluster(text: &str, mut f: impl FnMut(usize, usize, &str)) {
+    let mut chars = text.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut end = start + c.len_utf8();
+        while let Some(&(i, m)) = chars.peek() {
+            if !is_combining_mark(m) {
+                break;
+            }
+            end = i + m.len_utf8();
+            chars.next();
+        }
+        f(start, end, &text[start..end]);
+    }
+}
+
+fn fold_cluster(cluster: &str, keep: &KeepDiacritics, out: &mut String) {
+    for c in cluster.nfc().flat_map(char::to_lowercase) {
+        if c.is_ascii() || c >= '\u{3000}' || keep.contains(c) {
+            out.push(c);
+            continue;
+        }
+        match c {
+            'ß' => out.push_str("ss"),
+            'æ' => out.push_str("ae"),
+            'œ' => out.push_str("oe"),
+            'þ' => out.push_str("th"),
+            'ø' => out.push('o'),
+            'đ' | 'ð' => out.push('d'),
+            'ł' => out.push('l'),
+            'ı' => out.push('i'),
+            _ => decompose_canonical(c, |d| {
+                if !is_combining_mark(d) {
+                    out.push(d);
+                }
+            }),
+        }
+    }
+}
+
+/// Token filter folding token text; offsets keep pointing at the original text.
+///
+/// The exempt characters are shared with the owning [`crate::index::Index`] so
+/// a settings change applies to documents indexed afterwards.
+#[derive(Clone, Default)]
+pub struct DiacriticFoldingFilter {
+    keep: Arc<RwLock<KeepDiacritics>>,
+}
+
+impl DiacriticFoldingFilter {
+    pub fn new(keep: Arc<RwLock<KeepDiacritics>>) -> Self {
+        DiacriticFoldingFilter { keep }
+    }
+}
+
+impl TokenFilter for DiacriticFoldingFilter {
+    type Tokenizer<T: Tokenizer> = DiacriticFoldingWrapper<T>;
+
+    fn transform<T: Tokenizer>(self, tokenizer: T) -> Self::Tokenizer<T> {
+        DiacriticFoldingWrapper {
+            inner: tokenizer,
+            keep: self.keep,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DiacriticFoldingWrapper<T> {
+    inner: T,
+    keep: Arc<RwLock<KeepDiacritics>>,
+}
+
+impl<T: Tokenizer> Tokenizer for DiacriticFoldingWrapper<T> {
+    type TokenStream<'a> = DiacriticFoldingTokenStream<T::TokenStream<'a>>;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        DiacriticFoldingTokenStream {
+            inner: self.inner.token_stream(text),
+            keep: self.keep.read().unwrap().clone(),
+            buffer: String::new(),
+        }
+    }
+}
+
+pub struct DiacriticFoldingTokenStream<T> {
+    inner: T,
+    keep: KeepDiacritics,
+    buffer: String,
+}
+
+impl<T: TokenStream> TokenStream for DiacriticFoldingTokenStream<T> {
+    fn advance(&mut self) -> bool {
+        if !self.inner.advance() {
+            return false;
+        }
+        let token = self.inner.token_mut();
+        if !token.text.is_ascii() {
+            self.buffer.clear();
+            fold_into(&token.text, &self.keep, &mut self.buffer);
+            std::mem::swap(&mut token.text, &mut self.buffer);
+        }
+        true
+    }
+
+    fn token(&self) -> &Token {
+        self.inner.token()
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        self.inner.token_mut()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_accents_case_and_decomposed_input() {
+        let keep = KeepDiacritics::default();
+        assert_eq!(fold("Café", &keep), "cafe");
+        assert_eq!(fold("cafe\u{301}", &keep), "cafe");
+        assert_eq!(fold("Müller Straße", &keep), "muller strasse");
+        assert_eq!(fold("Œuvre Ærø Łódź", &keep), "oeuvre aero lodz");
+        // CJK, kana and Hangul are untouched
+        assert_eq!(fold("東京 がっこう 한국", &keep), "東京 がっこう 한국");
+    }
+
+    #[test]
+    fn keeps_exempt_characters() {
+        let keep = KeepDiacritics::parse("ØÉ");
+        assert!(keep.contains('ø') && keep.contains('é'));
+        assert_eq!(fold("Øre Élan Ève", &keep), "øre élan eve");
+        assert_eq!(fold("e\u{301}lan", &keep), "élan");
+    }
+
+    #[test]
+    fn offsets_point_at_original_characters() {
+        let text = "Straße Cafe\u{301}";
+        let (folded, spans) = fold_with_offsets(text, &KeepDiacritics::default());
+        assert_eq!(folded, "strasse cafe");
+        assert_eq!(spans.len(), folded.len());
+        // Both 's' of "ss" map back to 'ß'
+        assert_eq!(spans[4], (4, 6));
+        assert_eq!(spans[5], (4, 6));
+        // The 'e' carries its combining accent
+        let e = folded.len() - 1;
+        assert_eq!(&text[spans[e].0..spans[e].1], "e\u{301}");
+    }
+}
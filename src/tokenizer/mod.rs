@@ -1,4 +1,12 @@
+pub mod camel_case;
+pub mod cjk_segmenter;
 pub mod cjk_tokenizer;
+pub mod diacritics;
 pub mod edge_ngram_filter;
-pub use cjk_tokenizer::CjkAwareTokenizer;
+pub mod separators;
+pub use camel_case::CamelCaseFilter;
+pub use cjk_segmenter::CjkSegmenter;
+pub use cjk_tokenizer::{CjkAwareTokenizer, SegmentedCjkTokenizer};
+pub use diacritics::{DiacriticFoldingFilter, KeepDiacritics};
 pub use edge_ngram_filter::EdgeNgramTokenFilter;
+pub use separators::IndexedSeparators;
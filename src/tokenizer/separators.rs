@@ -0,0 +1,57 @@
+//! Separators indexed as part of words (`separatorsToIndex`).
+//!
+//! Punctuation normally splits words, so "c++" and "c#" are indexed as "c"
+//! and ".net" as "net". Characters listed in `separatorsToIndex` are kept
+//! inside words instead, by the indexing analyzers and the query parser alike.
+
+/// Characters treated as word characters, sorted and deduplicated.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexedSeparators(Vec<char>);
+
+impl IndexedSeparators {
+    /// Parse a `separatorsToIndex` value such as `"+#."`. Letters, digits and
+    /// whitespace are already handled by the tokenizers and are ignored.
+    pub fn parse(chars: &str) -> Self {
+        let mut separators: Vec<char> = chars
+            .chars()
+            .filter(|c| !c.is_alphanumeric() && !c.is_whitespace())
+            .collect();
+        separators.sort_unstable();
+        separators.dedup();
+        IndexedSeparators(separators)
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        self.0.binary_search(&c).is_ok()
+    }
+
+    /// Whether `c` belongs to a word: alphanumeric or an indexed separator.
+    pub fn is_word_char(&self, c: char) -> bool {
+        c.is_alphanumeric() || self.contains(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_separators() {
+        let separators = IndexedSeparators::parse("+ #.+a1");
+        assert!(separators.contains('+'));
+        assert!(separators.contains('#'));
+        assert!(separators.contains('.'));
+        assert!(!separators.contains('a'));
+        assert!(!separators.contains(' '));
+        assert!(!separators.contains('-'));
+    }
+
+    #[test]
+    fn word_chars() {
+        let separators = IndexedSeparators::parse("+");
+        assert!(separators.is_word_char('c'));
+        assert!(separators.is_word_char('+'));
+        assert!(!separators.is_word_char('#'));
+        assert!(!IndexedSeparators::default().is_word_char('+'));
+    }
+}
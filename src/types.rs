@@ -250,6 +250,11 @@ pub struct FacetCount {
 pub struct ScoredDocument {
     pub document: Document,
     pub score: f32,
+    /// Word distance between the query terms (`_rankingInfo.proximityDistance`).
+    pub proximity_distance: u32,
+    /// Cosine similarity to the query vector, for hits found by vector search
+    /// (`_rankingInfo.semanticScore`).
+    pub semantic_score: Option<f32>,
 }
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TaskInfo {
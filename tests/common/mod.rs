@@ -3,6 +3,7 @@ use axum::{
     routing::{delete, get, post},
     Router,
 };
+use serde_json::{json, Value};
 use std::sync::Arc;
 use tempfile::TempDir;
 use tokio::net::TcpListener;
@@ -17,17 +18,33 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
     let manager = flapjack::IndexManager::new(temp_dir.path());
 
     let key_store = admin_key.map(|k| {
-        Arc::new(flapjack_http::auth::KeyStore::load_or_create(
-            temp_dir.path(),
-            k,
-        ))
+        Arc::new(flapjack_http::auth::KeyStore::load_or_create(temp_dir.path(), k).unwrap())
     });
 
+    let ab_tests = Arc::new(flapjack::analytics::AbTestStore::load(temp_dir.path()));
+    let personalization = Arc::new(flapjack::analytics::PersonalizationStore::load(
+        temp_dir.path(),
+    ));
+    let analytics_config = flapjack::analytics::AnalyticsConfig {
+        enabled: true,
+        data_dir: temp_dir.path().join("analytics"),
+        flush_interval_secs: 3600,
+        flush_size: 10_000,
+        retention_days: 90,
+    };
+    let analytics_collector =
+        flapjack::analytics::AnalyticsCollector::new(analytics_config.clone());
+    let analytics_engine = Arc::new(flapjack::analytics::AnalyticsQueryEngine::new(
+        analytics_config,
+    ));
+
     let state = Arc::new(flapjack_http::handlers::AppState {
         manager,
         key_store: key_store.clone(),
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: Some(ab_tests.clone()),
+        personalization: Some(personalization.clone()),
     });
 
     let key_routes = if let Some(ref ks) = key_store {
@@ -62,6 +79,10 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
             "/1/indexes/:indexName/batch",
             post(flapjack_http::handlers::add_documents),
         )
+        .route(
+            "/1/indexes/:indexName/bulk",
+            post(flapjack_http::handlers::bulk::bulk_import),
+        )
         .route(
             "/1/indexes/:indexName/query",
             post(flapjack_http::handlers::search),
@@ -151,6 +172,46 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
             "/1/indexes/:indexName/operation",
             post(flapjack_http::handlers::operation_index),
         )
+        .route(
+            "/1/indexes/:indexName/migrate",
+            post(flapjack_http::handlers::migrate_index),
+        )
+        .route(
+            "/1/indexes/:indexName/export",
+            get(flapjack_http::handlers::snapshot::export_snapshot),
+        )
+        .route(
+            "/1/indexes/:indexName/import",
+            post(flapjack_http::handlers::snapshot::import_snapshot),
+        )
+        .route(
+            "/1/indexes/:indexName/dump",
+            get(flapjack_http::handlers::dump::export_dump)
+                .post(flapjack_http::handlers::dump::import_dump),
+        )
+        .route(
+            "/1/dictionaries/:dictionaryName/batch",
+            post(flapjack_http::handlers::dictionaries::batch_dictionary_entries),
+        )
+        .route(
+            "/1/dictionaries/:dictionaryName/search",
+            post(flapjack_http::handlers::dictionaries::search_dictionary_entries),
+        )
+        .route(
+            "/1/dictionaries/:dictionaryName/settings",
+            get(flapjack_http::handlers::dictionaries::get_dictionary_settings)
+                .put(flapjack_http::handlers::dictionaries::set_dictionary_settings),
+        )
+        .route(
+            "/1/aliases",
+            get(flapjack_http::handlers::aliases::list_aliases),
+        )
+        .route(
+            "/1/aliases/:alias",
+            get(flapjack_http::handlers::aliases::get_alias)
+                .put(flapjack_http::handlers::aliases::set_alias)
+                .delete(flapjack_http::handlers::aliases::delete_alias),
+        )
         .route("/1/tasks/:task_id", get(flapjack_http::handlers::get_task))
         .route(
             "/1/indexes/:indexName/task/:task_id",
@@ -158,6 +219,50 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
         )
         .with_state(state.clone());
 
+    let ab_testing_state = Arc::new(flapjack_http::handlers::ab_testing::AbTestingState {
+        manager: state.manager.clone(),
+        engine: analytics_engine,
+        store: ab_tests,
+    });
+    let ab_testing_routes = Router::new()
+        .route(
+            "/2/abtests",
+            get(flapjack_http::handlers::ab_testing::list_ab_tests)
+                .post(flapjack_http::handlers::ab_testing::create_ab_test),
+        )
+        .route(
+            "/2/abtests/:id",
+            get(flapjack_http::handlers::ab_testing::get_ab_test)
+                .delete(flapjack_http::handlers::ab_testing::delete_ab_test),
+        )
+        .route(
+            "/2/abtests/:id/stop",
+            post(flapjack_http::handlers::ab_testing::stop_ab_test),
+        )
+        .with_state(ab_testing_state);
+
+    let insights_state = Arc::new(flapjack_http::handlers::insights::InsightsState {
+        collector: analytics_collector,
+        manager: state.manager.clone(),
+        personalization: personalization.clone(),
+    });
+    let insights_routes = Router::new()
+        .route(
+            "/1/events",
+            post(flapjack_http::handlers::insights::post_events),
+        )
+        .with_state(insights_state);
+    let personalization_routes = Router::new()
+        .route(
+            "/1/profiles/personalization/:userToken",
+            get(flapjack_http::handlers::personalization::get_user_profile),
+        )
+        .route(
+            "/1/profiles/:userToken",
+            delete(flapjack_http::handlers::personalization::delete_user_profile),
+        )
+        .with_state(personalization);
+
     let quickstart = Router::new()
         .route(
             "/indexes",
@@ -198,16 +303,19 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
             "/migrate",
             post(flapjack_http::handlers::quickstart::qs_migrate),
         )
-        .with_state(state);
+        .with_state(state.clone());
 
     let ks_for_middleware = key_store.clone();
+    let aliases_for_middleware = Arc::clone(state.manager.aliases());
     let auth_middleware = middleware::from_fn(
         move |mut request: axum::extract::Request, next: middleware::Next| {
             let ks = ks_for_middleware.clone();
+            let aliases = aliases_for_middleware.clone();
             async move {
                 if let Some(ref store) = ks {
                     request.extensions_mut().insert(store.clone());
                 }
+                request.extensions_mut().insert(aliases);
                 flapjack_http::auth::authenticate_and_authorize(request, next).await
             }
         },
@@ -217,6 +325,9 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
         .merge(health_route)
         .merge(key_routes)
         .merge(protected)
+        .merge(ab_testing_routes)
+        .merge(insights_routes)
+        .merge(personalization_routes)
         .layer(auth_middleware)
         .merge(quickstart);
 
@@ -231,3 +342,127 @@ pub async fn spawn_server_with_key(admin_key: Option<&str>) -> (String, TempDir)
 
     (addr, temp_dir)
 }
+
+/// Send `body` to `url` with the API key `key`, without a body if `body`
+/// is `Null`. Returns the status and the JSON response, `Null` if there is
+/// none. Servers without an admin key accept any key, e.g. "test".
+#[allow(dead_code)]
+pub async fn send(
+    client: &reqwest::Client,
+    method: reqwest::Method,
+    url: String,
+    key: &str,
+    body: Value,
+) -> (u16, Value) {
+    let mut request = client
+        .request(method, url)
+        .header("x-algolia-api-key", key)
+        .header("x-algolia-application-id", "test");
+    if !body.is_null() {
+        request = request.json(&body);
+    }
+    let resp = request.send().await.unwrap();
+    let status = resp.status().as_u16();
+    (status, resp.json().await.unwrap_or(Value::Null))
+}
+
+/// Poll the task `task_id` until it is published, and return it.
+#[allow(dead_code)]
+pub async fn wait_for_task(client: &reqwest::Client, addr: &str, task_id: &Value) -> Value {
+    for _ in 0..50 {
+        let (_, task) = send(
+            client,
+            reqwest::Method::GET,
+            format!("http://{}/1/tasks/{}", addr, task_id),
+            "test",
+            Value::Null,
+        )
+        .await;
+        if task["status"] == "published" {
+            return task;
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    }
+    panic!("task {} was not published", task_id);
+}
+
+/// Search `index` for `query` without typo tolerance.
+#[allow(dead_code)]
+pub async fn search(client: &reqwest::Client, addr: &str, index: &str, query: &str) -> Value {
+    let (status, body) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/query", addr, index),
+        "test",
+        json!({"query": query, "typoTolerance": false}),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    body
+}
+
+/// The sorted objectIDs of a search response's hits.
+#[allow(dead_code)]
+pub fn ids(body: &Value) -> Vec<String> {
+    let mut ids: Vec<String> = body["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|h| h["objectID"].as_str().unwrap().to_string())
+        .collect();
+    ids.sort();
+    ids
+}
+
+/// The highlighted `name` of the hit `object_id`.
+#[allow(dead_code)]
+pub fn highlighted_name(body: &Value, object_id: &str) -> String {
+    body["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|h| h["objectID"] == object_id)
+        .map(|h| {
+            h["_highlightResult"]["name"]["value"]
+                .as_str()
+                .unwrap()
+                .to_string()
+        })
+        .unwrap()
+}
+
+/// Add one record per name to `index`, with objectIDs "1", "2", ...
+#[allow(dead_code)]
+pub async fn setup(client: &reqwest::Client, addr: &str, index: &str, names: &[&str]) {
+    let requests: Vec<Value> = names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            json!({"action": "addObject", "body": {"objectID": (i + 1).to_string(), "name": name}})
+        })
+        .collect();
+    send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/batch", addr, index),
+        "test",
+        json!({ "requests": requests }),
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+}
+
+/// Update the settings of `index`, waiting for the records to be reindexed.
+#[allow(dead_code)]
+pub async fn set_settings(client: &reqwest::Client, addr: &str, index: &str, settings: Value) {
+    let (status, _) = send(
+        client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/indexes/{}/settings", addr, index),
+        "test",
+        settings,
+    )
+    .await;
+    assert_eq!(status, 200);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+}
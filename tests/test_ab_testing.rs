@@ -0,0 +1,255 @@
+//! A/B testing: traffic splitting in the search handler and per-variant analytics.
+
+use flapjack::analytics::config::AnalyticsConfig;
+use flapjack::analytics::query::AnalyticsQueryEngine;
+use flapjack::analytics::schema::{InsightEvent, SearchEvent};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+mod common;
+
+fn tagged_search(query_id: &str, user: &str, variant: u32) -> SearchEvent {
+    SearchEvent {
+        timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        query: "laptop".to_string(),
+        query_id: Some(query_id.to_string()),
+        index_name: "products".to_string(),
+        nb_hits: 10,
+        processing_time_ms: 2,
+        user_token: Some(user.to_string()),
+        user_ip: None,
+        filters: None,
+        facets: None,
+        analytics_tags: None,
+        page: 0,
+        hits_per_page: 20,
+        has_results: true,
+        country: None,
+        region: None,
+        ab_test_id: Some(1),
+        ab_test_variant_id: Some(variant),
+    }
+}
+
+fn insight(event_type: &str, query_id: &str, user: &str) -> InsightEvent {
+    InsightEvent {
+        event_type: event_type.to_string(),
+        event_subtype: None,
+        event_name: "Event".to_string(),
+        index: "products".to_string(),
+        user_token: user.to_string(),
+        authenticated_user_token: None,
+        query_id: Some(query_id.to_string()),
+        object_ids: vec!["obj1".to_string()],
+        object_ids_alt: vec![],
+        positions: if event_type == "click" {
+            Some(vec![1])
+        } else {
+            None
+        },
+        timestamp: Some(chrono::Utc::now().timestamp_millis()),
+        value: None,
+        currency: None,
+    }
+}
+
+#[tokio::test]
+async fn metrics_attribute_clicks_and_conversions_per_variant() {
+    let tmp = TempDir::new().unwrap();
+    let config = AnalyticsConfig {
+        enabled: true,
+        data_dir: tmp.path().to_path_buf(),
+        flush_interval_secs: 3600,
+        flush_size: 10_000,
+        retention_days: 90,
+    };
+
+    // Variant 1: 10 searches, 2 clicked. Variant 2: 10 searches, 6 clicked, 3 converted.
+    let mut searches = Vec::new();
+    let mut events = Vec::new();
+    for i in 0..10 {
+        let qid = format!("{:032x}", i);
+        searches.push(tagged_search(&qid, &format!("a{}", i % 5), 1));
+        if i < 2 {
+            events.push(insight("click", &qid, "a"));
+        }
+    }
+    for i in 10..20 {
+        let qid = format!("{:032x}", i);
+        searches.push(tagged_search(&qid, &format!("b{}", i), 2));
+        if i < 16 {
+            events.push(insight("click", &qid, "b"));
+            events.push(insight("click", &qid, "b"));
+        }
+        if i < 13 {
+            events.push(insight("conversion", &qid, "b"));
+        }
+    }
+    // A click on a search that is not part of the test is ignored
+    events.push(insight("click", &"f".repeat(32), "c"));
+
+    flapjack::analytics::writer::flush_search_events(&searches, &config.searches_dir("products"))
+        .unwrap();
+    flapjack::analytics::writer::flush_insight_events(&events, &config.events_dir("products"))
+        .unwrap();
+
+    let engine = AnalyticsQueryEngine::new(config);
+    let metrics = engine.ab_test_metrics("products", 1, 2, 0).await.unwrap();
+
+    let a = &metrics["variants"][0];
+    assert_eq!(a["variantID"], 1);
+    assert_eq!(a["searchCount"], 10);
+    assert_eq!(a["trackedSearchCount"], 10);
+    assert_eq!(a["userCount"], 5);
+    assert_eq!(a["clickCount"], 2);
+    assert_eq!(a["clickThroughRate"], 0.2);
+    assert_eq!(a["conversionRate"], 0.0);
+
+    let b = &metrics["variants"][1];
+    assert_eq!(b["variantID"], 2);
+    assert_eq!(b["clickCount"], 12);
+    assert_eq!(b["conversionCount"], 3);
+    assert_eq!(b["clickThroughRate"], 0.6);
+    assert_eq!(b["conversionRate"], 0.3);
+
+    let significance = metrics["clickSignificance"].as_f64().unwrap();
+    assert!(significance > 0.9 && significance <= 1.0);
+
+    // Another test ID has no data
+    let empty = engine.ab_test_metrics("products", 2, 2, 0).await.unwrap();
+    assert_eq!(empty["variants"][0]["searchCount"], 0);
+    assert!(empty["clickSignificance"].is_null());
+}
+
+async fn post(client: &reqwest::Client, url: String, body: Value) -> (u16, Value) {
+    let resp = client
+        .post(url)
+        .header("x-algolia-api-key", "test")
+        .header("x-algolia-application-id", "test")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    let status = resp.status().as_u16();
+    (status, resp.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn search_splits_traffic_between_settings_variants() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+
+    let settings = client
+        .put(format!("http://{}/1/indexes/products/settings", addr))
+        .header("x-algolia-api-key", "test")
+        .header("x-algolia-application-id", "test")
+        .json(&json!({"customRanking": ["desc(price)"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(settings.status(), 200);
+    post(
+        &client,
+        format!("http://{}/1/indexes/products/batch", addr),
+        json!({"requests": [
+            {"action": "addObject", "body": {"objectID": "expensive", "name": "Laptop", "price": 900}},
+            {"action": "addObject", "body": {"objectID": "cheap", "name": "Laptop", "price": 300}}
+        ]}),
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // Invalid traffic split is rejected
+    let end_at = (chrono::Utc::now() + chrono::Duration::days(7)).to_rfc3339();
+    let (status, _) = post(
+        &client,
+        format!("http://{}/2/abtests", addr),
+        json!({"name": "bad", "endAt": end_at, "variants": [
+            {"index": "products", "trafficPercentage": 50},
+            {"index": "products", "trafficPercentage": 40, "customSearchParameters": {"customRanking": ["asc(price)"]}}
+        ]}),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    let (status, created) = post(
+        &client,
+        format!("http://{}/2/abtests", addr),
+        json!({"name": "price order", "endAt": end_at, "variants": [
+            {"index": "products", "trafficPercentage": 50},
+            {"index": "products", "trafficPercentage": 50, "customSearchParameters": {"customRanking": ["asc(price)"]}}
+        ]}),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", created);
+    let ab_test_id = created["abTestID"].as_i64().unwrap();
+    assert_eq!(created["index"], "products");
+
+    let query_url = format!("http://{}/1/indexes/products/query", addr);
+    let mut seen = [false, false];
+    for i in 0..40 {
+        let user = format!("user-{}", i);
+        let (_, body) = post(
+            &client,
+            query_url.clone(),
+            json!({"query": "laptop", "userToken": user}),
+        )
+        .await;
+        assert_eq!(body["abTestID"].as_i64(), Some(ab_test_id));
+        assert_eq!(body["indexUsed"], "products");
+        let variant = body["abTestVariantID"].as_u64().unwrap();
+        let expected_first = if variant == 1 { "expensive" } else { "cheap" };
+        assert_eq!(body["hits"][0]["objectID"], expected_first, "{:?}", body);
+        seen[variant as usize - 1] = true;
+
+        // Assignment is sticky per user
+        let (_, again) = post(
+            &client,
+            query_url.clone(),
+            json!({"query": "laptop", "userToken": user}),
+        )
+        .await;
+        assert_eq!(again["abTestVariantID"].as_u64(), Some(variant));
+    }
+    assert!(seen[0] && seen[1], "both variants should receive traffic");
+
+    // enableABTest=false opts out
+    let (_, body) = post(
+        &client,
+        query_url.clone(),
+        json!({"query": "laptop", "userToken": "user-1", "enableABTest": false}),
+    )
+    .await;
+    assert!(body.get("abTestID").is_none());
+    assert_eq!(body["hits"][0]["objectID"], "expensive");
+
+    // Stopped tests no longer split traffic but keep their results
+    let (status, _) = post(
+        &client,
+        format!("http://{}/2/abtests/{}/stop", addr, ab_test_id),
+        json!({}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (_, body) = post(
+        &client,
+        query_url,
+        json!({"query": "laptop", "userToken": "user-1"}),
+    )
+    .await;
+    assert!(body.get("abTestID").is_none());
+
+    let test: Value = client
+        .get(format!("http://{}/2/abtests/{}", addr, ab_test_id))
+        .header("x-algolia-api-key", "test")
+        .header("x-algolia-application-id", "test")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(test["status"], "stopped");
+    assert_eq!(test["variants"].as_array().unwrap().len(), 2);
+    assert!(test["variants"][0].get("clickThroughRate").is_some());
+}
@@ -0,0 +1,214 @@
+//! Index aliases: a reindex into a new index is swapped in atomically behind
+//! the alias, and keys restricted to the alias name keep working.
+
+use flapjack::types::Document;
+use flapjack::IndexManager;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+use common::send;
+
+const ADMIN_KEY: &str = "test-admin-key-abc123";
+
+async fn add_doc(client: &reqwest::Client, addr: &str, index: &str, title: &str) {
+    let (status, _) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/batch", addr, index),
+        ADMIN_KEY,
+        json!({"requests": [{"action": "addObject", "body": {"objectID": "1", "title": title}}]}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+}
+
+async fn search(client: &reqwest::Client, addr: &str, index: &str, key: &str) -> (u16, Value) {
+    send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/query", addr, index),
+        key,
+        json!({"query": ""}),
+    )
+    .await
+}
+
+async fn set_alias(client: &reqwest::Client, addr: &str, alias: &str, index: &str) -> Value {
+    let (status, body) = send(
+        client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/aliases/{}", addr, alias),
+        ADMIN_KEY,
+        json!({"index": index}),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    body
+}
+
+#[tokio::test]
+async fn swapping_an_alias_replaces_a_live_index() {
+    let (addr, temp) = common::spawn_server_with_key(Some(ADMIN_KEY)).await;
+    let client = reqwest::Client::new();
+    add_doc(&client, &addr, "products", "old catalog").await;
+
+    let (status, body) = send(
+        &client,
+        reqwest::Method::POST,
+        format!("http://{}/1/keys", addr),
+        ADMIN_KEY,
+        json!({"acl": ["search"], "indexes": ["products"]}),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let restricted = body["key"].as_str().unwrap().to_string();
+
+    // Reindex into a new index, then point the production name at it
+    add_doc(&client, &addr, "products_v2", "new catalog").await;
+    let body = set_alias(&client, &addr, "products", "products_v2").await;
+    assert_eq!(body["previousIndex"], Value::Null);
+
+    let (status, body) = search(&client, &addr, "products", &restricted).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["hits"][0]["title"], "new catalog");
+    let (status, _) = search(&client, &addr, "products_v2", &restricted).await;
+    assert_eq!(status, 403, "the key is still limited to the alias");
+
+    add_doc(&client, &addr, "products_v3", "newest catalog").await;
+    let body = set_alias(&client, &addr, "products", "products_v3").await;
+    assert_eq!(body["previousIndex"], "products_v2");
+    let (_, body) = search(&client, &addr, "products", &restricted).await;
+    assert_eq!(body["hits"][0]["title"], "newest catalog");
+
+    // Only the admin key can repoint aliases
+    let (status, _) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/aliases/products", addr),
+        &restricted,
+        json!({"index": "products_v2"}),
+    )
+    .await;
+    assert_eq!(status, 403);
+
+    let (status, body) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/aliases", addr),
+        ADMIN_KEY,
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(body["items"][0]["alias"], "products");
+    assert_eq!(body["items"][0]["index"], "products_v3");
+
+    assert!(temp.path().join(".aliases").join("oplog").exists());
+
+    let (status, _) = send(
+        &client,
+        reqwest::Method::DELETE,
+        format!("http://{}/1/aliases/products", addr),
+        ADMIN_KEY,
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    // The shadowed index is visible again
+    let (_, body) = search(&client, &addr, "products", &restricted).await;
+    assert_eq!(body["hits"][0]["title"], "old catalog");
+}
+
+#[tokio::test]
+async fn invalid_aliases_are_rejected() {
+    let (addr, _temp) = common::spawn_server_with_key(Some(ADMIN_KEY)).await;
+    let client = reqwest::Client::new();
+    add_doc(&client, &addr, "products_v1", "catalog").await;
+
+    let (status, _) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/aliases/products", addr),
+        ADMIN_KEY,
+        json!({"index": "missing"}),
+    )
+    .await;
+    assert_eq!(status, 404);
+
+    set_alias(&client, &addr, "products", "products_v1").await;
+    let (status, _) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/aliases/catalog", addr),
+        ADMIN_KEY,
+        json!({"index": "products"}),
+    )
+    .await;
+    assert_eq!(status, 400, "aliases cannot point to aliases");
+}
+
+#[tokio::test]
+async fn writes_through_an_alias_reach_its_index() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("products_v1").unwrap();
+    manager.aliases().set("products", "products_v1").unwrap();
+
+    let doc = Document::from_json(&json!({"objectID": "1", "title": "lamp"})).unwrap();
+    manager
+        .add_documents_sync("products", vec![doc])
+        .await
+        .unwrap();
+
+    assert!(!temp.path().join("products").exists());
+    assert_eq!(
+        manager
+            .search("products_v1", "lamp", None, None, 10)
+            .unwrap()
+            .total,
+        1
+    );
+    assert_eq!(
+        manager
+            .search("products", "lamp", None, None, 10)
+            .unwrap()
+            .total,
+        1
+    );
+}
+
+#[tokio::test]
+async fn settings_through_an_alias_reach_its_index() {
+    let (addr, temp) = common::spawn_server_with_key(Some(ADMIN_KEY)).await;
+    let client = reqwest::Client::new();
+
+    add_doc(&client, &addr, "products_v1", "lamp").await;
+    set_alias(&client, &addr, "products", "products_v1").await;
+
+    let (status, body) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/indexes/products/settings", addr),
+        ADMIN_KEY,
+        json!({"attributesForFaceting": ["color"]}),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let (status, settings) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/indexes/products_v1/settings", addr),
+        ADMIN_KEY,
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(settings["attributesForFaceting"], json!(["color"]));
+    assert!(!temp.path().join("products").exists());
+}
@@ -33,6 +33,8 @@ fn make_search(query: &str, index: &str, query_id: Option<&str>) -> SearchEvent
         has_results: true,
         country: None,
         region: None,
+        ab_test_id: None,
+        ab_test_variant_id: None,
     }
 }
 
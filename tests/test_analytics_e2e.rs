@@ -44,6 +44,8 @@ fn search_event(
         has_results: nb_hits > 0,
         country: None,
         region: None,
+        ab_test_id: None,
+        ab_test_variant_id: None,
     }
 }
 
@@ -0,0 +1,231 @@
+//! Raw analytics access: event export (NDJSON / CSV / Parquet) and read-only SQL.
+
+use flapjack::analytics::config::AnalyticsConfig;
+use flapjack::analytics::export::{EventTable, ExportFormat, RowEncoder};
+use flapjack::analytics::query::AnalyticsQueryEngine;
+use flapjack::analytics::schema::{InsightEvent, SearchEvent};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn test_config(dir: &std::path::Path) -> AnalyticsConfig {
+    AnalyticsConfig {
+        enabled: true,
+        data_dir: dir.to_path_buf(),
+        flush_interval_secs: 3600,
+        flush_size: 10_000,
+        retention_days: 90,
+    }
+}
+
+fn search_event(query: &str, nb_hits: u32, query_id: Option<&str>) -> SearchEvent {
+    SearchEvent {
+        timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        query: query.to_string(),
+        query_id: query_id.map(|s| s.to_string()),
+        index_name: "products".to_string(),
+        nb_hits,
+        processing_time_ms: 4,
+        user_token: Some("user-1".to_string()),
+        user_ip: None,
+        filters: None,
+        facets: None,
+        analytics_tags: None,
+        page: 0,
+        hits_per_page: 20,
+        has_results: nb_hits > 0,
+        country: None,
+        region: None,
+        ab_test_id: None,
+        ab_test_variant_id: None,
+    }
+}
+
+fn click(query_id: &str) -> InsightEvent {
+    InsightEvent {
+        event_type: "click".to_string(),
+        event_subtype: None,
+        event_name: "Result Click".to_string(),
+        index: "products".to_string(),
+        user_token: "user-1".to_string(),
+        authenticated_user_token: None,
+        query_id: Some(query_id.to_string()),
+        object_ids: vec!["obj1".to_string()],
+        object_ids_alt: vec![],
+        positions: Some(vec![1]),
+        timestamp: Some(chrono::Utc::now().timestamp_millis()),
+        value: None,
+        currency: None,
+    }
+}
+
+fn today() -> String {
+    chrono::Utc::now().format("%Y-%m-%d").to_string()
+}
+
+fn setup(tmp: &TempDir) -> AnalyticsQueryEngine {
+    let config = test_config(tmp.path());
+    let qid = "a".repeat(32);
+    let mut searches = vec![
+        search_event("laptop", 12, Some(&qid)),
+        search_event("usb-c, \"fast\" charger", 3, None),
+        search_event("xyzzy", 0, None),
+    ];
+    // Distinct timestamps so the export order is deterministic
+    let now = chrono::Utc::now().timestamp_millis();
+    for (i, event) in searches.iter_mut().enumerate() {
+        event.timestamp_ms = now - 1000 + i as i64;
+    }
+    flapjack::analytics::writer::flush_search_events(&searches, &config.searches_dir("products"))
+        .unwrap();
+    flapjack::analytics::writer::flush_insight_events(
+        &[click(&qid)],
+        &config.events_dir("products"),
+    )
+    .unwrap();
+    AnalyticsQueryEngine::new(config)
+}
+
+async fn export_text(
+    engine: &AnalyticsQueryEngine,
+    table: EventTable,
+    format: ExportFormat,
+) -> String {
+    let stream = engine
+        .export_stream("products", table, &today(), &today())
+        .await
+        .unwrap();
+    let batches = datafusion::physical_plan::common::collect(stream)
+        .await
+        .unwrap();
+    let mut encoder = RowEncoder::new(format).unwrap();
+    let bytes: Vec<u8> = batches.iter().flat_map(|b| encoder.encode(b)).collect();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[tokio::test]
+async fn export_searches_as_ndjson() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let text = export_text(&engine, EventTable::Searches, ExportFormat::Ndjson).await;
+    let rows: Vec<serde_json::Value> = text
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    assert_eq!(rows.len(), 3);
+    assert_eq!(rows[0]["query"], "laptop");
+    assert_eq!(rows[0]["nb_hits"], 12);
+    assert_eq!(rows[0]["query_id"], "a".repeat(32));
+    assert!(rows[1]["query_id"].is_null());
+
+    let events = export_text(&engine, EventTable::Events, ExportFormat::Ndjson).await;
+    assert_eq!(events.lines().count(), 1);
+    assert!(events.contains("\"event_type\":\"click\""));
+}
+
+#[tokio::test]
+async fn export_searches_as_csv_quotes_special_characters() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let text = export_text(&engine, EventTable::Searches, ExportFormat::Csv).await;
+    let mut lines = text.lines();
+    let header = lines.next().unwrap();
+    assert!(header.starts_with("timestamp_ms,query,query_id,index_name,nb_hits"));
+    assert_eq!(lines.clone().count(), 3);
+    assert!(text.contains(",\"usb-c, \"\"fast\"\" charger\",,products,3,"));
+}
+
+#[tokio::test]
+async fn export_as_parquet_and_empty_ranges() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let bytes = engine
+        .export_parquet("products", EventTable::Searches, &today(), &today())
+        .await
+        .unwrap();
+    assert_eq!(&bytes[..4], b"PAR1");
+
+    // Dates outside the data range and unknown indices export nothing
+    let rows = {
+        let stream = engine
+            .export_stream("products", EventTable::Searches, "2001-01-01", "2001-01-02")
+            .await
+            .unwrap();
+        datafusion::physical_plan::common::collect(stream)
+            .await
+            .unwrap()
+            .iter()
+            .map(|b| b.num_rows())
+            .sum::<usize>()
+    };
+    assert_eq!(rows, 0);
+    let missing = engine
+        .export_parquet("missing", EventTable::Events, &today(), &today())
+        .await
+        .unwrap();
+    assert_eq!(&missing[..4], b"PAR1");
+
+    assert!(ExportFormat::parse("xml").is_err());
+    assert!(EventTable::parse("clicks").is_err());
+    assert!(RowEncoder::new(ExportFormat::Parquet).is_err());
+}
+
+#[tokio::test]
+async fn sql_joins_searches_and_events() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let result = engine
+        .run_sql(
+            "products",
+            "SELECT s.query, COUNT(e.query_id) AS clicks FROM searches s \
+             LEFT JOIN events e ON s.query_id = e.query_id \
+             GROUP BY s.query ORDER BY clicks DESC, s.query",
+            100,
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["columns"], serde_json::json!(["query", "clicks"]));
+    assert_eq!(result["nbRows"], 3);
+    assert_eq!(result["truncated"], false);
+    assert_eq!(result["rows"][0]["query"], "laptop");
+    assert_eq!(result["rows"][0]["clicks"], 1);
+}
+
+#[tokio::test]
+async fn sql_enforces_row_limit_and_read_only() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let result = engine
+        .run_sql(
+            "products",
+            "SELECT query FROM searches",
+            2,
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["nbRows"], 2);
+    assert_eq!(result["truncated"], true);
+
+    for sql in [
+        "CREATE TABLE t AS SELECT * FROM searches",
+        "INSERT INTO searches SELECT * FROM searches",
+        "DROP TABLE searches",
+        "SET datafusion.execution.batch_size = 1",
+        "COPY searches TO '/tmp/out.parquet'",
+    ] {
+        assert!(
+            engine
+                .run_sql("products", sql, 10, Duration::from_secs(10))
+                .await
+                .is_err(),
+            "{} should be rejected",
+            sql
+        );
+    }
+}
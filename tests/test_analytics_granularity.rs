@@ -0,0 +1,181 @@
+//! Analytics series granularity (minute / hour / day / week) and reporting timezones.
+
+use flapjack::analytics::buckets::TimeBuckets;
+use flapjack::analytics::config::AnalyticsConfig;
+use flapjack::analytics::query::AnalyticsQueryEngine;
+use flapjack::analytics::schema::SearchEvent;
+use tempfile::TempDir;
+
+fn test_config(dir: &std::path::Path) -> AnalyticsConfig {
+    AnalyticsConfig {
+        enabled: true,
+        data_dir: dir.to_path_buf(),
+        flush_interval_secs: 3600,
+        flush_size: 10_000,
+        retention_days: 90,
+    }
+}
+
+fn search_at(rfc3339: &str, user: &str) -> SearchEvent {
+    SearchEvent {
+        timestamp_ms: chrono::DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .timestamp_millis(),
+        query: "laptop".to_string(),
+        query_id: None,
+        index_name: "products".to_string(),
+        nb_hits: 3,
+        processing_time_ms: 4,
+        user_token: Some(user.to_string()),
+        user_ip: None,
+        filters: None,
+        facets: None,
+        analytics_tags: None,
+        page: 0,
+        hits_per_page: 20,
+        has_results: true,
+        country: None,
+        region: None,
+        ab_test_id: None,
+        ab_test_variant_id: None,
+    }
+}
+
+/// Searches around the Europe/Paris switch to summer time (2026-03-29 01:00 UTC).
+fn setup(tmp: &TempDir) -> AnalyticsQueryEngine {
+    let config = test_config(tmp.path());
+    let searches = vec![
+        search_at("2026-03-28T22:30:00Z", "u1"), // 23:30 CET on the 28th
+        search_at("2026-03-28T23:10:00Z", "u2"), // 00:10 CET on the 29th
+        search_at("2026-03-29T00:20:00Z", "u1"),
+        search_at("2026-03-29T00:40:00Z", "u1"),
+        search_at("2026-03-29T00:50:00Z", "u3"),
+        search_at("2026-03-29T21:30:00Z", "u2"), // 23:30 CEST on the 29th
+        search_at("2026-03-29T22:30:00Z", "u2"), // 00:30 CEST on the 30th
+    ];
+    flapjack::analytics::writer::flush_search_events(&searches, &config.searches_dir("products"))
+        .unwrap();
+    AnalyticsQueryEngine::new(config)
+}
+
+fn series(result: &serde_json::Value, field: &str) -> Vec<(String, i64)> {
+    result["dates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|d| {
+            (
+                d["date"].as_str().unwrap().to_string(),
+                d[field].as_i64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+fn owned(pairs: &[(&str, i64)]) -> Vec<(String, i64)> {
+    pairs.iter().map(|(d, c)| (d.to_string(), *c)).collect()
+}
+
+#[tokio::test]
+async fn timezone_moves_day_boundaries() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let utc = engine
+        .search_count("products", "2026-03-28", "2026-03-30")
+        .await
+        .unwrap();
+    assert_eq!(
+        series(&utc, "count"),
+        owned(&[("2026-03-28", 2), ("2026-03-29", 5)])
+    );
+
+    let paris =
+        engine.with_time_buckets(TimeBuckets::parse(None, None, Some("Europe/Paris")).unwrap());
+    let result = paris
+        .search_count("products", "2026-03-28", "2026-03-30")
+        .await
+        .unwrap();
+    assert_eq!(result["count"], 7);
+    assert_eq!(
+        series(&result, "count"),
+        owned(&[("2026-03-28", 1), ("2026-03-29", 5), ("2026-03-30", 1)])
+    );
+
+    // The date range itself follows the timezone: local 2026-03-29 only
+    let result = paris
+        .search_count("products", "2026-03-29", "2026-03-29")
+        .await
+        .unwrap();
+    assert_eq!(result["count"], 5);
+}
+
+#[tokio::test]
+async fn hourly_and_minute_buckets() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    let hourly = engine
+        .with_time_buckets(TimeBuckets::parse(Some("hour"), None, Some("Europe/Paris")).unwrap());
+    let result = hourly
+        .search_count("products", "2026-03-29", "2026-03-29")
+        .await
+        .unwrap();
+    assert_eq!(
+        series(&result, "count"),
+        owned(&[
+            ("2026-03-29T00:00:00+01:00", 1),
+            ("2026-03-29T01:00:00+01:00", 3),
+            ("2026-03-29T23:00:00+02:00", 1),
+        ])
+    );
+
+    // Distinct users are counted per bucket
+    let users = hourly
+        .users_count("products", "2026-03-29", "2026-03-29")
+        .await
+        .unwrap();
+    assert_eq!(
+        series(&users, "count"),
+        owned(&[
+            ("2026-03-29T00:00:00+01:00", 1),
+            ("2026-03-29T01:00:00+01:00", 2),
+            ("2026-03-29T23:00:00+02:00", 1),
+        ])
+    );
+
+    let quarter_hours =
+        engine.with_time_buckets(TimeBuckets::parse(Some("minute"), Some(15), None).unwrap());
+    let result = quarter_hours
+        .search_count("products", "2026-03-29", "2026-03-29")
+        .await
+        .unwrap();
+    assert_eq!(
+        series(&result, "count"),
+        owned(&[
+            ("2026-03-29T00:15:00+00:00", 1),
+            ("2026-03-29T00:30:00+00:00", 1),
+            ("2026-03-29T00:45:00+00:00", 1),
+            ("2026-03-29T21:30:00+00:00", 1),
+            ("2026-03-29T22:30:00+00:00", 1),
+        ])
+    );
+}
+
+#[tokio::test]
+async fn weekly_buckets_start_on_monday() {
+    let tmp = TempDir::new().unwrap();
+    let engine = setup(&tmp);
+
+    // 2026-03-29 is a Sunday: everything but the last search falls in the week of the 23rd
+    let weekly = engine
+        .with_time_buckets(TimeBuckets::parse(Some("week"), None, Some("Europe/Paris")).unwrap());
+    let result = weekly
+        .search_count("products", "2026-03-23", "2026-04-05")
+        .await
+        .unwrap();
+    assert_eq!(
+        series(&result, "count"),
+        owned(&[("2026-03-23", 6), ("2026-03-30", 1)])
+    );
+}
@@ -34,6 +34,8 @@ fn make_search_event(query: &str, index: &str, nb_hits: u32) -> SearchEvent {
         has_results: nb_hits > 0,
         country: None,
         region: None,
+        ab_test_id: None,
+        ab_test_variant_id: None,
     }
 }
 
@@ -840,3 +840,200 @@ async fn test_search_key_cannot_escalate_via_settings() {
     .unwrap();
     assert_eq!(resp.status(), 403, "search key should block clear index");
 }
+
+async fn create_key(
+    client: &reqwest::Client,
+    addr: &str,
+    acl: &[&str],
+    indexes: &[&str],
+) -> String {
+    let resp = authed(
+        client,
+        "POST",
+        &format!("http://{}/1/keys", addr),
+        ADMIN_KEY,
+    )
+    .json(&json!({"acl": acl, "indexes": indexes, "description": "Scoped key"}))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 201);
+    let body: serde_json::Value = resp.json().await.unwrap();
+    body["key"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_suggestion_configs_respect_key_indexes() {
+    let (addr, _temp, _) = setup().await;
+    let client = reqwest::Client::new();
+    create_index(&client, &addr, "public", ADMIN_KEY).await;
+    create_index(&client, &addr, "secret", ADMIN_KEY).await;
+    let scoped_key = create_key(&client, &addr, &["editSettings"], &["public*"]).await;
+    let configs = format!("http://{}/1/configs", addr);
+
+    for body in [
+        json!({"indexName": "public_qs", "sourceIndex": "secret"}),
+        json!({"indexName": "secret_qs", "sourceIndex": "public"}),
+    ] {
+        let resp = authed(&client, "POST", &configs, &scoped_key)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 403, "{}", body);
+    }
+
+    let resp = authed(&client, "POST", &configs, &scoped_key)
+        .json(&json!({"indexName": "public_qs", "sourceIndex": "public"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 201);
+}
+
+#[tokio::test]
+async fn test_suggestion_configs_cannot_target_existing_records() {
+    let (addr, _temp, _) = setup().await;
+    let client = reqwest::Client::new();
+    create_index(&client, &addr, "products", ADMIN_KEY).await;
+    create_index(&client, &addr, "searches", ADMIN_KEY).await;
+    let configs = format!("http://{}/1/configs", addr);
+
+    let resp = authed(&client, "POST", &configs, ADMIN_KEY)
+        .json(&json!({"indexName": "products", "sourceIndex": "searches"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(
+        resp.status(),
+        409,
+        "a catalog must not become a suggestions index"
+    );
+
+    let resp = authed(&client, "POST", &configs, ADMIN_KEY)
+        .json(&json!({"indexName": "../products", "sourceIndex": "searches"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400);
+
+    let resp = authed(
+        &client,
+        "GET",
+        &format!("http://{}/1/indexes/products/1", addr),
+        ADMIN_KEY,
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200, "the catalog keeps its records");
+}
+
+#[tokio::test]
+async fn test_ab_test_variants_respect_key_indexes() {
+    let (addr, _temp, _) = setup().await;
+    let client = reqwest::Client::new();
+    create_index(&client, &addr, "public", ADMIN_KEY).await;
+    create_index(&client, &addr, "secret_index", ADMIN_KEY).await;
+    let scoped_key = create_key(&client, &addr, &["editSettings"], &["public"]).await;
+    let abtests = format!("http://{}/2/abtests", addr);
+    let ab_test = |variant: &str| {
+        json!({
+            "name": "leak",
+            "endAt": "2099-01-01T00:00:00Z",
+            "variants": [
+                {"index": "public", "trafficPercentage": 50},
+                {"index": variant, "trafficPercentage": 50}
+            ]
+        })
+    };
+
+    let resp = authed(&client, "POST", &abtests, &scoped_key)
+        .json(&ab_test("secret_index"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403, "variant outside the key's indexes");
+
+    let resp = authed(&client, "POST", &abtests, ADMIN_KEY)
+        .json(&ab_test("missing"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404, "variant index must exist");
+
+    let resp = authed(&client, "POST", &abtests, ADMIN_KEY)
+        .json(&ab_test("secret_index"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let id = resp.json::<serde_json::Value>().await.unwrap()["abTestID"]
+        .as_i64()
+        .unwrap();
+    let resp = authed(
+        &client,
+        "DELETE",
+        &format!("http://{}/2/abtests/{}", addr, id),
+        &scoped_key,
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(
+        resp.status(),
+        403,
+        "cannot delete a test reaching other indexes"
+    );
+}
+
+#[tokio::test]
+async fn test_dictionary_writes_need_an_unrestricted_key() {
+    let (addr, _temp, _) = setup().await;
+    let client = reqwest::Client::new();
+    let scoped_key = create_key(&client, &addr, &["settings", "editSettings"], &["public"]).await;
+    let unrestricted_key = create_key(&client, &addr, &["settings", "editSettings"], &[]).await;
+    let batch = format!("http://{}/1/dictionaries/stopwords/batch", addr);
+    let entry = json!({
+        "clearExistingDictionaryEntries": false,
+        "requests": [{"action": "addEntry", "body": {"objectID": "le", "language": "fr", "word": "le"}}]
+    });
+
+    let resp = authed(&client, "POST", &batch, &scoped_key)
+        .json(&entry)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403, "dictionaries reach every index");
+    let secured = flapjack_http::auth::generate_secured_api_key(
+        &unrestricted_key,
+        "restrictIndices=public&validUntil=9999999999",
+    );
+    let resp = authed(&client, "POST", &batch, &secured)
+        .json(&entry)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 403, "secured key limited to some indexes");
+
+    let resp = authed(
+        &client,
+        "POST",
+        &format!("http://{}/1/dictionaries/stopwords/search", addr),
+        &scoped_key,
+    )
+    .json(&json!({"query": ""}))
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200, "searching only reads");
+
+    for key in [&unrestricted_key, ADMIN_KEY] {
+        let resp = authed(&client, "POST", &batch, key)
+            .json(&entry)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), 200);
+    }
+}
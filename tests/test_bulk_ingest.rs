@@ -0,0 +1,148 @@
+//! Bulk ingestion: NDJSON and CSV bodies, plain or gzipped, are streamed into
+//! the write queue as one task that reports the lines it rejected.
+
+use flapjack::index::bulk::{BulkFormat, BULK_CHUNK_SIZE};
+use flapjack::types::TaskStatus;
+use flapjack::IndexManager;
+use flate2::write::GzEncoder;
+use serde_json::Value;
+use std::io::Write;
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+use common::{search, wait_for_task};
+
+async fn bulk(client: &reqwest::Client, url: String, content_type: &str, body: Vec<u8>) -> Value {
+    let resp = client
+        .post(url)
+        .header("content-type", content_type)
+        .body(body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    resp.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn gzipped_ndjson_with_rejected_lines() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+
+    let ndjson = "{\"objectID\":\"1\",\"title\":\"red lamp\"}\n\
+                  {\"objectID\":\"2\",\"title\":\"blue lamp\"}\n\
+                  {\"objectID\":\"3\",\"title\":\n\
+                  \n\
+                  {\"title\":\"no id lamp\"}\n";
+    let mut gz = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gz.write_all(ndjson.as_bytes()).unwrap();
+
+    let body = bulk(
+        &client,
+        format!("http://{}/1/indexes/products/bulk", addr),
+        "application/x-ndjson",
+        gz.finish().unwrap(),
+    )
+    .await;
+    assert_eq!(body["receivedDocuments"], 4);
+    assert_eq!(body["rejectedCount"], 2);
+
+    let task = wait_for_task(&client, &addr, &body["taskID"]).await;
+    assert_eq!(task["indexed_documents"], 2);
+    assert_eq!(task["rejected_count"], 2);
+    let rejected = task["rejected_documents"].as_array().unwrap();
+    assert!(rejected[0]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("line 3:"));
+    assert_eq!(rejected[1]["error"], "missing_field");
+    assert!(rejected[1]["message"]
+        .as_str()
+        .unwrap()
+        .starts_with("line 5:"));
+
+    assert_eq!(
+        search(&client, &addr, "products", "lamp").await["nbHits"],
+        2
+    );
+}
+
+#[tokio::test]
+async fn csv_with_mapping_and_types() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+
+    let csv = "sku;name;price;stock;tags\n\
+               A1;lamp;19.5;3;\"[\"\"home\"\"]\"\n\
+               A2;desk;250;many;\n\
+               A3;chair;;7;\n";
+    let body = bulk(
+        &client,
+        format!(
+            "http://{}/1/indexes/furniture/bulk?delimiter=;&mapping=sku:objectID,name:title&types=price:float,stock:integer,tags:json",
+            addr
+        ),
+        "text/csv",
+        csv.as_bytes().to_vec(),
+    )
+    .await;
+    assert_eq!(body["receivedDocuments"], 3);
+    assert_eq!(body["rejectedCount"], 1);
+    assert_eq!(body["rejectedDocuments"][0]["objectID"], "A2");
+    assert_eq!(body["rejectedDocuments"][0]["error"], "type_mismatch");
+
+    wait_for_task(&client, &addr, &body["taskID"]).await;
+    let hits = search(&client, &addr, "furniture", "lamp").await["hits"].clone();
+    assert_eq!(hits[0]["objectID"], "A1");
+    assert_eq!(hits[0]["price"], 19.5);
+    assert_eq!(hits[0]["stock"], 3);
+    assert_eq!(hits[0]["tags"][0], "home");
+    let hits = search(&client, &addr, "furniture", "chair").await["hits"].clone();
+    assert!(hits[0].get("price").is_none(), "empty cells are left out");
+
+    let resp = client
+        .post(format!(
+            "http://{}/1/indexes/furniture/bulk?format=csv",
+            addr
+        ))
+        .body("title\nlamp\n")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 400, "a CSV needs an objectID column");
+}
+
+#[tokio::test]
+async fn large_import_is_one_task() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("logs").unwrap();
+
+    let count = BULK_CHUNK_SIZE * 2 + 500;
+    let ndjson: String = (0..count)
+        .map(|i| format!("{{\"objectID\":\"{}\",\"level\":\"info\"}}\n", i))
+        .collect();
+    let task = tokio::task::spawn_blocking(move || {
+        let task = manager
+            .import_records("logs", ndjson.as_bytes(), &BulkFormat::Ndjson)
+            .unwrap();
+        (manager, task)
+    });
+    let (manager, task) = task.await.unwrap();
+    assert_eq!(task.received_documents, count);
+
+    for _ in 0..100 {
+        let task = manager.get_task(&task.id).unwrap();
+        if task.status == TaskStatus::Succeeded {
+            assert_eq!(task.indexed_documents, count);
+            assert_eq!(task.rejected_count, 0);
+            let result = manager.search("logs", "info", None, None, 1).unwrap();
+            assert_eq!(result.total, count);
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("bulk import did not finish");
+}
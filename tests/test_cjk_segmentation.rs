@@ -0,0 +1,76 @@
+//! Dictionary word segmentation for Chinese and Japanese, selected by
+//! `indexLanguages`, with per-character tokens as the fallback.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{highlighted_name, ids, search, send, set_settings, setup};
+
+async fn set_index_languages(client: &reqwest::Client, addr: &str, index: &str, langs: Value) {
+    // Existing records are reindexed with the new segmenter
+    set_settings(client, addr, index, json!({ "indexLanguages": langs })).await;
+}
+
+#[tokio::test]
+async fn chinese_words_match_whole_words_only() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(
+        &client,
+        &addr,
+        "docs",
+        &["我在北京大学读书", "大学在北京", "龘靐"],
+    )
+    .await;
+
+    // Per-character tokens: the characters match in any order
+    assert_eq!(
+        ids(&search(&client, &addr, "docs", "北京大学 ").await),
+        vec!["1", "2"]
+    );
+
+    set_index_languages(&client, &addr, "docs", json!(["zh"])).await;
+
+    let body = search(&client, &addr, "docs", "北京大学 ").await;
+    assert_eq!(ids(&body), vec!["1"]);
+    assert_eq!(highlighted_name(&body, "1"), "我在<em>北京大学</em>读书");
+
+    // Dictionary words inside a longer word are still found
+    let body = search(&client, &addr, "docs", "大学 ").await;
+    assert_eq!(ids(&body), vec!["1", "2"]);
+    assert_eq!(highlighted_name(&body, "1"), "我在北京<em>大学</em>读书");
+    assert_eq!(highlighted_name(&body, "2"), "<em>大学</em>在北京");
+
+    // Characters missing from the dictionary fall back to unigrams
+    assert_eq!(ids(&search(&client, &addr, "docs", "龘 ").await), vec!["3"]);
+
+    // Switching back restores per-character matching
+    set_index_languages(&client, &addr, "docs", json!([])).await;
+    assert_eq!(
+        ids(&search(&client, &addr, "docs", "北京大学 ").await),
+        vec!["1", "2"]
+    );
+}
+
+#[tokio::test]
+async fn japanese_segmentation() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(
+        &client,
+        &addr,
+        "jp",
+        &["東京大学の研究", "大学は東京にある"],
+    )
+    .await;
+    set_index_languages(&client, &addr, "jp", json!(["ja"])).await;
+
+    let body = search(&client, &addr, "jp", "東京大学 ").await;
+    assert_eq!(ids(&body), vec!["1"]);
+    assert_eq!(highlighted_name(&body, "1"), "<em>東京大学</em>の研究");
+
+    let body = search(&client, &addr, "jp", "東京 ").await;
+    assert_eq!(ids(&body), vec!["1", "2"]);
+    assert_eq!(highlighted_name(&body, "2"), "大学は<em>東京</em>にある");
+}
@@ -0,0 +1,95 @@
+//! Identifier-friendly indexing: `separatorsToIndex` keeps punctuation such as
+//! "+" and "#" inside words, and `camelCaseAttributes` indexes camelCase parts.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{highlighted_name, ids, search, send, set_settings, setup};
+
+#[tokio::test]
+async fn separators_to_index_keep_punctuation_in_words() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(
+        &client,
+        &addr,
+        "books",
+        &[
+            "C++ Primer",
+            "C# in Depth",
+            "Getting started with .NET",
+            "The C Programming Language",
+        ],
+    )
+    .await;
+
+    // Punctuation splits words: "c++" and "c#" are searched as "c"
+    assert_eq!(
+        ids(&search(&client, &addr, "books", "c++ ").await),
+        vec!["1", "2", "4"]
+    );
+
+    set_settings(&client, &addr, "books", json!({"separatorsToIndex": "+#."})).await;
+
+    let body = search(&client, &addr, "books", "c++ ").await;
+    assert_eq!(ids(&body), vec!["1"]);
+    assert_eq!(highlighted_name(&body, "1"), "<em>C++</em> Primer");
+    assert_eq!(
+        ids(&search(&client, &addr, "books", "c# ").await),
+        vec!["2"]
+    );
+    assert_eq!(
+        ids(&search(&client, &addr, "books", ".net").await),
+        vec!["3"]
+    );
+    assert_eq!(ids(&search(&client, &addr, "books", "c ").await), vec!["4"]);
+
+    let (_, settings) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/indexes/books/settings", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(settings["separatorsToIndex"], "+#.");
+}
+
+#[tokio::test]
+async fn camel_case_attributes_index_camel_case_parts() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(
+        &client,
+        &addr,
+        "api",
+        &["getUserProfile", "setUserName", "user_profile_id"],
+    )
+    .await;
+
+    assert_eq!(
+        ids(&search(&client, &addr, "api", "user profile ").await),
+        vec!["3"]
+    );
+
+    set_settings(
+        &client,
+        &addr,
+        "api",
+        json!({"camelCaseAttributes": ["name"]}),
+    )
+    .await;
+
+    let body = search(&client, &addr, "api", "user profile ").await;
+    assert_eq!(ids(&body), vec!["1", "3"]);
+    assert!(highlighted_name(&body, "1").starts_with("get<em>User"));
+    assert_eq!(
+        ids(&search(&client, &addr, "api", "user ").await),
+        vec!["1", "2", "3"]
+    );
+    // The whole identifier still matches, and the record is unchanged
+    let body = search(&client, &addr, "api", "getuserprofile ").await;
+    assert_eq!(ids(&body), vec!["1"]);
+    assert_eq!(body["hits"][0]["name"], "getUserProfile");
+}
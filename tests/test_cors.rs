@@ -16,6 +16,8 @@ async fn spawn_server_with_cors() -> (String, TempDir) {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let app = Router::new()
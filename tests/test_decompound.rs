@@ -0,0 +1,86 @@
+//! Compound-word decomposition: `decompoundedAttributes` at index time and
+//! `decompoundQuery` at query time.
+
+use serde_json::json;
+
+mod common;
+
+use common::{highlighted_name, ids, search, set_settings, setup};
+
+#[tokio::test]
+async fn decompounded_attributes_index_compound_parts() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(
+        &client,
+        &addr,
+        "shoes",
+        &["Kinderschuhe aus Leder", "Schuhe für Kinder", "Damenschuhe"],
+    )
+    .await;
+    set_settings(&client, &addr, "shoes", json!({"indexLanguages": ["de"]})).await;
+
+    assert_eq!(
+        ids(&search(&client, &addr, "shoes", "schuhe ").await),
+        vec!["2"]
+    );
+
+    set_settings(
+        &client,
+        &addr,
+        "shoes",
+        json!({"decompoundedAttributes": {"de": ["name"]}}),
+    )
+    .await;
+
+    let body = search(&client, &addr, "shoes", "schuhe ").await;
+    assert_eq!(ids(&body), vec!["1", "2", "3"]);
+    assert_eq!(
+        highlighted_name(&body, "1"),
+        "Kinder<em>schuhe</em> aus Leder"
+    );
+    assert_eq!(highlighted_name(&body, "3"), "Damen<em>schuhe</em>");
+
+    // Only the indexed text gets the parts, not the stored record
+    let hit = body["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|h| h["objectID"] == "1")
+        .unwrap();
+    assert_eq!(hit["name"], "Kinderschuhe aus Leder");
+}
+
+#[tokio::test]
+async fn decompound_query_splits_compound_query_words() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(
+        &client,
+        &addr,
+        "jobs",
+        &[
+            "Ein ruhiger Platz für die Arbeit",
+            "Arbeitsplatz mit Aussicht",
+        ],
+    )
+    .await;
+    set_settings(&client, &addr, "jobs", json!({"indexLanguages": ["de"]})).await;
+
+    let body = search(&client, &addr, "jobs", "arbeitsplatz ").await;
+    assert_eq!(ids(&body), vec!["1", "2"]);
+    assert_eq!(
+        highlighted_name(&body, "1"),
+        "Ein ruhiger <em>Platz</em> für die <em>Arbeit</em>"
+    );
+    assert_eq!(
+        highlighted_name(&body, "2"),
+        "<em>Arbeitsplatz</em> mit Aussicht"
+    );
+
+    set_settings(&client, &addr, "jobs", json!({"decompoundQuery": false})).await;
+    assert_eq!(
+        ids(&search(&client, &addr, "jobs", "arbeitsplatz ").await),
+        vec!["2"]
+    );
+}
@@ -0,0 +1,78 @@
+//! Diacritic folding: accented and unaccented spellings match each other,
+//! except for characters listed in `keepDiacriticsOnCharacters`.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{highlighted_name, ids, search, send, set_settings, setup};
+
+const SHOPS: &[&str] = &["Café de Flore", "Müller Straße", "Cafe Noir"];
+
+#[tokio::test]
+async fn accents_fold_in_both_directions_and_highlight_original_text() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(&client, &addr, "shops", SHOPS).await;
+
+    let body = search(&client, &addr, "shops", "cafe").await;
+    assert_eq!(ids(&body), vec!["1", "3"]);
+    assert_eq!(highlighted_name(&body, "1"), "<em>Café</em> de Flore");
+    assert_eq!(highlighted_name(&body, "3"), "<em>Cafe</em> Noir");
+
+    assert_eq!(
+        ids(&search(&client, &addr, "shops", "CAFÉ").await),
+        vec!["1", "3"]
+    );
+
+    let body = search(&client, &addr, "shops", "muller").await;
+    assert_eq!(ids(&body), vec!["2"]);
+    assert_eq!(highlighted_name(&body, "2"), "<em>Müller</em> Straße");
+
+    let body = search(&client, &addr, "shops", "strasse").await;
+    assert_eq!(ids(&body), vec!["2"]);
+    assert_eq!(highlighted_name(&body, "2"), "Müller <em>Straße</em>");
+}
+
+#[tokio::test]
+async fn keep_diacritics_on_characters_exempts_listed_characters() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(&client, &addr, "shops", SHOPS).await;
+
+    // Existing records are reindexed with the new exemptions
+    set_settings(
+        &client,
+        &addr,
+        "shops",
+        json!({"keepDiacriticsOnCharacters": "é"}),
+    )
+    .await;
+
+    let (_, settings) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/indexes/shops/settings", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(settings["keepDiacriticsOnCharacters"], "é");
+
+    assert_eq!(
+        ids(&search(&client, &addr, "shops", "café").await),
+        vec!["1"]
+    );
+    assert_eq!(
+        ids(&search(&client, &addr, "shops", "cafe").await),
+        vec!["3"]
+    );
+    let body = search(&client, &addr, "shops", "café").await;
+    assert_eq!(highlighted_name(&body, "1"), "<em>Café</em> de Flore");
+
+    // Characters not listed are still folded
+    assert_eq!(
+        ids(&search(&client, &addr, "shops", "muller").await),
+        vec!["2"]
+    );
+}
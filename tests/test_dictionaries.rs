@@ -0,0 +1,250 @@
+//! Custom stop words, plurals and compounds from the dictionaries API, merged
+//! with the bundled lists at query time.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::{ids, search, send, set_settings, setup};
+
+async fn add_entries(client: &reqwest::Client, addr: &str, dictionary: &str, entries: Value) {
+    let requests: Vec<Value> = entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|entry| json!({"action": "addEntry", "body": entry}))
+        .collect();
+    let (status, body) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/dictionaries/{}/batch", addr, dictionary),
+        "test",
+        json!({"clearExistingDictionaryEntries": false, "requests": requests}),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    assert!(body["taskID"].is_number());
+}
+
+#[tokio::test]
+async fn custom_stop_words_are_removed_with_the_standard_ones() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(&client, &addr, "shop", &["red shoes", "cheap shoes"]).await;
+    set_settings(
+        &client,
+        &addr,
+        "shop",
+        json!({"indexLanguages": ["en"], "removeStopWords": true}),
+    )
+    .await;
+
+    assert_eq!(
+        ids(&search(&client, &addr, "shop", "the cheap shoes ").await),
+        vec!["2"]
+    );
+
+    add_entries(
+        &client,
+        &addr,
+        "stopwords",
+        json!([{"objectID": "cheap-en", "language": "en", "word": "cheap"}]),
+    )
+    .await;
+    assert_eq!(
+        ids(&search(&client, &addr, "shop", "the cheap shoes ").await),
+        vec!["1", "2"]
+    );
+
+    // Without the standard list "the" has to match too
+    let (status, _) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/dictionaries/*/settings", addr),
+        "test",
+        json!({"disableStandardEntries": {"stopwords": {"en": true}}}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert!(
+        search(&client, &addr, "shop", "the cheap shoes ").await["hits"]
+            .as_array()
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        ids(&search(&client, &addr, "shop", "cheap shoes ").await),
+        vec!["1", "2"]
+    );
+
+    let (status, body) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/dictionaries/*/settings", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(
+        body,
+        json!({"disableStandardEntries": {"stopwords": {"en": true}}})
+    );
+}
+
+#[tokio::test]
+async fn custom_plurals_and_compounds() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(&client, &addr, "toys", &["octopodes plush", "octopus mug"]).await;
+    set_settings(
+        &client,
+        &addr,
+        "toys",
+        json!({"indexLanguages": ["en"], "ignorePlurals": true}),
+    )
+    .await;
+
+    assert_eq!(
+        ids(&search(&client, &addr, "toys", "octopus ").await),
+        vec!["2"]
+    );
+    add_entries(
+        &client,
+        &addr,
+        "plurals",
+        json!([{"objectID": "octopus", "language": "en", "words": ["octopus", "octopodes"]}]),
+    )
+    .await;
+    assert_eq!(
+        ids(&search(&client, &addr, "toys", "octopus ").await),
+        vec!["1", "2"]
+    );
+
+    setup(
+        &client,
+        &addr,
+        "helme",
+        &["Helm für das Fahrrad", "Fahrradhelm"],
+    )
+    .await;
+    set_settings(&client, &addr, "helme", json!({"indexLanguages": ["de"]})).await;
+
+    assert_eq!(
+        ids(&search(&client, &addr, "helme", "fahrradhelm ").await),
+        vec!["2"]
+    );
+    add_entries(
+        &client,
+        &addr,
+        "compounds",
+        json!([{
+            "objectID": "fahrradhelm",
+            "language": "de",
+            "word": "fahrradhelm",
+            "decomposition": ["fahrrad", "helm"]
+        }]),
+    )
+    .await;
+    assert_eq!(
+        ids(&search(&client, &addr, "helme", "fahrradhelm ").await),
+        vec!["1", "2"]
+    );
+}
+
+#[tokio::test]
+async fn search_batch_and_validation() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    add_entries(
+        &client,
+        &addr,
+        "stopwords",
+        json!([
+            {"objectID": "a", "language": "en", "word": "cheap"},
+            {"objectID": "b", "language": "en", "word": "cheapest"},
+            {"objectID": "c", "language": "fr", "word": "pas cher"},
+        ]),
+    )
+    .await;
+
+    let dictionary_search = |body: Value| {
+        send(
+            &client,
+            reqwest::Method::POST,
+            format!("http://{}/1/dictionaries/stopwords/search", addr),
+            "test",
+            body,
+        )
+    };
+
+    let (status, body) = dictionary_search(json!({"query": "cheap", "hitsPerPage": 1})).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["nbHits"], 2);
+    assert_eq!(body["nbPages"], 2);
+    assert_eq!(body["hits"][0]["objectID"], "a");
+
+    let (_, body) = dictionary_search(json!({"query": "", "language": "fr"})).await;
+    assert_eq!(body["nbHits"], 1);
+    assert_eq!(body["hits"][0]["word"], "pas cher");
+
+    let (status, _) = send(
+        &client,
+        reqwest::Method::POST,
+        format!("http://{}/1/dictionaries/stopwords/batch", addr),
+        "test",
+        json!({"requests": [{"action": "deleteEntry", "body": {"objectID": "a"}}]}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    let (_, body) = dictionary_search(json!({"query": "cheap"})).await;
+    assert_eq!(body["nbHits"], 1);
+
+    // Invalid entries reject the whole batch
+    let (status, _) = send(
+        &client,
+        reqwest::Method::POST,
+        format!("http://{}/1/dictionaries/plurals/batch", addr),
+        "test",
+        json!({"requests": [
+            {"action": "addEntry", "body": {"objectID": "x", "language": "en", "words": ["ox", "oxen"]}},
+            {"action": "addEntry", "body": {"objectID": "y", "language": "en", "words": ["sheep"]}},
+        ]}),
+    )
+    .await;
+    assert_eq!(status, 400);
+    let (_, body) = send(
+        &client,
+        reqwest::Method::POST,
+        format!("http://{}/1/dictionaries/plurals/search", addr),
+        "test",
+        json!({"query": ""}),
+    )
+    .await;
+    assert_eq!(body["nbHits"], 0);
+
+    let (status, _) = send(
+        &client,
+        reqwest::Method::POST,
+        format!("http://{}/1/dictionaries/synonyms/search", addr),
+        "test",
+        json!({"query": ""}),
+    )
+    .await;
+    assert_eq!(status, 400);
+
+    // The dictionaries directory is not listed as an index
+    let (_, body) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/indexes", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert!(body["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|i| i["name"] != ".dictionaries"));
+}
@@ -0,0 +1,283 @@
+//! Logical dumps: an index exported as NDJSON is restored into a fresh index
+//! through the write queue, with its settings, synonyms, rules and keys.
+
+use flapjack::index::rules::RuleStore;
+use flapjack::index::settings::IndexSettings;
+use flapjack::index::synonyms::{Synonym, SynonymStore};
+use flapjack::types::{Document, TaskStatus};
+use flapjack::IndexManager;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+const ADMIN_KEY: &str = "test-admin-key-abc123";
+
+async fn wait_for(manager: &IndexManager, task_id: &str) {
+    for _ in 0..50 {
+        if manager.get_task(task_id).unwrap().status == TaskStatus::Succeeded {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("task {} did not succeed", task_id);
+}
+
+#[tokio::test]
+async fn dump_round_trip() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("products").unwrap();
+    let docs = (0..3)
+        .map(|i| {
+            Document::from_json(&json!({
+                "objectID": i.to_string(),
+                "title": format!("sofa {}", i),
+                "price": i * 100,
+                "tags": ["living", "room"]
+            }))
+            .unwrap()
+        })
+        .collect();
+    manager.add_documents_sync("products", docs).await.unwrap();
+
+    let tenant = temp.path().join("products");
+    IndexSettings {
+        attributes_for_faceting: vec!["tags".to_string()],
+        ..Default::default()
+    }
+    .save(tenant.join("settings.json"))
+    .unwrap();
+    let mut synonyms = SynonymStore::new();
+    synonyms.insert(Synonym::Regular {
+        object_id: "couch".to_string(),
+        synonyms: vec!["couch".to_string(), "sofa".to_string()],
+    });
+    synonyms.save(tenant.join("synonyms.json")).unwrap();
+    let mut rules = RuleStore::new();
+    rules.insert(
+        serde_json::from_value(json!({
+            "objectID": "pin",
+            "conditions": [{"pattern": "sofa", "anchoring": "contains"}],
+            "consequence": {"promote": [{"objectID": "2", "position": 0}]}
+        }))
+        .unwrap(),
+    );
+    rules.save(&tenant.join("rules.json")).unwrap();
+    manager.invalidate_settings_cache("products");
+
+    let mut dump = Vec::new();
+    let count = manager
+        .export_dump("products", vec![json!({"acl": ["search"]})], &mut dump)
+        .unwrap();
+    assert_eq!(count, 3);
+    let lines: Vec<Value> = dump
+        .split(|b| *b == b'\n')
+        .filter(|l| !l.is_empty())
+        .map(|l| serde_json::from_slice(l).unwrap())
+        .collect();
+    assert_eq!(lines[0]["type"], "header");
+    assert_eq!(lines[0]["format"], "flapjack-dump");
+    assert_eq!(lines.len(), 1 + 1 + 1 + 1 + 1 + 3);
+    assert_eq!(lines.last().unwrap()["type"], "document");
+
+    // Into a fresh manager, as after an upgrade
+    let restored_dir = TempDir::new().unwrap();
+    let restored = IndexManager::new(restored_dir.path());
+    let imported = tokio::task::spawn_blocking(move || {
+        let imported = restored
+            .import_dump("products_v2", dump.as_slice())
+            .unwrap();
+        (restored, imported)
+    });
+    let (restored, imported) = imported.await.unwrap();
+    assert!(imported.settings);
+    assert_eq!(imported.synonyms, 1);
+    assert_eq!(imported.rules, 1);
+    assert_eq!(imported.keys, vec![json!({"acl": ["search"]})]);
+    assert_eq!(imported.task.received_documents, 3);
+    wait_for(&restored, &imported.task.id).await;
+
+    let settings = restored.get_settings("products_v2").unwrap();
+    assert_eq!(settings.attributes_for_faceting, vec!["tags".to_string()]);
+    assert_eq!(restored.get_rules("products_v2").unwrap().all().len(), 1);
+    let result = restored
+        .search("products_v2", "couch", None, None, 10)
+        .unwrap();
+    assert_eq!(result.total, 3, "synonyms are restored");
+    let doc = restored.get_document("products_v2", "2").unwrap().unwrap();
+    assert_eq!(doc.to_json()["price"], 200);
+    assert_eq!(doc.to_json()["tags"], json!(["living", "room"]));
+}
+
+#[tokio::test]
+async fn dumps_without_synonyms_or_rules_clear_them() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("products").unwrap();
+    let tenant = temp.path().join("products");
+    let mut synonyms = SynonymStore::new();
+    synonyms.insert(Synonym::Regular {
+        object_id: "couch".to_string(),
+        synonyms: vec!["couch".to_string(), "sofa".to_string()],
+    });
+    synonyms.save(tenant.join("synonyms.json")).unwrap();
+    let mut rules = RuleStore::new();
+    rules.insert(
+        serde_json::from_value(json!({
+            "objectID": "pin",
+            "conditions": [{"pattern": "sofa", "anchoring": "contains"}],
+            "consequence": {"promote": [{"objectID": "2", "position": 0}]}
+        }))
+        .unwrap(),
+    );
+    rules.save(&tenant.join("rules.json")).unwrap();
+    assert_eq!(manager.get_synonyms("products").unwrap().all().len(), 1);
+    assert_eq!(manager.get_rules("products").unwrap().all().len(), 1);
+
+    let dump = format!(
+        "{}\n",
+        json!({"type": "header", "format": "flapjack-dump", "version": 1,
+               "index": "products", "flapjackVersion": "0", "createdAt": ""}),
+    );
+    let imported = tokio::task::spawn_blocking(move || {
+        let imported = manager.import_dump("products", dump.as_bytes()).unwrap();
+        (manager, imported)
+    });
+    let (manager, imported) = imported.await.unwrap();
+    assert_eq!((imported.synonyms, imported.rules), (0, 0));
+    assert_eq!(
+        manager.get_synonyms("products").map(|s| s.all().len()),
+        Some(0)
+    );
+    assert_eq!(
+        manager.get_rules("products").map(|r| r.all().len()),
+        Some(0)
+    );
+}
+
+#[tokio::test]
+async fn invalid_dumps_are_rejected() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+
+    let err = manager.import_dump("products", "{\"objectID\":\"1\"}\n".as_bytes());
+    assert!(err.is_err(), "a dump starts with a header");
+
+    let dump = format!(
+        "{}\n{}\n{}\n",
+        json!({"type": "header", "format": "flapjack-dump", "version": 1,
+               "index": "products", "flapjackVersion": "0", "createdAt": ""}),
+        json!({"type": "document", "document": {"objectID": "1", "title": "lamp"}}),
+        json!({"type": "document", "document": {"title": "no id"}}),
+    );
+    let imported = tokio::task::spawn_blocking(move || {
+        let imported = manager.import_dump("products", dump.as_bytes()).unwrap();
+        (manager, imported)
+    });
+    let (manager, imported) = imported.await.unwrap();
+    assert_eq!(imported.task.received_documents, 2);
+    assert_eq!(imported.task.rejected_count, 1);
+    assert!(imported.task.rejected_documents[0]
+        .message
+        .starts_with("line 3:"));
+    wait_for(&manager, &imported.task.id).await;
+    assert!(manager.get_document("products", "1").unwrap().is_some());
+}
+
+#[tokio::test]
+async fn dump_over_http_restores_keys() {
+    let (addr, _temp) = common::spawn_server_with_key(Some(ADMIN_KEY)).await;
+    let client = reqwest::Client::new();
+    let send = |method: reqwest::Method, url: String| {
+        client
+            .request(method, url)
+            .header("x-algolia-api-key", ADMIN_KEY)
+            .header("x-algolia-application-id", "test")
+    };
+
+    let resp = send(
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products/batch", addr),
+    )
+    .json(
+        &json!({"requests": [{"action": "addObject", "body": {"objectID": "1", "title": "lamp"}}]}),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200);
+    let resp = send(reqwest::Method::POST, format!("http://{}/1/keys", addr))
+        .json(&json!({"acl": ["search"], "indexes": ["products"], "description": "storefront"}))
+        .send()
+        .await
+        .unwrap();
+    let key: Value = resp.json().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let resp = send(
+        reqwest::Method::GET,
+        format!("http://{}/1/indexes/products/dump", addr),
+    )
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/x-ndjson");
+    let dump = resp.bytes().await.unwrap();
+    let text = String::from_utf8(dump.to_vec()).unwrap();
+    assert!(text.contains("storefront"));
+    assert!(
+        !text.contains(key["key"].as_str().unwrap()),
+        "key values are not dumped"
+    );
+    assert!(!text.contains(ADMIN_KEY));
+
+    let resp = send(
+        reqwest::Method::POST,
+        format!(
+            "http://{}/1/indexes/products_v2/dump?restoreKeys=true",
+            addr
+        ),
+    )
+    .body(dump)
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["receivedDocuments"], 1);
+    assert_eq!(body["keys"][0]["description"], "storefront");
+    assert_ne!(body["keys"][0]["key"], key["key"]);
+
+    // An index with records is only restored into when asked to
+    let resp = send(
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products/dump", addr),
+    )
+    .body(dump.clone())
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 409);
+    let resp = send(
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products/dump?overwrite=true", addr),
+    )
+    .body(dump)
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 200);
+
+    let resp = send(
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products_v3/dump", addr),
+    )
+    .body("not a dump")
+    .send()
+    .await
+    .unwrap();
+    assert_eq!(resp.status(), 400);
+}
@@ -0,0 +1,160 @@
+//! Encryption at rest: with a master key, index files, vectors, the oplog,
+//! snapshot archives and keys.json are unreadable on disk, while searches,
+//! oplog recovery and restores read them back.
+
+use flapjack::index::encryption::{self, Keyring};
+use flapjack::index::snapshot;
+use flapjack::index::vectors::{VECTORS_FILE, VECTORS_LOG_FILE};
+use flapjack::types::Document;
+use flapjack::IndexManager;
+use flapjack_http::auth::KeyStore;
+use serde_json::json;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+const SECRET: &str = "Jane Roe, 221B Baker Street";
+/// A component of every seeded embedding, looked for in the vector files.
+const EMBEDDING: f32 = 0.7071;
+
+fn setup() {
+    // All tests of this binary share the keyring
+    encryption::init(Keyring::new(&[[7; 32]]).unwrap());
+}
+
+async fn seed(manager: &IndexManager) {
+    manager.create_tenant("customers").unwrap();
+    let docs = (0..3)
+        .map(|i| {
+            Document::from_json(&json!({
+                "objectID": i.to_string(),
+                "address": SECRET,
+                "_vectors": [EMBEDDING, i as f32]
+            }))
+            .unwrap()
+        })
+        .collect();
+    manager.add_documents_sync("customers", docs).await.unwrap();
+}
+
+fn assert_unreadable(dir: &Path) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            assert_unreadable(&path);
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        let data = fs::read(&path).unwrap();
+        if encryption::is_index_file(&name) || name == VECTORS_FILE {
+            assert!(encryption::is_encrypted(&data), "{} is not encrypted", name);
+        }
+        assert!(
+            !data
+                .windows(4)
+                .any(|w| w == EMBEDDING.to_bits().to_le_bytes()),
+            "{} holds a plaintext embedding",
+            name
+        );
+        assert!(
+            !String::from_utf8_lossy(&data).contains("Baker"),
+            "{} holds plaintext",
+            name
+        );
+    }
+}
+
+#[tokio::test]
+async fn index_and_oplog_are_encrypted_on_disk() {
+    setup();
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    seed(&manager).await;
+    assert!(manager.evict_tenant("customers").await);
+
+    let tenant = temp.path().join("customers");
+    assert!(tenant.join("oplog").exists());
+    assert!(tenant.join(VECTORS_LOG_FILE).exists());
+    assert_unreadable(&tenant);
+
+    let result = manager
+        .search("customers", "baker", None, None, 10)
+        .unwrap();
+    assert_eq!(result.total, 3);
+    assert_eq!(manager.reencrypt_tenant("customers").await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn recovery_replays_the_encrypted_oplog() {
+    setup();
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    seed(&manager).await;
+    assert!(manager.evict_tenant("customers").await);
+
+    fs::remove_file(temp.path().join("customers").join("meta.json")).unwrap();
+    let result = manager
+        .search("customers", "baker", None, None, 10)
+        .unwrap();
+    assert_eq!(result.total, 3);
+}
+
+#[tokio::test]
+async fn snapshot_archives_are_encrypted() {
+    setup();
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    seed(&manager).await;
+    assert!(manager.evict_tenant("customers").await);
+
+    let archive = snapshot::export_to_bytes(&temp.path().join("customers")).unwrap();
+    assert!(encryption::is_encrypted(&archive));
+
+    let restored = TempDir::new().unwrap();
+    snapshot::import_from_bytes(&archive, &restored.path().join("customers")).unwrap();
+    let restored_manager = IndexManager::new(restored.path());
+    let result = restored_manager
+        .search("customers", "baker", None, None, 10)
+        .unwrap();
+    assert_eq!(result.total, 3);
+
+    let mut tampered = archive.clone();
+    let middle = tampered.len() / 2;
+    tampered[middle] ^= 1;
+    assert!(snapshot::import_from_bytes(&tampered, &restored.path().join("customers")).is_err());
+}
+
+#[tokio::test]
+async fn api_keys_are_encrypted() {
+    setup();
+    let temp = TempDir::new().unwrap();
+    let admin_key = "admin-key-0123456789";
+    let keys = KeyStore::load_or_create(temp.path(), admin_key)
+        .unwrap()
+        .list_all();
+
+    let data = fs::read(temp.path().join("keys.json")).unwrap();
+    assert!(encryption::is_encrypted(&data));
+    assert!(!String::from_utf8_lossy(&data).contains(admin_key));
+
+    let reloaded = KeyStore::load_or_create(temp.path(), admin_key)
+        .unwrap()
+        .list_all();
+    let values = |keys: &[flapjack_http::auth::ApiKey]| {
+        keys.iter().map(|k| k.value.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(values(&reloaded), values(&keys));
+}
+
+#[tokio::test]
+async fn api_keys_sealed_with_another_key_fail_to_load() {
+    setup();
+    let temp = TempDir::new().unwrap();
+    let other = Keyring::new(&[[9; 32]]).unwrap();
+    fs::write(
+        temp.path().join("keys.json"),
+        other.encrypt(b"{\"keys\": []}"),
+    )
+    .unwrap();
+    assert!(KeyStore::load_or_create(temp.path(), "admin-key-0123456789").is_err());
+}
@@ -0,0 +1,179 @@
+//! Document expiry: expired documents are hidden from searches, browse,
+//! facets and getObject right away and deleted by the sweeper through the
+//! write queue and oplog.
+
+use flapjack::index::expiry;
+use flapjack::index::settings::IndexSettings;
+use flapjack::types::{Document, FacetRequest};
+use flapjack::IndexManager;
+use serde_json::json;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn doc(value: serde_json::Value) -> Document {
+    Document::from_json(&value).unwrap()
+}
+
+fn oplog_deletes(manager: &IndexManager, tenant: &str) -> Vec<String> {
+    manager
+        .get_oplog(tenant)
+        .unwrap()
+        .read_since(0)
+        .unwrap()
+        .into_iter()
+        .filter(|e| e.op_type == "delete")
+        .map(|e| e.payload["objectID"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn wait_for_deletes(manager: &IndexManager, tenant: &str, id: &str) {
+    for _ in 0..50 {
+        if oplog_deletes(manager, tenant).iter().any(|d| d == id) {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("{} was not deleted", id);
+}
+
+#[tokio::test]
+async fn expired_documents_are_hidden_then_swept() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("sales").unwrap();
+    let now = expiry::now();
+    manager
+        .add_documents_sync(
+            "sales",
+            vec![
+                doc(json!({"objectID": "ended", "title": "flash sale", "_expiresAt": now - 10})),
+                doc(json!({"objectID": "soon", "title": "flash sale", "_expiresAt": now + 1})),
+                doc(json!({"objectID": "later", "title": "flash sale", "_expiresAt": now + 3600})),
+                doc(json!({"objectID": "always", "title": "flash sale"})),
+            ],
+        )
+        .await
+        .unwrap();
+
+    let result = manager.search("sales", "flash", None, None, 10).unwrap();
+    assert_eq!(result.total, 3);
+
+    // Hidden as soon as it expires, before any sweep
+    tokio::time::sleep(Duration::from_millis(2100)).await;
+    let result = manager.search("sales", "flash", None, None, 10).unwrap();
+    assert_eq!(result.total, 2);
+    assert!(manager.get_document("sales", "soon").unwrap().is_none());
+
+    assert_eq!(manager.sweep_expired_documents(), 2);
+    wait_for_deletes(&manager, "sales", "ended").await;
+    wait_for_deletes(&manager, "sales", "soon").await;
+    assert!(manager.get_document("sales", "later").unwrap().is_some());
+
+    let deletes = oplog_deletes(&manager, "sales");
+    assert_eq!(deletes.len(), 2);
+    assert!(deletes.contains(&"ended".to_string()));
+
+    // Reloaded from disk, the remaining documents are still checked
+    assert!(manager.evict_tenant("sales").await);
+    let result = manager.search("sales", "flash", None, None, 10).unwrap();
+    assert_eq!(result.total, 2);
+    assert_eq!(manager.sweep_expired_documents(), 0);
+}
+
+#[tokio::test]
+async fn index_ttl_on_a_date_attribute() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("jobs").unwrap();
+    IndexSettings {
+        expires_at_attribute: Some("postedAt".to_string()),
+        time_to_live: Some(60),
+        ..Default::default()
+    }
+    .save(temp.path().join("jobs").join("settings.json"))
+    .unwrap();
+    manager.invalidate_settings_cache("jobs");
+
+    let posted = chrono::Utc::now() - chrono::Duration::seconds(120);
+    manager
+        .add_documents_sync(
+            "jobs",
+            vec![
+                doc(json!({"objectID": "old", "title": "engineer", "postedAt": posted.to_rfc3339()})),
+                doc(json!({"objectID": "new", "title": "engineer", "postedAt": expiry::now()})),
+            ],
+        )
+        .await
+        .unwrap();
+
+    let result = manager.search("jobs", "engineer", None, None, 10).unwrap();
+    assert_eq!(result.total, 1);
+    assert_eq!(result.documents[0].document.id, "new");
+
+    assert_eq!(manager.sweep_expired("jobs").unwrap(), 1);
+    wait_for_deletes(&manager, "jobs", "old").await;
+}
+
+#[tokio::test]
+async fn expired_documents_are_hidden_from_browse_facets_and_get_object() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("deals").unwrap();
+    IndexSettings {
+        attributes_for_faceting: vec!["category".to_string()],
+        ..Default::default()
+    }
+    .save(temp.path().join("deals").join("settings.json"))
+    .unwrap();
+    manager.invalidate_settings_cache("deals");
+
+    let now = expiry::now();
+    manager
+        .add_documents_sync(
+            "deals",
+            vec![
+                doc(json!({"objectID": "ended", "category": "clearance", "_expiresAt": now - 10})),
+                doc(json!({"objectID": "live", "category": "tools", "_expiresAt": now + 3600})),
+            ],
+        )
+        .await
+        .unwrap();
+
+    assert!(manager.get_document("deals", "ended").unwrap().is_none());
+    assert!(manager.get_document("deals", "live").unwrap().is_some());
+
+    let facets = vec![FacetRequest {
+        field: "category".to_string(),
+        path: "/category".to_string(),
+    }];
+    let browsed = manager
+        .search_with_facets("deals", "", None, None, 10, 0, Some(&facets))
+        .unwrap();
+    assert_eq!(browsed.total, 1);
+    assert_eq!(browsed.documents[0].document.id, "live");
+    let counts = &browsed.facets["category"];
+    assert_eq!(counts.iter().map(|c| c.count).sum::<u64>(), 1);
+    assert!(counts.iter().all(|c| !c.path.contains("clearance")));
+}
+
+#[tokio::test]
+async fn sweep_skips_unloaded_tenants() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("idle").unwrap();
+    manager
+        .add_documents_sync(
+            "idle",
+            vec![doc(
+                json!({"objectID": "ended", "title": "old", "_expiresAt": expiry::now() - 10}),
+            )],
+        )
+        .await
+        .unwrap();
+    assert!(manager.evict_tenant("idle").await);
+
+    // Sweeping neither loads the tenant nor keeps it from being evicted
+    assert_eq!(manager.sweep_expired("idle").unwrap(), 0);
+    assert_eq!(manager.sweep_expired_documents(), 0);
+    assert_eq!(manager.loaded_count(), 0);
+}
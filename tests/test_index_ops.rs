@@ -210,6 +210,71 @@ mod compact_index {
     }
 }
 
+// ============================================================
+// REINDEX
+// ============================================================
+
+mod reindex {
+    use super::*;
+    use flapjack::index::settings::IndexSettings;
+    use flapjack::types::TaskStatus;
+
+    #[tokio::test]
+    async fn reindex_applies_new_analyzer_settings_in_the_background() {
+        let tmp = TempDir::new().unwrap();
+        let mgr = IndexManager::new(tmp.path());
+        mgr.create_tenant("test").unwrap();
+        mgr.add_documents_sync(
+            "test",
+            vec![
+                make_doc("1", "Café de Flore"),
+                make_doc("2", "Cafe Noir"),
+                make_doc("3", "Tea Room"),
+            ],
+        )
+        .await
+        .unwrap();
+        assert_eq!(mgr.search("test", "cafe", None, None, 10).unwrap().total, 2);
+
+        IndexSettings {
+            keep_diacritics_on_characters: "é".to_string(),
+            ..Default::default()
+        }
+        .save(tmp.path().join("test").join("settings.json"))
+        .unwrap();
+        mgr.invalidate_settings_cache("test");
+
+        let task = mgr.reindex("test").unwrap();
+        assert_eq!(task.received_documents, 3);
+        let done = loop {
+            let task = mgr.get_task(&task.id).unwrap();
+            match task.status {
+                TaskStatus::Enqueued | TaskStatus::Processing => {
+                    tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+                }
+                TaskStatus::Succeeded => break task,
+                TaskStatus::Failed(e) => panic!("reindex failed: {}", e),
+            }
+        };
+        assert_eq!(done.indexed_documents, 3);
+
+        let results = mgr.search("test", "cafe", None, None, 10).unwrap();
+        assert_eq!(results.total, 1);
+        assert_eq!(results.documents[0].document.id, "2");
+        assert_eq!(mgr.search("test", "", None, None, 10).unwrap().total, 3);
+    }
+
+    #[tokio::test]
+    async fn reindex_empty_index() {
+        let tmp = TempDir::new().unwrap();
+        let mgr = IndexManager::new(tmp.path());
+        mgr.create_tenant("test").unwrap();
+
+        let task = mgr.reindex("test").unwrap();
+        assert_eq!(task.status, TaskStatus::Succeeded);
+    }
+}
+
 // ============================================================
 // OPERATION INDEX (move/copy)
 // ============================================================
@@ -5,6 +5,7 @@
 /// - Index-level manual writer API (Document type, explicit commit)
 /// - IndexManager-level multi-tenant search
 /// - Persistence across open/close cycles
+use flapjack::index::vectors::VECTORS_FILE;
 use flapjack::index::{schema::Schema, Index};
 use flapjack::types::{Document, FieldValue};
 use flapjack::IndexManager;
@@ -191,6 +192,33 @@ fn test_persistence_across_reopen() {
     }
 }
 
+#[test]
+fn test_unreadable_vectors_are_rebuilt_on_open() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().to_owned();
+
+    {
+        let index = Index::create_in_dir(&path).unwrap();
+        index
+            .add_documents_simple(&[
+                json!({"objectID": "1", "title": "North", "_vectors": [0.0, 1.0]}),
+                json!({"objectID": "2", "title": "East", "_vectors": [1.0, 0.0]}),
+                json!({"objectID": "3", "title": "No vector"}),
+            ])
+            .unwrap();
+    }
+    std::fs::write(path.join(VECTORS_FILE), b"not a vector index").unwrap();
+
+    // The vectors come back from the documents and are saved again
+    for _ in 0..2 {
+        let index = Index::open(&path).unwrap();
+        let vectors = index.vectors();
+        assert_eq!(vectors.len(), 2);
+        let hits = vectors.search(&[0.9, 0.1], 1, |_| true).unwrap();
+        assert_eq!(hits[0].0, "2");
+    }
+}
+
 // ============================================================
 // IndexManager: Multi-tenant search
 // ============================================================
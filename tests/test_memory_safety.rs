@@ -342,6 +342,14 @@ async fn test_health_returns_json_with_memory_fields() {
         body["allocator"].is_string(),
         "allocator should be a string"
     );
+    assert!(
+        body["tenant_eviction"]["loaded_tenants"].is_number(),
+        "tenant_eviction.loaded_tenants should be a number"
+    );
+    assert!(
+        body["tenant_eviction"]["lru_evictions"].is_number(),
+        "tenant_eviction.lru_evictions should be a number"
+    );
 }
 
 // ============================================================
@@ -358,6 +366,8 @@ async fn spawn_server_with_pressure_middleware() -> (String, TempDir) {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let health_route = Router::new()
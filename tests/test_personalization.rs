@@ -0,0 +1,177 @@
+//! Personalization: profiles built from Insights events, applied as search boosts.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::send;
+
+async fn first_brand(client: &reqwest::Client, addr: &str, params: Value) -> String {
+    let (status, body) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products/query", addr),
+        "test",
+        params,
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    body["hits"][0]["brand"].as_str().unwrap().to_string()
+}
+
+async fn click(client: &reqwest::Client, addr: &str, user: &str, object_id: &str, times: usize) {
+    let events: Vec<Value> = (0..times)
+        .map(|_| {
+            json!({
+                "eventType": "click",
+                "eventName": "Product Clicked",
+                "index": "products",
+                "userToken": user,
+                "objectIDs": [object_id]
+            })
+        })
+        .collect();
+    let (status, body) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/events", addr),
+        "test",
+        json!({ "events": events }),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    // Profiles are updated in the background
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+}
+
+async fn setup(client: &reqwest::Client, addr: &str) {
+    send(
+        client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/indexes/products/settings", addr),
+        "test",
+        json!({"attributesForFaceting": ["brand", "category"]}),
+    )
+    .await;
+    send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products/batch", addr),
+        "test",
+        json!({"requests": [
+            {"action": "addObject", "body": {"objectID": "1", "name": "Laptop", "brand": "Apple", "category": "computers"}},
+            {"action": "addObject", "body": {"objectID": "2", "name": "Laptop", "brand": "Asus", "category": "computers"}},
+            {"action": "addObject", "body": {"objectID": "3", "name": "Phone", "brand": "Asus", "category": "phones"}}
+        ]}),
+    )
+    .await;
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+}
+
+#[tokio::test]
+async fn profiles_boost_preferred_facet_values_and_can_be_deleted() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(&client, &addr).await;
+
+    click(&client, &addr, "asus-fan", "3", 3).await;
+    click(&client, &addr, "apple-fan", "1", 3).await;
+
+    let query = |user: &str, enabled: bool| json!({"query": "laptop", "userToken": user, "enablePersonalization": enabled});
+    assert_eq!(
+        first_brand(&client, &addr, query("asus-fan", true)).await,
+        "Asus"
+    );
+    assert_eq!(
+        first_brand(&client, &addr, query("apple-fan", true)).await,
+        "Apple"
+    );
+    // personalizationImpact=0 turns boosts off, so both users see the same ranking
+    let neutral = json!({"query": "laptop", "userToken": "asus-fan", "enablePersonalization": true, "personalizationImpact": 0});
+    let other = json!({"query": "laptop", "userToken": "apple-fan", "enablePersonalization": true, "personalizationImpact": 0});
+    assert_eq!(
+        first_brand(&client, &addr, neutral).await,
+        first_brand(&client, &addr, other).await
+    );
+
+    let (status, profile) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/profiles/personalization/asus-fan", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(profile["userToken"], "asus-fan");
+    assert!(profile["scores"]["brand"]["Asus"].as_f64().unwrap() > 5.0);
+    assert!(profile["scores"]["category"]["phones"].as_f64().unwrap() > 5.0);
+    assert!(profile["scores"]["brand"].get("Apple").is_none());
+    assert!(profile["lastEventAt"].is_string());
+
+    let (status, deleted) = send(
+        &client,
+        reqwest::Method::DELETE,
+        format!("http://{}/1/profiles/asus-fan", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 200);
+    assert_eq!(deleted["userToken"], "asus-fan");
+
+    let (status, _) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/profiles/personalization/asus-fan", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 404);
+    let (status, _) = send(
+        &client,
+        reqwest::Method::DELETE,
+        format!("http://{}/1/profiles/asus-fan", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn events_of_authenticated_users_personalize_their_user_token() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup(&client, &addr).await;
+
+    let query = |boost: bool| json!({"query": "laptop", "userToken": "visitor-1", "enablePersonalization": boost});
+    let baseline = first_brand(&client, &addr, query(false)).await;
+    let preferred = if baseline == "Apple" { "2" } else { "1" };
+    let events: Vec<Value> = (0..3)
+        .map(|_| {
+            json!({
+                "eventType": "click",
+                "eventName": "Product Clicked",
+                "index": "products",
+                "userToken": "visitor-1",
+                "authenticatedUserToken": "account-1",
+                "objectIDs": [preferred]
+            })
+        })
+        .collect();
+    let (status, body) = send(
+        &client,
+        reqwest::Method::POST,
+        format!("http://{}/1/events", addr),
+        "test",
+        json!({ "events": events }),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    tokio::time::sleep(tokio::time::Duration::from_millis(200)).await;
+
+    assert_ne!(first_brand(&client, &addr, query(true)).await, baseline);
+    assert_eq!(first_brand(&client, &addr, query(false)).await, baseline);
+}
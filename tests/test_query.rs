@@ -333,6 +333,38 @@ mod plurals {
         assert!(!ids.contains(&"2"));
     }
 
+    #[tokio::test]
+    async fn index_languages_drive_french_plurals() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path());
+        manager.create_tenant("test").unwrap();
+
+        let settings = IndexSettings {
+            ignore_plurals: IgnorePluralsValue::All,
+            index_languages: vec!["fr".to_string()],
+            ..Default::default()
+        };
+        settings
+            .save(temp_dir.path().join("test/settings.json"))
+            .unwrap();
+        manager.invalidate_settings_cache("test");
+
+        let docs = vec![
+            doc("1", vec![("name", text("cheval"))]),
+            doc("2", vec![("name", text("chevaux"))]),
+            doc("3", vec![("name", text("Œil"))]),
+            doc("4", vec![("name", text("yeux"))]),
+        ];
+        manager.add_documents_sync("test", docs).await.unwrap();
+
+        let mut ids = search_ids(&manager, "cheval ");
+        ids.sort();
+        assert_eq!(ids, vec!["1", "2"]);
+        let mut ids = search_ids(&manager, "oeil ");
+        ids.sort();
+        assert_eq!(ids, vec!["3", "4"]);
+    }
+
     #[tokio::test]
     async fn serde_roundtrip_settings() {
         let settings = IndexSettings {
@@ -470,6 +502,33 @@ mod stopwords {
         );
     }
 
+    #[tokio::test]
+    async fn query_languages_drive_stop_words() {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path());
+        manager.create_tenant("test").unwrap();
+
+        let settings = IndexSettings {
+            remove_stop_words: RemoveStopWordsValue::All,
+            query_languages: vec!["fr".to_string()],
+            ..IndexSettings::default()
+        };
+        settings
+            .save(temp_dir.path().join("test/settings.json"))
+            .unwrap();
+        manager.invalidate_settings_cache("test");
+
+        let docs = vec![
+            doc("1", vec![("title", text("meilleur moteur"))]),
+            doc("2", vec![("title", text("le moteur de recherche"))]),
+        ];
+        manager.add_documents_sync("test", docs).await.unwrap();
+
+        let mut ids = search_ids(&manager, "le moteur ");
+        ids.sort();
+        assert_eq!(ids, vec!["1", "2"], "fr stop words should strip 'le'");
+    }
+
     #[tokio::test]
     async fn does_not_affect_content_words() {
         let f = get_stopword_enabled().await;
@@ -838,3 +897,306 @@ mod synonyms {
         assert!(expanded.contains(&"black trousers".to_string()));
     }
 }
+
+// ============================================================
+// PER-ATTRIBUTE TYPO AND PREFIX CONTROLS
+// ============================================================
+
+mod typo_and_prefix {
+    use super::*;
+
+    async fn make_manager(settings: IndexSettings) -> (TempDir, Arc<IndexManager>) {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path());
+        manager.create_tenant("test").unwrap();
+        settings
+            .save(temp_dir.path().join("test/settings.json"))
+            .unwrap();
+        manager.invalidate_settings_cache("test");
+
+        let docs = vec![
+            doc(
+                "1",
+                vec![("name", text("Wireless mouse")), ("sku", text("AB1234"))],
+            ),
+            doc(
+                "2",
+                vec![("name", text("Wireless house")), ("sku", text("AB1235"))],
+            ),
+            doc("3", vec![("name", text("Model 2024"))]),
+            doc("4", vec![("name", text("Model 2025"))]),
+        ];
+        manager.add_documents_sync("test", docs).await.unwrap();
+        (temp_dir, manager)
+    }
+
+    fn sorted_ids(mgr: &IndexManager, query: &str) -> Vec<String> {
+        let mut ids = search_ids(mgr, query);
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn typos_allowed_by_default() {
+        let (_tmp, mgr) = make_manager(IndexSettings::default()).await;
+        assert_eq!(sorted_ids(&mgr, "ab1235 "), vec!["1", "2"]);
+        assert_eq!(sorted_ids(&mgr, "mouse "), vec!["1", "2"]);
+        assert_eq!(sorted_ids(&mgr, "2024 "), vec!["3", "4"]);
+        assert_eq!(sorted_ids(&mgr, "ab12"), vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn disable_typo_tolerance_on_attributes() {
+        let (_tmp, mgr) = make_manager(IndexSettings {
+            disable_typo_tolerance_on_attributes: vec!["sku".to_string()],
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(sorted_ids(&mgr, "ab1235 "), vec!["2"]);
+        // Other attributes keep their typo tolerance
+        assert_eq!(sorted_ids(&mgr, "mouse "), vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn disable_typo_tolerance_on_words() {
+        let (_tmp, mgr) = make_manager(IndexSettings {
+            disable_typo_tolerance_on_words: vec!["Mouse".to_string()],
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(sorted_ids(&mgr, "mouse "), vec!["1"]);
+        assert_eq!(sorted_ids(&mgr, "house "), vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn disallow_typos_on_numeric_tokens() {
+        let (_tmp, mgr) = make_manager(IndexSettings {
+            allow_typos_on_numeric_tokens: false,
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(sorted_ids(&mgr, "2024 "), vec!["3"]);
+        // Alphanumeric tokens are not numeric
+        assert_eq!(sorted_ids(&mgr, "ab1235 "), vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn disable_prefix_on_attributes() {
+        let (_tmp, mgr) = make_manager(IndexSettings {
+            disable_prefix_on_attributes: vec!["sku".to_string()],
+            ..Default::default()
+        })
+        .await;
+        assert!(search_ids(&mgr, "ab12").is_empty());
+        // Other attributes still match on prefixes
+        assert_eq!(sorted_ids(&mgr, "wirel"), vec!["1", "2"]);
+    }
+
+    #[tokio::test]
+    async fn settings_serde() {
+        let settings: IndexSettings = serde_json::from_str(
+            r#"{"disableTypoToleranceOnAttributes":["sku"],"disablePrefixOnAttributes":["sku"],"allowTyposOnNumericTokens":false}"#,
+        )
+        .unwrap();
+        assert_eq!(settings.disable_typo_tolerance_on_attributes, vec!["sku"]);
+        assert_eq!(settings.disable_prefix_on_attributes, vec!["sku"]);
+        assert!(!settings.allow_typos_on_numeric_tokens);
+        assert!(IndexSettings::default().allow_typos_on_numeric_tokens);
+    }
+}
+
+mod proximity {
+    use super::*;
+
+    async fn make_manager(settings: IndexSettings, names: &[&str]) -> (TempDir, Arc<IndexManager>) {
+        let docs = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| doc(&(i + 1).to_string(), vec![("name", text(name))]))
+            .collect();
+        make_manager_with_docs(settings, docs).await
+    }
+
+    async fn make_manager_with_docs(
+        settings: IndexSettings,
+        docs: Vec<Document>,
+    ) -> (TempDir, Arc<IndexManager>) {
+        let temp_dir = TempDir::new().unwrap();
+        let manager = IndexManager::new(temp_dir.path());
+        manager.create_tenant("test").unwrap();
+        settings
+            .save(temp_dir.path().join("test/settings.json"))
+            .unwrap();
+        manager.invalidate_settings_cache("test");
+        manager.add_documents_sync("test", docs).await.unwrap();
+        (temp_dir, manager)
+    }
+
+    fn distances(mgr: &IndexManager, query: &str) -> Vec<(String, u32)> {
+        mgr.search("test", query, None, None, 20)
+            .unwrap()
+            .documents
+            .iter()
+            .map(|d| (d.document.id.clone(), d.proximity_distance))
+            .collect()
+    }
+
+    fn phrase_ids(mgr: &IndexManager, query: &str) -> Vec<String> {
+        let mut ids: Vec<String> = mgr
+            .search_full_with_stop_words(
+                "test",
+                query,
+                None,
+                None,
+                20,
+                0,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+                Some(false),
+                Some(true),
+                None,
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap()
+            .documents
+            .iter()
+            .map(|d| d.document.id.clone())
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    const NAMES: &[&str] = &["alpha beta gamma delta", "alpha gamma delta beta"];
+
+    #[tokio::test]
+    async fn closer_words_rank_first() {
+        let (_tmp, mgr) = make_manager(IndexSettings::default(), NAMES).await;
+        assert_eq!(search_ids(&mgr, "alpha beta "), vec!["1", "2"]);
+        assert_eq!(search_ids(&mgr, "delta beta "), vec!["2", "1"]);
+        // The last word is matched as a prefix, its positions still count
+        assert_eq!(search_ids(&mgr, "alpha beta"), vec!["1", "2"]);
+        assert_eq!(search_ids(&mgr, "delta beta"), vec!["2", "1"]);
+    }
+
+    #[tokio::test]
+    async fn words_split_by_the_analyzer_are_measured() {
+        let (_tmp, mgr) = make_manager(IndexSettings::default(), NAMES).await;
+        assert_eq!(
+            distances(&mgr, "alpha-beta"),
+            vec![("1".to_string(), 1), ("2".to_string(), 3)]
+        );
+    }
+
+    #[tokio::test]
+    async fn criteria_apply_in_ranking_order() {
+        let docs = vec![
+            doc(
+                "1",
+                vec![("name", text(NAMES[0])), ("rank", FieldValue::Integer(1))],
+            ),
+            doc(
+                "2",
+                vec![("name", text(NAMES[1])), ("rank", FieldValue::Integer(5))],
+            ),
+        ];
+        let settings = IndexSettings {
+            custom_ranking: Some(vec!["desc(rank)".to_string()]),
+            ..Default::default()
+        };
+        let (_tmp, mgr) = make_manager_with_docs(settings, docs.clone()).await;
+        // Proximity comes before custom in the default ranking
+        assert_eq!(search_ids(&mgr, "alpha beta"), vec!["1", "2"]);
+
+        let settings = IndexSettings {
+            custom_ranking: Some(vec!["desc(rank)".to_string()]),
+            ranking: Some(
+                [
+                    "typo",
+                    "geo",
+                    "words",
+                    "filters",
+                    "custom",
+                    "proximity",
+                    "attribute",
+                ]
+                .iter()
+                .map(|c| c.to_string())
+                .collect(),
+            ),
+            ..Default::default()
+        };
+        let (_tmp, mgr) = make_manager_with_docs(settings, docs).await;
+        assert_eq!(search_ids(&mgr, "alpha beta"), vec!["2", "1"]);
+        // The proximity is still reported
+        assert_eq!(
+            distances(&mgr, "alpha beta"),
+            vec![("2".to_string(), 3), ("1".to_string(), 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn distance_is_reported() {
+        let (_tmp, mgr) = make_manager(IndexSettings::default(), NAMES).await;
+        assert_eq!(
+            distances(&mgr, "alpha beta "),
+            vec![("1".to_string(), 1), ("2".to_string(), 3)]
+        );
+        // Words in reverse order count one more than in query order
+        assert_eq!(
+            distances(&mgr, "beta alpha "),
+            vec![("1".to_string(), 2), ("2".to_string(), 4)]
+        );
+        // A single word has no pairs
+        assert_eq!(
+            distances(&mgr, "gamma "),
+            vec![("1".to_string(), 0), ("2".to_string(), 0)]
+        );
+        assert_eq!(
+            distances(&mgr, "beta alpha"),
+            vec![("1".to_string(), 2), ("2".to_string(), 4)]
+        );
+    }
+
+    #[tokio::test]
+    async fn min_proximity_floors_distances() {
+        let (_tmp, mgr) = make_manager(
+            IndexSettings {
+                min_proximity: 4,
+                ..Default::default()
+            },
+            NAMES,
+        )
+        .await;
+        // Both records are within 4 words, so proximity no longer separates them
+        assert_eq!(
+            distances(&mgr, "delta beta "),
+            vec![("1".to_string(), 4), ("2".to_string(), 4)]
+        );
+    }
+
+    #[tokio::test]
+    async fn quoted_phrase_requires_adjacent_words() {
+        let (_tmp, mgr) = make_manager(
+            IndexSettings::default(),
+            &[
+                "Blue Wireless Earbuds",
+                "Wireless Blue Speaker",
+                "Blue wired wireless",
+            ],
+        )
+        .await;
+        assert_eq!(phrase_ids(&mgr, "\"blue wireless\""), vec!["1"]);
+        assert_eq!(phrase_ids(&mgr, "\"wireless blue\" speaker"), vec!["2"]);
+        // Without quotes all three match
+        assert_eq!(phrase_ids(&mgr, "blue wireless"), vec!["1", "2", "3"]);
+    }
+}
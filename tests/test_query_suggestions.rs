@@ -0,0 +1,231 @@
+//! Query suggestions builder: analytics -> suggestions index.
+
+use flapjack::analytics::config::AnalyticsConfig;
+use flapjack::analytics::query::AnalyticsQueryEngine;
+use flapjack::analytics::schema::SearchEvent;
+use flapjack::analytics::suggestions::{
+    build_suggestions, rebuild, QuerySuggestionsConfig, QuerySuggestionsStore, SuggestionFacet,
+};
+use flapjack::index::settings::IndexSettings;
+use flapjack::types::{Document, FieldValue};
+use flapjack::IndexManager;
+use serde_json::json;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn test_config(dir: &std::path::Path) -> AnalyticsConfig {
+    AnalyticsConfig {
+        enabled: true,
+        data_dir: dir.to_path_buf(),
+        flush_interval_secs: 3600,
+        flush_size: 10_000,
+        retention_days: 90,
+    }
+}
+
+fn search_event(query: &str, nb_hits: u32) -> SearchEvent {
+    SearchEvent {
+        timestamp_ms: chrono::Utc::now().timestamp_millis(),
+        query: query.to_string(),
+        query_id: None,
+        index_name: "products".to_string(),
+        nb_hits,
+        processing_time_ms: 3,
+        user_token: Some("user".to_string()),
+        user_ip: None,
+        filters: None,
+        facets: None,
+        analytics_tags: None,
+        page: 0,
+        hits_per_page: 20,
+        has_results: nb_hits > 0,
+        country: None,
+        region: None,
+        ab_test_id: None,
+        ab_test_variant_id: None,
+    }
+}
+
+fn repeat(query: &str, nb_hits: u32, times: usize) -> Vec<SearchEvent> {
+    (0..times).map(|_| search_event(query, nb_hits)).collect()
+}
+
+/// Seeds analytics for "products" and a small catalog to compute facets from.
+async fn setup(tmp: &TempDir) -> (AnalyticsQueryEngine, Arc<IndexManager>) {
+    let analytics = test_config(&tmp.path().join("analytics"));
+    let mut events = Vec::new();
+    events.extend(repeat("laptop", 10, 6));
+    events.extend(repeat("Laptop ", 12, 1)); // normalized into "laptop"
+    events.extend(repeat("phone", 3, 5));
+    events.extend(repeat("xyzzy", 0, 10)); // zero hits
+    events.extend(repeat("tv", 5, 10)); // too short
+    events.extend(repeat("tablet", 4, 2)); // below min count
+    flapjack::analytics::writer::flush_search_events(&events, &analytics.searches_dir("products"))
+        .unwrap();
+
+    let base = tmp.path().join("indexes");
+    let manager = IndexManager::new(&base);
+    manager.create_tenant("products").unwrap();
+    IndexSettings {
+        attributes_for_faceting: vec!["brand".to_string()],
+        ..Default::default()
+    }
+    .save(base.join("products/settings.json"))
+    .unwrap();
+    manager.invalidate_settings_cache("products");
+
+    let docs: Vec<Document> = vec![
+        json!({"objectID": "1", "name": "Laptop Pro", "brand": "Apple"}),
+        json!({"objectID": "2", "name": "Laptop Air", "brand": "Apple"}),
+        json!({"objectID": "3", "name": "Gaming Laptop", "brand": "Asus"}),
+        json!({"objectID": "4", "name": "Phone X", "brand": "Apple"}),
+    ]
+    .iter()
+    .map(|v| Document::from_json(v).unwrap())
+    .collect();
+    manager.add_documents_sync("products", docs).await.unwrap();
+
+    (AnalyticsQueryEngine::new(analytics), manager)
+}
+
+fn list_suggestions(manager: &IndexManager) -> Vec<Document> {
+    manager
+        .search("products_qs", "", None, None, 100)
+        .unwrap()
+        .documents
+        .into_iter()
+        .map(|d| d.document)
+        .collect()
+}
+
+#[tokio::test]
+async fn build_drops_zero_hit_short_and_rare_queries() {
+    let tmp = TempDir::new().unwrap();
+    let (engine, manager) = setup(&tmp).await;
+
+    let config = QuerySuggestionsConfig::new("products_qs", "products");
+    let result = build_suggestions(&engine, &manager, &config).await.unwrap();
+    assert_eq!(result.nb_suggestions, 2);
+    assert_eq!(result.nb_removed, 0);
+
+    let docs = list_suggestions(&manager);
+    let mut ids: Vec<&str> = docs.iter().map(|d| d.id.as_str()).collect();
+    ids.sort();
+    assert_eq!(ids, vec!["laptop", "phone"]);
+
+    let laptop = docs.iter().find(|d| d.id == "laptop").unwrap();
+    assert_eq!(
+        laptop.fields.get("query"),
+        Some(&FieldValue::Text("laptop".to_string()))
+    );
+    assert_eq!(
+        laptop.fields.get("popularity"),
+        Some(&FieldValue::Integer(7))
+    );
+    assert!(matches!(
+        laptop.fields.get("nb_hits"),
+        Some(FieldValue::Integer(n)) if *n >= 10
+    ));
+    assert!(laptop.fields.get("facets").is_none());
+
+    // Ranking settings are seeded on the first build
+    let settings =
+        IndexSettings::load(tmp.path().join("indexes/products_qs/settings.json")).unwrap();
+    assert_eq!(
+        settings.custom_ranking,
+        Some(vec!["desc(popularity)".to_string()])
+    );
+}
+
+#[tokio::test]
+async fn build_attaches_top_facet_values() {
+    let tmp = TempDir::new().unwrap();
+    let (engine, manager) = setup(&tmp).await;
+
+    let mut config = QuerySuggestionsConfig::new("products_qs", "products");
+    config.facets = vec![SuggestionFacet {
+        attribute: "brand".to_string(),
+        amount: 1,
+    }];
+    build_suggestions(&engine, &manager, &config).await.unwrap();
+
+    let docs = list_suggestions(&manager);
+    let laptop = docs.iter().find(|d| d.id == "laptop").unwrap();
+    let json = laptop.to_json();
+    assert_eq!(
+        json["facets"]["brand"],
+        json!([{"value": "Apple", "count": 2}])
+    );
+}
+
+#[tokio::test]
+async fn rebuild_removes_stale_suggestions_and_records_status() {
+    let tmp = TempDir::new().unwrap();
+    let (engine, manager) = setup(&tmp).await;
+    let store = QuerySuggestionsStore::load(tmp.path());
+
+    let mut config = QuerySuggestionsConfig::new("products_qs", "products");
+    assert!(store.upsert(config.clone()));
+    rebuild(&engine, &manager, &store, &config).await.unwrap();
+
+    config.exclude = vec!["Phone".to_string()];
+    assert!(!store.upsert(config.clone()));
+    let result = rebuild(&engine, &manager, &store, &config).await.unwrap();
+    assert_eq!(result.nb_suggestions, 1);
+    assert_eq!(result.nb_removed, 1);
+
+    let ids: Vec<String> = list_suggestions(&manager)
+        .into_iter()
+        .map(|d| d.id)
+        .collect();
+    assert_eq!(ids, vec!["laptop".to_string()]);
+
+    let status = store.status("products_qs").unwrap();
+    assert!(!status.is_running);
+    assert_eq!(status.nb_suggestions, 1);
+    assert!(status.last_built_at.is_some());
+    assert!(status.error.is_none());
+}
+
+#[test]
+fn store_persists_configs_and_schedules_rebuilds() {
+    let tmp = TempDir::new().unwrap();
+    let store = QuerySuggestionsStore::load(tmp.path());
+
+    let mut config = QuerySuggestionsConfig::new("products_qs", "products");
+    config.rebuild_interval_secs = 60;
+    store.upsert(config.clone());
+    let now = chrono::Utc::now().timestamp_millis();
+    assert_eq!(store.due_configs(now), vec![config.clone()]);
+
+    let reloaded = QuerySuggestionsStore::load(tmp.path());
+    assert_eq!(reloaded.get("products_qs"), Some(config));
+    assert!(reloaded.delete("products_qs"));
+    assert!(reloaded.get("products_qs").is_none());
+    assert!(reloaded.status("products_qs").is_none());
+}
+
+#[test]
+fn config_validation_rejects_self_referencing_index() {
+    let config = QuerySuggestionsConfig::new("products", "products");
+    assert!(config.validate().is_err());
+
+    let parsed: QuerySuggestionsConfig =
+        serde_json::from_value(json!({"indexName": "qs", "sourceIndex": "products"})).unwrap();
+    assert_eq!(parsed.min_count, 5);
+    assert_eq!(parsed.min_hits, 1);
+    assert!(parsed.validate().is_ok());
+}
+
+#[test]
+fn config_validation_rejects_invalid_index_names() {
+    for (index_name, source_index) in [("../products", "queries"), ("qs", "a/b"), (".qs", "x")] {
+        let config = QuerySuggestionsConfig::new(index_name, source_index);
+        assert!(
+            config.validate().is_err(),
+            "{} {}",
+            index_name,
+            source_index
+        );
+    }
+}
@@ -20,6 +20,8 @@ async fn test_internal_status_no_auth_required() {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     // Create internal router like server.rs does
@@ -60,6 +62,8 @@ async fn test_internal_replicate_no_auth_required() {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let internal = Router::new()
@@ -113,6 +117,8 @@ async fn test_internal_get_ops_no_auth_required() {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let internal = Router::new()
@@ -162,6 +168,8 @@ async fn test_internal_tenant_isolation() {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let internal = Router::new()
@@ -284,6 +284,8 @@ async fn test_replicate_ops_handler_applies_correctly() {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let internal = Router::new()
@@ -0,0 +1,204 @@
+//! Schema migration: an index stamped with an older schema version is rebuilt
+//! from its stored documents into a shadow directory and swapped in from the
+//! write queue, without losing the writes made meanwhile.
+
+use flapjack::index::migration::{self, MIGRATIONS_DIR, SCHEMA_VERSION};
+use flapjack::index::settings::IndexSettings;
+use flapjack::types::{Document, TaskStatus};
+use flapjack::IndexManager;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tempfile::TempDir;
+
+mod common;
+
+fn doc(id: usize) -> Document {
+    Document::from_json(&json!({
+        "objectID": id.to_string(),
+        "title": format!("lamp {}", id),
+        "color": if id % 2 == 0 { "red" } else { "blue" }
+    }))
+    .unwrap()
+}
+
+async fn wait_for(manager: &IndexManager, task_id: &str) -> flapjack::types::TaskInfo {
+    for _ in 0..100 {
+        let task = manager.get_task(task_id).unwrap();
+        match task.status {
+            TaskStatus::Succeeded => return task,
+            TaskStatus::Failed(e) => panic!("task {} failed: {}", task_id, e),
+            _ => tokio::time::sleep(Duration::from_millis(100)).await,
+        }
+    }
+    panic!("task {} did not finish", task_id);
+}
+
+#[tokio::test]
+async fn outdated_index_is_rebuilt_and_swapped() {
+    let temp = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp.path());
+    manager.create_tenant("products").unwrap();
+    manager
+        .add_documents_sync("products", (0..3).map(doc).collect())
+        .await
+        .unwrap();
+    let tenant = temp.path().join("products");
+    IndexSettings {
+        attributes_for_faceting: vec!["color".to_string()],
+        ..Default::default()
+    }
+    .save(tenant.join("settings.json"))
+    .unwrap();
+    manager.invalidate_settings_cache("products");
+
+    assert!(manager.outdated_tenants().is_empty());
+    std::fs::write(tenant.join(migration::SCHEMA_VERSION_FILE), "0").unwrap();
+    assert_eq!(manager.outdated_tenants(), vec!["products".to_string()]);
+
+    let task = manager.migrate_schema("products").unwrap();
+    assert_eq!(task.received_documents, 3);
+    let running = manager.migrate_schema("products").unwrap();
+    assert_eq!(
+        running.id, task.id,
+        "a running migration is not started twice"
+    );
+
+    // Written while the copy runs: in the copy or replayed at the swap
+    let write = manager.add_documents("products", vec![doc(3)]).unwrap();
+    let result = manager.search("products", "lamp", None, None, 10).unwrap();
+    assert!(result.total >= 3, "the live index serves during the copy");
+
+    let done = wait_for(&manager, &task.id).await;
+    assert_eq!(done.indexed_documents, done.received_documents);
+    wait_for(&manager, &write.id).await;
+    assert_eq!(
+        migration::read_schema_version(&tenant),
+        Some(SCHEMA_VERSION)
+    );
+    assert!(manager.outdated_tenants().is_empty());
+    assert!(!temp.path().join(MIGRATIONS_DIR).join("products").exists());
+
+    let result = manager.search("products", "lamp", None, None, 10).unwrap();
+    assert_eq!(result.total, 4);
+    assert_eq!(
+        manager
+            .get_settings("products")
+            .unwrap()
+            .attributes_for_faceting,
+        vec!["color".to_string()]
+    );
+
+    // The write queue goes on with the new index, and its oplog
+    manager
+        .add_documents_sync("products", vec![doc(4)])
+        .await
+        .unwrap();
+    let reopened = IndexManager::new(temp.path());
+    assert!(reopened.get_document("products", "4").unwrap().is_some());
+    assert_eq!(
+        reopened
+            .search("products", "lamp", None, None, 10)
+            .unwrap()
+            .total,
+        5
+    );
+}
+
+#[tokio::test]
+async fn unstamped_index_is_migrated_on_startup() {
+    let temp = TempDir::new().unwrap();
+    {
+        let manager = IndexManager::new(temp.path());
+        manager.create_tenant("products").unwrap();
+        manager
+            .add_documents_sync("products", (0..3).map(doc).collect())
+            .await
+            .unwrap();
+    }
+    // Indexes written before schema versions were stamped
+    let tenant = temp.path().join("products");
+    std::fs::remove_file(tenant.join(migration::SCHEMA_VERSION_FILE)).unwrap();
+
+    let manager = IndexManager::new(temp.path());
+    assert!(manager.needs_schema_migration("products"));
+    assert_eq!(manager.outdated_tenants(), vec!["products".to_string()]);
+
+    std::sync::Arc::clone(&manager)
+        .run_schema_migrations()
+        .await;
+    assert_eq!(
+        migration::read_schema_version(&tenant),
+        Some(SCHEMA_VERSION)
+    );
+    assert!(manager.outdated_tenants().is_empty());
+    assert_eq!(
+        manager
+            .search("products", "lamp", None, None, 10)
+            .unwrap()
+            .total,
+        3
+    );
+}
+
+#[tokio::test]
+async fn migrate_over_http() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+
+    let resp = client
+        .post(format!("http://{}/1/indexes/products/batch", addr))
+        .json(&json!({"requests": [
+            {"action": "addObject", "body": {"objectID": "1", "title": "red lamp"}},
+            {"action": "addObject", "body": {"objectID": "2", "title": "blue lamp"}}
+        ]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let resp = client
+        .post(format!("http://{}/1/indexes/products/migrate", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let body: Value = resp.json().await.unwrap();
+    assert_eq!(body["schemaVersion"], SCHEMA_VERSION);
+
+    let mut published = false;
+    for _ in 0..50 {
+        let task: Value = client
+            .get(format!("http://{}/1/tasks/{}", addr, body["taskID"]))
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        if task["status"] == "published" {
+            published = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(published, "migration task was not published");
+
+    let result: Value = client
+        .post(format!("http://{}/1/indexes/products/query", addr))
+        .json(&json!({"query": "lamp"}))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(result["nbHits"], 2);
+
+    let resp = client
+        .post(format!("http://{}/1/indexes/missing/migrate", addr))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 404);
+}
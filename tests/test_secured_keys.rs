@@ -5,7 +5,7 @@ use tempfile::TempDir;
 
 fn setup_key_store() -> (TempDir, KeyStore) {
     let temp_dir = TempDir::new().unwrap();
-    let store = KeyStore::load_or_create(temp_dir.path(), "admin_key_1234567890abcdef");
+    let store = KeyStore::load_or_create(temp_dir.path(), "admin_key_1234567890abcdef").unwrap();
     (temp_dir, store)
 }
 
@@ -49,7 +49,7 @@ async fn setup_index(addr: &str, admin_key: &str) {
 async fn test_e2e_secured_key_forces_filter() -> Result<()> {
     let admin_key = "admin_key_1234567890abcdef";
     let (addr, tmp) = common::spawn_server_with_key(Some(admin_key)).await;
-    let store = KeyStore::load_or_create(tmp.path(), admin_key);
+    let store = KeyStore::load_or_create(tmp.path(), admin_key).unwrap();
     let search_key = get_search_key(&store);
 
     setup_index(&addr, admin_key).await;
@@ -99,7 +99,7 @@ async fn test_e2e_secured_key_forces_filter() -> Result<()> {
 async fn test_e2e_secured_key_restrict_indices_blocks_wrong_index() -> Result<()> {
     let admin_key = "admin_key_1234567890abcdef";
     let (addr, tmp) = common::spawn_server_with_key(Some(admin_key)).await;
-    let store = KeyStore::load_or_create(tmp.path(), admin_key);
+    let store = KeyStore::load_or_create(tmp.path(), admin_key).unwrap();
     let search_key = get_search_key(&store);
 
     setup_index(&addr, admin_key).await;
@@ -124,7 +124,7 @@ async fn test_e2e_secured_key_restrict_indices_blocks_wrong_index() -> Result<()
 async fn test_e2e_secured_key_expired_rejected() -> Result<()> {
     let admin_key = "admin_key_1234567890abcdef";
     let (addr, tmp) = common::spawn_server_with_key(Some(admin_key)).await;
-    let store = KeyStore::load_or_create(tmp.path(), admin_key);
+    let store = KeyStore::load_or_create(tmp.path(), admin_key).unwrap();
     let search_key = get_search_key(&store);
 
     let secured = generate_secured_api_key(&search_key, "validUntil=1000000000");
@@ -144,7 +144,7 @@ async fn test_e2e_secured_key_expired_rejected() -> Result<()> {
 async fn test_e2e_secured_key_parent_index_scope_enforced() -> Result<()> {
     let admin_key = "admin_key_1234567890abcdef";
     let (addr, tmp) = common::spawn_server_with_key(Some(admin_key)).await;
-    let store = KeyStore::load_or_create(tmp.path(), admin_key);
+    let store = KeyStore::load_or_create(tmp.path(), admin_key).unwrap();
 
     let scoped = store.create_key(flapjack_http::auth::ApiKey {
         value: String::new(),
@@ -180,7 +180,7 @@ async fn test_e2e_secured_key_parent_index_scope_enforced() -> Result<()> {
 async fn test_e2e_secured_key_filter_merges_with_user_filter() -> Result<()> {
     let admin_key = "admin_key_1234567890abcdef";
     let (addr, tmp) = common::spawn_server_with_key(Some(admin_key)).await;
-    let store = KeyStore::load_or_create(tmp.path(), admin_key);
+    let store = KeyStore::load_or_create(tmp.path(), admin_key).unwrap();
     let search_key = get_search_key(&store);
 
     setup_index(&addr, admin_key).await;
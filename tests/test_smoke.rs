@@ -237,7 +237,7 @@ fn smoke_parser_and_synonyms() {
 #[test]
 fn smoke_auth() {
     let tmp = TempDir::new().unwrap();
-    let store = auth::KeyStore::load_or_create(tmp.path(), "admin_key_1234567890abcdef");
+    let store = auth::KeyStore::load_or_create(tmp.path(), "admin_key_1234567890abcdef").unwrap();
 
     // KeyStore creates default search + admin keys
     let keys = store.list_all();
@@ -348,6 +348,8 @@ async fn smoke_internal_endpoint() {
         key_store: None,
         replication_manager: None,
         ssl_manager: None,
+        ab_tests: None,
+        personalization: None,
     });
 
     let internal = Router::new()
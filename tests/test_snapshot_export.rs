@@ -0,0 +1,83 @@
+//! Streamed index export and import over HTTP, with the archive checksums
+//! verified before an import replaces an index.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::send;
+
+async fn nb_hits(client: &reqwest::Client, addr: &str, index: &str) -> Value {
+    let (_, body) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/query", addr, index),
+        "test",
+        json!({"query": ""}),
+    )
+    .await;
+    body["nbHits"].clone()
+}
+
+async fn import(client: &reqwest::Client, addr: &str, index: &str, archive: Vec<u8>) -> u16 {
+    client
+        .post(format!("http://{}/1/indexes/{}/import", addr, index))
+        .header("x-algolia-api-key", "test")
+        .header("x-algolia-application-id", "test")
+        .header("content-type", "application/gzip")
+        .body(archive)
+        .send()
+        .await
+        .unwrap()
+        .status()
+        .as_u16()
+}
+
+async fn setup_products(client: &reqwest::Client, addr: &str) -> Vec<u8> {
+    let requests: Vec<Value> = (0..50)
+        .map(|i| json!({"action": "addObject", "body": {"objectID": i.to_string(), "name": format!("Product {}", i)}}))
+        .collect();
+    let (status, _) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/products/batch", addr),
+        "test",
+        json!({ "requests": requests }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    let resp = client
+        .get(format!("http://{}/1/indexes/products/export", addr))
+        .header("x-algolia-api-key", "test")
+        .header("x-algolia-application-id", "test")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(resp.headers()["content-type"], "application/gzip");
+    resp.bytes().await.unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn exported_index_imports_under_another_name() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    let archive = setup_products(&client, &addr).await;
+    assert!(archive.len() > 100);
+
+    assert_eq!(import(&client, &addr, "products_copy", archive).await, 200);
+    assert_eq!(nb_hits(&client, &addr, "products_copy").await, 50);
+}
+
+#[tokio::test]
+async fn truncated_import_keeps_the_existing_index() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    let archive = setup_products(&client, &addr).await;
+
+    let truncated = archive[..archive.len() / 2].to_vec();
+    assert_eq!(import(&client, &addr, "products", truncated).await, 500);
+    assert_eq!(nb_hits(&client, &addr, "products").await, 50);
+}
@@ -0,0 +1,404 @@
+//! Backups through the snapshot stores: a local directory, and an
+//! S3-compatible stand-in serving path-style requests.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use flapjack::index::s3::{S3Config, S3Store};
+use flapjack::index::snapshot_store::{self, LocalStore, SnapshotStore};
+use flapjack::types::Document;
+use flapjack::IndexManager;
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn doc(id: &str, title: &str) -> Document {
+    Document::from_json(&json!({"objectID": id, "title": title})).unwrap()
+}
+
+fn now_ms() -> u64 {
+    chrono::Utc::now().timestamp_millis() as u64
+}
+
+async fn seed(manager: &IndexManager, tenant: &str, ids: &[&str]) {
+    for id in ids {
+        manager
+            .add_documents_sync(tenant, vec![doc(id, "a book")])
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn full_snapshots_in_a_directory() {
+    let data = TempDir::new().unwrap();
+    let backups = TempDir::new().unwrap();
+    let store = LocalStore::new(backups.path());
+    let manager = IndexManager::new(data.path());
+    manager.create_tenant("books").unwrap();
+    seed(&manager, "books", &["1", "2", "3"]).await;
+
+    let (key, size) = snapshot_store::upload_snapshot(&store, "books", &data.path().join("books"))
+        .await
+        .unwrap();
+    assert!(size > 0);
+    assert!(backups.path().join(&key).exists());
+    assert_eq!(
+        snapshot_store::list_snapshots(&store, "books")
+            .await
+            .unwrap(),
+        vec![key.clone()]
+    );
+    assert_eq!(
+        snapshot_store::list_indexes(&store).await.unwrap(),
+        vec!["books"]
+    );
+
+    let restored = TempDir::new().unwrap();
+    snapshot_store::restore_snapshot(&store, &key, &restored.path().join("books"))
+        .await
+        .unwrap();
+    let restored_manager = IndexManager::new(restored.path());
+    assert_eq!(
+        restored_manager
+            .search("books", "", None, None, 10)
+            .unwrap()
+            .total,
+        3
+    );
+
+    for older in ["20200101T000000Z", "20210101T000000Z"] {
+        store
+            .put(
+                &format!("snapshots/books/{}.tar.gz", older),
+                b"old".to_vec(),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(store.enforce_retention("books", 1).await.unwrap(), 2);
+    assert_eq!(
+        snapshot_store::list_snapshots(&store, "books")
+            .await
+            .unwrap(),
+        vec![key]
+    );
+}
+
+#[tokio::test]
+async fn incremental_snapshots_restore_to_a_point_in_time() {
+    let data = TempDir::new().unwrap();
+    let backups = TempDir::new().unwrap();
+    let store = LocalStore::new(backups.path());
+    let manager = IndexManager::new(data.path());
+    let index_path = data.path().join("books");
+    manager.create_tenant("books").unwrap();
+
+    seed(&manager, "books", &["1"]).await;
+    let first = snapshot_store::upload_incremental_snapshot(
+        &store,
+        "books",
+        &index_path,
+        &manager.pin_commit("books").unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(first.uploaded_files, first.files);
+
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    seed(&manager, "books", &["2"]).await;
+    let target = now_ms();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    seed(&manager, "books", &["3"]).await;
+
+    // Before the next snapshot, the ops after the first one come from the
+    // live oplog
+    assert!(manager.evict_tenant("books").await);
+    let restore = snapshot_store::restore_point_in_time(&store, "books", &index_path, Some(target))
+        .await
+        .unwrap();
+    assert_eq!(restore.manifest_key, first.manifest_key);
+    assert_eq!(restore.replayed_ops, 1);
+    manager.unload_tenant("books");
+    assert_eq!(
+        manager.search("books", "", None, None, 10).unwrap().total,
+        2
+    );
+
+    seed(&manager, "books", &["3", "4"]).await;
+    let second = snapshot_store::upload_incremental_snapshot(
+        &store,
+        "books",
+        &index_path,
+        &manager.pin_commit("books").unwrap(),
+    )
+    .await
+    .unwrap();
+    assert!(
+        second.uploaded_files < second.files,
+        "segments already stored are not uploaded again"
+    );
+
+    // Between two snapshots, they come from the next snapshot's oplog
+    assert!(manager.evict_tenant("books").await);
+    snapshot_store::restore_point_in_time(&store, "books", &index_path, Some(target))
+        .await
+        .unwrap();
+    manager.unload_tenant("books");
+    assert_eq!(
+        manager.search("books", "", None, None, 10).unwrap().total,
+        2
+    );
+
+    // Without a target the latest snapshot is restored
+    assert!(manager.evict_tenant("books").await);
+    snapshot_store::restore_point_in_time(&store, "books", &index_path, None)
+        .await
+        .unwrap();
+    manager.unload_tenant("books");
+    assert_eq!(
+        manager.search("books", "", None, None, 10).unwrap().total,
+        4
+    );
+
+    assert_eq!(
+        snapshot_store::enforce_incremental_retention(&store, "books", 1)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        snapshot_store::list_manifests(&store, "books")
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(
+        snapshot_store::restore_point_in_time(&store, "books", &index_path, Some(0))
+            .await
+            .is_err()
+    );
+}
+
+#[tokio::test]
+async fn incremental_snapshot_keeps_the_pinned_commit_through_merges() {
+    let data = TempDir::new().unwrap();
+    let backups = TempDir::new().unwrap();
+    let store = LocalStore::new(backups.path());
+    let manager = IndexManager::new(data.path());
+    let index_path = data.path().join("books");
+    manager.create_tenant("books").unwrap();
+    seed(&manager, "books", &["1", "2", "3"]).await;
+
+    // Writes and a merge land between the pin and the upload
+    let commit = manager.pin_commit("books").unwrap();
+    seed(&manager, "books", &["4", "5"]).await;
+    manager.compact_index_sync("books").await.unwrap();
+    let upload = snapshot_store::upload_incremental_snapshot(&store, "books", &index_path, &commit)
+        .await
+        .unwrap();
+    drop(commit);
+    assert_eq!(upload.uploaded_files, upload.files);
+
+    // The merged segments and the later writes go with the next snapshot
+    seed(&manager, "books", &["6"]).await;
+    let next = snapshot_store::upload_incremental_snapshot(
+        &store,
+        "books",
+        &index_path,
+        &manager.pin_commit("books").unwrap(),
+    )
+    .await
+    .unwrap();
+    assert!(next.uploaded_files > 0);
+
+    let restored = TempDir::new().unwrap();
+    let restored_manager = IndexManager::new(restored.path());
+    for (manifest_key, total) in [(&upload.manifest_key, 5), (&next.manifest_key, 6)] {
+        let manifest = snapshot_store::download_manifest(&store, manifest_key)
+            .await
+            .unwrap();
+        let target = manifest.created_at_ms;
+        restored_manager.evict_tenant("books").await;
+        let restore = snapshot_store::restore_point_in_time(
+            &store,
+            "books",
+            &restored.path().join("books"),
+            Some(target),
+        )
+        .await
+        .unwrap();
+        assert_eq!(&restore.manifest_key, manifest_key);
+        restored_manager.unload_tenant("books");
+        assert_eq!(
+            restored_manager
+                .search("books", "", None, None, 10)
+                .unwrap()
+                .total,
+            total
+        );
+    }
+}
+
+type Objects = Arc<Mutex<BTreeMap<String, Vec<u8>>>>;
+
+/// Just enough of the S3 API for the snapshot store: path-style object
+/// PUT/GET/DELETE and ListObjectsV2.
+async fn spawn_s3_stand_in() -> (String, Objects) {
+    async fn put_object(
+        State(objects): State<Objects>,
+        Path((_bucket, key)): Path<(String, String)>,
+        body: Bytes,
+    ) -> impl IntoResponse {
+        objects.lock().unwrap().insert(key, body.to_vec());
+        (StatusCode::OK, [("ETag", "\"stand-in\"")])
+    }
+
+    async fn get_object(
+        State(objects): State<Objects>,
+        Path((_bucket, key)): Path<(String, String)>,
+    ) -> impl IntoResponse {
+        match objects.lock().unwrap().get(&key) {
+            Some(data) => (StatusCode::OK, data.clone()),
+            None => (StatusCode::NOT_FOUND, Vec::new()),
+        }
+    }
+
+    async fn delete_object(
+        State(objects): State<Objects>,
+        Path((_bucket, key)): Path<(String, String)>,
+    ) -> StatusCode {
+        objects.lock().unwrap().remove(&key);
+        StatusCode::NO_CONTENT
+    }
+
+    async fn list_objects(
+        State(objects): State<Objects>,
+        Path(bucket): Path<String>,
+        Query(params): Query<HashMap<String, String>>,
+    ) -> impl IntoResponse {
+        let prefix = params.get("prefix").cloned().unwrap_or_default();
+        let delimiter = params.get("delimiter").cloned();
+        let mut contents = String::new();
+        let mut common_prefixes = Vec::new();
+        for (key, data) in objects.lock().unwrap().iter() {
+            let Some(rest) = key.strip_prefix(&prefix) else {
+                continue;
+            };
+            match delimiter.as_deref().and_then(|d| rest.find(d)) {
+                Some(end) => {
+                    let common = format!("{}{}", prefix, &rest[..=end]);
+                    if !common_prefixes.contains(&common) {
+                        common_prefixes.push(common);
+                    }
+                }
+                None => contents.push_str(&format!(
+                    "<Contents><Key>{}</Key><LastModified>2024-01-01T00:00:00.000Z</LastModified><ETag>\"stand-in\"</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+                    key,
+                    data.len()
+                )),
+            }
+        }
+        let common_prefixes: String = common_prefixes
+            .iter()
+            .map(|p| format!("<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>", p))
+            .collect();
+        let xml = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+             <ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+             <Name>{}</Name><Prefix>{}</Prefix><MaxKeys>1000</MaxKeys><IsTruncated>false</IsTruncated>\
+             {}{}</ListBucketResult>",
+            bucket, prefix, contents, common_prefixes
+        );
+        (StatusCode::OK, [("Content-Type", "application/xml")], xml)
+    }
+
+    let objects: Objects = Arc::default();
+    let app = Router::new()
+        .route("/:bucket", get(list_objects))
+        .route("/:bucket/", get(list_objects))
+        .route(
+            "/:bucket/*key",
+            get(get_object).put(put_object).delete(delete_object),
+        )
+        .with_state(objects.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+    (format!("http://{}", addr), objects)
+}
+
+#[tokio::test]
+async fn snapshots_in_an_s3_compatible_store() {
+    let (endpoint, objects) = spawn_s3_stand_in().await;
+    let store = S3Store::new(S3Config {
+        bucket_name: "backups".to_string(),
+        region: "minio".to_string(),
+        endpoint: Some(endpoint),
+        path_style: true,
+        credentials: Some(("access".to_string(), "secret".to_string())),
+    })
+    .unwrap();
+
+    let data = TempDir::new().unwrap();
+    let manager = IndexManager::new(data.path());
+    manager.create_tenant("books").unwrap();
+    seed(&manager, "books", &["1", "2"]).await;
+    let index_path = data.path().join("books");
+
+    let (key, _) = snapshot_store::upload_snapshot(&store, "books", &index_path)
+        .await
+        .unwrap();
+    assert!(objects.lock().unwrap().contains_key(&key));
+    let upload = snapshot_store::upload_incremental_snapshot(
+        &store,
+        "books",
+        &index_path,
+        &manager.pin_commit("books").unwrap(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        snapshot_store::list_indexes(&store).await.unwrap(),
+        vec!["books"]
+    );
+
+    let restored = TempDir::new().unwrap();
+    snapshot_store::restore_snapshot(&store, &key, &restored.path().join("books"))
+        .await
+        .unwrap();
+    let restored_manager = IndexManager::new(restored.path());
+    assert_eq!(
+        restored_manager
+            .search("books", "", None, None, 10)
+            .unwrap()
+            .total,
+        2
+    );
+
+    let restore = snapshot_store::restore_point_in_time(
+        &store,
+        "books",
+        &restored.path().join("incremental"),
+        None,
+    )
+    .await
+    .unwrap();
+    assert_eq!(restore.manifest_key, upload.manifest_key);
+
+    store.delete(&key).await.unwrap();
+    assert!(snapshot_store::list_snapshots(&store, "books")
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,142 @@
+//! Idle and LRU eviction of loaded tenants, with transparent reloads.
+
+use flapjack::error::FlapjackError;
+use flapjack::index::eviction::EvictionConfig;
+use flapjack::types::Document;
+use flapjack::IndexManager;
+use serde_json::json;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn doc(id: &str, title: &str) -> Document {
+    Document::from_json(&json!({"objectID": id, "title": title})).unwrap()
+}
+
+#[tokio::test]
+async fn idle_and_least_recently_used_tenants_are_evicted() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp_dir.path());
+    let budget = flapjack::get_global_budget();
+    let writers_before = budget.active_writers();
+
+    for tenant in ["alpha", "beta", "gamma"] {
+        manager.create_tenant(tenant).unwrap();
+        manager
+            .add_documents_sync(tenant, vec![doc("1", "hello world")])
+            .await
+            .unwrap();
+    }
+    assert_eq!(manager.loaded_count(), 3);
+    assert_eq!(budget.active_writers(), writers_before + 3);
+
+    // "beta" becomes the least recently used
+    manager.search("alpha", "hello", None, None, 10).unwrap();
+    manager.search("gamma", "hello", None, None, 10).unwrap();
+    manager.set_eviction_config(EvictionConfig {
+        max_loaded_tenants: 2,
+        idle_timeout: None,
+        check_interval: Duration::from_secs(3600),
+    });
+    assert_eq!(manager.evict_idle_tenants().await, 1);
+    assert_eq!(manager.loaded_count(), 2);
+    assert_eq!(budget.active_writers(), writers_before + 2);
+    assert_eq!(manager.eviction_stats().lru_evictions, 1);
+
+    // Reloaded on the next access, with its writes intact
+    let result = manager.search("beta", "hello", None, None, 10).unwrap();
+    assert_eq!(result.total, 1);
+    manager
+        .add_documents_sync("beta", vec![doc("2", "hello again")])
+        .await
+        .unwrap();
+    assert_eq!(
+        manager
+            .search("beta", "hello", None, None, 10)
+            .unwrap()
+            .total,
+        2
+    );
+
+    manager.set_eviction_config(EvictionConfig {
+        max_loaded_tenants: 0,
+        idle_timeout: Some(Duration::from_millis(50)),
+        check_interval: Duration::from_secs(3600),
+    });
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(manager.evict_idle_tenants().await, 3);
+    assert_eq!(manager.loaded_count(), 0);
+    assert_eq!(budget.active_writers(), writers_before);
+
+    let stats = manager.eviction_stats();
+    assert_eq!(stats.idle_evictions, 3);
+    assert_eq!(stats.lru_evictions, 1);
+    assert_eq!(
+        manager
+            .search("gamma", "hello", None, None, 10)
+            .unwrap()
+            .total,
+        1
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn writes_racing_evictions_are_not_lost() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp_dir.path());
+    manager.create_tenant("race").unwrap();
+
+    let evicting = std::sync::Arc::clone(&manager);
+    let evictions = tokio::spawn(async move {
+        let mut evicted = 0;
+        for _ in 0..200 {
+            if evicting.evict_tenant("race").await {
+                evicted += 1;
+            }
+            tokio::task::yield_now().await;
+        }
+        evicted
+    });
+    for i in 0..50 {
+        manager
+            .add_documents_sync("race", vec![doc(&i.to_string(), "hello world")])
+            .await
+            .unwrap();
+    }
+    evictions.await.unwrap();
+
+    assert_eq!(
+        manager
+            .search("race", "hello", None, None, 10)
+            .unwrap()
+            .total,
+        50
+    );
+}
+
+#[tokio::test]
+async fn rewrites_wait_for_pending_tasks() {
+    let temp_dir = TempDir::new().unwrap();
+    let manager = IndexManager::new(temp_dir.path());
+    manager.create_tenant("busy").unwrap();
+
+    manager
+        .add_documents("busy", vec![doc("1", "hello world")])
+        .unwrap();
+    let rewritten = manager.rewrite_tenant("busy", async { Ok(()) }).await;
+    assert!(matches!(rewritten, Err(FlapjackError::TenantBusy(_))));
+
+    manager
+        .add_documents_sync("busy", vec![doc("2", "hello again")])
+        .await
+        .unwrap();
+    let rewritten = manager.rewrite_tenant("busy", async { Ok(7) }).await;
+    assert_eq!(rewritten.unwrap(), 7);
+    assert_eq!(manager.loaded_count(), 0);
+    assert_eq!(
+        manager
+            .search("busy", "hello", None, None, 10)
+            .unwrap()
+            .total,
+        2
+    );
+}
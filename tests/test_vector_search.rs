@@ -0,0 +1,379 @@
+//! Vector search over the records' `_vectors`: nearest neighbours for an
+//! empty query, fused with the keyword ranking otherwise, or appended to
+//! sparse keyword results with `semanticSearch`.
+
+use serde_json::{json, Value};
+
+mod common;
+
+use common::send;
+
+async fn search(client: &reqwest::Client, addr: &str, index: &str, params: Value) -> (u16, Value) {
+    send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/query", addr, index),
+        "test",
+        params,
+    )
+    .await
+}
+
+fn ordered_ids(body: &Value) -> Vec<String> {
+    body["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|h| h["objectID"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn add_objects(client: &reqwest::Client, addr: &str, index: &str, objects: Value) {
+    let requests: Vec<Value> = objects
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|body| json!({"action": "addObject", "body": body}))
+        .collect();
+    let (status, _) = send(
+        client,
+        reqwest::Method::POST,
+        format!("http://{}/1/indexes/{}/batch", addr, index),
+        "test",
+        json!({ "requests": requests }),
+    )
+    .await;
+    assert_eq!(status, 200);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+}
+
+/// Shoes with a made-up 3-dimensional embedding: warmth, sportiness, formality.
+async fn setup_shoes(client: &reqwest::Client, addr: &str) {
+    add_objects(
+        client,
+        addr,
+        "shoes",
+        json!([
+            {"objectID": "boots", "name": "Fleece lined boots", "brand": "north", "_vectors": [0.9, 0.1, 0.1]},
+            {"objectID": "slippers", "name": "Wool slippers", "brand": "home", "_vectors": [0.8, 0.0, 0.2]},
+            {"objectID": "runners", "name": "Trail running shoes", "brand": "north", "_vectors": [0.1, 0.9, 0.0]},
+            {"objectID": "oxfords", "name": "Leather oxfords", "brand": "city", "_vectors": [0.1, 0.0, 0.9]},
+            {"objectID": "sandals", "name": "Beach sandals", "brand": "home"},
+        ]),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn empty_query_returns_nearest_neighbours() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup_shoes(&client, &addr).await;
+
+    // No words to match: the records closest to a warm vector come first
+    let (status, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"query": "", "vector": [1.0, 0.0, 0.1], "hitsPerPage": 2, "getRankingInfo": true}),
+    )
+    .await;
+    assert_eq!(status, 200, "{:?}", body);
+    assert_eq!(ordered_ids(&body), vec!["boots", "slippers"]);
+    assert_eq!(body["nbHits"], 4);
+    let similarity = body["hits"][0]["_rankingInfo"]["semanticScore"]
+        .as_f64()
+        .unwrap();
+    assert!(similarity > 0.95 && similarity <= 1.0, "{}", similarity);
+
+    // Vectors go through the params string too
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"params": "vector=%5B0%2C1%2C0%5D&hitsPerPage=1"}),
+    )
+    .await;
+    assert_eq!(ordered_ids(&body), vec!["runners"]);
+}
+
+#[tokio::test]
+async fn filters_and_distinct_apply_to_vector_hits() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup_shoes(&client, &addr).await;
+
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"vector": [1.0, 0.0, 0.1], "filters": "brand:city OR brand:home"}),
+    )
+    .await;
+    assert_eq!(ordered_ids(&body), vec!["slippers", "oxfords"]);
+    assert_eq!(body["nbHits"], 2);
+
+    let (status, _) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/indexes/shoes/settings", addr),
+        "test",
+        json!({"attributeForDistinct": "brand"}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"vector": [0.6, 0.6, 0.0], "distinct": true}),
+    )
+    .await;
+    let ids = ordered_ids(&body);
+    assert_eq!(ids.len(), 3, "{:?}", ids);
+    assert!(!(ids.contains(&"boots".to_string()) && ids.contains(&"runners".to_string())));
+}
+
+#[tokio::test]
+async fn keyword_and_vector_rankings_are_fused() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup_shoes(&client, &addr).await;
+
+    // The runners match the keyword, the boots the vector
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"query": "running", "vector": [1.0, 0.0, 0.1], "getRankingInfo": true}),
+    )
+    .await;
+    let ids = ordered_ids(&body);
+    assert!(ids.contains(&"runners".to_string()), "{:?}", ids);
+    assert!(ids.contains(&"boots".to_string()), "{:?}", ids);
+    let runners = body["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|h| h["objectID"] == "runners")
+        .unwrap();
+    // Found by both searches, so it ranks first and counts once
+    assert_eq!(body["hits"][0]["objectID"], "runners");
+    assert!(runners["_rankingInfo"]["semanticScore"].is_number());
+    assert_eq!(body["nbHits"], 4);
+}
+
+#[tokio::test]
+async fn vectors_are_stored_but_not_indexed_or_returned_by_default() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup_shoes(&client, &addr).await;
+
+    // Dimensions are not filterable numbers
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"filters": "_vectors = 0.9"}),
+    )
+    .await;
+    assert_eq!(body["nbHits"], 0);
+
+    let (_, body) = search(&client, &addr, "shoes", json!({"query": "boots"})).await;
+    assert!(body["hits"][0].get("_vectors").is_none(), "{:?}", body);
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"query": "boots", "attributesToRetrieve": ["*"]}),
+    )
+    .await;
+    assert!(body["hits"][0].get("_vectors").is_none(), "{:?}", body);
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"query": "boots", "attributesToRetrieve": ["name", "_vectors"]}),
+    )
+    .await;
+    assert_eq!(body["hits"][0]["_vectors"], json!([0.9, 0.1, 0.1]));
+
+    let url = format!("http://{}/1/indexes/shoes/boots", addr);
+    let (_, body) = send(
+        &client,
+        reqwest::Method::GET,
+        url.clone(),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(body["name"], "Fleece lined boots");
+    assert!(body.get("_vectors").is_none(), "{:?}", body);
+    let (_, body) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("{}?attributesToRetrieve=name,_vectors", url),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(body["_vectors"], json!([0.9, 0.1, 0.1]));
+}
+
+#[tokio::test]
+async fn mismatched_dimensions_are_rejected() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup_shoes(&client, &addr).await;
+
+    add_objects(
+        &client,
+        &addr,
+        "shoes",
+        json!([{"objectID": "clogs", "name": "Garden clogs", "_vectors": [0.5, 0.5]}]),
+    )
+    .await;
+    let (_, body) = search(&client, &addr, "shoes", json!({"query": "clogs"})).await;
+    assert_eq!(body["nbHits"], 0);
+
+    let (status, _) = search(&client, &addr, "shoes", json!({"vector": [1.0, 0.0]})).await;
+    assert_eq!(status, 400);
+
+    // Replacing a record without a vector drops it from vector search
+    add_objects(
+        &client,
+        &addr,
+        "shoes",
+        json!([{"objectID": "boots", "name": "Fleece lined boots"}]),
+    )
+    .await;
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"vector": [1.0, 0.0, 0.1], "hitsPerPage": 1}),
+    )
+    .await;
+    assert_eq!(ordered_ids(&body), vec!["slippers"]);
+    assert_eq!(body["nbHits"], 3);
+}
+
+#[tokio::test]
+async fn semantic_search_backs_up_sparse_keyword_results() {
+    let (addr, _temp) = common::spawn_server().await;
+    let client = reqwest::Client::new();
+    setup_shoes(&client, &addr).await;
+    let (status, _) = send(
+        &client,
+        reqwest::Method::PUT,
+        format!("http://{}/1/indexes/shoes/settings", addr),
+        "test",
+        json!({"semanticSearch": {"minLexicalHits": 2}}),
+    )
+    .await;
+    assert_eq!(status, 200);
+    tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+    // One keyword hit: the nearest neighbours follow it
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"query": "running", "vector": [1.0, 0.0, 0.1], "getRankingInfo": true}),
+    )
+    .await;
+    assert_eq!(
+        ordered_ids(&body),
+        vec!["runners", "boots", "slippers", "oxfords"]
+    );
+    assert_eq!(body["nbHits"], 4);
+    assert!(body["hits"][0]["_rankingInfo"]
+        .get("semanticScore")
+        .is_none());
+    assert!(body["hits"][1]["_rankingInfo"]["semanticScore"].is_number());
+
+    // Enough keyword hits: the vector is not used
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({"query": "home", "vector": [0.1, 0.0, 0.9], "getRankingInfo": true}),
+    )
+    .await;
+    let mut ids = ordered_ids(&body);
+    ids.sort();
+    assert_eq!(ids, vec!["sandals", "slippers"]);
+    assert_eq!(body["nbHits"], 2);
+
+    // Replaces removeWordsIfNoResults for zero-result queries
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({
+            "query": "fleece cozy winter",
+            "vector": [1.0, 0.0, 0.1],
+            "removeWordsIfNoResults": "lastWords",
+            "typoTolerance": false
+        }),
+    )
+    .await;
+    assert_eq!(
+        ordered_ids(&body),
+        vec!["boots", "slippers", "oxfords", "runners"]
+    );
+    assert_eq!(body["nbHits"], 4);
+
+    let (_, body) = send(
+        &client,
+        reqwest::Method::GET,
+        format!("http://{}/1/indexes/shoes/settings", addr),
+        "test",
+        Value::Null,
+    )
+    .await;
+    assert_eq!(body["semanticSearch"], json!({"minLexicalHits": 2}));
+
+    // Without a vector hit either, the keywords are relaxed
+    let (_, body) = search(
+        &client,
+        &addr,
+        "shoes",
+        json!({
+            "query": "beach cozy",
+            "vector": [1.0, 0.0, 0.1],
+            "filters": "objectID:sandals",
+            "removeWordsIfNoResults": "lastWords",
+            "typoTolerance": false
+        }),
+    )
+    .await;
+    assert_eq!(ordered_ids(&body), vec!["sandals"]);
+    assert_eq!(body["nbHits"], 1);
+
+    // `null` and `{}` clear the setting
+    for cleared in [Value::Null, json!({})] {
+        let url = format!("http://{}/1/indexes/shoes/settings", addr);
+        let (status, _) = send(
+            &client,
+            reqwest::Method::PUT,
+            url.clone(),
+            "test",
+            json!({"semanticSearch": {"minLexicalHits": 2}}),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let (status, _) = send(
+            &client,
+            reqwest::Method::PUT,
+            url.clone(),
+            "test",
+            json!({ "semanticSearch": cleared }),
+        )
+        .await;
+        assert_eq!(status, 200);
+        let (_, body) = send(&client, reqwest::Method::GET, url, "test", Value::Null).await;
+        assert!(body.get("semanticSearch").is_none(), "{:?}", body);
+    }
+}